@@ -0,0 +1,100 @@
+//! Measure the throughput of sending data over a TCP connection.
+//!
+//! The data is sent to a TCP server that discards it, e.g. one started with
+//! `nc -l 5001 > /dev/null`, once with `AT+CIPSEND` and once with segments
+//! pipelined through the send buffer of the device (NONOS firmwares only).
+
+use std::{
+    env,
+    net::{SocketAddr, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use espresso::{
+    commands::requests,
+    host::{spawn_reader, SerialWriter, SysTimer},
+    types::{MultiplexingType, Target, WifiMode},
+    StaticResources,
+};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+
+/// Send windows to compare, 1 disables pipelining.
+const SEND_WINDOWS: &[u8] = &[1, 2, 4];
+
+fn main() {
+    env_logger::init();
+
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 7 {
+        println!(
+            "Usage: {} <path-to-serial> <baudrate> <ssid> <psk> <server> <kibibytes>",
+            args[0]
+        );
+        println!(
+            "Example: {} /dev/ttyUSB0 921600 mywifi hellopasswd123 192.168.1.10:5001 256",
+            args[0]
+        );
+        std::process::exit(1);
+    }
+    let dev = &args[1];
+    let baud_rate: u32 = args[2].parse().unwrap();
+    let ssid = &args[3];
+    let psk = &args[4];
+    let server = args[5]
+        .to_socket_addrs()
+        .expect("Could not resolve server")
+        .find_map(|addr| match addr {
+            SocketAddr::V4(addr) => Some(addr),
+            SocketAddr::V6(_) => None,
+        })
+        .expect("Server has no IPv4 address");
+    let len = args[6].parse::<usize>().unwrap() * 1024;
+
+    let serial_port = serialport::new(dev, baud_rate)
+        .data_bits(DataBits::Eight)
+        .flow_control(FlowControl::None)
+        .parity(Parity::None)
+        .stop_bits(StopBits::One)
+        .timeout(Duration::from_millis(5000))
+        .open()
+        .expect("Could not open serial port");
+    let serial_rx = serial_port
+        .try_clone()
+        .expect("Could not clone serial port");
+    let serial_tx = SerialWriter(serial_port);
+
+    static RESOURCES: StaticResources<{ espresso::INGRESS_BUF_LEN }, 512> = StaticResources::new();
+    let queues = RESOURCES.queues().expect("Queues already in use");
+    let (mut client, ingress) = espresso::EspClient::new(serial_tx, SysTimer::new(), queues);
+    spawn_reader(serial_rx, ingress).expect("Could not spawn reading thread");
+
+    client.selftest().expect("Self test failed");
+    client
+        .set_wifi_mode(WifiMode::Station, Target::Current)
+        .expect("Could not set current wifi mode");
+    client
+        .join_access_point(ssid.as_str(), psk.as_str(), Target::Current)
+        .expect("Could not connect to access point");
+
+    let data: Vec<u8> = (0..len).map(|i| b'a' + (i % 26) as u8).collect();
+    let mux = MultiplexingType::NonMultiplexed;
+    for &window in SEND_WINDOWS {
+        client
+            .establish_connection(&requests::EstablishConnection::tcp(mux, server))
+            .expect("Could not establish a TCP connection");
+        client.set_send_window(window);
+        let start = Instant::now();
+        client.send(mux, &data).expect("Could not send data");
+        let elapsed = start.elapsed();
+        println!(
+            "Window {}: {} bytes in {:.2} s, {:.1} KiB/s",
+            window,
+            len,
+            elapsed.as_secs_f64(),
+            len as f64 / 1024.0 / elapsed.as_secs_f64(),
+        );
+        client
+            .close_connection(&requests::CloseConnection::new(mux))
+            .expect("Could not close connection");
+    }
+}
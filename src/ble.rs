@@ -0,0 +1,136 @@
+//! High level access to the Bluetooth LE GATT server of ESP32 ESP-AT
+//! firmwares.
+
+use core::convert::TryInto;
+
+use atat::AtatClient;
+use heapless::Vec;
+
+use crate::{
+    commands::{
+        ble, requests, responses,
+        urcs::{Urc, MAX_DATA_LEN},
+    },
+    EspClient, EspError, EspResult,
+};
+
+pub use crate::commands::ble::AttributeWrite;
+
+/// Maximum length of a value that can be sent with
+/// [`notify`](struct.BleServer.html#method.notify) or
+/// [`set_value`](struct.BleServer.html#method.set_value).
+pub const MAX_SEND_LEN: usize = 256;
+
+/// A handle to the Bluetooth LE GATT server of the device.
+///
+/// Created with [`EspClient::ble`](../struct.EspClient.html#method.ble).
+pub struct BleServer<'a, C: AtatClient, const N: usize = MAX_DATA_LEN> {
+    pub(crate) esp: &'a mut EspClient<C, N>,
+}
+
+impl<'a, C: AtatClient, const N: usize> BleServer<'a, C, N> {
+    /// Start the GATT server and advertise it.
+    ///
+    /// Bluetooth LE is initialized in the server role, the device name is
+    /// set, the services from the firmware image are created and started and
+    /// advertising is started with the specified raw advertising data. Names
+    /// with control characters are rejected with `EspError::InvalidArgument`,
+    /// names longer than 32 bytes with `EspError::Overflow`.
+    pub fn start(
+        &mut self,
+        name: &str,
+        advertising_data: Vec<u8, { ble::MAX_ADVERTISING_DATA_LEN }>,
+    ) -> EspResult<()> {
+        let set_name = ble::SetName::new(name).ok_or(nb::Error::Other(EspError::Overflow))?;
+        if !set_name.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.esp
+            .send_blocking(&ble::Init::new(ble::Role::Server))
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp
+            .send_blocking(&set_name)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp
+            .send_blocking(&ble::CreateServices)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp
+            .send_blocking(&ble::StartServices)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp
+            .send_blocking(&ble::SetAdvertisingData::new(advertising_data))
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp
+            .send_blocking(&ble::StartAdvertising)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Stop advertising and disable Bluetooth LE.
+    pub fn stop(&mut self) -> EspResult<()> {
+        self.esp
+            .send_blocking(&ble::StopAdvertising)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp
+            .send_blocking(&ble::Init::new(ble::Role::Disabled))
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp.state.ble_connections = 0;
+        Ok(())
+    }
+
+    /// Whether a peer with the specified connection index is connected.
+    pub fn is_connected(&mut self, conn_index: u8) -> bool {
+        self.esp.process_urcs();
+        conn_index < 8 && self.esp.state.ble_connections & (1 << conn_index) != 0
+    }
+
+    /// Set the value of a characteristic.
+    pub fn set_value(&mut self, service_index: u8, char_index: u8, value: &[u8]) -> EspResult<()> {
+        if value.len() > MAX_SEND_LEN {
+            return Err(nb::Error::Other(EspError::Overflow));
+        }
+        self.esp
+            .send_blocking(&ble::PrepareSetAttribute::new(
+                service_index,
+                char_index,
+                value.len().try_into().unwrap(),
+            ))
+            .map(|_: responses::EmptyResponse| ())?;
+        nb::block!(self
+            .esp
+            .send_data(&requests::SendData::<MAX_SEND_LEN>::from_bytes(value)))
+        .map_err(nb::Error::Other)
+    }
+
+    /// Notify a connected peer of a new characteristic value.
+    pub fn notify(
+        &mut self,
+        conn_index: u8,
+        service_index: u8,
+        char_index: u8,
+        value: &[u8],
+    ) -> EspResult<()> {
+        if value.len() > MAX_SEND_LEN {
+            return Err(nb::Error::Other(EspError::Overflow));
+        }
+        self.esp
+            .send_blocking(&ble::PrepareNotify::new(
+                conn_index,
+                service_index,
+                char_index,
+                value.len().try_into().unwrap(),
+            ))
+            .map(|_: responses::EmptyResponse| ())?;
+        nb::block!(self
+            .esp
+            .send_data(&requests::SendData::<MAX_SEND_LEN>::from_bytes(value)))
+        .map_err(nb::Error::Other)
+    }
+
+    /// Return the next attribute value written by a peer, if any.
+    pub fn poll(&mut self) -> Option<AttributeWrite> {
+        match self.esp.take_urc(|urc| matches!(urc, Urc::BleWrite(_))) {
+            Some(Urc::BleWrite(write)) => Some(write),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! Bluetooth LE commands of ESP32 ESP-AT firmwares.
+//!
+//! Only the GATT server role is supported. The GATT services are defined in
+//! the firmware image (`ble_data` partition) and created with
+//! [`CreateServices`](struct.CreateServices.html).
+
+use core::{fmt::Write, str::FromStr};
+
+use atat::{AtatCmd, InternalError};
+use heapless::{String, Vec};
+use numtoa::NumToA;
+
+use crate::commands::{
+    requests::{is_valid_string, quoted_max_len, write_quoted},
+    responses, serialize, to_string,
+};
+
+/// Maximum length of an attribute value written by a peer.
+pub const MAX_VALUE_LEN: usize = 256;
+
+/// Maximum length of advertising data.
+pub const MAX_ADVERTISING_DATA_LEN: usize = 31;
+
+/// The role of the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Role {
+    /// Bluetooth LE is disabled
+    Disabled,
+    Client,
+    Server,
+}
+
+impl Role {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            Role::Disabled => "0",
+            Role::Client => "1",
+            Role::Server => "2",
+        }
+    }
+}
+
+/// An attribute value written by a connected peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AttributeWrite {
+    pub conn_index: u8,
+    pub service_index: u8,
+    pub char_index: u8,
+    /// Set if a descriptor was written instead of the characteristic value
+    pub desc_index: Option<u8>,
+    pub value: Vec<u8, MAX_VALUE_LEN>,
+}
+
+impl AttributeWrite {
+    /// Parse a `+WRITE:<conn>,<srv>,<char>,[<desc>],<len>,<value>` URC.
+    pub(crate) fn parse(resp: &[u8]) -> Option<Self> {
+        let rest = resp.strip_prefix(b"+WRITE:")?;
+        let mut fields = rest.splitn(6, |&b| b == b',');
+        let conn_index = parse_number(fields.next()?)?;
+        let service_index = parse_number(fields.next()?)?;
+        let char_index = parse_number(fields.next()?)?;
+        let desc_index = match fields.next()? {
+            b"" => None,
+            field => Some(parse_number(field)?),
+        };
+        let len: usize = parse_number(fields.next()?)?;
+        let value = fields.next()?.get(..len)?;
+        Some(Self {
+            conn_index,
+            service_index,
+            char_index,
+            desc_index,
+            value: Vec::from_slice(value).ok()?,
+        })
+    }
+}
+
+/// Parse a decimal number field.
+fn parse_number<T: FromStr>(field: &[u8]) -> Option<T> {
+    core::str::from_utf8(field).ok()?.parse().ok()
+}
+
+/// Initialize Bluetooth LE in the specified role.
+#[derive(Debug)]
+pub struct Init {
+    role: Role,
+}
+
+impl Init {
+    pub fn new(role: Role) -> Self {
+        Self { role }
+    }
+}
+
+/// Length of the longest `AT+BLEINIT` command.
+const INIT_LEN: usize = "AT+BLEINIT=2\r\n".len();
+
+impl AtatCmd<INIT_LEN> for Init {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, INIT_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+BLEINIT={}\r\n", self.role.as_at_str())?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Maximum length of the device name.
+const MAX_NAME_LEN: usize = 32;
+
+/// Set the Bluetooth LE device name.
+#[derive(Debug)]
+pub struct SetName {
+    name: String<MAX_NAME_LEN>,
+}
+
+impl SetName {
+    /// Return `None` if the name is longer than 32 bytes.
+    pub fn new(name: &str) -> Option<Self> {
+        Some(Self {
+            name: to_string(name).ok()?,
+        })
+    }
+
+    /// Whether the name contains no control characters.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.name.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+BLENAME` command.
+const SET_NAME_LEN: usize = "AT+BLENAME=".len() + quoted_max_len(MAX_NAME_LEN) + "\r\n".len();
+
+impl AtatCmd<SET_NAME_LEN> for SetName {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_NAME_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+BLENAME=")?;
+            write_quoted(buf, self.name.as_bytes())?;
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Create the GATT services defined in the firmware image.
+#[derive(Debug)]
+pub struct CreateServices;
+
+impl AtatCmd<19> for CreateServices {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, 19> {
+        serialize(|buf| buf.write_str("AT+BLEGATTSSRVCRE\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Start all created GATT services.
+#[derive(Debug)]
+pub struct StartServices;
+
+impl AtatCmd<21> for StartServices {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, 21> {
+        serialize(|buf| buf.write_str("AT+BLEGATTSSRVSTART\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Set the raw advertising data (at most 31 bytes).
+#[derive(Debug)]
+pub struct SetAdvertisingData {
+    data: Vec<u8, MAX_ADVERTISING_DATA_LEN>,
+}
+
+impl SetAdvertisingData {
+    pub fn new(data: Vec<u8, MAX_ADVERTISING_DATA_LEN>) -> Self {
+        Self { data }
+    }
+}
+
+/// Length of the longest `AT+BLEADVDATA` command.
+/// The data is hex encoded.
+const SET_ADVERTISING_DATA_LEN: usize =
+    "AT+BLEADVDATA=\"".len() + 2 * MAX_ADVERTISING_DATA_LEN + "\"\r\n".len();
+
+impl AtatCmd<SET_ADVERTISING_DATA_LEN> for SetAdvertisingData {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_ADVERTISING_DATA_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+BLEADVDATA=\"")?;
+            for byte in &self.data {
+                write!(buf, "{:02x}", byte)?;
+            }
+            write!(buf, "\"\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Start advertising.
+#[derive(Debug)]
+pub struct StartAdvertising;
+
+impl AtatCmd<16> for StartAdvertising {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, 16> {
+        serialize(|buf| buf.write_str("AT+BLEADVSTART\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Stop advertising.
+#[derive(Debug)]
+pub struct StopAdvertising;
+
+impl AtatCmd<15> for StopAdvertising {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, 15> {
+        serialize(|buf| buf.write_str("AT+BLEADVSTOP\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Prepare to notify a connected peer of `length` bytes of a characteristic
+/// value.
+///
+/// This message MUST be followed by a `SendData` message.
+#[derive(Debug)]
+pub struct PrepareNotify {
+    conn_index: u8,
+    service_index: u8,
+    char_index: u8,
+    length: u16,
+}
+
+impl PrepareNotify {
+    pub fn new(conn_index: u8, service_index: u8, char_index: u8, length: u16) -> Self {
+        Self {
+            conn_index,
+            service_index,
+            char_index,
+            length,
+        }
+    }
+}
+
+/// Length of the longest `AT+BLEGATTSNTFY` command.
+const PREPARE_NOTIFY_LEN: usize = "AT+BLEGATTSNTFY=255,255,255,65535\r\n".len();
+
+impl AtatCmd<PREPARE_NOTIFY_LEN> for PrepareNotify {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, PREPARE_NOTIFY_LEN> {
+        serialize(|buf| {
+            let mut conn_buf = [0; 3];
+            let mut service_buf = [0; 3];
+            let mut char_buf = [0; 3];
+            let mut length_buf = [0; 5];
+            write!(
+                buf,
+                "AT+BLEGATTSNTFY={},{},{},{}\r\n",
+                self.conn_index.numtoa_str(10, &mut conn_buf),
+                self.service_index.numtoa_str(10, &mut service_buf),
+                self.char_index.numtoa_str(10, &mut char_buf),
+                self.length.numtoa_str(10, &mut length_buf),
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Prepare to set `length` bytes of a characteristic value.
+///
+/// This message MUST be followed by a `SendData` message.
+#[derive(Debug)]
+pub struct PrepareSetAttribute {
+    service_index: u8,
+    char_index: u8,
+    length: u16,
+}
+
+impl PrepareSetAttribute {
+    pub fn new(service_index: u8, char_index: u8, length: u16) -> Self {
+        Self {
+            service_index,
+            char_index,
+            length,
+        }
+    }
+}
+
+/// Length of the longest `AT+BLEGATTSSETATTR` command.
+const PREPARE_SET_ATTRIBUTE_LEN: usize = "AT+BLEGATTSSETATTR=255,255,,65535\r\n".len();
+
+impl AtatCmd<PREPARE_SET_ATTRIBUTE_LEN> for PrepareSetAttribute {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, PREPARE_SET_ATTRIBUTE_LEN> {
+        serialize(|buf| {
+            let mut service_buf = [0; 3];
+            let mut char_buf = [0; 3];
+            let mut length_buf = [0; 5];
+            write!(
+                buf,
+                "AT+BLEGATTSSETATTR={},{},,{}\r\n",
+                self.service_index.numtoa_str(10, &mut service_buf),
+                self.char_index.numtoa_str(10, &mut char_buf),
+                self.length.numtoa_str(10, &mut length_buf),
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attribute_write_parse() {
+        let write = AttributeWrite::parse(b"+WRITE:0,1,2,,4,a,\r\n").unwrap();
+        assert_eq!(
+            (write.conn_index, write.service_index, write.char_index),
+            (0, 1, 2)
+        );
+        assert_eq!(write.desc_index, None);
+        assert_eq!(&write.value[..], b"a,\r\n");
+
+        let write = AttributeWrite::parse(b"+WRITE:0,1,2,1,2,\x01\x00").unwrap();
+        assert_eq!(write.desc_index, Some(1));
+        assert_eq!(&write.value[..], b"\x01\x00");
+
+        // Truncated URCs
+        assert_eq!(AttributeWrite::parse(b"+WRITE:0,1,2,,4,a"), None);
+        assert_eq!(AttributeWrite::parse(b"+WRITE:0,1,2,,4"), None);
+        assert_eq!(AttributeWrite::parse(b"+WRITE:0,1"), None);
+
+        // Binary garbage in the indices or the length
+        assert_eq!(AttributeWrite::parse(b"+WRITE:\xff,1,2,,1,a"), None);
+        assert_eq!(AttributeWrite::parse(b"+WRITE:0,1,2,\xff,1,a"), None);
+        assert_eq!(AttributeWrite::parse(b"+WRITE:0,1,2,,\xff,a"), None);
+    }
+}
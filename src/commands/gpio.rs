@@ -0,0 +1,239 @@
+//! Commands for controlling the GPIO pins of the ESP8266 device.
+//!
+//! Note that most pins have a special function by default (e.g. UART or
+//! flash). Use [`ConfigurePin`](struct.ConfigurePin.html) to switch a pin to
+//! its GPIO function before using it.
+
+use core::fmt::Write;
+
+use atat::{AtatCmd, AtatResp, InternalError};
+use heapless::Vec;
+use numtoa::NumToA;
+
+use crate::commands::{responses, serialize, utf8};
+
+/// The direction of a GPIO pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+impl Direction {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            Direction::Input => "0",
+            Direction::Output => "1",
+        }
+    }
+}
+
+/// The logic level of a GPIO pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Level {
+    Low,
+    High,
+}
+
+impl Level {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            Level::Low => "0",
+            Level::High => "1",
+        }
+    }
+}
+
+/// The state of a GPIO pin.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PinState {
+    pub pin: u8,
+    pub direction: Direction,
+    pub level: Level,
+}
+
+impl AtatResp for PinState {}
+
+/// Select the IO MUX function of a pin and enable or disable its pull-up.
+///
+/// Refer to the ESP8266 pin list for the function numbers, e.g. GPIO2 uses
+/// function 0 for GPIO, while GPIO12 uses function 3.
+#[derive(Debug)]
+pub struct ConfigurePin {
+    pin: u8,
+    function: u8,
+    pull_up: bool,
+}
+
+impl ConfigurePin {
+    pub fn new(pin: u8, function: u8, pull_up: bool) -> Self {
+        Self {
+            pin,
+            function,
+            pull_up,
+        }
+    }
+}
+
+/// Length of the longest `AT+SYSIOSETCFG` command.
+const CONFIGURE_PIN_LEN: usize = "AT+SYSIOSETCFG=255,255,1\r\n".len();
+
+impl AtatCmd<CONFIGURE_PIN_LEN> for ConfigurePin {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, CONFIGURE_PIN_LEN> {
+        serialize(|buf| {
+            let mut pin_buf = [0; 3];
+            let mut function_buf = [0; 3];
+            write!(
+                buf,
+                "AT+SYSIOSETCFG={},{},{}\r\n",
+                self.pin.numtoa_str(10, &mut pin_buf),
+                self.function.numtoa_str(10, &mut function_buf),
+                if self.pull_up { "1" } else { "0" },
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Set the direction of a GPIO pin.
+#[derive(Debug)]
+pub struct SetDirection {
+    pin: u8,
+    direction: Direction,
+}
+
+impl SetDirection {
+    pub fn new(pin: u8, direction: Direction) -> Self {
+        Self { pin, direction }
+    }
+}
+
+/// Length of the longest `AT+SYSGPIODIR` command.
+const SET_DIRECTION_LEN: usize = "AT+SYSGPIODIR=255,1\r\n".len();
+
+impl AtatCmd<SET_DIRECTION_LEN> for SetDirection {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_DIRECTION_LEN> {
+        serialize(|buf| {
+            let mut pin_buf = [0; 3];
+            write!(
+                buf,
+                "AT+SYSGPIODIR={},{}\r\n",
+                self.pin.numtoa_str(10, &mut pin_buf),
+                self.direction.as_at_str(),
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Set the level of a GPIO pin configured as output.
+#[derive(Debug)]
+pub struct WritePin {
+    pin: u8,
+    level: Level,
+}
+
+impl WritePin {
+    pub fn new(pin: u8, level: Level) -> Self {
+        Self { pin, level }
+    }
+}
+
+/// Length of the longest `AT+SYSGPIOWRITE` command.
+const WRITE_PIN_LEN: usize = "AT+SYSGPIOWRITE=255,1\r\n".len();
+
+impl AtatCmd<WRITE_PIN_LEN> for WritePin {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, WRITE_PIN_LEN> {
+        serialize(|buf| {
+            let mut pin_buf = [0; 3];
+            write!(
+                buf,
+                "AT+SYSGPIOWRITE={},{}\r\n",
+                self.pin.numtoa_str(10, &mut pin_buf),
+                self.level.as_at_str(),
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Read the direction and level of a GPIO pin.
+#[derive(Debug)]
+pub struct ReadPin {
+    pin: u8,
+}
+
+impl ReadPin {
+    pub fn new(pin: u8) -> Self {
+        Self { pin }
+    }
+}
+
+/// Length of the longest `AT+SYSGPIOREAD` command.
+const READ_PIN_LEN: usize = "AT+SYSGPIOREAD=255\r\n".len();
+
+impl AtatCmd<READ_PIN_LEN> for ReadPin {
+    type Response = PinState;
+
+    fn as_bytes(&self) -> Vec<u8, READ_PIN_LEN> {
+        serialize(|buf| {
+            let mut pin_buf = [0; 3];
+            write!(
+                buf,
+                "AT+SYSGPIOREAD={}\r\n",
+                self.pin.numtoa_str(10, &mut pin_buf),
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: "+SYSGPIOREAD:2,1,0" (pin, direction, level)
+        let mut fields = resp
+            .strip_prefix("+SYSGPIOREAD:")
+            .ok_or(atat::Error::InvalidResponse)?
+            .trim()
+            .split(',');
+        let pin = fields
+            .next()
+            .and_then(|pin| pin.parse().ok())
+            .ok_or(atat::Error::Parse)?;
+        let direction = match fields.next() {
+            Some("0") => Direction::Input,
+            Some("1") => Direction::Output,
+            _ => return Err(atat::Error::Parse),
+        };
+        let level = match fields.next() {
+            Some("0") => Level::Low,
+            Some("1") => Level::High,
+            _ => return Err(atat::Error::Parse),
+        };
+        Ok(PinState {
+            pin,
+            direction,
+            level,
+        })
+    }
+}
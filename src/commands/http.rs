@@ -0,0 +1,285 @@
+//! Commands for the HTTP client built into ESP-AT firmwares.
+
+use core::{cell::RefCell, fmt::Write};
+
+use atat::{AtatCmd, AtatResp, InternalError};
+use heapless::Vec;
+
+use crate::commands::{
+    requests::{is_valid_string, quoted_len, write_quoted},
+    serialize,
+};
+
+/// Maximum length of an encoded `AT+HTTPCLIENT` command.
+pub const MAX_COMMAND_LEN: usize = 1024;
+
+/// The HTTP request method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Method {
+    Head,
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            Method::Head => "1",
+            Method::Get => "2",
+            Method::Post => "3",
+            Method::Put => "4",
+            Method::Delete => "5",
+        }
+    }
+
+    /// Return the name of the method as used in HTTP requests.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Head => "HEAD",
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// The content type of the request body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ContentType {
+    /// `application/x-www-form-urlencoded`
+    FormUrlEncoded,
+    /// `application/json`
+    Json,
+    /// `multipart/form-data`
+    Multipart,
+    /// `text/xml`
+    Xml,
+}
+
+impl ContentType {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            ContentType::FormUrlEncoded => "0",
+            ContentType::Json => "1",
+            ContentType::Multipart => "2",
+            ContentType::Xml => "3",
+        }
+    }
+}
+
+/// An HTTP request.
+///
+/// The URL, data and headers are sent as quoted strings, so they must not
+/// contain line breaks or other control characters.
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    pub method: Method,
+    pub url: &'a str,
+    pub content_type: ContentType,
+    /// Request body
+    pub data: Option<&'a str>,
+    /// Additional headers, e.g. `"Accept: text/plain"`
+    pub headers: &'a [&'a str],
+}
+
+impl<'a> Request<'a> {
+    /// Create a GET request.
+    pub fn get(url: &'a str) -> Self {
+        Self {
+            method: Method::Get,
+            url,
+            content_type: ContentType::FormUrlEncoded,
+            data: None,
+            headers: &[],
+        }
+    }
+
+    /// Create a POST request with the specified body.
+    pub fn post(url: &'a str, content_type: ContentType, data: &'a str) -> Self {
+        Self {
+            method: Method::Post,
+            url,
+            content_type,
+            data: Some(data),
+            headers: &[],
+        }
+    }
+
+    /// Add headers to the request.
+    pub fn with_headers(self, headers: &'a [&'a str]) -> Self {
+        Self { headers, ..self }
+    }
+
+    /// Return the length of the encoded `AT+HTTPCLIENT` command.
+    pub(crate) fn encoded_len(&self) -> usize {
+        // AT+HTTPCLIENT=<opt>,<type>,"<url>",,,<transport>\r\n
+        let mut len = 24 + quoted_len(self.url.as_bytes());
+        if self.data.is_some() || !self.headers.is_empty() {
+            len += 1 + quoted_len(self.data.unwrap_or("").as_bytes());
+        }
+        len + self
+            .headers
+            .iter()
+            .map(|header| 1 + quoted_len(header.as_bytes()))
+            .sum::<usize>()
+    }
+
+    /// Whether the URL, data and headers contain no control characters.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.url.as_bytes())
+            && is_valid_string(self.data.unwrap_or("").as_bytes())
+            && self
+                .headers
+                .iter()
+                .all(|header| is_valid_string(header.as_bytes()))
+    }
+
+    fn transport_str(&self) -> &'static str {
+        if self.url.starts_with("https://") {
+            "2"
+        } else {
+            "1"
+        }
+    }
+}
+
+/// The result of an HTTP request.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Response {
+    /// Number of body bytes written to the buffer
+    pub len: usize,
+}
+
+impl AtatResp for Response {}
+
+/// Send an HTTP request and write the response body into a buffer.
+///
+/// The buffer must be large enough for the whole body, otherwise parsing
+/// fails with `EspError::Overflow`. Note that the whole response must also
+/// fit into the response queue of the client.
+#[derive(Debug)]
+pub struct HttpClient<'a, 'b> {
+    request: Request<'a>,
+    body: RefCell<&'b mut [u8]>,
+}
+
+impl<'a, 'b> HttpClient<'a, 'b> {
+    /// Return `None` if the request is longer than
+    /// [`MAX_COMMAND_LEN`](constant.MAX_COMMAND_LEN.html) when encoded.
+    pub fn new(request: Request<'a>, body: &'b mut [u8]) -> Option<Self> {
+        if request.encoded_len() > MAX_COMMAND_LEN {
+            return None;
+        }
+        Some(Self {
+            request,
+            body: RefCell::new(body),
+        })
+    }
+}
+
+impl<'a, 'b> AtatCmd<MAX_COMMAND_LEN> for HttpClient<'a, 'b> {
+    type Response = Response;
+    const MAX_TIMEOUT_MS: u32 = 30_000;
+
+    fn as_bytes(&self) -> Vec<u8, MAX_COMMAND_LEN> {
+        serialize(|buf| {
+            let request = &self.request;
+            write!(
+                buf,
+                "AT+HTTPCLIENT={},{},",
+                request.method.as_at_str(),
+                request.content_type.as_at_str(),
+            )?;
+            write_quoted(buf, request.url.as_bytes())?;
+            write!(buf, ",,,{}", request.transport_str())?;
+            if request.data.is_some() || !request.headers.is_empty() {
+                buf.write_char(',')?;
+                write_quoted(buf, request.data.unwrap_or("").as_bytes())?;
+            }
+            for header in request.headers {
+                buf.write_char(',')?;
+                write_quoted(buf, header.as_bytes())?;
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        // Example: +HTTPCLIENT:5,hello\r\n+HTTPCLIENT:6,world!
+        let mut resp = resp?;
+        let mut body = self.body.borrow_mut();
+        let mut len = 0;
+        while !resp.is_empty() {
+            let chunk = resp
+                .strip_prefix(b"+HTTPCLIENT:")
+                .ok_or(atat::Error::InvalidResponse)?;
+            let size_len = chunk
+                .iter()
+                .position(|&b| b == b',')
+                .ok_or(atat::Error::Parse)?;
+            let size: usize = core::str::from_utf8(&chunk[..size_len])
+                .map_err(|_| atat::Error::Parse)?
+                .parse()
+                .map_err(|_| atat::Error::Parse)?;
+            let data = chunk
+                .get(size_len + 1..)
+                .and_then(|data| data.get(..size))
+                .ok_or(atat::Error::Parse)?;
+            body.get_mut(len..)
+                .and_then(|body| body.get_mut(..size))
+                .ok_or(atat::Error::Overflow)?
+                .copy_from_slice(data);
+            len += size;
+            resp = &chunk[size_len + 1 + size..];
+            resp = resp.strip_prefix(b"\r\n").unwrap_or(resp);
+        }
+        Ok(Response { len })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_client_parse() {
+        // The body is split into chunks, which may contain line breaks
+        let mut body = [0; 16];
+        let cmd = HttpClient::new(Request::get("http://example.com/"), &mut body).unwrap();
+        let resp = cmd
+            .parse(Ok(b"+HTTPCLIENT:5,he\r\nl\r\n+HTTPCLIENT:3,lo!"))
+            .unwrap();
+        assert_eq!(resp.len, 8);
+        assert_eq!(&cmd.body.borrow()[..8], b"he\r\nllo!");
+
+        let mut body = [0; 4];
+        let cmd = HttpClient::new(Request::get("http://example.com/"), &mut body).unwrap();
+        // Truncated chunks
+        assert!(matches!(
+            cmd.parse(Ok(b"+HTTPCLIENT:5,hel")),
+            Err(atat::Error::Parse)
+        ));
+        assert!(matches!(
+            cmd.parse(Ok(b"+HTTPCLIENT:5")),
+            Err(atat::Error::Parse)
+        ));
+        // Binary garbage instead of the size or a chunk
+        assert!(matches!(
+            cmd.parse(Ok(b"+HTTPCLIENT:\xff,hello")),
+            Err(atat::Error::Parse)
+        ));
+        assert!(cmd.parse(Ok(b"\xff\xfe")).is_err());
+        // A body larger than the buffer
+        assert!(matches!(
+            cmd.parse(Ok(b"+HTTPCLIENT:5,hello")),
+            Err(atat::Error::Overflow)
+        ));
+    }
+}
@@ -1,5 +1,59 @@
-//! Collection of commands (requests and responses) that can be used for
+//! Collection of commands (requests, responses and URCs) that can be used for
 //! communicating with the ESP8266 device.
+//!
+//! The commands implement `AtatCmd` by hand rather than with the derive
+//! macros of atat. Most of them don't map onto the derived syntax:
+//!
+//! - The command name depends on the firmware dialect at runtime, e.g.
+//!   `AT+CWMODE_CUR` or `AT+CWMODE`, as do some values like the sleep modes.
+//! - The link ID is a leading argument that's omitted without multiplexing.
+//! - String arguments must escape `"`, `,` and `\`.
+//! - Many responses are irregular, e.g. the lists of `AT+CWLAP` and
+//!   `AT+CIPSTATE?`, the text of `AT+GMR` and the data of `+IPD`.
 
+#[cfg(feature = "ble")]
+pub mod ble;
+pub mod gpio;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "pki")]
+pub mod pki;
 pub mod requests;
 pub mod responses;
+pub mod urcs;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+use core::fmt;
+
+use heapless::{String, Vec};
+
+/// Serialize a command into a buffer of `L` bytes.
+///
+/// The buffer of every command is sized for the longest arguments its
+/// constructor accepts, so `write` can't run out of space. Should it do so
+/// anyway, an empty command is returned instead of a truncated one. Nothing
+/// is sent then, and the command fails with a timeout.
+pub(crate) fn serialize<const L: usize>(
+    write: impl FnOnce(&mut Vec<u8, L>) -> fmt::Result,
+) -> Vec<u8, L> {
+    let mut buf = Vec::new();
+    match write(&mut buf) {
+        Ok(()) => buf,
+        Err(fmt::Error) => Vec::new(),
+    }
+}
+
+/// Interpret a response as UTF-8.
+pub(crate) fn utf8(resp: &[u8]) -> Result<&str, atat::Error> {
+    core::str::from_utf8(resp).map_err(|_| atat::Error::Parse)
+}
+
+/// Copy a field of a response into a string, failing if it doesn't fit.
+pub(crate) fn to_string<const N: usize>(field: &str) -> Result<String<N>, atat::Error> {
+    let mut string = String::new();
+    string.push_str(field).map_err(|_| atat::Error::Parse)?;
+    Ok(string)
+}
@@ -0,0 +1,410 @@
+//! Commands for the MQTT client built into ESP-AT firmwares.
+//!
+//! The firmware currently only supports a single MQTT connection, so the link
+//! ID is always 0.
+
+use core::fmt::Write;
+
+use atat::{AtatCmd, InternalError};
+use heapless::{String, Vec};
+use numtoa::NumToA;
+
+use crate::commands::{
+    requests::{is_valid_string, quoted_max_len, write_quoted},
+    responses, serialize, to_string,
+};
+
+/// Maximum length of an MQTT topic.
+pub const MAX_TOPIC_LEN: usize = 128;
+
+/// Maximum length of an MQTT payload that can be published or received.
+pub const MAX_PAYLOAD_LEN: usize = 256;
+
+/// The transport used for the MQTT connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Scheme {
+    /// MQTT over TCP
+    Tcp,
+    /// MQTT over TLS, without certificate verification
+    Tls,
+    /// MQTT over WebSocket
+    WebSocket,
+    /// MQTT over WebSocket secured by TLS, without certificate verification
+    WebSocketTls,
+}
+
+impl Scheme {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            Scheme::Tcp => "1",
+            Scheme::Tls => "2",
+            Scheme::WebSocket => "6",
+            Scheme::WebSocketTls => "7",
+        }
+    }
+}
+
+/// The MQTT quality of service level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QoS {
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+impl QoS {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            QoS::AtMostOnce => "0",
+            QoS::AtLeastOnce => "1",
+            QoS::ExactlyOnce => "2",
+        }
+    }
+}
+
+/// A message received on a subscribed topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Message {
+    pub topic: String<MAX_TOPIC_LEN>,
+    pub payload: Vec<u8, MAX_PAYLOAD_LEN>,
+}
+
+impl Message {
+    /// Parse a `+MQTTSUBRECV:<LinkID>,"<topic>",<len>,<data>` URC.
+    pub(crate) fn parse(resp: &[u8]) -> Option<Self> {
+        let rest = resp.strip_prefix(b"+MQTTSUBRECV:0,\"")?;
+        let topic_len = rest.iter().position(|&b| b == b'"')?;
+        let topic = core::str::from_utf8(&rest[..topic_len]).ok()?;
+        let rest = rest[topic_len..].strip_prefix(b"\",")?;
+        let len_len = rest.iter().position(|&b| b == b',')?;
+        let len: usize = core::str::from_utf8(&rest[..len_len]).ok()?.parse().ok()?;
+        let payload = rest.get(len_len + 1..len_len + 1 + len)?;
+        Some(Self {
+            topic: to_string(topic).ok()?,
+            payload: Vec::from_slice(payload).ok()?,
+        })
+    }
+}
+
+/// Maximum length of the client ID, username and password.
+const MAX_USER_FIELD_LEN: usize = 64;
+
+/// Configure the MQTT user properties.
+#[derive(Debug)]
+pub struct SetUserConfig {
+    scheme: Scheme,
+    client_id: String<MAX_USER_FIELD_LEN>,
+    username: String<MAX_USER_FIELD_LEN>,
+    password: String<MAX_USER_FIELD_LEN>,
+}
+
+impl SetUserConfig {
+    /// Return `None` if a string is longer than 64 bytes.
+    pub fn new(scheme: Scheme, client_id: &str, username: &str, password: &str) -> Option<Self> {
+        Some(Self {
+            scheme,
+            client_id: to_string(client_id).ok()?,
+            username: to_string(username).ok()?,
+            password: to_string(password).ok()?,
+        })
+    }
+
+    /// Whether the strings contain no control characters.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.client_id.as_bytes())
+            && is_valid_string(self.username.as_bytes())
+            && is_valid_string(self.password.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+MQTTUSERCFG` command.
+const SET_USER_CONFIG_LEN: usize = "AT+MQTTUSERCFG=0,7,".len()
+    + 3 * quoted_max_len(MAX_USER_FIELD_LEN)
+    + ",,".len()
+    + ",0,0,\"\"\r\n".len();
+
+impl AtatCmd<SET_USER_CONFIG_LEN> for SetUserConfig {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_USER_CONFIG_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+MQTTUSERCFG=0,{},", self.scheme.as_at_str())?;
+            write_quoted(buf, self.client_id.as_bytes())?;
+            buf.write_char(',')?;
+            write_quoted(buf, self.username.as_bytes())?;
+            buf.write_char(',')?;
+            write_quoted(buf, self.password.as_bytes())?;
+            write!(buf, ",0,0,\"\"\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Maximum length of the host of a broker.
+const MAX_HOST_LEN: usize = 128;
+
+/// Connect to an MQTT broker.
+///
+/// If `reconnect` is set to `true`, the firmware automatically reconnects
+/// when the connection to the broker is lost.
+#[derive(Debug)]
+pub struct Connect {
+    host: String<MAX_HOST_LEN>,
+    port: u16,
+    reconnect: bool,
+}
+
+impl Connect {
+    /// Return `None` if the host is longer than 128 bytes.
+    pub fn new(host: &str, port: u16, reconnect: bool) -> Option<Self> {
+        Some(Self {
+            host: to_string(host).ok()?,
+            port,
+            reconnect,
+        })
+    }
+
+    /// Whether the host contains no control characters.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.host.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+MQTTCONN` command.
+const CONNECT_LEN: usize =
+    "AT+MQTTCONN=0,".len() + quoted_max_len(MAX_HOST_LEN) + ",65535,1\r\n".len();
+
+impl AtatCmd<CONNECT_LEN> for Connect {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 20_000;
+
+    fn as_bytes(&self) -> Vec<u8, CONNECT_LEN> {
+        serialize(|buf| {
+            let mut num_buf = [0; 5];
+            write!(buf, "AT+MQTTCONN=0,")?;
+            write_quoted(buf, self.host.as_bytes())?;
+            write!(
+                buf,
+                ",{},{}\r\n",
+                self.port.numtoa_str(10, &mut num_buf),
+                if self.reconnect { "1" } else { "0" },
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Publish a message.
+///
+/// The payload is sent as a quoted string, so it must not contain line breaks
+/// or other control characters. Topics are limited to
+/// [`MAX_TOPIC_LEN`](constant.MAX_TOPIC_LEN.html) and payloads to
+/// [`MAX_PAYLOAD_LEN`](constant.MAX_PAYLOAD_LEN.html) bytes.
+#[derive(Debug)]
+pub struct Publish<'a> {
+    topic: &'a str,
+    payload: &'a str,
+    qos: QoS,
+    retain: bool,
+}
+
+impl<'a> Publish<'a> {
+    /// Return `None` if the topic or payload is too long.
+    pub fn new(topic: &'a str, payload: &'a str, qos: QoS, retain: bool) -> Option<Self> {
+        if topic.len() > MAX_TOPIC_LEN || payload.len() > MAX_PAYLOAD_LEN {
+            return None;
+        }
+        Some(Self {
+            topic,
+            payload,
+            qos,
+            retain,
+        })
+    }
+
+    /// Whether the topic and payload contain no control characters.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.topic.as_bytes()) && is_valid_string(self.payload.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+MQTTPUB` command.
+const PUBLISH_LEN: usize = "AT+MQTTPUB=0,".len()
+    + quoted_max_len(MAX_TOPIC_LEN)
+    + ",".len()
+    + quoted_max_len(MAX_PAYLOAD_LEN)
+    + ",2,1\r\n".len();
+
+impl<'a> AtatCmd<PUBLISH_LEN> for Publish<'a> {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 10_000;
+
+    fn as_bytes(&self) -> Vec<u8, PUBLISH_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+MQTTPUB=0,")?;
+            write_quoted(buf, self.topic.as_bytes())?;
+            buf.write_char(',')?;
+            write_quoted(buf, self.payload.as_bytes())?;
+            write!(
+                buf,
+                ",{},{}\r\n",
+                self.qos.as_at_str(),
+                if self.retain { "1" } else { "0" },
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Subscribe to a topic.
+///
+/// Subscribing to a topic that is already subscribed is not an error. Topics
+/// are limited to [`MAX_TOPIC_LEN`](constant.MAX_TOPIC_LEN.html) bytes.
+#[derive(Debug)]
+pub struct Subscribe<'a> {
+    topic: &'a str,
+    qos: QoS,
+}
+
+impl<'a> Subscribe<'a> {
+    /// Return `None` if the topic is too long.
+    pub fn new(topic: &'a str, qos: QoS) -> Option<Self> {
+        if topic.len() > MAX_TOPIC_LEN {
+            return None;
+        }
+        Some(Self { topic, qos })
+    }
+
+    /// Whether the topic contains no control characters.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.topic.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+MQTTSUB` command.
+const SUBSCRIBE_LEN: usize = "AT+MQTTSUB=0,".len() + quoted_max_len(MAX_TOPIC_LEN) + ",2\r\n".len();
+
+impl<'a> AtatCmd<SUBSCRIBE_LEN> for Subscribe<'a> {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 10_000;
+
+    fn as_bytes(&self) -> Vec<u8, SUBSCRIBE_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+MQTTSUB=0,")?;
+            write_quoted(buf, self.topic.as_bytes())?;
+            write!(buf, ",{}\r\n", self.qos.as_at_str())?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        match resp? {
+            b"" | b"ALREADY SUBSCRIBE" => Ok(responses::EmptyResponse),
+            _ => Err(atat::Error::InvalidResponse),
+        }
+    }
+}
+
+/// Unsubscribe from a topic.
+///
+/// Unsubscribing from a topic that isn't subscribed is not an error. Topics
+/// are limited to [`MAX_TOPIC_LEN`](constant.MAX_TOPIC_LEN.html) bytes.
+#[derive(Debug)]
+pub struct Unsubscribe<'a> {
+    topic: &'a str,
+}
+
+impl<'a> Unsubscribe<'a> {
+    /// Return `None` if the topic is too long.
+    pub fn new(topic: &'a str) -> Option<Self> {
+        if topic.len() > MAX_TOPIC_LEN {
+            return None;
+        }
+        Some(Self { topic })
+    }
+
+    /// Whether the topic contains no control characters.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.topic.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+MQTTUNSUB` command.
+const UNSUBSCRIBE_LEN: usize =
+    "AT+MQTTUNSUB=0,".len() + quoted_max_len(MAX_TOPIC_LEN) + "\r\n".len();
+
+impl<'a> AtatCmd<UNSUBSCRIBE_LEN> for Unsubscribe<'a> {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 10_000;
+
+    fn as_bytes(&self) -> Vec<u8, UNSUBSCRIBE_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+MQTTUNSUB=0,")?;
+            write_quoted(buf, self.topic.as_bytes())?;
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        match resp? {
+            b"" | b"NO UNSUBSCRIBE" => Ok(responses::EmptyResponse),
+            _ => Err(atat::Error::InvalidResponse),
+        }
+    }
+}
+
+/// Close the MQTT connection and release its resources.
+#[derive(Debug)]
+pub struct Clean;
+
+impl AtatCmd<16> for Clean {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, 16> {
+        serialize(|buf| buf.write_str("AT+MQTTCLEAN=0\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_parse() {
+        // The payload may contain commas and line breaks
+        let message = Message::parse(b"+MQTTSUBRECV:0,\"a/b\",7,on,\r\nto").unwrap();
+        assert_eq!(message.topic, "a/b");
+        assert_eq!(&message.payload[..], b"on,\r\nto");
+
+        // Truncated URCs
+        assert_eq!(Message::parse(b"+MQTTSUBRECV:0,\"a/b\",7,on"), None);
+        assert_eq!(Message::parse(b"+MQTTSUBRECV:0,\"a/b\","), None);
+        assert_eq!(Message::parse(b"+MQTTSUBRECV:0,\"a/b"), None);
+        assert_eq!(Message::parse(b"+MQTTSUBRECV:"), None);
+
+        // Binary garbage in the topic or the length
+        assert_eq!(Message::parse(b"+MQTTSUBRECV:0,\"a\xff\",2,on"), None);
+        assert_eq!(Message::parse(b"+MQTTSUBRECV:0,\"a/b\",\xff,on"), None);
+    }
+}
@@ -0,0 +1,337 @@
+//! Certificate and key storage commands of ESP-AT firmwares.
+//!
+//! Certificates and keys are stored in the manufacturing NVS partition, one
+//! namespace per kind of object, and are addressed by an index. The index is
+//! what TLS connections refer to when selecting their certificates. As an
+//! alternative to certificates, TLS client connections can authenticate
+//! with a pre-shared key.
+
+use core::fmt::Write;
+
+use atat::{AtatCmd, AtatResp, InternalError};
+use heapless::{String, Vec};
+
+use crate::{
+    commands::{requests, responses, serialize, to_string, utf8},
+    types,
+};
+
+/// Maximum length of a certificate or key.
+pub const MAX_OBJECT_LEN: usize = 4096;
+
+/// Maximum number of objects of a kind returned by a listing.
+pub const MAX_OBJECTS: usize = 8;
+
+/// The kind of a stored certificate or key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Kind {
+    /// Certificate the device presents as TLS client
+    ClientCert,
+    /// Private key of the client certificate
+    ClientKey,
+    /// CA the device verifies servers with
+    ClientCa,
+    /// Certificate the device presents as TLS server
+    ServerCert,
+    /// Private key of the server certificate
+    ServerKey,
+    /// CA the server of the device verifies clients with
+    ServerCa,
+}
+
+impl Kind {
+    /// Return the NVS namespace of the kind, which is also the prefix of the
+    /// keys.
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            Kind::ClientCert => "client_cert",
+            Kind::ClientKey => "client_key",
+            Kind::ClientCa => "client_ca",
+            Kind::ServerCert => "server_cert",
+            Kind::ServerKey => "server_key",
+            Kind::ServerCa => "server_ca",
+        }
+    }
+}
+
+/// The indices of the stored objects of a kind.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Objects {
+    pub indices: Vec<u8, MAX_OBJECTS>,
+}
+
+impl AtatResp for Objects {}
+
+/// List the stored objects of a kind.
+#[derive(Debug)]
+pub struct ListObjects {
+    kind: Kind,
+}
+
+impl ListObjects {
+    pub fn new(kind: Kind) -> Self {
+        Self { kind }
+    }
+}
+
+/// Length of the longest `AT+SYSMFG` command. that lists objects.
+const LIST_OBJECTS_LEN: usize = "AT+SYSMFG=1,\"server_cert\"\r\n".len();
+
+impl AtatCmd<LIST_OBJECTS_LEN> for ListObjects {
+    type Response = Objects;
+
+    fn as_bytes(&self) -> Vec<u8, LIST_OBJECTS_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+SYSMFG=1,\"{}\"\r\n", self.kind.as_at_str())?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +SYSMFG:"client_cert","client_cert.0",8
+        let mut indices = Vec::new();
+        for line in resp.lines() {
+            let key = match line
+                .strip_prefix("+SYSMFG:")
+                .and_then(|fields| fields.split(',').nth(1))
+            {
+                Some(key) => key,
+                None => continue,
+            };
+            let index = key
+                .trim_matches('"')
+                .strip_prefix(self.kind.as_at_str())
+                .and_then(|key| key.strip_prefix('.'))
+                .and_then(|index| index.parse().ok())
+                .ok_or(atat::Error::Parse)?;
+            indices.push(index).map_err(|_| atat::Error::Parse)?;
+        }
+        Ok(Objects { indices })
+    }
+}
+
+/// Announce that an object of `len` bytes is written.
+///
+/// This message MUST be followed by a `SendData` message with the content
+/// of the object, an existing object with the same index is replaced.
+#[derive(Debug)]
+pub struct PrepareWriteObject {
+    kind: Kind,
+    index: u8,
+    len: usize,
+}
+
+impl PrepareWriteObject {
+    pub fn new(kind: Kind, index: u8, len: usize) -> Self {
+        Self { kind, index, len }
+    }
+}
+
+/// Length of the longest `AT+SYSMFG` command. that writes an object.
+const PREPARE_WRITE_OBJECT_LEN: usize =
+    "AT+SYSMFG=2,\"server_cert\",\"server_cert.255\",8,18446744073709551615\r\n".len();
+
+impl AtatCmd<PREPARE_WRITE_OBJECT_LEN> for PrepareWriteObject {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, PREPARE_WRITE_OBJECT_LEN> {
+        serialize(|buf| {
+            let namespace = self.kind.as_at_str();
+            // Type 8 is binary data
+            write!(
+                buf,
+                "AT+SYSMFG=2,\"{}\",\"{}.{}\",8,{}\r\n",
+                namespace, namespace, self.index, self.len
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Erase a stored object.
+#[derive(Debug)]
+pub struct EraseObject {
+    kind: Kind,
+    index: u8,
+}
+
+impl EraseObject {
+    pub fn new(kind: Kind, index: u8) -> Self {
+        Self { kind, index }
+    }
+}
+
+/// Length of the longest `AT+SYSMFG` command. that erases an object.
+const ERASE_OBJECT_LEN: usize = "AT+SYSMFG=0,\"server_cert\",\"server_cert.255\"\r\n".len();
+
+impl AtatCmd<ERASE_OBJECT_LEN> for EraseObject {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, ERASE_OBJECT_LEN> {
+        serialize(|buf| {
+            let namespace = self.kind.as_at_str();
+            write!(
+                buf,
+                "AT+SYSMFG=0,\"{}\",\"{}.{}\"\r\n",
+                namespace, namespace, self.index
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// The certificates a TLS client connection uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TlsCredentials {
+    /// Index of the client certificate and key to present to the server,
+    /// `None` to not authenticate the device
+    pub client_cert: Option<u8>,
+    /// Index of the CA to verify the server with, `None` to not verify the
+    /// server
+    pub ca: Option<u8>,
+}
+
+/// Select the certificates of a TLS client connection.
+///
+/// Applies to connections opened afterwards on the link.
+#[derive(Debug)]
+pub struct ConfigureTlsClient {
+    mux: types::MultiplexingType,
+    credentials: TlsCredentials,
+}
+
+impl ConfigureTlsClient {
+    pub fn new(mux: types::MultiplexingType, credentials: TlsCredentials) -> Self {
+        Self { mux, credentials }
+    }
+}
+
+/// Length of the longest `AT+CIPSSLCCONF` command.
+const CONFIGURE_TLS_CLIENT_LEN: usize = "AT+CIPSSLCCONF=4,3,255,255\r\n".len();
+
+impl AtatCmd<CONFIGURE_TLS_CLIENT_LEN> for ConfigureTlsClient {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, CONFIGURE_TLS_CLIENT_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPSSLCCONF=")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "{},", id.as_at_str())?;
+            }
+            let TlsCredentials { client_cert, ca } = self.credentials;
+            let auth_mode = u8::from(client_cert.is_some()) | u8::from(ca.is_some()) << 1;
+            write!(buf, "{}", auth_mode)?;
+            if auth_mode != 0 {
+                write!(
+                    buf,
+                    ",{},{}",
+                    client_cert.unwrap_or_default(),
+                    ca.unwrap_or_default()
+                )?;
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Maximum length of a TLS pre-shared key and its hint.
+const MAX_PSK_LEN: usize = 32;
+
+/// Set the pre-shared key and hint of a TLS client connection.
+///
+/// Applies to connections opened afterwards on the link. Both the key and
+/// the hint are strings of at most 32 bytes.
+#[derive(Debug)]
+pub struct ConfigureTlsPsk {
+    mux: types::MultiplexingType,
+    psk: String<MAX_PSK_LEN>,
+    hint: String<MAX_PSK_LEN>,
+}
+
+impl ConfigureTlsPsk {
+    /// Return `None` if the key or hint is longer than 32 bytes.
+    pub fn new(mux: types::MultiplexingType, psk: &str, hint: &str) -> Option<Self> {
+        Some(Self {
+            mux,
+            psk: to_string(psk).ok()?,
+            hint: to_string(hint).ok()?,
+        })
+    }
+
+    /// Whether the key and hint can be sent to the device, i.e. contain no
+    /// control characters.
+    pub fn is_valid(&self) -> bool {
+        requests::is_valid_string(self.psk.as_bytes())
+            && requests::is_valid_string(self.hint.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+CIPSSLCPSK` command.
+const CONFIGURE_TLS_PSK_LEN: usize =
+    "AT+CIPSSLCPSK=4,".len() + 2 * requests::quoted_max_len(MAX_PSK_LEN) + ",\r\n".len();
+
+impl AtatCmd<CONFIGURE_TLS_PSK_LEN> for ConfigureTlsPsk {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, CONFIGURE_TLS_PSK_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPSSLCPSK=")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "{},", id.as_at_str())?;
+            }
+            requests::write_quoted(buf, self.psk.as_bytes())?;
+            buf.write_char(',')?;
+            requests::write_quoted(buf, self.hint.as_bytes())?;
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_objects_parse() {
+        let cmd = ListObjects::new(Kind::ClientCert);
+        let objects = cmd
+            .parse(Ok(b"+SYSMFG:\"client_cert\",\"client_cert.0\",8\r\n\
+                +SYSMFG:\"client_cert\",\"client_cert.2\",8\r\n"))
+            .unwrap();
+        assert_eq!(&objects.indices[..], &[0, 2]);
+
+        // No stored objects
+        assert!(cmd.parse(Ok(b"")).unwrap().indices.is_empty());
+
+        // Truncated keys, keys of another kind and binary garbage
+        assert!(cmd
+            .parse(Ok(b"+SYSMFG:\"client_cert\",\"client_cert."))
+            .is_err());
+        assert!(cmd
+            .parse(Ok(b"+SYSMFG:\"client_ca\",\"client_ca.0\",8"))
+            .is_err());
+        assert!(cmd.parse(Ok(b"+SYSMFG:\"client_cert\",\"\xff\"")).is_err());
+    }
+}
@@ -1,13 +1,19 @@
 //! Raw requests that can be sent from the driver to the ESP8266 device.
 
-use core::fmt::Write;
+use core::{
+    cell::RefCell,
+    fmt::{self, Write},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+};
 
 use atat::{AtatCmd, Error, InternalError};
 use heapless::{String, Vec};
-use no_std_net::SocketAddr;
 use numtoa::NumToA;
 
-use crate::{commands::responses, types};
+use crate::{
+    commands::{responses, serialize, to_string, urcs, utf8},
+    types,
+};
 
 /// An AT test command.
 ///
@@ -25,7 +31,7 @@ impl AtatCmd<4> for At {
     type Response = responses::EmptyResponse;
 
     fn as_bytes(&self) -> Vec<u8, 4> {
-        Vec::from_slice(b"AT\r\n").unwrap()
+        serialize(|buf| buf.write_str("AT\r\n"))
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, Error> {
@@ -34,6 +40,54 @@ impl AtatCmd<4> for At {
     }
 }
 
+/// Maximum length of a raw command, without the line break.
+const MAX_RAW_COMMAND_LEN: usize = 254;
+
+/// Send an arbitrary command and return the raw response text.
+///
+/// This allows issuing commands that are not implemented by this crate, e.g.
+/// vendor-specific ones. The command is sent as is, followed by `\r\n`. The
+/// response text is everything the device sends before the final `OK`, and
+/// fails to parse if it's longer than `L` bytes.
+#[derive(Debug)]
+pub struct RawCommand<'a, const L: usize> {
+    command: &'a str,
+}
+
+impl<'a, const L: usize> RawCommand<'a, L> {
+    /// Return `None` if the command is longer than 254 bytes.
+    pub fn new(command: &'a str) -> Option<Self> {
+        if command.len() > MAX_RAW_COMMAND_LEN {
+            return None;
+        }
+        Some(Self { command })
+    }
+
+    /// Return whether the command can be sent to the device, i.e. starts
+    /// with `AT` and contains no control characters.
+    pub fn is_valid(&self) -> bool {
+        self.command.starts_with("AT") && is_valid_string(self.command.as_bytes())
+    }
+}
+
+/// Length of the longest raw command, including the line break.
+const RAW_COMMAND_LEN: usize = MAX_RAW_COMMAND_LEN + "\r\n".len();
+
+impl<const L: usize> AtatCmd<RAW_COMMAND_LEN> for RawCommand<'_, L> {
+    type Response = responses::StringResponse<L>;
+
+    fn as_bytes(&self) -> Vec<u8, RAW_COMMAND_LEN> {
+        serialize(|buf| {
+            write!(buf, "{}\r\n", self.command)?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, Error> {
+        to_string(utf8(resp?)?.trim_end()).map(responses::StringResponse)
+    }
+}
+
 /// Return information about the firmware version.
 #[derive(Debug)]
 pub struct GetFirmwareVersion;
@@ -42,39 +96,45 @@ impl AtatCmd<8> for GetFirmwareVersion {
     type Response = responses::FirmwareVersion;
 
     fn as_bytes(&self) -> Vec<u8, 8> {
-        Vec::from_slice(b"AT+GMR\r\n").unwrap()
+        serialize(|buf| buf.write_str("AT+GMR\r\n"))
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        let resp = core::str::from_utf8(resp?).unwrap();
-        let mut lines = resp.lines();
-
-        // AT version (Example: "AT version:1.1.0.0(May 11 2016 18:09:56)")
-        let at_version_raw = lines.next().ok_or(atat::Error::Parse)?;
-        if !at_version_raw.starts_with("AT version:") {
-            return Err(atat::Error::Parse);
-        }
-        let at_version = &at_version_raw[11..];
-
-        // SDK version (example: "SDK version:1.5.4(baaeaebb)")
-        let sdk_version_raw = lines.next().ok_or(atat::Error::Parse)?;
-        if !sdk_version_raw.starts_with("SDK version:") {
-            return Err(atat::Error::Parse);
-        }
-        let sdk_version = &sdk_version_raw[12..];
-
-        // Compile time (example: "compile time:May 20 2016 15:08:19")
-        let compile_time_raw = lines.next().ok_or(atat::Error::Parse)?;
-        if !compile_time_raw.starts_with("compile time:") {
-            return Err(atat::Error::Parse);
+        let resp = utf8(resp?)?;
+        // Example (NONOS):
+        //   AT version:1.1.0.0(May 11 2016 18:09:56)
+        //   SDK version:1.5.4(baaeaebb)
+        //   compile time:May 20 2016 15:08:19
+        // ESP-AT firmwares include a commit hash and the binary version:
+        //   compile time(3a696ba):Jul  2 2021 11:54:43
+        //   Bin version:2.2.0(WROOM-32)
+        // Some module vendors add lines with their name.
+        let mut version = responses::FirmwareVersion::default();
+        let (mut at_version, mut sdk_version) = (None, None);
+        for line in resp.lines() {
+            let (key, value) = match line.split_once(':') {
+                Some(field) => field,
+                None => continue,
+            };
+            match key {
+                "AT version" => at_version = Some(to_string(value)?),
+                "SDK version" => sdk_version = Some(to_string(value)?),
+                "Bin version" => version.bin_version = Some(to_string(value)?),
+                "compile time" => version.compile_time = Some(to_string(value)?),
+                _ => {
+                    if let Some(commit) = key
+                        .strip_prefix("compile time(")
+                        .and_then(|rest| rest.strip_suffix(')'))
+                    {
+                        version.compile_time = Some(to_string(value)?);
+                        version.commit = Some(to_string(commit)?);
+                    }
+                }
+            }
         }
-        let compile_time = &compile_time_raw[13..];
-
-        Ok(responses::FirmwareVersion {
-            at_version: String::from(at_version),
-            sdk_version: String::from(sdk_version),
-            compile_time: String::from(compile_time),
-        })
+        version.at_version = at_version.ok_or(atat::Error::Parse)?;
+        version.sdk_version = sdk_version.ok_or(atat::Error::Parse)?;
+        Ok(version)
     }
 }
 
@@ -86,7 +146,7 @@ impl AtatCmd<8> for Restart {
     type Response = responses::EmptyResponse;
 
     fn as_bytes(&self) -> Vec<u8, 8> {
-        Vec::from_slice(b"AT+RST\r\n").unwrap()
+        serialize(|buf| buf.write_str("AT+RST\r\n"))
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
@@ -94,88 +154,58 @@ impl AtatCmd<8> for Restart {
     }
 }
 
-/// Query the current WiFi mode.
+/// Update the firmware over the air.
+///
+/// The device downloads the latest firmware and restarts when done. Progress
+/// is reported through [`Urc::UpdateProgress`][UpdateProgress].
+///
+/// [UpdateProgress]: ../urcs/enum.Urc.html#variant.UpdateProgress
 #[derive(Debug)]
-pub struct GetCurrentWifiMode;
+pub struct UpdateFirmware;
 
-impl AtatCmd<16> for GetCurrentWifiMode {
-    type Response = types::WifiMode;
+impl AtatCmd<13> for UpdateFirmware {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 300_000;
 
-    fn as_bytes(&self) -> Vec<u8, 16> {
-        Vec::from_slice(b"AT+CWMODE_CUR?\r\n").unwrap()
+    fn as_bytes(&self) -> Vec<u8, 13> {
+        serialize(|buf| buf.write_str("AT+CIUPDATE\r\n"))
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        let resp = core::str::from_utf8(resp?).unwrap();
-        if !resp.starts_with("+CWMODE_CUR:") {
-            return Err(atat::Error::InvalidResponse);
-        }
-        match resp.get(12..13) {
-            Some("1") => Ok(types::WifiMode::Station),
-            Some("2") => Ok(types::WifiMode::Ap),
-            Some("3") => Ok(types::WifiMode::Both),
-            _ => Err(atat::Error::InvalidResponse),
-        }
+        responses::EmptyResponse::from_resp(resp)
     }
 }
 
-/// Query the default WiFi mode.
+/// Roll back to the firmware that was running before the last update.
 ///
-/// TODO: Either merge this with `GetCurrentWifiMode`, or use macro to generate.
+/// The device switches to the other OTA partition and restarts. Only
+/// available on ESP-AT firmwares.
 #[derive(Debug)]
-pub struct GetDefaultWifiMode;
+pub struct RollbackFirmware;
 
-impl AtatCmd<16> for GetDefaultWifiMode {
-    type Response = types::WifiMode;
+impl AtatCmd<16> for RollbackFirmware {
+    type Response = responses::EmptyResponse;
 
     fn as_bytes(&self) -> Vec<u8, 16> {
-        Vec::from_slice(b"AT+CWMODE_DEF?\r\n").unwrap()
+        serialize(|buf| buf.write_str("AT+SYSROLLBACK\r\n"))
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        let resp = core::str::from_utf8(resp?).unwrap();
-        if !resp.starts_with("+CWMODE_DEF:") {
-            return Err(atat::Error::InvalidResponse);
-        }
-        match resp.get(12..13) {
-            Some("1") => Ok(types::WifiMode::Station),
-            Some("2") => Ok(types::WifiMode::Ap),
-            Some("3") => Ok(types::WifiMode::Both),
-            _ => Err(atat::Error::InvalidResponse),
-        }
+        responses::EmptyResponse::from_resp(resp)
     }
 }
 
-/// Set the WiFi mode.
+/// Restore the factory default settings.
 ///
-/// If `persist` is set to `true`, then the configuration will be persisted
-/// to flash.
+/// All parameters stored in flash are erased and the module is restarted.
 #[derive(Debug)]
-pub struct SetWifiMode {
-    mode: types::WifiMode,
-    persist: bool,
-}
-
-impl SetWifiMode {
-    pub fn to(mode: types::WifiMode, persist: bool) -> Self {
-        Self { mode, persist }
-    }
-}
+pub struct RestoreFactoryDefaults;
 
-impl AtatCmd<17> for SetWifiMode {
+impl AtatCmd<12> for RestoreFactoryDefaults {
     type Response = responses::EmptyResponse;
 
-    fn as_bytes(&self) -> Vec<u8, 17> {
-        let mut buf: Vec<u8, 17> = Vec::new();
-        let persist_str = if self.persist { "DEF" } else { "CUR" };
-        write!(
-            buf,
-            "AT+CWMODE_{}={}\r\n",
-            persist_str,
-            self.mode.as_at_str()
-        )
-        .unwrap();
-        buf
+    fn as_bytes(&self) -> Vec<u8, 12> {
+        serialize(|buf| buf.write_str("AT+RESTORE\r\n"))
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
@@ -183,249 +213,234 @@ impl AtatCmd<17> for SetWifiMode {
     }
 }
 
-/// Query available Access Points.
+/// Enable or disable command echo.
+///
+/// With echo enabled (the factory default), the device repeats every command
+/// before answering it.
 #[derive(Debug)]
-pub struct ListAccessPoints;
+pub struct SetEcho {
+    enabled: bool,
+}
 
-impl AtatCmd<10> for ListAccessPoints {
+impl SetEcho {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl AtatCmd<6> for SetEcho {
     type Response = responses::EmptyResponse;
-    const MAX_TIMEOUT_MS: u32 = 10_000;
 
-    fn as_bytes(&self) -> Vec<u8, 10> {
-        Vec::from_slice(b"AT+CWLAP\r\n").unwrap()
+    fn as_bytes(&self) -> Vec<u8, 6> {
+        if self.enabled {
+            serialize(|buf| buf.write_str("ATE1\r\n"))
+        } else {
+            serialize(|buf| buf.write_str("ATE0\r\n"))
+        }
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        // TODO: This currently overflows
         responses::EmptyResponse::from_resp(resp)
     }
 }
 
-/// Join an Access Point.
+/// Change the UART configuration of the device.
 ///
-/// If `persist` is set to `true`, then the credentials will be persisted to
-/// flash.
+/// The response is still sent with the old configuration, the new one takes
+/// effect right afterwards. With `Target::Default`, the configuration is
+/// persisted to flash. Unlike most other commands, this one has `_CUR` and
+/// `_DEF` variants on ESP-AT firmwares as well.
 #[derive(Debug)]
-pub struct JoinAccessPoint {
-    ssid: String<32>,
-    psk: String<64>,
-    persist: bool,
+pub struct SetUartConfig {
+    config: types::UartConfig,
+    target: types::Target,
 }
 
-impl JoinAccessPoint {
-    pub fn new(ssid: impl Into<String<32>>, psk: impl Into<String<64>>, persist: bool) -> Self {
-        Self {
-            ssid: ssid.into(),
-            psk: psk.into(),
-            persist,
-        }
+impl SetUartConfig {
+    pub fn to(config: types::UartConfig, target: types::Target) -> Self {
+        Self { config, target }
     }
 }
 
-impl AtatCmd<116> for JoinAccessPoint {
-    type Response = responses::JoinResponse;
-    const MAX_TIMEOUT_MS: u32 = 25_000;
+/// Length of the longest `AT+UART` command.
+const SET_UART_CONFIG_LEN: usize = "AT+UART_CUR=4294967295,8,3,2,3\r\n".len();
+
+impl AtatCmd<SET_UART_CONFIG_LEN> for SetUartConfig {
+    type Response = responses::EmptyResponse;
 
-    fn as_bytes(&self) -> Vec<u8, 116> {
-        let mut buf: Vec<u8, 116> = Vec::new();
-        let persist_str = if self.persist { "DEF" } else { "CUR" };
-        // TODO: Proper quoting
-        write!(
-            buf,
-            "AT+CWJAP_{}=\"{}\",\"{}\"\r\n",
-            persist_str,
-            self.ssid.as_str(),
-            self.psk.as_str()
-        )
-        .unwrap();
-        buf
+    fn as_bytes(&self) -> Vec<u8, SET_UART_CONFIG_LEN> {
+        serialize(|buf| {
+            // Baud rate is at most 4608000
+            let mut num_buf = [0; 10];
+            write!(
+                buf,
+                "AT+UART_{}={},{},{},{},{}\r\n",
+                self.target.as_at_str(),
+                self.config.baud_rate.numtoa_str(10, &mut num_buf),
+                self.config.data_bits.as_at_str(),
+                self.config.stop_bits.as_at_str(),
+                self.config.parity.as_at_str(),
+                self.config.flow_control.as_at_str(),
+            )?;
+            Ok(())
+        })
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        let resp = core::str::from_utf8(resp?).unwrap();
-        let mut response = responses::JoinResponse {
-            connected: false,
-            got_ip: false,
-        };
-        for line in resp.lines() {
-            match line {
-                "WIFI DISCONNECTED" => response.connected = false,
-                "WIFI CONNECTED" => response.connected = true,
-                "WIFI GOT IP" => response.got_ip = true,
-                _ => { /* throw away unknown lines for now */ }
-            }
-        }
-        Ok(response)
+        responses::EmptyResponse::from_resp(resp)
     }
 }
 
-/// Query information about current connection.
+/// Query the current or the persisted UART configuration.
+///
+/// The ESP8266 reports the baud rate it actually uses, which may differ
+/// slightly from the configured one (e.g. 115273 instead of 115200).
 #[derive(Debug)]
-pub struct GetConnectionStatus;
-
-impl AtatCmd<14> for GetConnectionStatus {
-    type Response = types::ConnectionStatus;
-
-    fn as_bytes(&self) -> Vec<u8, 14> {
-        Vec::from_slice(b"AT+CIPSTATUS\r\n").unwrap()
-    }
+pub struct GetUartConfig {
+    target: types::Target,
+}
 
-    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        let resp = core::str::from_utf8(resp?).unwrap();
-        if !resp.starts_with("STATUS:") {
-            return Err(atat::Error::InvalidResponse);
-        }
-        match resp.get(7..8) {
-            Some("2") => Ok(types::ConnectionStatus::ConnectedToAccessPoint),
-            Some("3") => Ok(types::ConnectionStatus::InTransmission),
-            Some("4") => Ok(types::ConnectionStatus::TransmissionEnded),
-            Some("5") => Ok(types::ConnectionStatus::Disconnected),
-            Some(other) => Ok(types::ConnectionStatus::Other(
-                other.parse().map_err(|_| atat::Error::Parse)?,
-            )),
-            None => Err(atat::Error::InvalidResponse),
-        }
+impl GetUartConfig {
+    pub fn new(target: types::Target) -> Self {
+        Self { target }
     }
 }
 
-/// Query the local IP and MAC addresses.
-#[derive(Debug)]
-pub struct GetLocalAddress;
+/// Length of the longest `AT+UART?` command.
+const GET_UART_CONFIG_LEN: usize = "AT+UART_CUR?\r\n".len();
 
-impl AtatCmd<10> for GetLocalAddress {
-    type Response = responses::LocalAddress;
+impl AtatCmd<GET_UART_CONFIG_LEN> for GetUartConfig {
+    type Response = types::UartConfig;
 
-    fn as_bytes(&self) -> Vec<u8, 10> {
-        Vec::from_slice(b"AT+CIFSR\r\n").unwrap()
+    fn as_bytes(&self) -> Vec<u8, GET_UART_CONFIG_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+UART_{}?\r\n", self.target.as_at_str())?;
+            Ok(())
+        })
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        let resp = core::str::from_utf8(resp?).unwrap();
-        // Example: +CIFSR:STAIP,"10.0.99.164"\r\n+CIFSR:STAMAC,"dc:4f:22:7e:41:b4"
-        let mut mac = None;
-        let mut ip = None;
-        for line in resp.lines() {
-            if line.starts_with("+CIFSR:STAIP,") {
-                let ip_raw = &line[14..line.len() - 1];
-                ip = if ip_raw == "0.0.0.0" {
-                    None
-                } else {
-                    Some(ip_raw.parse().map_err(|_| atat::Error::Parse)?)
-                };
-            } else if line.starts_with("+CIFSR:STAMAC,") {
-                mac = Some(String::from(&line[15..32]));
-            }
-        }
-        Ok(responses::LocalAddress {
-            ip,
-            mac: mac.ok_or(atat::Error::Parse)?,
+        // Example: +UART_CUR:115273,8,1,0,0
+        let resp = utf8(resp?)?;
+        let config = resp
+            .strip_prefix("+UART_")
+            .and_then(|rest| rest.strip_prefix(self.target.as_at_str()))
+            .and_then(|rest| rest.strip_prefix(':'))
+            .ok_or(atat::Error::InvalidResponse)?;
+        let mut fields = config.trim_end().split(',');
+        let mut field = || fields.next().ok_or(atat::Error::Parse);
+        let baud_rate = field()?.parse().map_err(|_| atat::Error::Parse)?;
+        let data_bits = types::DataBits::from_at_str(field()?).ok_or(atat::Error::Parse)?;
+        let stop_bits = types::StopBits::from_at_str(field()?).ok_or(atat::Error::Parse)?;
+        let parity = types::Parity::from_at_str(field()?).ok_or(atat::Error::Parse)?;
+        let flow_control = types::FlowControl::from_at_str(field()?).ok_or(atat::Error::Parse)?;
+        Ok(types::UartConfig {
+            baud_rate,
+            data_bits,
+            stop_bits,
+            parity,
+            flow_control,
         })
     }
 }
 
-/// Establish TCP Connection, UDP Transmission or SSL Connection.
+/// Query the current or the default WiFi mode.
 ///
-/// Note: The ESP8266 can also do DNS based requests, but that is not yet
-/// implemented.
-#[derive(Debug)]
-pub struct EstablishConnection {
-    mux: types::MultiplexingType,
-    protocol: types::Protocol,
-    remote_addr: SocketAddr,
+/// On ESP-AT firmwares, there is no separate default configuration, so both
+/// targets return the current WiFi mode.
+#[derive(Debug, Default)]
+pub struct GetWifiMode {
+    target: types::Target,
+    dialect: types::Dialect,
 }
 
-impl EstablishConnection {
-    pub fn tcp(mux: types::MultiplexingType, remote_addr: SocketAddr) -> Self {
+impl GetWifiMode {
+    pub fn new(target: types::Target) -> Self {
         Self {
-            mux,
-            protocol: types::Protocol::Tcp,
-            remote_addr,
+            target,
+            dialect: types::Dialect::default(),
         }
     }
 
-    pub fn udp(mux: types::MultiplexingType, remote_addr: SocketAddr) -> Self {
-        Self {
-            mux,
-            protocol: types::Protocol::Udp,
-            remote_addr,
-        }
+    /// Serialize the command for the specified firmware dialect.
+    pub fn with_dialect(self, dialect: types::Dialect) -> Self {
+        Self { dialect, ..self }
     }
 }
 
-impl AtatCmd<42> for EstablishConnection {
-    type Response = responses::ConnectResponse;
-    const MAX_TIMEOUT_MS: u32 = 30_000;
+/// Length of the longest `AT+CWMODE?` command.
+const GET_WIFI_MODE_LEN: usize = "AT+CWMODE_CUR?\r\n".len();
 
-    fn as_bytes(&self) -> Vec<u8, 42> {
-        // Single: AT+CIPSTART=<type>,<remote IP>,<remote port>[,<TCP keep alive>]
-        // Multiple: AT+CIPSTART=<link ID>,<type>,<remote IP>,<remote port>[,<TCP keep alive>]
-        let mut buf: Vec<u8, 42> = Vec::new();
-        write!(buf, "AT+CIPSTART=").unwrap();
-        if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
-            write!(buf, "{},", id.as_at_str()).unwrap();
-        }
-        write!(buf, "\"{}\",", self.protocol.as_at_str()).unwrap();
-        match self.remote_addr {
-            SocketAddr::V4(addr) => {
-                let octets = addr.ip().octets();
-                let mut num_buf = [0; 5];
-                write!(buf, "\"").unwrap();
-                for (i, octet) in octets.iter().enumerate() {
-                    write!(buf, "{}", octet.numtoa_str(10, &mut num_buf)).unwrap();
-                    if i != 3 {
-                        write!(buf, ".").unwrap();
-                    }
-                }
-                write!(buf, "\",{}", addr.port().numtoa_str(10, &mut num_buf)).unwrap();
-            }
-            SocketAddr::V6(_addr) => {
-                unimplemented!("IPv6 support is not implemented");
-            }
-        }
-        write!(buf, "\r\n").unwrap();
-        buf
+impl AtatCmd<GET_WIFI_MODE_LEN> for GetWifiMode {
+    type Response = types::WifiMode;
+
+    fn as_bytes(&self) -> Vec<u8, GET_WIFI_MODE_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CWMODE{}?\r\n", self.dialect.suffix(self.target))?;
+            Ok(())
+        })
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        match resp? {
-            b"CONNECT" => Ok(responses::ConnectResponse::Connected),
-            b"ALREADY CONNECTED" => Ok(responses::ConnectResponse::AlreadyConnected),
-            _ => Err(atat::Error::Parse),
+        let resp = utf8(resp?)?;
+        let mode = resp
+            .strip_prefix("+CWMODE")
+            .and_then(|rest| rest.strip_prefix(self.dialect.suffix(self.target)))
+            .and_then(|rest| rest.strip_prefix(':'))
+            .ok_or(atat::Error::InvalidResponse)?;
+        match mode.get(0..1) {
+            Some("0") => Ok(types::WifiMode::Off),
+            Some("1") => Ok(types::WifiMode::Station),
+            Some("2") => Ok(types::WifiMode::Ap),
+            Some("3") => Ok(types::WifiMode::Both),
+            _ => Err(atat::Error::InvalidResponse),
         }
     }
 }
 
-/// Prepare to send `length` bytes of data.
+/// Set the WiFi mode.
 ///
-/// This message MUST be followed by a `SendData` message.
+/// With `Target::Default`, the configuration is persisted to flash. On
+/// ESP-AT firmwares, persistence is controlled globally by `AT+SYSSTORE`
+/// instead, so the target is ignored.
 #[derive(Debug)]
-pub struct PrepareSendData {
-    mux: types::MultiplexingType,
-    length: u16,
+pub struct SetWifiMode {
+    mode: types::WifiMode,
+    target: types::Target,
+    dialect: types::Dialect,
 }
 
-impl PrepareSendData {
-    pub fn new(mux: types::MultiplexingType, length: u16) -> Self {
-        Self { mux, length }
+impl SetWifiMode {
+    pub fn to(mode: types::WifiMode, target: types::Target) -> Self {
+        Self {
+            mode,
+            target,
+            dialect: types::Dialect::default(),
+        }
+    }
+
+    /// Serialize the command for the specified firmware dialect.
+    pub fn with_dialect(self, dialect: types::Dialect) -> Self {
+        Self { dialect, ..self }
     }
 }
 
-impl AtatCmd<20> for PrepareSendData {
+/// Length of the longest `AT+CWMODE` command.
+const SET_WIFI_MODE_LEN: usize = "AT+CWMODE_CUR=3\r\n".len();
+
+impl AtatCmd<SET_WIFI_MODE_LEN> for SetWifiMode {
     type Response = responses::EmptyResponse;
-    const MAX_TIMEOUT_MS: u32 = 5_000;
 
-    fn as_bytes(&self) -> Vec<u8, 20> {
-        let mut buf: Vec<u8, 20> = Vec::new();
-        write!(buf, "AT+CIPSEND=").unwrap();
-        if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
-            write!(buf, "{},", id.as_at_str()).unwrap();
-        }
-        {
-            // Length can only be in the range 0-65535
-            let mut num_buf = [0; 5];
-            write!(buf, "{}\r\n", self.length.numtoa_str(10, &mut num_buf)).unwrap();
-        }
-        buf
+    fn as_bytes(&self) -> Vec<u8, SET_WIFI_MODE_LEN> {
+        serialize(|buf| {
+            write!(
+                buf,
+                "AT+CWMODE{}={}\r\n",
+                self.dialect.suffix(self.target),
+                self.mode.as_at_str()
+            )?;
+            Ok(())
+        })
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
@@ -433,28 +448,72 @@ impl AtatCmd<20> for PrepareSendData {
     }
 }
 
-/// Send data.
-///
-/// This message MUST directly follow by a `PrepareSendData` message.
+/// Configure the soft AP.
 ///
-/// The type argument `L` must be at least as large as the data length.
+/// With `Target::Default`, the configuration is persisted to flash. On
+/// ESP-AT firmwares, persistence is controlled globally by `AT+SYSSTORE`
+/// instead, so the target is ignored. The soft AP must be enabled with the
+/// WiFi mode.
 #[derive(Debug)]
-pub struct SendData<'a, const L: usize> {
-    data: &'a str,
+pub struct SetSoftApConfig {
+    config: types::SoftApConfig,
+    target: types::Target,
+    dialect: types::Dialect,
 }
 
-impl<'a, const L: usize> SendData<'a, L> {
-    pub fn new(data: &'a str) -> Self {
-        Self { data }
+impl SetSoftApConfig {
+    pub fn to(config: types::SoftApConfig, target: types::Target) -> Self {
+        Self {
+            config,
+            target,
+            dialect: types::Dialect::default(),
+        }
+    }
+
+    /// Serialize the command for the specified firmware dialect.
+    pub fn with_dialect(self, dialect: types::Dialect) -> Self {
+        Self { dialect, ..self }
+    }
+
+    /// Return whether the command can be sent to the device.
+    pub fn is_valid(&self) -> bool {
+        self.config.is_valid()
+            && is_valid_string(self.config.ssid.as_bytes())
+            && is_valid_string(self.config.psk.as_bytes())
     }
 }
 
-impl<'a, const L: usize> AtatCmd<L> for SendData<'a, L> {
+/// Length of the longest `AT+CWSAP` command.
+const SET_SOFT_AP_CONFIG_LEN: usize = "AT+CWSAP_CUR=".len()
+    + quoted_max_len(types::MAX_SSID_LEN)
+    + ",".len()
+    + quoted_max_len(types::MAX_PSK_LEN)
+    + ",255,3,255,1\r\n".len();
+
+impl AtatCmd<SET_SOFT_AP_CONFIG_LEN> for SetSoftApConfig {
     type Response = responses::EmptyResponse;
-    const MAX_TIMEOUT_MS: u32 = 30_000;
 
-    fn as_bytes(&self) -> Vec<u8, L> {
-        Vec::from_slice(self.data.as_bytes()).unwrap()
+    fn as_bytes(&self) -> Vec<u8, SET_SOFT_AP_CONFIG_LEN> {
+        serialize(|buf| {
+            // AT+CWSAP=<ssid>,<pwd>,<chl>,<ecn>[,<max conn>][,<ssid hidden>]
+            write!(buf, "AT+CWSAP{}=", self.dialect.suffix(self.target))?;
+            write_quoted(buf, self.config.ssid.as_bytes())?;
+            buf.write_char(',')?;
+            write_quoted(buf, self.config.psk.as_bytes())?;
+            // Encryption 0 is open, 3 is WPA2-PSK
+            let encryption = if self.config.psk.is_empty() { 0 } else { 3 };
+            write!(buf, ",{},{}", self.config.channel, encryption)?;
+            if self.config.max_connections.is_some() || self.config.hidden {
+                // The firmware default for up to 10 connections
+                let max_connections = self.config.max_connections.unwrap_or(10);
+                write!(buf, ",{}", max_connections)?;
+            }
+            if self.config.hidden {
+                write!(buf, ",1")?;
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
     }
 
     fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
@@ -462,33 +521,2473 @@ impl<'a, const L: usize> AtatCmd<L> for SendData<'a, L> {
     }
 }
 
-/// Close the TCP/UDP/SSL Connection.
+/// Query available Access Points.
+///
+/// Every access point takes about 60 bytes in the response, so the response
+/// queue must be large enough for the number of networks in range. At most
+/// [`MAX_ACCESS_POINTS`](../responses/constant.MAX_ACCESS_POINTS.html) are
+/// returned.
 #[derive(Debug)]
-pub struct CloseConnection {
-    mux: types::MultiplexingType,
-}
+pub struct ListAccessPoints;
 
-impl CloseConnection {
-    pub fn new(mux: types::MultiplexingType) -> Self {
-        Self { mux }
+impl AtatCmd<10> for ListAccessPoints {
+    type Response = responses::AccessPoints;
+    const MAX_TIMEOUT_MS: u32 = 10_000;
+
+    fn as_bytes(&self) -> Vec<u8, 10> {
+        serialize(|buf| buf.write_str("AT+CWLAP\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        // Example: +CWLAP:(3,"MyNetwork",-67,"aa:bb:cc:dd:ee:ff",6,-11,0)
+        let mut access_points = Vec::new();
+        for line in resp?.split(|&b| b == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            let access_point = parse_access_point(line).ok_or(atat::Error::Parse)?;
+            // Networks beyond the capacity are dropped
+            let _ = access_points.push(access_point);
+        }
+        Ok(responses::AccessPoints { access_points })
     }
 }
 
-impl AtatCmd<15> for CloseConnection {
-    type Response = responses::EmptyResponse;
-    const MAX_TIMEOUT_MS: u32 = 5_000;
+/// Parse a `+CWLAP:(<ecn>,"<ssid>",<rssi>,"<mac>",<channel>,...)` line.
+///
+/// The SSID is neither escaped nor necessarily UTF-8, so the fields after it
+/// are located from the end of the line.
+fn parse_access_point(line: &[u8]) -> Option<responses::AccessPoint> {
+    let fields = line.strip_prefix(b"+CWLAP:(")?.strip_suffix(b")")?;
+    let ecn_len = fields.iter().position(|&b| b == b',')?;
+    let encryption = core::str::from_utf8(&fields[..ecn_len])
+        .ok()?
+        .parse()
+        .ok()?;
+    let rest = fields[ecn_len + 1..].strip_prefix(b"\"")?;
+
+    // The MAC is the last quoted field
+    let mac_end = rest.iter().rposition(|&b| b == b'"')?;
+    let mac_start = rest[..mac_end].iter().rposition(|&b| b == b'"')?;
+    let bssid =
+        types::MacAddress::parse(core::str::from_utf8(&rest[mac_start + 1..mac_end]).ok()?)?;
+    let rssi_field = rest[..mac_start].strip_suffix(b",")?;
+    let rssi_start = rssi_field.iter().rposition(|&b| b == b',')?;
+    let rssi = core::str::from_utf8(&rssi_field[rssi_start + 1..])
+        .ok()?
+        .parse()
+        .ok()?;
+    let ssid = rssi_field[..rssi_start].strip_suffix(b"\"")?;
+    let channel = rest[mac_end + 1..]
+        .strip_prefix(b",")?
+        .split(|&b| b == b',')
+        .next()
+        .and_then(|channel| core::str::from_utf8(channel).ok())
+        .and_then(|channel| channel.parse().ok())?;
+
+    Some(responses::AccessPoint {
+        ssid: types::Ssid::new(ssid)?,
+        encryption: types::Encryption::from_at_code(encryption),
+        rssi,
+        bssid,
+        channel,
+    })
+}
 
-    fn as_bytes(&self) -> Vec<u8, 15> {
-        let mut buf: Vec<u8, 15> = Vec::new();
-        write!(buf, "AT+CIPCLOSE").unwrap();
-        if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
-            write!(buf, "={}", id.as_at_str()).unwrap();
+/// Join an Access Point.
+///
+/// With `Target::Default`, the credentials are persisted to flash. On ESP-AT
+/// firmwares, persistence is controlled globally by `AT+SYSSTORE` instead,
+/// so the target is ignored.
+///
+/// Hidden networks can be joined by SSID as well. To associate with a
+/// specific access point of a network with multiple access points (e.g.
+/// repeaters), set its BSSID with [`with_bssid`](#method.with_bssid).
+/// Extended association parameters are only sent to ESP-AT firmwares.
+#[derive(Debug)]
+pub struct JoinAccessPoint {
+    ssid: types::Ssid,
+    psk: String<{ types::MAX_PSK_LEN }>,
+    bssid: Option<types::MacAddress>,
+    options: Option<types::JoinOptions>,
+    target: types::Target,
+    dialect: types::Dialect,
+}
+
+impl JoinAccessPoint {
+    /// Return `None` if the PSK is longer than 64 bytes.
+    pub fn new(ssid: impl Into<types::Ssid>, psk: &str, target: types::Target) -> Option<Self> {
+        Some(Self {
+            ssid: ssid.into(),
+            psk: to_string(psk).ok()?,
+            bssid: None,
+            options: None,
+            target,
+            dialect: types::Dialect::default(),
+        })
+    }
+
+    /// Only associate with the access point with the specified BSSID.
+    pub fn with_bssid(self, bssid: types::MacAddress) -> Self {
+        Self {
+            bssid: Some(bssid),
+            ..self
         }
-        write!(buf, "\r\n").unwrap();
-        buf
     }
 
-    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
-        responses::EmptyResponse::from_resp(resp)
+    /// Set the extended association parameters (ESP-AT only).
+    pub fn with_options(self, options: types::JoinOptions) -> Self {
+        Self {
+            options: Some(options),
+            ..self
+        }
+    }
+
+    /// Serialize the command for the specified firmware dialect.
+    pub fn with_dialect(self, dialect: types::Dialect) -> Self {
+        Self { dialect, ..self }
+    }
+
+    /// Return whether the command can be sent to the device.
+    ///
+    /// Control characters like line breaks would terminate the command, and
+    /// the extended parameters must be in the ranges accepted by the
+    /// firmware.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.ssid.as_bytes())
+            && is_valid_string(self.psk.as_bytes())
+            && self.options.iter().all(types::JoinOptions::is_valid)
+    }
+}
+
+/// Length of the longest `AT+CWJAP` command.
+const JOIN_ACCESS_POINT_LEN: usize = "AT+CWJAP_CUR=".len()
+    + quoted_max_len(types::MAX_SSID_LEN)
+    + ",".len()
+    + quoted_max_len(types::MAX_PSK_LEN)
+    + ",\"aa:bb:cc:dd:ee:ff\",1,65535,255\r\n".len();
+
+impl AtatCmd<JOIN_ACCESS_POINT_LEN> for JoinAccessPoint {
+    type Response = responses::JoinResponse;
+    const MAX_TIMEOUT_MS: u32 = 25_000;
+
+    fn as_bytes(&self) -> Vec<u8, JOIN_ACCESS_POINT_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CWJAP{}=", self.dialect.suffix(self.target))?;
+            write_quoted(buf, self.ssid.as_bytes())?;
+            buf.write_char(',')?;
+            write_quoted(buf, self.psk.as_bytes())?;
+            let options = match self.dialect {
+                types::Dialect::EspAt => self.options,
+                types::Dialect::NonOs => None,
+            };
+            if self.bssid.is_some() || options.is_some() {
+                buf.write_char(',')?;
+            }
+            if let Some(ref bssid) = self.bssid {
+                write!(buf, "\"")?;
+                bssid.write_at_str(buf)?;
+                write!(buf, "\"")?;
+            }
+            if let Some(options) = options {
+                // The parameters are positional, so unset ones get the defaults
+                // of the firmware
+                write!(
+                    buf,
+                    ",{},{},{}",
+                    u8::from(options.require_encryption),
+                    options.reconnect_interval_s.unwrap_or(1),
+                    options.listen_interval.unwrap_or(3)
+                )?;
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        let mut response = responses::JoinResponse {
+            connected: false,
+            got_ip: false,
+        };
+        for line in resp.lines() {
+            match line {
+                "WIFI DISCONNECTED" => response.connected = false,
+                "WIFI CONNECTED" => response.connected = true,
+                "WIFI GOT IP" => response.got_ip = true,
+                _ => { /* throw away unknown lines for now */ }
+            }
+        }
+        Ok(response)
+    }
+}
+
+/// Disconnect a station, or all stations, from the soft AP.
+#[derive(Debug)]
+pub struct DisconnectStation {
+    /// The station to disconnect, or `None` to disconnect all stations
+    mac: Option<types::MacAddress>,
+}
+
+impl DisconnectStation {
+    pub fn new(mac: types::MacAddress) -> Self {
+        Self { mac: Some(mac) }
+    }
+
+    pub fn all() -> Self {
+        Self { mac: None }
+    }
+}
+
+/// Length of the longest `AT+CWQIF` command.
+const DISCONNECT_STATION_LEN: usize = "AT+CWQIF=\"aa:bb:cc:dd:ee:ff\"\r\n".len();
+
+impl AtatCmd<DISCONNECT_STATION_LEN> for DisconnectStation {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, DISCONNECT_STATION_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CWQIF")?;
+            if let Some(ref mac) = self.mac {
+                write!(buf, "=\"")?;
+                mac.write_at_str(buf)?;
+                write!(buf, "\"")?;
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Query the access point the station is connected to.
+#[derive(Debug, Default)]
+pub struct GetJoinedAccessPoint {
+    dialect: types::Dialect,
+}
+
+impl GetJoinedAccessPoint {
+    pub fn new(dialect: types::Dialect) -> Self {
+        Self { dialect }
+    }
+}
+
+/// Length of the longest `AT+CWJAP?` command.
+const GET_JOINED_ACCESS_POINT_LEN: usize = "AT+CWJAP_CUR?\r\n".len();
+
+impl AtatCmd<GET_JOINED_ACCESS_POINT_LEN> for GetJoinedAccessPoint {
+    type Response = responses::StationStatus;
+
+    fn as_bytes(&self) -> Vec<u8, GET_JOINED_ACCESS_POINT_LEN> {
+        serialize(|buf| {
+            write!(
+                buf,
+                "AT+CWJAP{}?\r\n",
+                self.dialect.suffix(types::Target::Current)
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        // Example: +CWJAP_CUR:"MyNetwork","aa:bb:cc:dd:ee:ff",6,-58
+        // (ESP-AT firmwares use +CWJAP and append further fields)
+        let resp = resp?;
+        let resp = resp.strip_suffix(b"\r\n").unwrap_or(resp);
+        if resp == b"No AP" {
+            return Ok(responses::StationStatus { access_point: None });
+        }
+        let access_point = resp
+            .strip_prefix(b"+CWJAP")
+            .and_then(|rest| {
+                rest.strip_prefix(self.dialect.suffix(types::Target::Current).as_bytes())
+            })
+            .and_then(|rest| rest.strip_prefix(b":"))
+            .ok_or(atat::Error::InvalidResponse)?;
+        let access_point = parse_joined_access_point(access_point).ok_or(atat::Error::Parse)?;
+        Ok(responses::StationStatus {
+            access_point: Some(access_point),
+        })
+    }
+}
+
+/// Parse the `"<ssid>","<bssid>",<channel>,<rssi>,...` fields of a
+/// `+CWJAP` response.
+///
+/// Like in scan results, the SSID is not escaped, so the BSSID is located as
+/// the last quoted field.
+fn parse_joined_access_point(fields: &[u8]) -> Option<responses::JoinedAccessPoint> {
+    let rest = fields.strip_prefix(b"\"")?;
+    let mac_end = rest.iter().rposition(|&b| b == b'"')?;
+    let mac_start = rest[..mac_end].iter().rposition(|&b| b == b'"')?;
+    let bssid =
+        types::MacAddress::parse(core::str::from_utf8(&rest[mac_start + 1..mac_end]).ok()?)?;
+    let ssid = rest[..mac_start].strip_suffix(b"\",")?;
+    let mut numbers = core::str::from_utf8(rest[mac_end + 1..].strip_prefix(b",")?)
+        .ok()?
+        .split(',');
+    let channel = numbers.next()?.parse().ok()?;
+    let rssi = numbers.next()?.parse().ok()?;
+    Some(responses::JoinedAccessPoint {
+        ssid: types::Ssid::new(ssid)?,
+        bssid,
+        channel,
+        rssi,
+    })
+}
+
+/// Disconnect from the access point.
+#[derive(Debug)]
+pub struct LeaveAccessPoint;
+
+impl AtatCmd<10> for LeaveAccessPoint {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, 10> {
+        serialize(|buf| buf.write_str("AT+CWQAP\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        // The WIFI DISCONNECT line is processed as URC
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Return whether `string` contains no control characters.
+pub(crate) fn is_valid_string(string: &[u8]) -> bool {
+    !string.iter().any(|b| b.is_ascii_control())
+}
+
+/// Write `string` in quotes, escaping the characters that have a special
+/// meaning in string parameters with a backslash.
+pub(crate) fn write_quoted<const L: usize>(buf: &mut Vec<u8, L>, string: &[u8]) -> fmt::Result {
+    buf.push(b'"').map_err(|_| fmt::Error)?;
+    for &byte in string {
+        if matches!(byte, b'"' | b',' | b'\\') {
+            buf.push(b'\\').map_err(|_| fmt::Error)?;
+        }
+        buf.push(byte).map_err(|_| fmt::Error)?;
+    }
+    buf.push(b'"').map_err(|_| fmt::Error)
+}
+
+/// Return the maximum length of a string of `len` bytes when written with
+/// [`write_quoted`](fn.write_quoted.html), i.e. if every byte is escaped.
+pub(crate) const fn quoted_max_len(len: usize) -> usize {
+    2 * len + "\"\"".len()
+}
+
+/// Return the length of `string` when written with
+/// [`write_quoted`](fn.write_quoted.html).
+#[cfg(feature = "http")]
+pub(crate) fn quoted_len(string: &[u8]) -> usize {
+    let escaped = string
+        .iter()
+        .filter(|b| matches!(b, b'"' | b',' | b'\\'))
+        .count();
+    string.len() + escaped + 2
+}
+
+/// Query information about current connection.
+#[derive(Debug)]
+pub struct GetConnectionStatus;
+
+impl AtatCmd<14> for GetConnectionStatus {
+    type Response = types::ConnectionStatus;
+
+    fn as_bytes(&self) -> Vec<u8, 14> {
+        serialize(|buf| buf.write_str("AT+CIPSTATUS\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        if !resp.starts_with("STATUS:") {
+            return Err(atat::Error::InvalidResponse);
+        }
+        match resp.get(7..8) {
+            Some("2") => Ok(types::ConnectionStatus::ConnectedToAccessPoint),
+            Some("3") => Ok(types::ConnectionStatus::InTransmission),
+            Some("4") => Ok(types::ConnectionStatus::TransmissionEnded),
+            Some("5") => Ok(types::ConnectionStatus::Disconnected),
+            Some(other) => Ok(types::ConnectionStatus::Other(
+                other.parse().map_err(|_| atat::Error::Parse)?,
+            )),
+            None => Err(atat::Error::InvalidResponse),
+        }
+    }
+}
+
+/// Start a server that accepts TCP or SSL connections on a port.
+///
+/// The server requires multiplexing to be enabled. SSL servers are only
+/// supported by ESP-AT firmwares and use the server certificate and key
+/// stored in flash. With client verification, clients must present a
+/// certificate signed by the CA stored in flash.
+#[derive(Debug)]
+pub struct StartServer {
+    port: u16,
+    ssl: bool,
+    verify_client: bool,
+}
+
+impl StartServer {
+    pub fn tcp(port: u16) -> Self {
+        Self {
+            port,
+            ssl: false,
+            verify_client: false,
+        }
+    }
+
+    pub fn ssl(port: u16) -> Self {
+        Self {
+            port,
+            ssl: true,
+            verify_client: false,
+        }
+    }
+
+    /// Require SSL clients to present a certificate signed by the CA in
+    /// flash.
+    pub fn with_client_verification(self) -> Self {
+        Self {
+            verify_client: true,
+            ..self
+        }
+    }
+
+    pub(crate) fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub(crate) fn is_ssl(&self) -> bool {
+        self.ssl
+    }
+}
+
+/// Length of the longest `AT+CIPSERVER` command.
+const START_SERVER_LEN: usize = "AT+CIPSERVER=1,65535,\"SSL\",1\r\n".len();
+
+impl AtatCmd<START_SERVER_LEN> for StartServer {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, START_SERVER_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPSERVER=1,{}", self.port)?;
+            if self.ssl {
+                write!(buf, ",\"SSL\",{}", u8::from(self.verify_client))?;
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        match resp? {
+            // Reported if the server is already running
+            b"" | b"no change" => Ok(responses::EmptyResponse),
+            _ => Err(atat::Error::InvalidResponse),
+        }
+    }
+}
+
+/// Stop the server, connections that it accepted stay open.
+#[derive(Debug)]
+pub struct StopServer;
+
+impl AtatCmd<16> for StopServer {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, 16> {
+        serialize(|buf| buf.write_str("AT+CIPSERVER=0\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Query the open connections.
+///
+/// Uses `AT+CIPSTATUS` on NONOS firmwares and `AT+CIPSTATE?` on ESP-AT
+/// firmwares, where the former is deprecated.
+#[derive(Debug)]
+pub struct GetLinks {
+    dialect: types::Dialect,
+}
+
+impl GetLinks {
+    pub fn new(dialect: types::Dialect) -> Self {
+        Self { dialect }
+    }
+}
+
+impl AtatCmd<14> for GetLinks {
+    type Response = responses::Links;
+
+    fn as_bytes(&self) -> Vec<u8, 14> {
+        match self.dialect {
+            types::Dialect::NonOs => serialize(|buf| buf.write_str("AT+CIPSTATUS\r\n")),
+            types::Dialect::EspAt => serialize(|buf| buf.write_str("AT+CIPSTATE?\r\n")),
+        }
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CIPSTATUS:0,"TCP","192.168.1.2",80,49152,0
+        let mut links = Vec::new();
+        for line in resp.lines() {
+            let fields = match line
+                .strip_prefix("+CIPSTATUS:")
+                .or_else(|| line.strip_prefix("+CIPSTATE:"))
+            {
+                Some(fields) => fields,
+                None => continue,
+            };
+            // A seventh item collects any surplus fields
+            let fields: Vec<&str, 7> = fields.splitn(7, ',').collect();
+            let link = match fields[..] {
+                [link_id, protocol, ip, port, local_port, tetype] => {
+                    responses::LinkInfo::from_fields(
+                        link_id, protocol, tetype, ip, port, local_port,
+                    )
+                }
+                _ => None,
+            };
+            links
+                .push(link.ok_or(atat::Error::Parse)?)
+                .map_err(|_| atat::Error::Parse)?;
+        }
+        Ok(responses::Links { links })
+    }
+}
+
+/// Query the state of the station.
+///
+/// Only available on ESP-AT firmwares. Unlike `AT+CIPSTATUS`, it tells
+/// whether the station is still connecting or waiting for an IP address.
+#[derive(Debug)]
+pub struct GetStationState;
+
+impl AtatCmd<13> for GetStationState {
+    type Response = types::ConnectionStatus;
+
+    fn as_bytes(&self) -> Vec<u8, 13> {
+        serialize(|buf| buf.write_str("AT+CWSTATE?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CWSTATE:2,"mywifi"
+        let state = resp
+            .strip_prefix("+CWSTATE:")
+            .and_then(|rest| rest.split(',').next())
+            .ok_or(atat::Error::InvalidResponse)?;
+        match state {
+            "0" => Ok(types::ConnectionStatus::NotStarted),
+            "1" => Ok(types::ConnectionStatus::ConnectedWithoutIp),
+            "2" => Ok(types::ConnectionStatus::ConnectedToAccessPoint),
+            "3" => Ok(types::ConnectionStatus::Connecting),
+            "4" => Ok(types::ConnectionStatus::Disconnected),
+            other => Ok(types::ConnectionStatus::Other(
+                other.parse().map_err(|_| atat::Error::Parse)?,
+            )),
+        }
+    }
+}
+
+/// Query the SSID of the access point the station is configured to join.
+///
+/// Only available on ESP-AT firmwares. The SSID is reported also while the
+/// station is not connected, it's `None` if no access point is configured.
+#[derive(Debug)]
+pub struct GetConfiguredSsid;
+
+impl AtatCmd<13> for GetConfiguredSsid {
+    type Response = responses::StationConfig;
+
+    fn as_bytes(&self) -> Vec<u8, 13> {
+        serialize(|buf| buf.write_str("AT+CWSTATE?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        // Example: +CWSTATE:0,"mywifi"
+        // Like in scan results, the SSID is not escaped
+        let resp = resp?;
+        let resp = resp.strip_suffix(b"\r\n").unwrap_or(resp);
+        let ssid = resp
+            .strip_prefix(b"+CWSTATE:")
+            .and_then(|rest| rest.iter().position(|&b| b == b',').map(|i| &rest[i + 1..]))
+            .and_then(|ssid| ssid.strip_prefix(b"\""))
+            .and_then(|ssid| ssid.strip_suffix(b"\""))
+            .ok_or(atat::Error::InvalidResponse)?;
+        if ssid.is_empty() {
+            return Ok(responses::StationConfig { ssid: None });
+        }
+        let ssid = types::Ssid::new(ssid).ok_or(atat::Error::Parse)?;
+        Ok(responses::StationConfig { ssid: Some(ssid) })
+    }
+}
+
+/// Query the sleep mode.
+#[derive(Debug, Default)]
+pub struct GetSleepMode {
+    dialect: types::Dialect,
+}
+
+impl GetSleepMode {
+    pub fn new(dialect: types::Dialect) -> Self {
+        Self { dialect }
+    }
+}
+
+impl AtatCmd<11> for GetSleepMode {
+    type Response = types::SleepMode;
+
+    fn as_bytes(&self) -> Vec<u8, 11> {
+        serialize(|buf| buf.write_str("AT+SLEEP?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +SLEEP:1
+        let mode = resp
+            .strip_prefix("+SLEEP:")
+            .ok_or(atat::Error::InvalidResponse)?;
+        types::SleepMode::from_at_str(mode.trim_end(), self.dialect).ok_or(atat::Error::Parse)
+    }
+}
+
+/// Set the sleep mode.
+///
+/// The sleep mode is not persisted, the device doesn't sleep after a reset.
+#[derive(Debug)]
+pub struct SetSleepMode {
+    mode: types::SleepMode,
+    dialect: types::Dialect,
+}
+
+impl SetSleepMode {
+    pub fn to(mode: types::SleepMode) -> Self {
+        Self {
+            mode,
+            dialect: types::Dialect::default(),
+        }
+    }
+
+    /// Serialize the command for the specified firmware dialect.
+    pub fn with_dialect(self, dialect: types::Dialect) -> Self {
+        Self { dialect, ..self }
+    }
+}
+
+/// Length of the longest `AT+SLEEP` command.
+const SET_SLEEP_MODE_LEN: usize = "AT+SLEEP=3\r\n".len();
+
+impl AtatCmd<SET_SLEEP_MODE_LEN> for SetSleepMode {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_SLEEP_MODE_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+SLEEP={}\r\n", self.mode.as_at_str(self.dialect))?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Configure how the station reconnects to the access point by itself.
+///
+/// Only available on ESP-AT firmwares, persisted unless disabled with
+/// `AT+SYSSTORE`.
+#[derive(Debug)]
+pub struct SetReconnectConfig {
+    config: types::ReconnectConfig,
+}
+
+impl SetReconnectConfig {
+    pub fn new(config: types::ReconnectConfig) -> Self {
+        Self { config }
+    }
+}
+
+/// Length of the longest `AT+CWRECONNCFG` command.
+const SET_RECONNECT_CONFIG_LEN: usize = "AT+CWRECONNCFG=65535,65535\r\n".len();
+
+impl AtatCmd<SET_RECONNECT_CONFIG_LEN> for SetReconnectConfig {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_RECONNECT_CONFIG_LEN> {
+        serialize(|buf| {
+            write!(
+                buf,
+                "AT+CWRECONNCFG={},{}\r\n",
+                self.config.interval_s, self.config.max_attempts
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Query how the station reconnects to the access point by itself.
+///
+/// Only available on ESP-AT firmwares.
+#[derive(Debug)]
+pub struct GetReconnectConfig;
+
+impl AtatCmd<17> for GetReconnectConfig {
+    type Response = types::ReconnectConfig;
+
+    fn as_bytes(&self) -> Vec<u8, 17> {
+        serialize(|buf| buf.write_str("AT+CWRECONNCFG?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CWRECONNCFG:1,100
+        let (interval_s, max_attempts) = resp
+            .strip_prefix("+CWRECONNCFG:")
+            .and_then(|rest| rest.trim().split_once(','))
+            .ok_or(atat::Error::InvalidResponse)?;
+        Ok(types::ReconnectConfig {
+            interval_s: interval_s.parse().map_err(|_| atat::Error::Parse)?,
+            max_attempts: max_attempts.parse().map_err(|_| atat::Error::Parse)?,
+        })
+    }
+}
+
+/// Query the local IP and MAC addresses.
+#[derive(Debug)]
+pub struct GetLocalAddress;
+
+impl AtatCmd<10> for GetLocalAddress {
+    type Response = responses::LocalAddress;
+
+    fn as_bytes(&self) -> Vec<u8, 10> {
+        serialize(|buf| buf.write_str("AT+CIFSR\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CIFSR:STAIP,"10.0.99.164"\r\n+CIFSR:STAMAC,"dc:4f:22:7e:41:b4"
+        // In SoftAP mode, there are APIP and APMAC lines instead or in addition.
+        let (mut station_ip, mut station_mac) = (None, None);
+        let (mut ap_ip, mut ap_mac) = (None, None);
+        for line in resp.lines() {
+            let (key, value) = match line
+                .strip_prefix("+CIFSR:")
+                .and_then(|rest| rest.split_once(','))
+            {
+                Some(field) => field,
+                None => continue,
+            };
+            match key {
+                "STAIP" => station_ip = parse_assigned_ip(unquote(value)?)?,
+                "STAMAC" => station_mac = Some(to_string(unquote(value)?)?),
+                "APIP" => ap_ip = parse_assigned_ip(unquote(value)?)?,
+                "APMAC" => ap_mac = Some(to_string(unquote(value)?)?),
+                // IPv6 addresses of newer firmwares
+                _ => {}
+            }
+        }
+        let interface = |ip, mac: Option<_>| mac.map(|mac| responses::InterfaceAddress { ip, mac });
+        Ok(responses::LocalAddress {
+            station: interface(station_ip, station_mac),
+            ap: interface(ap_ip, ap_mac),
+        })
+    }
+}
+
+/// Parse an IP address, `0.0.0.0` means that none is assigned.
+fn parse_assigned_ip(ip: &str) -> Result<Option<Ipv4Addr>, atat::Error> {
+    match ip {
+        "0.0.0.0" => Ok(None),
+        _ => ip.parse().map(Some).map_err(|_| atat::Error::Parse),
+    }
+}
+
+/// Query the current or the default IP configuration of the station
+/// interface.
+#[derive(Debug, Default)]
+pub struct GetIpConfig {
+    target: types::Target,
+    dialect: types::Dialect,
+}
+
+impl GetIpConfig {
+    pub fn new(target: types::Target) -> Self {
+        Self {
+            target,
+            dialect: types::Dialect::default(),
+        }
+    }
+
+    /// Serialize the command for the specified firmware dialect.
+    pub fn with_dialect(self, dialect: types::Dialect) -> Self {
+        Self { dialect, ..self }
+    }
+}
+
+/// Length of the longest `AT+CIPSTA?` command.
+const GET_IP_CONFIG_LEN: usize = "AT+CIPSTA_CUR?\r\n".len();
+
+impl AtatCmd<GET_IP_CONFIG_LEN> for GetIpConfig {
+    type Response = responses::IpConfig;
+
+    fn as_bytes(&self) -> Vec<u8, GET_IP_CONFIG_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPSTA{}?\r\n", self.dialect.suffix(self.target))?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CIPSTA:ip:"192.168.1.42"\r\n+CIPSTA:gateway:"192.168.1.1"\r\n
+        // +CIPSTA:netmask:"255.255.255.0" (NONOS firmwares use +CIPSTA_CUR)
+        let (mut ip, mut gateway, mut netmask) = (None, None, None);
+        for line in resp.lines() {
+            let (key, value) = line
+                .strip_prefix("+CIPSTA")
+                .and_then(|rest| rest.strip_prefix(self.dialect.suffix(self.target)))
+                .and_then(|rest| rest.strip_prefix(':'))
+                .and_then(|rest| rest.split_once(':'))
+                .ok_or(atat::Error::InvalidResponse)?;
+            let field = match key {
+                "ip" => &mut ip,
+                "gateway" => &mut gateway,
+                "netmask" => &mut netmask,
+                // IPv6 addresses of newer firmwares
+                _ => continue,
+            };
+            *field = Some(unquote(value)?.parse().map_err(|_| atat::Error::Parse)?);
+        }
+        Ok(responses::IpConfig {
+            ip: ip.ok_or(atat::Error::Parse)?,
+            gateway: gateway.ok_or(atat::Error::Parse)?,
+            netmask: netmask.ok_or(atat::Error::Parse)?,
+        })
+    }
+}
+
+/// Enable or disable multiple connections.
+///
+/// This can only be changed while no connection is open.
+#[derive(Debug)]
+pub struct SetMultiplexing {
+    enabled: bool,
+}
+
+impl SetMultiplexing {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl AtatCmd<13> for SetMultiplexing {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, 13> {
+        if self.enabled {
+            serialize(|buf| buf.write_str("AT+CIPMUX=1\r\n"))
+        } else {
+            serialize(|buf| buf.write_str("AT+CIPMUX=0\r\n"))
+        }
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Configure whether the remote IP and port are shown in `+IPD` messages.
+#[derive(Debug)]
+pub struct SetShowRemoteInfo {
+    enabled: bool,
+}
+
+impl SetShowRemoteInfo {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl AtatCmd<15> for SetShowRemoteInfo {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, 15> {
+        if self.enabled {
+            serialize(|buf| buf.write_str("AT+CIPDINFO=1\r\n"))
+        } else {
+            serialize(|buf| buf.write_str("AT+CIPDINFO=0\r\n"))
+        }
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Configure whether received data is sent to the host immediately or
+/// buffered by the device.
+///
+/// Passive mode requires AT firmware 1.7 or ESP-AT.
+#[derive(Debug)]
+pub struct SetReceiveMode {
+    mode: types::ReceiveMode,
+}
+
+impl SetReceiveMode {
+    pub fn new(mode: types::ReceiveMode) -> Self {
+        Self { mode }
+    }
+}
+
+/// Length of the longest `AT+CIPRECVMODE` command.
+const SET_RECEIVE_MODE_LEN: usize = "AT+CIPRECVMODE=1\r\n".len();
+
+impl AtatCmd<SET_RECEIVE_MODE_LEN> for SetReceiveMode {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_RECEIVE_MODE_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPRECVMODE={}\r\n", self.mode.as_at_str())?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Maximum number of bytes that can be read with a single `AT+CIPRECVDATA`.
+pub const MAX_RECEIVE_LEN: usize = 2048;
+
+/// Read up to `N` bytes of data buffered by the device in passive receive
+/// mode.
+///
+/// At most [`MAX_RECEIVE_LEN`](constant.MAX_RECEIVE_LEN.html) bytes are
+/// requested, also if `N` is larger. The response queue must be able to hold
+/// the requested length plus about 40 bytes of header.
+#[derive(Debug)]
+pub struct ReceiveData<const N: usize> {
+    mux: types::MultiplexingType,
+}
+
+impl<const N: usize> ReceiveData<N> {
+    pub fn new(mux: types::MultiplexingType) -> Self {
+        Self { mux }
+    }
+}
+
+/// Length of the longest `AT+CIPRECVDATA` command, which requests at most
+/// [`MAX_RECEIVE_LEN`](constant.MAX_RECEIVE_LEN.html) bytes.
+const RECEIVE_DATA_LEN: usize = "AT+CIPRECVDATA=4,2048\r\n".len();
+
+impl<const N: usize> AtatCmd<RECEIVE_DATA_LEN> for ReceiveData<N> {
+    type Response = urcs::NetworkData<N>;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, RECEIVE_DATA_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPRECVDATA=")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "{},", id.as_at_str())?;
+            }
+            write!(buf, "{}\r\n", N.min(MAX_RECEIVE_LEN))?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let (remote, data) = parse_received_data(resp?)?;
+        Ok(urcs::NetworkData {
+            mux: self.mux,
+            remote,
+            data: Vec::from_slice(data).map_err(|_| atat::Error::Overflow)?,
+        })
+    }
+}
+
+/// Read data buffered by the device in passive receive mode into a buffer.
+///
+/// Up to the length of the buffer is requested, but at most
+/// [`MAX_RECEIVE_LEN`](constant.MAX_RECEIVE_LEN.html) bytes. The data is
+/// copied from the response queue into the buffer without an intermediate
+/// copy. The response queue must be able to hold the requested length plus
+/// about 40 bytes of header.
+#[derive(Debug)]
+pub struct ReceiveDataInto<'a> {
+    mux: types::MultiplexingType,
+    buf: RefCell<&'a mut [u8]>,
+}
+
+impl<'a> ReceiveDataInto<'a> {
+    pub fn new(mux: types::MultiplexingType, buf: &'a mut [u8]) -> Self {
+        Self {
+            mux,
+            buf: RefCell::new(buf),
+        }
+    }
+}
+
+impl AtatCmd<RECEIVE_DATA_LEN> for ReceiveDataInto<'_> {
+    type Response = responses::ReceivedData;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, RECEIVE_DATA_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPRECVDATA=")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "{},", id.as_at_str())?;
+            }
+            let len = self.buf.borrow().len().min(MAX_RECEIVE_LEN);
+            write!(buf, "{}\r\n", len)?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let (remote, data) = parse_received_data(resp?)?;
+        self.buf
+            .borrow_mut()
+            .get_mut(..data.len())
+            .ok_or(atat::Error::Overflow)?
+            .copy_from_slice(data);
+        Ok(responses::ReceivedData {
+            len: data.len(),
+            remote,
+        })
+    }
+}
+
+/// Parse an `AT+CIPRECVDATA` response into the sender, if reported, and the
+/// data.
+fn parse_received_data(resp: &[u8]) -> Result<(Option<SocketAddr>, &[u8]), atat::Error> {
+    // Examples: +CIPRECVDATA,5:hello (NONOS), +CIPRECVDATA:5,hello
+    // (ESP-AT), +CIPRECVDATA:5,"192.168.1.1",80,hello (ESP-AT with
+    // AT+CIPDINFO=1)
+    let (len, rest) = if let Some(rest) = resp.strip_prefix(b"+CIPRECVDATA,") {
+        split_at_byte(rest, b':').ok_or(atat::Error::Parse)?
+    } else if let Some(rest) = resp.strip_prefix(b"+CIPRECVDATA:") {
+        split_at_byte(rest, b',').ok_or(atat::Error::Parse)?
+    } else {
+        return Err(atat::Error::InvalidResponse);
+    };
+    let len: usize = utf8(len)?.parse().map_err(|_| atat::Error::Parse)?;
+    let (remote, data) = match rest.strip_prefix(b"\"") {
+        Some(rest) => {
+            let (ip, rest) = split_at_byte(rest, b'"').ok_or(atat::Error::Parse)?;
+            let rest = rest.strip_prefix(b",").ok_or(atat::Error::Parse)?;
+            let (port, data) = split_at_byte(rest, b',').ok_or(atat::Error::Parse)?;
+            let ip = utf8(ip)?.parse().map_err(|_| atat::Error::Parse)?;
+            let port = utf8(port)?.parse().map_err(|_| atat::Error::Parse)?;
+            (Some(SocketAddr::new(ip, port)), data)
+        }
+        None => (None, rest),
+    };
+    if data.len() != len {
+        return Err(atat::Error::Parse);
+    }
+    Ok((remote, data))
+}
+
+/// Split `bytes` at the first occurrence of `separator`, which is removed.
+fn split_at_byte(bytes: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let index = bytes.iter().position(|&b| b == separator)?;
+    Some((&bytes[..index], &bytes[index + 1..]))
+}
+
+/// Maximum length of a hostname passed to the device.
+const MAX_HOSTNAME_LEN: usize = 64;
+
+/// Resolve a hostname of at most 64 bytes to an IPv4 address.
+#[derive(Debug)]
+pub struct ResolveHostname<'a> {
+    hostname: &'a str,
+}
+
+impl<'a> ResolveHostname<'a> {
+    /// Return `None` if the hostname is longer than 64 bytes.
+    pub fn new(hostname: &'a str) -> Option<Self> {
+        if hostname.len() > MAX_HOSTNAME_LEN {
+            return None;
+        }
+        Some(Self { hostname })
+    }
+
+    /// Return whether the hostname can be sent to the device.
+    pub fn is_valid(&self) -> bool {
+        !self.hostname.is_empty() && is_valid_string(self.hostname.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+CIPDOMAIN` command.
+const RESOLVE_HOSTNAME_LEN: usize =
+    "AT+CIPDOMAIN=".len() + quoted_max_len(MAX_HOSTNAME_LEN) + "\r\n".len();
+
+impl AtatCmd<RESOLVE_HOSTNAME_LEN> for ResolveHostname<'_> {
+    type Response = responses::ResolvedAddress;
+    const MAX_TIMEOUT_MS: u32 = 20_000;
+
+    fn as_bytes(&self) -> Vec<u8, RESOLVE_HOSTNAME_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPDOMAIN=")?;
+            write_quoted(buf, self.hostname.as_bytes())?;
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CIPDOMAIN:"93.184.216.34" (NONOS firmwares don't quote)
+        let ip = resp
+            .strip_prefix("+CIPDOMAIN:")
+            .ok_or(atat::Error::InvalidResponse)?
+            .trim()
+            .trim_matches('"');
+        Ok(responses::ResolvedAddress {
+            ip: ip.parse().map_err(|_| atat::Error::Parse)?,
+        })
+    }
+}
+
+/// Establish TCP Connection, UDP Transmission or SSL Connection.
+///
+/// Hostnames must be resolved first, e.g. with
+/// [`ResolveHostname`](struct.ResolveHostname.html). Only IPv4 addresses
+/// are supported.
+#[derive(Debug)]
+pub struct EstablishConnection {
+    mux: types::MultiplexingType,
+    protocol: types::Protocol,
+    remote_addr: SocketAddrV4,
+}
+
+impl EstablishConnection {
+    pub fn tcp(mux: types::MultiplexingType, remote_addr: SocketAddrV4) -> Self {
+        Self {
+            mux,
+            protocol: types::Protocol::Tcp,
+            remote_addr,
+        }
+    }
+
+    pub fn udp(mux: types::MultiplexingType, remote_addr: SocketAddrV4) -> Self {
+        Self {
+            mux,
+            protocol: types::Protocol::Udp,
+            remote_addr,
+        }
+    }
+
+    /// Open a TLS connection, whose encryption is handled by the firmware.
+    pub fn ssl(mux: types::MultiplexingType, remote_addr: SocketAddrV4) -> Self {
+        Self {
+            mux,
+            protocol: types::Protocol::Ssl,
+            remote_addr,
+        }
+    }
+
+    pub(crate) fn mux(&self) -> types::MultiplexingType {
+        self.mux
+    }
+}
+
+/// Length of the longest `AT+CIPSTART` command.
+const ESTABLISH_CONNECTION_LEN: usize = "AT+CIPSTART=4,\"SSL\",\"255.255.255.255\",65535\r\n".len();
+
+impl AtatCmd<ESTABLISH_CONNECTION_LEN> for EstablishConnection {
+    type Response = responses::ConnectResponse;
+    const MAX_TIMEOUT_MS: u32 = 30_000;
+
+    fn as_bytes(&self) -> Vec<u8, ESTABLISH_CONNECTION_LEN> {
+        serialize(|buf| {
+            // Single: AT+CIPSTART=<type>,<remote IP>,<remote port>[,<TCP keep alive>]
+            // Multiple: AT+CIPSTART=<link ID>,<type>,<remote IP>,<remote port>[,<TCP keep alive>]
+            write!(buf, "AT+CIPSTART=")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "{},", id.as_at_str())?;
+            }
+            write!(buf, "\"{}\",", self.protocol.as_at_str())?;
+            let mut num_buf = [0; 5];
+            write!(buf, "\"")?;
+            for (i, octet) in self.remote_addr.ip().octets().iter().enumerate() {
+                write!(buf, "{}", octet.numtoa_str(10, &mut num_buf))?;
+                if i != 3 {
+                    write!(buf, ".")?;
+                }
+            }
+            let port = self.remote_addr.port();
+            write!(buf, "\",{}", port.numtoa_str(10, &mut num_buf))?;
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        match resp? {
+            // The CONNECT line is usually processed as URC
+            b"" | b"CONNECT" => Ok(responses::ConnectResponse::Connected),
+            b"ALREADY CONNECTED" => Ok(responses::ConnectResponse::AlreadyConnected),
+            _ => Err(atat::Error::Parse),
+        }
+    }
+}
+
+/// Set the server name that a TLS client connection announces (SNI).
+///
+/// Applies to connections opened afterwards on the link. Servers hosting
+/// multiple domains, which includes most cloud endpoints, need the name to
+/// select the certificate.
+#[derive(Debug)]
+pub struct ConfigureTlsSni<'a> {
+    mux: types::MultiplexingType,
+    hostname: &'a str,
+}
+
+impl<'a> ConfigureTlsSni<'a> {
+    /// Return `None` if the hostname is longer than 64 bytes.
+    pub fn new(mux: types::MultiplexingType, hostname: &'a str) -> Option<Self> {
+        if hostname.len() > MAX_HOSTNAME_LEN {
+            return None;
+        }
+        Some(Self { mux, hostname })
+    }
+
+    /// Whether the hostname can be sent to the device, i.e. is not empty and
+    /// contains no control characters.
+    pub fn is_valid(&self) -> bool {
+        !self.hostname.is_empty() && is_valid_string(self.hostname.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+CIPSSLCSNI` command.
+const CONFIGURE_TLS_SNI_LEN: usize =
+    "AT+CIPSSLCSNI=4,".len() + quoted_max_len(MAX_HOSTNAME_LEN) + "\r\n".len();
+
+impl AtatCmd<CONFIGURE_TLS_SNI_LEN> for ConfigureTlsSni<'_> {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, CONFIGURE_TLS_SNI_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPSSLCSNI=")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "{},", id.as_at_str())?;
+            }
+            write_quoted(buf, self.hostname.as_bytes())?;
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Maximum number of bytes that can be sent with a single `AT+CIPSEND`.
+pub const MAX_SEND_LEN: usize = 2048;
+
+/// Prepare to send `length` bytes of data.
+///
+/// This message MUST be followed by a `SendData` message. The length must be
+/// in the range 1 to [`MAX_SEND_LEN`](constant.MAX_SEND_LEN.html), the
+/// firmware rejects other lengths.
+#[derive(Debug)]
+pub struct PrepareSendData {
+    mux: types::MultiplexingType,
+    length: u16,
+}
+
+impl PrepareSendData {
+    pub fn new(mux: types::MultiplexingType, length: u16) -> Self {
+        Self { mux, length }
+    }
+}
+
+/// Length of the longest `AT+CIPSEND` command.
+const PREPARE_SEND_DATA_LEN: usize = "AT+CIPSEND=4,65535\r\n".len();
+
+impl AtatCmd<PREPARE_SEND_DATA_LEN> for PrepareSendData {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, PREPARE_SEND_DATA_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPSEND=")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "{},", id.as_at_str())?;
+            }
+            {
+                // Length can only be in the range 0-65535
+                let mut num_buf = [0; 5];
+                write!(buf, "{}\r\n", self.length.numtoa_str(10, &mut num_buf))?;
+            }
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Send data.
+///
+/// This message MUST directly follow by a `PrepareSendData` message.
+///
+/// The type argument `L` must be at least as large as the data length.
+#[derive(Debug)]
+pub struct SendData<'a, const L: usize> {
+    data: &'a [u8],
+}
+
+impl<'a, const L: usize> SendData<'a, L> {
+    pub fn new(data: &'a str) -> Self {
+        Self {
+            data: data.as_bytes(),
+        }
+    }
+
+    /// Send binary data.
+    pub fn from_bytes(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl<'a, const L: usize> AtatCmd<L> for SendData<'a, L> {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 30_000;
+
+    fn as_bytes(&self) -> Vec<u8, L> {
+        serialize(|buf| buf.extend_from_slice(self.data).map_err(|_| fmt::Error))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Prepare to write `length` bytes into the TCP send buffer of the device.
+///
+/// Unlike `AT+CIPSEND`, the firmware accepts further commands while the
+/// data is sent, so the next segment can be written before the previous one
+/// was acknowledged. The outcome of every segment is reported later with a
+/// [`SegmentSent`] or [`SegmentFailed`] URC. Only NONOS firmwares support
+/// this, for TCP connections.
+///
+/// This message MUST be followed by a `SendBufferedData` message. The
+/// length must be in the range 1 to
+/// [`MAX_SEND_LEN`](constant.MAX_SEND_LEN.html).
+///
+/// [`SegmentSent`]: ../urcs/enum.Urc.html#variant.SegmentSent
+/// [`SegmentFailed`]: ../urcs/enum.Urc.html#variant.SegmentFailed
+#[derive(Debug)]
+pub struct PrepareSendBuffer {
+    mux: types::MultiplexingType,
+    length: u16,
+}
+
+impl PrepareSendBuffer {
+    pub fn new(mux: types::MultiplexingType, length: u16) -> Self {
+        Self { mux, length }
+    }
+}
+
+/// Length of the longest `AT+CIPSENDBUF` command.
+const PREPARE_SEND_BUFFER_LEN: usize = "AT+CIPSENDBUF=4,65535\r\n".len();
+
+impl AtatCmd<PREPARE_SEND_BUFFER_LEN> for PrepareSendBuffer {
+    type Response = responses::SendBufferStatus;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, PREPARE_SEND_BUFFER_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPSENDBUF=")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "{},", id.as_at_str())?;
+            }
+            let mut num_buf = [0; 5];
+            write!(buf, "{}\r\n", self.length.numtoa_str(10, &mut num_buf))?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        // Example: 3,1 (the segment ID of the data, and of the last segment
+        // that was sent)
+        parse_send_buffer_status(utf8(resp?)?, self.mux, 2, 1)
+    }
+}
+
+/// Query the state of the TCP send buffer of a connection (NONOS only).
+#[derive(Debug)]
+pub struct GetSendBufferStatus {
+    mux: types::MultiplexingType,
+}
+
+impl GetSendBufferStatus {
+    pub fn new(mux: types::MultiplexingType) -> Self {
+        Self { mux }
+    }
+}
+
+/// Length of the longest `AT+CIPBUFSTATUS` command.
+const GET_SEND_BUFFER_STATUS_LEN: usize = "AT+CIPBUFSTATUS=4\r\n".len();
+
+impl AtatCmd<GET_SEND_BUFFER_STATUS_LEN> for GetSendBufferStatus {
+    type Response = responses::SendBufferStatus;
+
+    fn as_bytes(&self) -> Vec<u8, GET_SEND_BUFFER_STATUS_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPBUFSTATUS")?;
+            if let types::MultiplexingType::Multiplexed(ref id) = self.mux {
+                write!(buf, "={}", id.as_at_str())?;
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        // Example: 4,3,2,5120,0 (the next segment ID, the IDs of the last
+        // segments that were written to the network and acknowledged, the
+        // free buffer space and the number of queued segments)
+        parse_send_buffer_status(utf8(resp?)?, self.mux, 5, 2)
+    }
+}
+
+/// Parse the response to `AT+CIPSENDBUF` or `AT+CIPBUFSTATUS`, a line with
+/// `fields` numbers of which the first is the next segment ID and the one
+/// at `acknowledged` the last segment that was sent.
+///
+/// Outcomes of earlier segments may precede the line, e.g. `2,SEND OK` or
+/// `0,2,SEND OK` with multiplexing. Those of the connection are taken into
+/// account, as the URCs can't be reported anymore.
+fn parse_send_buffer_status(
+    resp: &str,
+    mux: types::MultiplexingType,
+    fields: usize,
+    acknowledged: usize,
+) -> Result<responses::SendBufferStatus, atat::Error> {
+    let mut status = None;
+    let (mut last_sent, mut failed) = (0, false);
+    for line in resp.lines().filter(|line| !line.is_empty()) {
+        if let Some((segment_mux, segment, sent)) = urcs::parse_segment_status(line.as_bytes()) {
+            if segment_mux == mux && sent {
+                last_sent = last_sent.max(segment);
+            } else if segment_mux == mux {
+                failed = true;
+            }
+            continue;
+        }
+        let mut numbers: Vec<u32, 5> = Vec::new();
+        for number in line.split(',') {
+            let number = number.parse().map_err(|_| atat::Error::Parse)?;
+            numbers
+                .push(number)
+                .map_err(|_| atat::Error::InvalidResponse)?;
+        }
+        if numbers.len() != fields {
+            return Err(atat::Error::InvalidResponse);
+        }
+        status = Some((numbers[0], numbers[acknowledged]));
+    }
+    let (segment, sent) = status.ok_or(atat::Error::InvalidResponse)?;
+    Ok(responses::SendBufferStatus {
+        segment,
+        acknowledged: last_sent.max(sent),
+        failed,
+    })
+}
+
+/// Write data into the TCP send buffer of the device.
+///
+/// This message MUST directly follow a `PrepareSendBuffer` message. The
+/// firmware only acknowledges that it received the data, which is skipped,
+/// so the command completes as soon as the data is written.
+///
+/// The type argument `L` must be at least as large as the data length.
+#[derive(Debug)]
+pub struct SendBufferedData<'a, const L: usize> {
+    data: &'a [u8],
+}
+
+impl<'a, const L: usize> SendBufferedData<'a, L> {
+    pub fn from_bytes(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a, const L: usize> AtatCmd<L> for SendBufferedData<'a, L> {
+    type Response = responses::EmptyResponse;
+    const EXPECTS_RESPONSE_CODE: bool = false;
+
+    fn as_bytes(&self) -> Vec<u8, L> {
+        serialize(|buf| buf.extend_from_slice(self.data).map_err(|_| fmt::Error))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Close the TCP/UDP/SSL Connection.
+///
+/// If the connection is not open, the firmware answers with
+/// `link is not valid` and `ERROR`.
+#[derive(Debug)]
+pub struct CloseConnection {
+    /// The connection to close, or `None` to close all connections
+    mux: Option<types::MultiplexingType>,
+}
+
+impl CloseConnection {
+    pub fn new(mux: types::MultiplexingType) -> Self {
+        Self { mux: Some(mux) }
+    }
+
+    /// Close all connections, only available with multiplexing enabled.
+    pub fn all() -> Self {
+        Self { mux: None }
+    }
+
+    /// The connection to close, `None` for all connections.
+    pub(crate) fn mux(&self) -> Option<types::MultiplexingType> {
+        self.mux
+    }
+}
+
+/// Length of the longest `AT+CIPCLOSE` command.
+const CLOSE_CONNECTION_LEN: usize = "AT+CIPCLOSE=5\r\n".len();
+
+impl AtatCmd<CLOSE_CONNECTION_LEN> for CloseConnection {
+    type Response = responses::CloseResponse;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, CLOSE_CONNECTION_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPCLOSE")?;
+            match self.mux {
+                Some(types::MultiplexingType::Multiplexed(ref id)) => {
+                    write!(buf, "={}", id.as_at_str())?
+                }
+                Some(types::MultiplexingType::NonMultiplexed) => {}
+                None => write!(buf, "=5")?,
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        match resp? {
+            // The CLOSED line is usually processed as URC
+            b"" | b"CLOSED" => Ok(responses::CloseResponse::Closed),
+            [_, b',', status @ ..] if status == b"CLOSED" => Ok(responses::CloseResponse::Closed),
+            _ => Err(atat::Error::Parse),
+        }
+    }
+}
+
+/// Maximum number of SNTP servers.
+const MAX_SNTP_SERVERS: usize = 3;
+
+/// Configure the SNTP client.
+///
+/// The time is synchronized with up to three servers of at most 64 bytes, or
+/// with the default servers of the firmware if none is specified. The
+/// timezone is specified as offset from UTC in minutes; NONOS firmwares only
+/// support whole hours.
+#[derive(Debug)]
+pub struct ConfigureSntp<'a> {
+    enabled: bool,
+    utc_offset_min: i16,
+    servers: &'a [&'a str],
+}
+
+impl<'a> ConfigureSntp<'a> {
+    pub fn new(utc_offset_min: i16) -> Self {
+        Self {
+            enabled: true,
+            utc_offset_min,
+            servers: &[],
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::new(0)
+        }
+    }
+
+    /// Synchronize with the specified servers, `None` if there are more
+    /// than three or one is longer than 64 bytes.
+    pub fn with_servers(self, servers: &'a [&'a str]) -> Option<Self> {
+        if servers.len() > MAX_SNTP_SERVERS
+            || servers.iter().any(|server| server.len() > MAX_HOSTNAME_LEN)
+        {
+            return None;
+        }
+        Some(Self { servers, ..self })
+    }
+
+    pub(crate) fn utc_offset_min(&self) -> i16 {
+        self.utc_offset_min
+    }
+
+    /// Whether the configuration can be sent to a firmware of `dialect`.
+    pub fn is_valid(&self, dialect: types::Dialect) -> bool {
+        let offset_valid = match dialect {
+            types::Dialect::NonOs => self.utc_offset_min % 60 == 0,
+            types::Dialect::EspAt => true,
+        };
+        offset_valid
+            && (-12 * 60..=14 * 60).contains(&self.utc_offset_min)
+            && self
+                .servers
+                .iter()
+                .all(|server| is_valid_string(server.as_bytes()))
+    }
+}
+
+/// Length of the longest `AT+CIPSNTPCFG` command, with the offset of any `i16`.
+const CONFIGURE_SNTP_LEN: usize = "AT+CIPSNTPCFG=1,-54608".len()
+    + MAX_SNTP_SERVERS * (",".len() + quoted_max_len(MAX_HOSTNAME_LEN))
+    + "\r\n".len();
+
+impl AtatCmd<CONFIGURE_SNTP_LEN> for ConfigureSntp<'_> {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, CONFIGURE_SNTP_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CIPSNTPCFG={}", u8::from(self.enabled))?;
+            if self.enabled {
+                // Whole hours, otherwise the [+|-]hhmm format of ESP-AT
+                let offset = i32::from(self.utc_offset_min);
+                if offset % 60 == 0 {
+                    write!(buf, ",{}", offset / 60)?;
+                } else {
+                    write!(buf, ",{}", offset / 60 * 100 + offset % 60)?;
+                }
+                for server in self.servers {
+                    buf.write_char(',')?;
+                    write_quoted(buf, server.as_bytes())?;
+                }
+            }
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Query the configuration of the SNTP client.
+#[derive(Debug)]
+pub struct GetSntpConfig;
+
+impl AtatCmd<16> for GetSntpConfig {
+    type Response = responses::SntpConfig;
+
+    fn as_bytes(&self) -> Vec<u8, 16> {
+        serialize(|buf| buf.write_str("AT+CIPSNTPCFG?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CIPSNTPCFG:1,8,"cn.ntp.org.cn","ntp.sjtu.edu.cn"
+        let mut fields = resp
+            .strip_prefix("+CIPSNTPCFG:")
+            .ok_or(atat::Error::InvalidResponse)?
+            .trim()
+            .split(',');
+        let enabled = match fields.next() {
+            Some("0") => false,
+            Some("1") => true,
+            _ => return Err(atat::Error::Parse),
+        };
+        // Hours in the range -12 to 14, otherwise [+|-]hhmm
+        let timezone: i16 = match fields.next() {
+            Some(timezone) => timezone.parse().map_err(|_| atat::Error::Parse)?,
+            None if !enabled => 0,
+            None => return Err(atat::Error::Parse),
+        };
+        let utc_offset_min = if (-12..=14).contains(&timezone) {
+            timezone * 60
+        } else {
+            timezone / 100 * 60 + timezone % 100
+        };
+        Ok(responses::SntpConfig {
+            enabled,
+            utc_offset_min,
+        })
+    }
+}
+
+/// Query the time of the SNTP client, as local time.
+///
+/// The response is interpreted with the specified timezone, which should be
+/// the configured one.
+#[derive(Debug)]
+pub struct GetSntpTime {
+    utc_offset_min: i16,
+}
+
+impl GetSntpTime {
+    pub fn new(utc_offset_min: i16) -> Self {
+        Self { utc_offset_min }
+    }
+}
+
+impl AtatCmd<17> for GetSntpTime {
+    type Response = responses::SntpTime;
+
+    fn as_bytes(&self) -> Vec<u8, 17> {
+        serialize(|buf| buf.write_str("AT+CIPSNTPTIME?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CIPSNTPTIME:Thu Aug 04 14:48:05 2016
+        let line = resp
+            .lines()
+            .find_map(|line| line.strip_prefix("+CIPSNTPTIME:"))
+            .ok_or(atat::Error::InvalidResponse)?;
+        let time =
+            types::DateTime::parse_asctime(line, self.utc_offset_min).ok_or(atat::Error::Parse)?;
+        Ok(responses::SntpTime {
+            // The clock starts at the epoch until it's synchronized
+            time: Some(time).filter(|time| time.year > 1970),
+        })
+    }
+}
+
+/// Query the Unix timestamp of the system clock.
+#[derive(Debug)]
+pub struct GetTimestamp;
+
+impl AtatCmd<18> for GetTimestamp {
+    type Response = responses::Timestamp;
+
+    fn as_bytes(&self) -> Vec<u8, 18> {
+        serialize(|buf| buf.write_str("AT+SYSTIMESTAMP?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +SYSTIMESTAMP:1565853509
+        let seconds = resp
+            .strip_prefix("+SYSTIMESTAMP:")
+            .ok_or(atat::Error::InvalidResponse)?;
+        Ok(responses::Timestamp {
+            seconds: seconds.trim().parse().map_err(|_| atat::Error::Parse)?,
+        })
+    }
+}
+
+/// Set the system clock to a Unix timestamp.
+///
+/// The clock keeps running from the timestamp until it is synchronized with
+/// SNTP.
+#[derive(Debug)]
+pub struct SetTimestamp {
+    seconds: u64,
+}
+
+impl SetTimestamp {
+    pub fn new(seconds: u64) -> Self {
+        Self { seconds }
+    }
+}
+
+/// Length of the longest `AT+SYSTIMESTAMP` command.
+const SET_TIMESTAMP_LEN: usize = "AT+SYSTIMESTAMP=18446744073709551615\r\n".len();
+
+impl AtatCmd<SET_TIMESTAMP_LEN> for SetTimestamp {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_TIMESTAMP_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+SYSTIMESTAMP={}\r\n", self.seconds)?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Query the remaining free heap of the device.
+#[derive(Debug)]
+pub struct GetFreeRam;
+
+impl AtatCmd<12> for GetFreeRam {
+    type Response = responses::FreeRam;
+
+    fn as_bytes(&self) -> Vec<u8, 12> {
+        serialize(|buf| buf.write_str("AT+SYSRAM?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: "+SYSRAM:52128" (newer firmwares append the minimum heap)
+        let value = resp
+            .strip_prefix("+SYSRAM:")
+            .ok_or(atat::Error::InvalidResponse)?;
+        let bytes_raw = value.split(',').next().unwrap_or_default();
+        Ok(responses::FreeRam {
+            bytes: bytes_raw.trim().parse().map_err(|_| atat::Error::Parse)?,
+        })
+    }
+}
+
+/// Read the value of the ADC (TOUT pin).
+#[derive(Debug)]
+pub struct ReadAdc;
+
+impl AtatCmd<12> for ReadAdc {
+    type Response = responses::AdcValue;
+
+    fn as_bytes(&self) -> Vec<u8, 12> {
+        serialize(|buf| buf.write_str("AT+SYSADC?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: "+SYSADC:1024"
+        let value = resp
+            .strip_prefix("+SYSADC:")
+            .ok_or(atat::Error::InvalidResponse)?;
+        Ok(responses::AdcValue {
+            raw: value.trim().parse().map_err(|_| atat::Error::Parse)?,
+        })
+    }
+}
+
+/// Query the list of commands supported by the firmware.
+///
+/// Only available on ESP-AT firmwares. The response lists up to 300
+/// commands, so the ingress buffer and the response queue must hold
+/// [`INGRESS_BUF_LEN`](../../constant.INGRESS_BUF_LEN.html) bytes.
+#[derive(Debug)]
+pub struct GetCapabilities;
+
+impl AtatCmd<10> for GetCapabilities {
+    type Response = types::Capabilities;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, 10> {
+        serialize(|buf| buf.write_str("AT+CMD?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: +CMD:0,"AT",0,0,0,1
+        let mut capabilities = types::Capabilities::default();
+        for line in resp.lines() {
+            if !line.starts_with("+CMD:") {
+                continue;
+            }
+            let name = line.split('"').nth(1).ok_or(atat::Error::Parse)?;
+            capabilities.add_command(name);
+        }
+        Ok(capabilities)
+    }
+}
+
+/// Set whether configuration changes are persisted to flash.
+///
+/// Only available on ESP-AT firmwares, which persist changes by default.
+#[derive(Debug)]
+pub struct SetParameterStore {
+    enabled: bool,
+}
+
+impl SetParameterStore {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+/// Length of the longest `AT+SYSSTORE` command.
+const SET_PARAMETER_STORE_LEN: usize = "AT+SYSSTORE=1\r\n".len();
+
+impl AtatCmd<SET_PARAMETER_STORE_LEN> for SetParameterStore {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_PARAMETER_STORE_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+SYSSTORE={}\r\n", u8::from(self.enabled))?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Set whether extended error codes are printed before `ERROR`.
+///
+/// Only available on ESP-AT firmwares.
+#[derive(Debug)]
+pub struct SetSystemLog {
+    enabled: bool,
+}
+
+impl SetSystemLog {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+/// Length of the longest `AT+SYSLOG` command.
+const SET_SYSTEM_LOG_LEN: usize = "AT+SYSLOG=1\r\n".len();
+
+impl AtatCmd<SET_SYSTEM_LOG_LEN> for SetSystemLog {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_SYSTEM_LOG_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+SYSLOG={}\r\n", u8::from(self.enabled))?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Query whether configuration changes are persisted to flash.
+///
+/// Only available on ESP-AT firmwares.
+#[derive(Debug)]
+pub struct GetParameterStore;
+
+impl AtatCmd<14> for GetParameterStore {
+    type Response = responses::ParameterStore;
+
+    fn as_bytes(&self) -> Vec<u8, 14> {
+        serialize(|buf| buf.write_str("AT+SYSSTORE?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: "+SYSSTORE:1"
+        match resp.strip_prefix("+SYSSTORE:").map(str::trim) {
+            Some("0") => Ok(responses::ParameterStore { enabled: false }),
+            Some("1") => Ok(responses::ParameterStore { enabled: true }),
+            Some(_) => Err(atat::Error::Parse),
+            None => Err(atat::Error::InvalidResponse),
+        }
+    }
+}
+
+/// Configure which optional system messages are printed.
+///
+/// Only available on ESP-AT firmwares.
+#[derive(Debug)]
+pub struct SetSystemMessages {
+    messages: types::SystemMessages,
+}
+
+impl SetSystemMessages {
+    pub fn new(messages: types::SystemMessages) -> Self {
+        Self { messages }
+    }
+}
+
+/// Length of the longest `AT+SYSMSG` command.
+const SET_SYSTEM_MESSAGES_LEN: usize = "AT+SYSMSG=7\r\n".len();
+
+impl AtatCmd<SET_SYSTEM_MESSAGES_LEN> for SetSystemMessages {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_SYSTEM_MESSAGES_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+SYSMSG={}\r\n", self.messages.to_at_value())?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Query which optional system messages are printed.
+///
+/// Only available on ESP-AT firmwares.
+#[derive(Debug)]
+pub struct GetSystemMessages;
+
+impl AtatCmd<12> for GetSystemMessages {
+    type Response = types::SystemMessages;
+
+    fn as_bytes(&self) -> Vec<u8, 12> {
+        serialize(|buf| buf.write_str("AT+SYSMSG?\r\n"))
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: "+SYSMSG:0"
+        let value = resp
+            .strip_prefix("+SYSMSG:")
+            .ok_or(atat::Error::InvalidResponse)?;
+        Ok(types::SystemMessages::from_at_value(
+            value.trim().parse().map_err(|_| atat::Error::Parse)?,
+        ))
+    }
+}
+
+/// Set the 802.11 PHY protocols of an interface.
+///
+/// Only available on ESP-AT firmwares.
+#[derive(Debug)]
+pub struct SetPhyProtocols {
+    interface: types::WifiInterface,
+    protocols: types::PhyProtocols,
+}
+
+impl SetPhyProtocols {
+    pub fn new(interface: types::WifiInterface, protocols: types::PhyProtocols) -> Self {
+        Self {
+            interface,
+            protocols,
+        }
+    }
+}
+
+/// Length of the longest `AT+CWSTAPROTO` command.
+const SET_PHY_PROTOCOLS_LEN: usize = "AT+CWSTAPROTO=15\r\n".len();
+
+impl AtatCmd<SET_PHY_PROTOCOLS_LEN> for SetPhyProtocols {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, SET_PHY_PROTOCOLS_LEN> {
+        serialize(|buf| {
+            write!(
+                buf,
+                "AT+CW{}PROTO={}\r\n",
+                self.interface.as_at_str(),
+                self.protocols.to_at_value()
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Query the 802.11 PHY protocols of an interface.
+///
+/// Only available on ESP-AT firmwares.
+#[derive(Debug)]
+pub struct GetPhyProtocols {
+    interface: types::WifiInterface,
+}
+
+impl GetPhyProtocols {
+    pub fn new(interface: types::WifiInterface) -> Self {
+        Self { interface }
+    }
+}
+
+/// Length of the longest `AT+CWSTAPROTO?` command.
+const GET_PHY_PROTOCOLS_LEN: usize = "AT+CWSTAPROTO?\r\n".len();
+
+impl AtatCmd<GET_PHY_PROTOCOLS_LEN> for GetPhyProtocols {
+    type Response = types::PhyProtocols;
+
+    fn as_bytes(&self) -> Vec<u8, GET_PHY_PROTOCOLS_LEN> {
+        serialize(|buf| {
+            write!(buf, "AT+CW{}PROTO?\r\n", self.interface.as_at_str())?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        let resp = utf8(resp?)?;
+        // Example: "+CWSTAPROTO:7"
+        let value = resp
+            .strip_prefix("+CW")
+            .and_then(|rest| rest.strip_prefix(self.interface.as_at_str()))
+            .and_then(|rest| rest.strip_prefix("PROTO:"))
+            .ok_or(atat::Error::InvalidResponse)?;
+        Ok(types::PhyProtocols::from_at_value(
+            value.trim().parse().map_err(|_| atat::Error::Parse)?,
+        ))
+    }
+}
+
+/// Strip the quotes around a string field.
+fn unquote(field: &str) -> Result<&str, atat::Error> {
+    field
+        .strip_prefix('"')
+        .and_then(|field| field.strip_suffix('"'))
+        .ok_or(atat::Error::Parse)
+}
+
+/// Wrap a command to override its timeout.
+///
+/// The timeouts of the commands are conservative defaults. The time some
+/// commands take depends on the network conditions and the firmware version,
+/// so it can be adjusted with this wrapper, e.g.
+/// `WithTimeout::<_, 5_000>::new(GetConnectionStatus)`.
+#[derive(Debug)]
+pub struct WithTimeout<C, const TIMEOUT_MS: u32> {
+    command: C,
+}
+
+impl<C, const TIMEOUT_MS: u32> WithTimeout<C, TIMEOUT_MS> {
+    pub fn new(command: C) -> Self {
+        Self { command }
+    }
+}
+
+impl<C, const LEN: usize, const TIMEOUT_MS: u32> AtatCmd<LEN> for WithTimeout<C, TIMEOUT_MS>
+where
+    C: AtatCmd<LEN>,
+{
+    type Response = C::Response;
+    const MAX_TIMEOUT_MS: u32 = TIMEOUT_MS;
+    const CAN_ABORT: bool = C::CAN_ABORT;
+    const EXPECTS_RESPONSE_CODE: bool = C::EXPECTS_RESPONSE_CODE;
+
+    fn as_bytes(&self) -> Vec<u8, LEN> {
+        self.command.as_bytes()
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, Error> {
+        self.command.parse(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_commands_serialize() {
+        // Commas are escaped, so they double the length of quoted strings
+        let commas = [b','; 64];
+        let ssid = types::Ssid::new(&commas[..32]).unwrap();
+        let psk = core::str::from_utf8(&commas).unwrap();
+
+        let config = types::SoftApConfig::wpa2(ssid.clone(), psk, 255)
+            .unwrap()
+            .with_max_connections(255)
+            .hidden();
+        let cmd =
+            SetSoftApConfig::to(config, types::Target::Current).with_dialect(types::Dialect::NonOs);
+        assert_eq!(cmd.as_bytes().len(), SET_SOFT_AP_CONFIG_LEN);
+
+        // The options are only sent to ESP-AT firmwares, which have no suffix
+        let options = types::JoinOptions::new()
+            .with_reconnect_interval(u16::MAX)
+            .with_listen_interval(u8::MAX);
+        let cmd = JoinAccessPoint::new(ssid, psk, types::Target::Current)
+            .unwrap()
+            .with_bssid(types::MacAddress([0xff; 6]))
+            .with_options(options)
+            .with_dialect(types::Dialect::EspAt);
+        assert!(cmd.as_bytes().ends_with(b"\r\n"));
+
+        let servers = [psk, psk, psk];
+        let cmd = ConfigureSntp::new(i16::MIN).with_servers(&servers).unwrap();
+        assert_eq!(cmd.as_bytes().len(), CONFIGURE_SNTP_LEN);
+
+        let mux = types::MultiplexingType::Multiplexed(types::ConnectionId::Four);
+        let cmd = ConfigureTlsSni::new(mux, psk).unwrap();
+        assert_eq!(cmd.as_bytes().len(), CONFIGURE_TLS_SNI_LEN);
+
+        let config = types::UartConfig {
+            baud_rate: u32::MAX,
+            ..Default::default()
+        };
+        let cmd = SetUartConfig::to(config, types::Target::Current);
+        assert!(cmd.as_bytes().ends_with(b"\r\n"));
+
+        let cmd = SetTimestamp::new(u64::MAX);
+        assert_eq!(cmd.as_bytes().len(), SET_TIMESTAMP_LEN);
+    }
+
+    #[test]
+    fn test_get_links_parse() {
+        let cmd = GetLinks::new(types::Dialect::EspAt);
+        let resp = cmd
+            .parse(Ok(
+                b"STATUS:3\r\n+CIPSTATUS:0,\"TCP\",\"192.168.1.2\",80,49152,0\r\n\
+                +CIPSTATUS:1,\"UDP\",\"192.168.1.3\",53,1024,1",
+            ))
+            .unwrap();
+        assert_eq!(resp.links.len(), 2);
+        assert_eq!(resp.links[0].protocol, types::Protocol::Tcp);
+        assert_eq!(resp.links[0].remote, "192.168.1.2:80".parse().unwrap());
+        assert_eq!(resp.links[0].local_port, 49152);
+        assert!(resp.links[1].incoming);
+
+        // No open connections
+        assert!(cmd.parse(Ok(b"STATUS:3")).unwrap().links.is_empty());
+        assert!(cmd.parse(Ok(b"")).unwrap().links.is_empty());
+
+        // Surplus, missing and unquoted fields, and invalid link IDs
+        assert!(cmd
+            .parse(Ok(
+                b"+CIPSTATE:0,\"TCP\",\"192.168.1.2\",80,49152,0,1,2,3,4,5"
+            ))
+            .is_err());
+        assert!(cmd
+            .parse(Ok(b"+CIPSTATE:0,\"TCP\",\"192.168.1.2\",80"))
+            .is_err());
+        assert!(cmd
+            .parse(Ok(b"+CIPSTATE:0,TCP,192.168.1.2,80,49152,0"))
+            .is_err());
+        assert!(cmd
+            .parse(Ok(b"+CIPSTATE:9,\"TCP\",\"192.168.1.2\",80,49152,0"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_sleep_mode_parse() {
+        use types::{Dialect, SleepMode};
+
+        // The firmwares number the light and modem sleep modes differently
+        let non_os = GetSleepMode::new(Dialect::NonOs);
+        assert_eq!(non_os.parse(Ok(b"+SLEEP:1")).unwrap(), SleepMode::Light);
+        assert_eq!(non_os.parse(Ok(b"+SLEEP:2")).unwrap(), SleepMode::Modem);
+        let esp_at = GetSleepMode::new(Dialect::EspAt);
+        assert_eq!(esp_at.parse(Ok(b"+SLEEP:1")).unwrap(), SleepMode::Modem);
+        assert_eq!(esp_at.parse(Ok(b"+SLEEP:2")).unwrap(), SleepMode::Light);
+        assert_eq!(
+            esp_at.parse(Ok(b"+SLEEP:3\r\n")).unwrap(),
+            SleepMode::ModemListenInterval
+        );
+
+        // Modes unknown to the firmware and malformed responses
+        assert!(non_os.parse(Ok(b"+SLEEP:3")).is_err());
+        assert!(esp_at.parse(Ok(b"+SLEEP:10")).is_err());
+        assert!(esp_at.parse(Ok(b"+SLEEP:")).is_err());
+        assert!(esp_at.parse(Ok(b"SLEEP:1")).is_err());
+    }
+
+    #[test]
+    fn test_get_configured_ssid_parse() {
+        let config = GetConfiguredSsid
+            .parse(Ok(b"+CWSTATE:0,\"my,wifi\"\r\n"))
+            .unwrap();
+        assert_eq!(config.ssid.unwrap().as_str(), Some("my,wifi"));
+
+        // An empty SSID stands for no configured access point
+        let config = GetConfiguredSsid.parse(Ok(b"+CWSTATE:0,\"\"")).unwrap();
+        assert!(config.ssid.is_none());
+
+        // Overlong, missing and unquoted SSIDs
+        assert!(GetConfiguredSsid
+            .parse(Ok(b"+CWSTATE:0,\"0123456789012345678901234567890123\""))
+            .is_err());
+        assert!(GetConfiguredSsid.parse(Ok(b"+CWSTATE:0")).is_err());
+        assert!(GetConfiguredSsid.parse(Ok(b"+CWSTATE:0,mywifi")).is_err());
+        assert!(GetConfiguredSsid.parse(Ok(b"+CWJAP:\"mywifi\"")).is_err());
+    }
+
+    #[test]
+    fn test_receive_data_into() {
+        let mux = types::MultiplexingType::NonMultiplexed;
+        let mut buf = [0; 8];
+        let cmd = ReceiveDataInto::new(mux, &mut buf);
+        assert_eq!(&cmd.as_bytes()[..], b"AT+CIPRECVDATA=8\r\n");
+        let received = cmd
+            .parse(Ok(b"+CIPRECVDATA:7,\"192.168.1.1\",80,a,\"b\"\r\n"))
+            .unwrap();
+        assert_eq!(received.len, 7);
+        assert_eq!(received.remote, Some("192.168.1.1:80".parse().unwrap()));
+        assert_eq!(&cmd.buf.borrow()[..7], b"a,\"b\"\r\n");
+
+        // NONOS firmwares separate the data with a colon
+        assert_eq!(cmd.parse(Ok(b"+CIPRECVDATA,5:hello")).unwrap().len, 5);
+        assert_eq!(cmd.parse(Ok(b"+CIPRECVDATA:0,")).unwrap().len, 0);
+
+        // Longer than the buffer, or the length doesn't match the data
+        assert!(matches!(
+            cmd.parse(Ok(b"+CIPRECVDATA:9,123456789")),
+            Err(atat::Error::Overflow)
+        ));
+        assert!(cmd.parse(Ok(b"+CIPRECVDATA:5,hell")).is_err());
+        assert!(cmd.parse(Ok(b"+CIPRECVDATA:\xff,hello")).is_err());
+        assert!(cmd.parse(Ok(b"+CIPRECVDATA:5")).is_err());
+        assert!(cmd.parse(Ok(b"+IPD,5:hello")).is_err());
+
+        // The firmware rejects reads of more than 2048 bytes
+        let mut buf = [0; 4096];
+        let cmd = ReceiveDataInto::new(mux, &mut buf);
+        assert_eq!(&cmd.as_bytes()[..], b"AT+CIPRECVDATA=2048\r\n");
+        let cmd = ReceiveData::<4096>::new(mux);
+        assert_eq!(&cmd.as_bytes()[..], b"AT+CIPRECVDATA=2048\r\n");
+    }
+
+    #[test]
+    fn test_prepare_send_buffer_parse() {
+        let cmd = PrepareSendBuffer::new(types::MultiplexingType::NonMultiplexed, 5);
+        let status = cmd.parse(Ok(b"4,2\r\n")).unwrap();
+        assert_eq!(status.segment, 4);
+        assert_eq!(status.acknowledged, 2);
+        assert!(!status.failed);
+
+        // Outcomes of earlier segments that arrived before the response
+        let status = cmd.parse(Ok(b"3,SEND OK\r\n\r\n4,2")).unwrap();
+        assert_eq!(status.acknowledged, 3);
+        assert!(!status.failed);
+        let status = cmd.parse(Ok(b"3,SEND FAIL\r\n\r\n4,2")).unwrap();
+        assert_eq!(status.acknowledged, 2);
+        assert!(status.failed);
+
+        // Missing, surplus and non-numeric fields
+        assert!(cmd.parse(Ok(b"1,SEND FAIL")).is_err());
+        assert!(cmd.parse(Ok(b"4")).is_err());
+        assert!(cmd.parse(Ok(b"4,2,1")).is_err());
+        assert!(cmd.parse(Ok(b"4,x")).is_err());
+        assert!(cmd.parse(Ok(b"")).is_err());
+    }
+
+    #[test]
+    fn test_get_send_buffer_status_parse() {
+        let mux = types::MultiplexingType::Multiplexed(types::ConnectionId::Zero);
+        let cmd = GetSendBufferStatus::new(mux);
+        let status = cmd.parse(Ok(b"4,3,2,5120,0")).unwrap();
+        assert_eq!(status.segment, 4);
+        assert_eq!(status.acknowledged, 2);
+
+        // Only outcomes of the queried connection count
+        let status = cmd.parse(Ok(b"0,3,SEND OK\r\n\r\n4,3,2,5120,0")).unwrap();
+        assert_eq!(status.acknowledged, 3);
+        let status = cmd.parse(Ok(b"1,3,SEND OK\r\n\r\n4,3,2,5120,0")).unwrap();
+        assert_eq!(status.acknowledged, 2);
+
+        // Missing and surplus fields
+        assert!(cmd.parse(Ok(b"4,3,2")).is_err());
+        assert!(cmd.parse(Ok(b"4,3,2,5120,0,1")).is_err());
+    }
+
+    #[test]
+    fn test_get_sntp_time_parse() {
+        let cmd = GetSntpTime::new(120);
+        let time = cmd
+            .parse(Ok(b"+CIPSNTPTIME:Mon Dec  4 09:05:00 2023\r\n"))
+            .unwrap()
+            .time
+            .unwrap();
+        let mut formatted: String<32> = String::new();
+        write!(formatted, "{}", time).unwrap();
+        assert_eq!(formatted, "2023-12-04T09:05:00+02:00");
+        assert_eq!(time.unix_timestamp(), 1_701_673_500);
+
+        // The clock starts at the epoch until it's synchronized
+        let resp = cmd
+            .parse(Ok(b"+CIPSNTPTIME:Thu Jan 01 00:00:00 1970"))
+            .unwrap();
+        assert_eq!(resp.time, None);
+
+        assert!(cmd
+            .parse(Ok(b"+CIPSNTPTIME:Thu Foo 04 14:48:05 2016"))
+            .is_err());
+        assert!(cmd.parse(Ok(b"+CIPSNTPTIME:Thu Aug 04 14:48")).is_err());
+    }
+
+    #[test]
+    fn test_list_access_points_parse() {
+        // SSIDs are neither escaped nor necessarily UTF-8
+        let resp = ListAccessPoints
+            .parse(Ok(
+                b"+CWLAP:(3,\"MyNetwork\",-67,\"aa:bb:cc:dd:ee:ff\",6,-11,0)\r\n\
+                +CWLAP:(4,\"my\",\"caf\xe9\",-90,\"11:22:33:44:55:66\",11)\r\n",
+            ))
+            .unwrap();
+        let access_points = &resp.access_points;
+        assert_eq!(access_points.len(), 2);
+        assert_eq!(access_points[0].ssid.as_str(), Some("MyNetwork"));
+        assert_eq!((access_points[0].rssi, access_points[0].channel), (-67, 6));
+        assert_eq!(access_points[1].ssid.as_bytes(), b"my\",\"caf\xe9");
+        assert_eq!(
+            access_points[1].bssid,
+            types::MacAddress([0x11, 0x22, 0x33, 0x44, 0x55, 0x66])
+        );
+
+        // A truncated line fails the scan instead of being skipped
+        assert!(ListAccessPoints
+            .parse(Ok(b"+CWLAP:(3,\"MyNetwork\",-67,\"aa:bb:cc"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_get_joined_access_point_parse() {
+        let status = GetJoinedAccessPoint::new(types::Dialect::EspAt)
+            .parse(Ok(
+                b"+CWJAP:\"my\",\"net\xff\",\"aa:bb:cc:dd:ee:ff\",6,-58,0,1,3,0,0",
+            ))
+            .unwrap();
+        let access_point = status.access_point.unwrap();
+        assert_eq!(access_point.ssid.as_bytes(), b"my\",\"net\xff");
+        assert_eq!((access_point.channel, access_point.rssi), (6, -58));
+
+        let status = GetJoinedAccessPoint::new(types::Dialect::NonOs)
+            .parse(Ok(b"No AP\r\n"))
+            .unwrap();
+        assert!(status.access_point.is_none());
+    }
+
+    #[test]
+    fn test_get_firmware_version_parse() {
+        // ESP-AT firmwares add the commit and the binary version, module
+        // vendors add lines with their name
+        let version = GetFirmwareVersion
+            .parse(Ok(
+                b"AT version:2.2.0.0(c6fa6bf - ESP32 - Jul  2 2021 06:44:05)\r\n\
+                SDK version:v4.2.2-76-gefa6eca\r\n\
+                compile time(3a696ba):Jul  2 2021 11:54:43\r\n\
+                Bin version:2.2.0(WROOM-32)\r\n\
+                AI-THINKER Technology Co. Ltd.\r\n",
+            ))
+            .unwrap();
+        assert_eq!(version.sdk_version, "v4.2.2-76-gefa6eca");
+        assert_eq!(
+            version.compile_time.as_deref(),
+            Some("Jul  2 2021 11:54:43")
+        );
+        assert_eq!(version.commit.as_deref(), Some("3a696ba"));
+        assert_eq!(version.bin_version.as_deref(), Some("2.2.0(WROOM-32)"));
+
+        // The AT and SDK versions are required
+        assert!(GetFirmwareVersion
+            .parse(Ok(b"AT version:1.2.0.0\r\n"))
+            .is_err());
     }
 }
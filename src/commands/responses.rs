@@ -1,13 +1,18 @@
 //! Responses from the ESP8266 device.
 
+use core::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr},
+};
+
 use atat::{AtatResp, Error, InternalError};
-use heapless::String;
-use no_std_net::Ipv4Addr;
+use heapless::{String, Vec};
 
-use crate::types;
+use crate::{commands::urcs, types};
 
 /// An empty response, no body.
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct EmptyResponse;
 
 impl AtatResp for EmptyResponse {}
@@ -24,25 +29,88 @@ impl EmptyResponse {
 }
 
 /// Firmware version.
-#[derive(Debug)]
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct FirmwareVersion {
-    pub at_version: String<32>,
+    pub at_version: String<64>,
     pub sdk_version: String<32>,
-    pub compile_time: String<32>,
+    pub compile_time: Option<String<32>>,
+    /// Commit hash of the AT firmware (ESP-AT only)
+    pub commit: Option<String<16>>,
+    /// Version of the firmware binary for the module type (ESP-AT only)
+    pub bin_version: Option<String<32>>,
 }
 
 impl AtatResp for FirmwareVersion {}
 
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AT {}, SDK {}", self.at_version, self.sdk_version)?;
+        if let Some(ref compile_time) = self.compile_time {
+            write!(f, ", compiled {}", compile_time)?;
+        }
+        Ok(())
+    }
+}
+
 /// Generic string response.
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct StringResponse<const L: usize>(pub(crate) String<L>);
 
 impl<const L: usize> AtatResp for StringResponse<L> {}
 
+impl<const L: usize> StringResponse<L> {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<const L: usize> fmt::Display for StringResponse<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 impl AtatResp for types::WifiMode {}
 
+impl AtatResp for types::Capabilities {}
+
+impl AtatResp for types::UartConfig {}
+
+impl AtatResp for types::SystemMessages {}
+
+impl AtatResp for types::ReconnectConfig {}
+
+impl AtatResp for types::PhyProtocols {}
+
+/// Maximum number of access points returned by a scan.
+pub const MAX_ACCESS_POINTS: usize = 16;
+
+/// An access point found by a scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccessPoint {
+    pub ssid: types::Ssid,
+    pub encryption: types::Encryption,
+    /// Signal strength in dBm
+    pub rssi: i8,
+    pub bssid: types::MacAddress,
+    pub channel: u8,
+}
+
+/// Access points found by a scan.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AccessPoints {
+    pub access_points: Vec<AccessPoint, MAX_ACCESS_POINTS>,
+}
+
+impl AtatResp for AccessPoints {}
+
 /// AP join result.
 #[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct JoinResponse {
     pub connected: bool,
     pub got_ip: bool,
@@ -50,17 +118,218 @@ pub struct JoinResponse {
 
 impl AtatResp for JoinResponse {}
 
+/// The access point the station is connected to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct JoinedAccessPoint {
+    pub ssid: types::Ssid,
+    pub bssid: types::MacAddress,
+    pub channel: u8,
+    /// Signal strength in dBm
+    pub rssi: i8,
+}
+
+/// Station status, as returned by `AT+CWJAP?`.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StationStatus {
+    /// The joined access point, `None` if the station is not connected
+    pub access_point: Option<JoinedAccessPoint>,
+}
+
+impl AtatResp for StationStatus {}
+
 impl AtatResp for types::ConnectionStatus {}
 
+/// The station configuration, as returned by `AT+CWSTATE?`.
 #[derive(Debug)]
-pub struct LocalAddress {
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StationConfig {
+    /// The SSID the station is configured to join, `None` if no access
+    /// point is configured
+    pub ssid: Option<types::Ssid>,
+}
+
+impl AtatResp for StationConfig {}
+
+impl AtatResp for types::SleepMode {}
+
+impl<const N: usize> AtatResp for urcs::NetworkData<N> {}
+
+/// The addresses of a network interface.
+#[derive(Debug)]
+pub struct InterfaceAddress {
+    /// The IP address, `None` if none was assigned yet
     pub ip: Option<Ipv4Addr>,
     pub mac: String<17>,
 }
 
+impl fmt::Display for InterfaceAddress {
+    /// Format as `<ip> (<mac>)`, or `no IP (<mac>)` without an address.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip {
+            Some(ip) => write!(f, "{} ({})", ip, self.mac),
+            None => write!(f, "no IP ({})", self.mac),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for InterfaceAddress {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "InterfaceAddress {{ ip: {}, mac: {} }}",
+            self.ip.map(|ip| ip.octets()),
+            self.mac
+        )
+    }
+}
+
+/// The addresses of the station and access point interfaces.
+///
+/// Which interfaces are present depends on the WiFi mode.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LocalAddress {
+    pub station: Option<InterfaceAddress>,
+    pub ap: Option<InterfaceAddress>,
+}
+
 impl AtatResp for LocalAddress {}
 
+impl fmt::Display for LocalAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.station, &self.ap) {
+            (Some(station), Some(ap)) => write!(f, "station {}, AP {}", station, ap),
+            (Some(station), None) => write!(f, "station {}", station),
+            (None, Some(ap)) => write!(f, "AP {}", ap),
+            (None, None) => f.write_str("no interfaces"),
+        }
+    }
+}
+
+/// The details of an open connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkInfo {
+    /// The connection, always multiplexed as the firmware reports link ID 0
+    /// in single connection mode as well
+    pub mux: types::MultiplexingType,
+    pub protocol: types::Protocol,
+    /// Whether the connection was accepted by the server of the device
+    pub incoming: bool,
+    pub remote: SocketAddr,
+    pub local_port: u16,
+}
+
+impl LinkInfo {
+    /// Build the info from the raw fields of the messages describing a
+    /// connection.
+    pub(crate) fn from_fields(
+        link_id: &str,
+        protocol: &str,
+        tetype: &str,
+        remote_ip: &str,
+        remote_port: &str,
+        local_port: &str,
+    ) -> Option<Self> {
+        fn unquote(field: &str) -> Option<&str> {
+            field.strip_prefix('"')?.strip_suffix('"')
+        }
+        let link_id = types::ConnectionId::from_index(link_id.parse().ok()?)?;
+        Some(Self {
+            mux: types::MultiplexingType::Multiplexed(link_id),
+            protocol: types::Protocol::from_at_str(unquote(protocol)?)?,
+            incoming: match tetype {
+                "0" => false,
+                "1" => true,
+                _ => return None,
+            },
+            remote: SocketAddr::new(unquote(remote_ip)?.parse().ok()?, remote_port.parse().ok()?),
+            local_port: local_port.parse().ok()?,
+        })
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for LinkInfo {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "LinkInfo {{ mux: {}, protocol: {}, incoming: {}, remote: {}, local_port: {} }}",
+            self.mux,
+            self.protocol,
+            self.incoming,
+            defmt::Debug2Format(&self.remote),
+            self.local_port
+        )
+    }
+}
+
+/// The open connections.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Links {
+    pub links: Vec<LinkInfo, { types::MAX_CONNECTIONS }>,
+}
+
+impl AtatResp for Links {}
+
+/// The address a hostname resolved to.
+#[derive(Debug)]
+pub struct ResolvedAddress {
+    pub ip: Ipv4Addr,
+}
+
+impl AtatResp for ResolvedAddress {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ResolvedAddress {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "ResolvedAddress {{ ip: {} }}", self.ip.octets())
+    }
+}
+
+/// The state of the TCP send buffer, as returned by `AT+CIPSENDBUF` and
+/// `AT+CIPBUFSTATUS`.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SendBufferStatus {
+    /// The ID of the next segment written to the buffer
+    pub segment: u32,
+    /// The ID of the last segment that was sent
+    pub acknowledged: u32,
+    /// Whether sending an earlier segment failed
+    pub failed: bool,
+}
+
+impl AtatResp for SendBufferStatus {}
+
+/// Data read into a buffer, in passive receive mode.
+#[derive(Debug)]
+pub struct ReceivedData {
+    /// Number of bytes written to the buffer
+    pub len: usize,
+    /// The sender, if reported by the firmware (see `AT+CIPDINFO`)
+    pub remote: Option<SocketAddr>,
+}
+
+impl AtatResp for ReceivedData {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ReceivedData {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "ReceivedData {{ len: {}, remote: {} }}",
+            self.len,
+            defmt::Debug2Format(&self.remote)
+        )
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ConnectResponse {
     /// The connection was opened
     Connected,
@@ -69,3 +338,100 @@ pub enum ConnectResponse {
 }
 
 impl AtatResp for ConnectResponse {}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CloseResponse {
+    /// The connection was closed
+    Closed,
+    /// The connection was not open
+    NotOpen,
+}
+
+impl AtatResp for CloseResponse {}
+
+/// IP configuration of an interface.
+///
+/// All addresses are `0.0.0.0` if no IP address was assigned yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpConfig {
+    pub ip: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+}
+
+impl AtatResp for IpConfig {}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for IpConfig {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "IpConfig {{ ip: {}, gateway: {}, netmask: {} }}",
+            self.ip.octets(),
+            self.gateway.octets(),
+            self.netmask.octets()
+        )
+    }
+}
+
+/// Remaining free heap of the device.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FreeRam {
+    /// Free heap in bytes
+    pub bytes: u32,
+}
+
+impl AtatResp for FreeRam {}
+
+/// Whether configuration changes are persisted, as returned by
+/// `AT+SYSSTORE?`.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ParameterStore {
+    pub enabled: bool,
+}
+
+impl AtatResp for ParameterStore {}
+
+/// The configuration of the SNTP client.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SntpConfig {
+    pub enabled: bool,
+    /// Offset of the local time from UTC in minutes
+    pub utc_offset_min: i16,
+}
+
+impl AtatResp for SntpConfig {}
+
+/// The time reported by the SNTP client, as local time.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SntpTime {
+    /// `None` if the time was not synchronized yet
+    pub time: Option<types::DateTime>,
+}
+
+impl AtatResp for SntpTime {}
+
+/// Unix timestamp of the system clock.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp {
+    /// Seconds since 1970-01-01 00:00:00 UTC
+    pub seconds: u64,
+}
+
+impl AtatResp for Timestamp {}
+
+/// Raw value of the ADC.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AdcValue {
+    /// ADC value in the range 0-1024 (1 mV per unit, 0-1 V input range)
+    pub raw: u16,
+}
+
+impl AtatResp for AdcValue {}
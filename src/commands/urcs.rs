@@ -0,0 +1,492 @@
+//! Unsolicited result codes (URCs) sent by the ESP8266 device.
+
+use core::net::{IpAddr, SocketAddr};
+
+use atat::{digest::ParseError, AtatUrc};
+use heapless::Vec;
+
+#[cfg(feature = "ble")]
+use crate::commands::ble;
+#[cfg(feature = "mqtt")]
+use crate::commands::mqtt;
+#[cfg(feature = "websocket")]
+use crate::commands::websocket;
+use crate::{
+    commands::responses,
+    error::EspError,
+    types::{ConnectionId, MultiplexingType, UpdateStep},
+};
+
+/// Default capacity of the data received in a single `+IPD` message, the
+/// largest segment size of the firmware.
+pub const MAX_DATA_LEN: usize = 1460;
+
+/// Data received over a TCP, UDP or SSL connection.
+///
+/// The payload holds up to `N` bytes, data of larger `+IPD` messages is
+/// dropped. The default of 1460 bytes fits the largest segments sent by the
+/// firmware.
+///
+/// The payload is copied out of the URC queue, as atat only hands out parsed
+/// URCs that can't borrow from the queue. To avoid the copy, use passive
+/// receive mode and read the data into a buffer of the application with
+/// [`EspClient::receive_data_into`](../../struct.EspClient.html#method.receive_data_into).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkData<const N: usize = MAX_DATA_LEN> {
+    /// The connection that the data was received on
+    pub mux: MultiplexingType,
+    /// The remote address, if showing it is enabled (`AT+CIPDINFO=1`)
+    pub remote: Option<SocketAddr>,
+    pub data: Vec<u8, N>,
+}
+
+impl<const N: usize> NetworkData<N> {
+    /// Parse a `+IPD,[<link_id>,]<len>[,<remote_ip>,<remote_port>]:<data>`
+    /// message.
+    fn parse(resp: &[u8]) -> Option<Self> {
+        let header = IpdHeader::parse(resp).ok()?;
+        let data_start = header.data_start?;
+        let remote = match header.remote {
+            Some((ip, port)) => Some(SocketAddr::new(
+                core::str::from_utf8(ip).ok()?.parse::<IpAddr>().ok()?,
+                core::str::from_utf8(port).ok()?.parse().ok()?,
+            )),
+            None => None,
+        };
+        let data = &resp[data_start..];
+        if data.len() != header.len {
+            return None;
+        }
+        Some(Self {
+            mux: header.mux()?,
+            remote,
+            data: Vec::from_slice(data).ok()?,
+        })
+    }
+}
+
+/// The header of an `+IPD` message.
+///
+/// The header is `+IPD,[<link_id>,]<len>[,<remote_ip>,<remote_port>]:`,
+/// depending on whether multiplexing and showing the remote info is enabled.
+/// ESP-AT firmwares quote the remote IP, which may be an IPv6 address
+/// containing colons. In passive receive mode, only the
+/// `+IPD,[<link_id>,]<len>` notification is sent, terminated by a line break.
+#[derive(Debug)]
+pub(crate) struct IpdHeader<'a> {
+    link_id: Option<&'a [u8]>,
+    /// The length of the data
+    pub(crate) len: usize,
+    /// The remote IP without quotes and the remote port
+    remote: Option<(&'a [u8], &'a [u8])>,
+    /// The index of the data, `None` for notifications of the passive
+    /// receive mode
+    pub(crate) data_start: Option<usize>,
+    /// The index of the `:` or the line break terminating the header
+    pub(crate) line_end: usize,
+}
+
+impl<'a> IpdHeader<'a> {
+    /// Parse the header at the start of `buf`.
+    ///
+    /// Returns `Incomplete` if the header isn't terminated yet, the data is
+    /// not checked.
+    pub(crate) fn parse(buf: &'a [u8]) -> Result<Self, ParseError> {
+        let rest = buf.strip_prefix(b"+IPD,").ok_or(ParseError::NoMatch)?;
+        let offset = b"+IPD,".len();
+        let mut fields: Vec<&[u8], 4> = Vec::new();
+        let mut pos = 0;
+        let terminator = loop {
+            let field_start = pos;
+            if rest.get(pos) == Some(&b'"') {
+                let quoted_len = rest[pos + 1..]
+                    .iter()
+                    .position(|&b| b == b'"')
+                    .ok_or(ParseError::Incomplete)?;
+                pos += quoted_len + 2;
+            }
+            pos += rest
+                .get(pos..)
+                .and_then(|field| field.iter().position(|&b| matches!(b, b',' | b':' | b'\r')))
+                .ok_or(ParseError::Incomplete)?;
+            fields
+                .push(&rest[field_start..pos])
+                .map_err(|_| ParseError::NoMatch)?;
+            match rest[pos] {
+                b',' => pos += 1,
+                terminator => break terminator,
+            }
+        };
+        let (link_id, len, remote) = match fields[..] {
+            [len] => (None, len, None),
+            [link_id, len] => (Some(link_id), len, None),
+            [len, ip, port] => (None, len, Some((ip, port))),
+            [link_id, len, ip, port] => (Some(link_id), len, Some((ip, port))),
+            _ => return Err(ParseError::NoMatch),
+        };
+        let len = core::str::from_utf8(len)
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .ok_or(ParseError::NoMatch)?;
+        let remote = remote.map(|(ip, port)| {
+            let ip = ip
+                .strip_prefix(b"\"")
+                .and_then(|ip| ip.strip_suffix(b"\""))
+                .unwrap_or(ip);
+            (ip, port)
+        });
+        let line_end = offset + pos;
+        let data_start = match terminator {
+            b':' => Some(line_end + 1),
+            _ if remote.is_some() => return Err(ParseError::NoMatch),
+            _ => match buf.get(line_end + 1) {
+                Some(b'\n') => None,
+                Some(_) => return Err(ParseError::NoMatch),
+                None => return Err(ParseError::Incomplete),
+            },
+        };
+        Ok(Self {
+            link_id,
+            len,
+            remote,
+            data_start,
+            line_end,
+        })
+    }
+
+    /// The connection of the message, `None` if the link ID is invalid.
+    fn mux(&self) -> Option<MultiplexingType> {
+        match self.link_id {
+            Some(link_id) => core::str::from_utf8(link_id)
+                .ok()
+                .and_then(|link_id| link_id.parse().ok())
+                .and_then(ConnectionId::from_index)
+                .map(MultiplexingType::Multiplexed),
+            None => Some(MultiplexingType::NonMultiplexed),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<const N: usize> defmt::Format for NetworkData<N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "NetworkData {{ mux: {}, remote: {}, data: {} }}",
+            self.mux,
+            defmt::Debug2Format(&self.remote),
+            self.data
+        )
+    }
+}
+
+/// Parse a `+LINK_CONN:<status>,<link_id>,"<type>",<c/s>,"<remote_ip>",
+/// <remote_port>,<local_port>` message of a successful connection.
+fn parse_link_connected(resp: &[u8]) -> Option<responses::LinkInfo> {
+    let fields = core::str::from_utf8(resp.strip_prefix(b"+LINK_CONN:")?).ok()?;
+    // An eighth item collects any surplus fields
+    let fields: Vec<&str, 8> = fields.splitn(8, ',').collect();
+    match fields[..] {
+        // Status 0 means that the connection was established
+        ["0", link_id, protocol, side, ip, port, local_port] => {
+            responses::LinkInfo::from_fields(link_id, protocol, side, ip, port, local_port)
+        }
+        _ => None,
+    }
+}
+
+/// Parse the `+IPD,[<link_id>,]<len>` notification of the passive receive
+/// mode and return the connection.
+fn parse_data_available(resp: &[u8]) -> Option<MultiplexingType> {
+    let header = core::str::from_utf8(resp.get(b"+IPD,".len()..)?).ok()?;
+    let fields: Vec<&str, 2> = header.split(',').take(2).collect();
+    match fields[..] {
+        [len] => len
+            .parse::<usize>()
+            .ok()
+            .map(|_| MultiplexingType::NonMultiplexed),
+        [link_id, len] => {
+            len.parse::<usize>().ok()?;
+            ConnectionId::from_index(link_id.parse().ok()?).map(MultiplexingType::Multiplexed)
+        }
+        _ => None,
+    }
+}
+
+/// An unsolicited message from the device.
+// Boxing is not an option without an allocator.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Urc<const N: usize = MAX_DATA_LEN> {
+    /// The device has booted and is ready to accept commands
+    Ready,
+    /// The station connected to an access point
+    WifiConnected,
+    /// The station obtained an IP address
+    WifiGotIp,
+    /// The station disconnected from the access point
+    WifiDisconnected,
+    /// A connection was established
+    ConnectionOpened(MultiplexingType),
+    /// A connection was closed
+    ConnectionClosed(MultiplexingType),
+    /// A connection was established, with the details printed by ESP-AT
+    /// firmwares if enabled with `AT+SYSMSG`
+    LinkConnected(responses::LinkInfo),
+    /// The transparent transmission mode was left
+    TransparentQuit,
+    /// Data was received over a connection
+    NetworkData(NetworkData<N>),
+    /// Data was received over a connection and is buffered by the device,
+    /// in passive receive mode
+    DataAvailable(MultiplexingType),
+    /// An over-the-air firmware update made progress
+    UpdateProgress(UpdateStep),
+    /// Joining an access point failed with the specified reason code
+    JoinFailed(u8),
+    /// A segment written with `AT+CIPSENDBUF` was sent, with the ID of the
+    /// segment (NONOS only)
+    SegmentSent(MultiplexingType, u32),
+    /// Sending a segment written with `AT+CIPSENDBUF` failed, with the ID of
+    /// the segment (NONOS only)
+    SegmentFailed(MultiplexingType, u32),
+    /// The device is busy and dropped the last command
+    Busy,
+    /// The link ID of the last command is not valid
+    LinkInvalid,
+    /// The connection of the last command is already established
+    AlreadyConnected,
+    /// The last command failed with the specified extended error code,
+    /// printed by ESP-AT firmwares if enabled with `AT+SYSLOG`
+    ErrorCode(u32),
+    /// The MQTT client connected to the broker
+    #[cfg(feature = "mqtt")]
+    MqttConnected,
+    /// The MQTT client lost the connection to the broker
+    #[cfg(feature = "mqtt")]
+    MqttDisconnected,
+    /// A message was received on a subscribed MQTT topic
+    #[cfg(feature = "mqtt")]
+    MqttMessage(mqtt::Message),
+    /// A Bluetooth LE peer connected
+    #[cfg(feature = "ble")]
+    BleConnected(u8),
+    /// A Bluetooth LE peer disconnected
+    #[cfg(feature = "ble")]
+    BleDisconnected(u8),
+    /// A Bluetooth LE peer wrote an attribute value
+    #[cfg(feature = "ble")]
+    BleWrite(ble::AttributeWrite),
+    /// A WebSocket connection was established
+    #[cfg(feature = "websocket")]
+    WebSocketConnected(u8),
+    /// A WebSocket connection was closed
+    #[cfg(feature = "websocket")]
+    WebSocketClosed(u8),
+    /// Data was received on a WebSocket connection
+    #[cfg(feature = "websocket")]
+    WebSocketData(websocket::Message),
+}
+
+impl<const N: usize> Urc<N> {
+    /// Whether the URC carries data for the application.
+    ///
+    /// Such URCs are left in the queue until the application fetches them,
+    /// while all other URCs are consumed when the client state is updated.
+    pub(crate) fn has_payload(&self) -> bool {
+        match self {
+            Urc::NetworkData(_) => true,
+            #[cfg(feature = "mqtt")]
+            Urc::MqttMessage(_) => true,
+            #[cfg(feature = "ble")]
+            Urc::BleWrite(_) => true,
+            #[cfg(feature = "websocket")]
+            Urc::WebSocketData(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The error that the URC reports for the last command, if any.
+    ///
+    /// Such URCs are printed before the final `ERROR` (or instead of any
+    /// result) and tell why a command failed.
+    pub(crate) fn error(&self) -> Option<EspError> {
+        match self {
+            Urc::JoinFailed(code) => Some(EspError::from_join_code(*code)),
+            Urc::Busy => Some(EspError::Busy),
+            Urc::LinkInvalid => Some(EspError::LinkInvalid),
+            Urc::AlreadyConnected => Some(EspError::AlreadyConnected),
+            Urc::ErrorCode(code) => Some(EspError::from_error_code(*code)),
+            _ => None,
+        }
+    }
+}
+
+impl<const N: usize> AtatUrc for Urc<N> {
+    type Response = Self;
+
+    fn parse(resp: &[u8]) -> Option<Self::Response> {
+        match resp {
+            b"ready" => Some(Urc::Ready),
+            b"WIFI CONNECTED" => Some(Urc::WifiConnected),
+            b"WIFI GOT IP" => Some(Urc::WifiGotIp),
+            b"WIFI DISCONNECT" => Some(Urc::WifiDisconnected),
+            b"CONNECT" => Some(Urc::ConnectionOpened(MultiplexingType::NonMultiplexed)),
+            b"CLOSED" => Some(Urc::ConnectionClosed(MultiplexingType::NonMultiplexed)),
+            [link_id, b',', status @ ..] if status == b"CONNECT" => {
+                parse_connection_id(*link_id).map(Urc::ConnectionOpened)
+            }
+            [link_id, b',', status @ ..] if status == b"CLOSED" => {
+                parse_connection_id(*link_id).map(Urc::ConnectionClosed)
+            }
+            _ if resp.starts_with(b"+IPD,") => match NetworkData::parse(resp) {
+                Some(data) => Some(Urc::NetworkData(data)),
+                None => parse_data_available(resp).map(Urc::DataAvailable),
+            },
+            _ if resp.starts_with(b"+LINK_CONN:") => {
+                parse_link_connected(resp).map(Urc::LinkConnected)
+            }
+            _ if resp.ends_with(b",SEND OK") || resp.ends_with(b",SEND FAIL") => {
+                match parse_segment_status(resp)? {
+                    (mux, segment, true) => Some(Urc::SegmentSent(mux, segment)),
+                    (mux, segment, false) => Some(Urc::SegmentFailed(mux, segment)),
+                }
+            }
+            b"+QUITT" => Some(Urc::TransparentQuit),
+            b"busy p..." | b"busy s..." => Some(Urc::Busy),
+            b"link is not valid" => Some(Urc::LinkInvalid),
+            b"ALREADY CONNECTED" => Some(Urc::AlreadyConnected),
+            _ if resp.starts_with(b"ERR CODE:0x") => core::str::from_utf8(&resp[11..])
+                .ok()
+                .and_then(|code| u32::from_str_radix(code, 16).ok())
+                .map(Urc::ErrorCode),
+            b"+CIPUPDATE:1" => Some(Urc::UpdateProgress(UpdateStep::FoundServer)),
+            b"+CIPUPDATE:2" => Some(Urc::UpdateProgress(UpdateStep::Connected)),
+            b"+CIPUPDATE:3" => Some(Urc::UpdateProgress(UpdateStep::GotVersion)),
+            b"+CIPUPDATE:4" => Some(Urc::UpdateProgress(UpdateStep::Updating)),
+            _ if resp.starts_with(b"+CWJAP:") => core::str::from_utf8(&resp[7..])
+                .ok()?
+                .parse()
+                .ok()
+                .map(Urc::JoinFailed),
+            #[cfg(feature = "mqtt")]
+            _ if resp.starts_with(b"+MQTTCONNECTED:") => Some(Urc::MqttConnected),
+            #[cfg(feature = "mqtt")]
+            _ if resp.starts_with(b"+MQTTDISCONNECTED:") => Some(Urc::MqttDisconnected),
+            #[cfg(feature = "mqtt")]
+            _ if resp.starts_with(b"+MQTTSUBRECV:") => {
+                mqtt::Message::parse(resp).map(Urc::MqttMessage)
+            }
+            #[cfg(feature = "ble")]
+            _ if resp.starts_with(b"+BLECONN:") => parse_link_id(resp).map(Urc::BleConnected),
+            #[cfg(feature = "ble")]
+            _ if resp.starts_with(b"+BLEDISCONN:") => parse_link_id(resp).map(Urc::BleDisconnected),
+            #[cfg(feature = "ble")]
+            _ if resp.starts_with(b"+WRITE:") => {
+                ble::AttributeWrite::parse(resp).map(Urc::BleWrite)
+            }
+            #[cfg(feature = "websocket")]
+            _ if resp.starts_with(b"+WS_CONNECTED:") => {
+                parse_link_id(resp).map(Urc::WebSocketConnected)
+            }
+            #[cfg(feature = "websocket")]
+            _ if resp.starts_with(b"+WS_DISCONNECTED:") || resp.starts_with(b"+WS_CLOSED:") => {
+                parse_link_id(resp).map(Urc::WebSocketClosed)
+            }
+            #[cfg(feature = "websocket")]
+            _ if resp.starts_with(b"+WS_DATA:") => {
+                websocket::Message::parse(resp).map(Urc::WebSocketData)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parse the link ID of a `<link_id>,CONNECT` or `<link_id>,CLOSED` URC.
+fn parse_connection_id(link_id: u8) -> Option<MultiplexingType> {
+    if !link_id.is_ascii_digit() {
+        return None;
+    }
+    ConnectionId::from_index(link_id - b'0').map(MultiplexingType::Multiplexed)
+}
+
+/// Parse a `[<link_id>,]<segment_id>,SEND OK` or `SEND FAIL` line into the
+/// connection, the segment ID and whether the segment was sent.
+pub(crate) fn parse_segment_status(line: &[u8]) -> Option<(MultiplexingType, u32, bool)> {
+    let (fields, sent) = match line.strip_suffix(b",SEND OK") {
+        Some(fields) => (fields, true),
+        None => (line.strip_suffix(b",SEND FAIL")?, false),
+    };
+    let fields = core::str::from_utf8(fields).ok()?;
+    let (mux, segment) = match fields.split_once(',') {
+        Some((link_id, segment)) => match link_id.as_bytes() {
+            [link_id] => (parse_connection_id(*link_id)?, segment),
+            _ => return None,
+        },
+        None => (MultiplexingType::NonMultiplexed, fields),
+    };
+    if !segment.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((mux, segment.parse().ok()?, sent))
+}
+
+/// Parse the link ID of a `+<NAME>:<link_id>[,...]` URC.
+#[cfg(any(feature = "ble", feature = "websocket"))]
+fn parse_link_id(resp: &[u8]) -> Option<u8> {
+    let fields = resp.split(|&b| b == b':').nth(1)?;
+    let link_id = fields.split(|&b| b == b',').next()?;
+    core::str::from_utf8(link_id).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_link_connected() {
+        let info =
+            parse_link_connected(b"+LINK_CONN:0,4,\"UDP\",1,\"192.168.1.2\",53,1024").unwrap();
+        assert_eq!(info.protocol, crate::types::Protocol::Udp);
+        assert!(info.incoming);
+        assert_eq!(info.remote, "192.168.1.2:53".parse().unwrap());
+        assert_eq!(info.local_port, 1024);
+
+        // The connection failed
+        assert!(
+            parse_link_connected(b"+LINK_CONN:1,0,\"TCP\",0,\"192.168.1.2\",80,49152").is_none()
+        );
+
+        // Surplus, missing and binary fields
+        assert!(
+            parse_link_connected(b"+LINK_CONN:0,0,\"TCP\",0,\"192.168.1.2\",80,49152,1,2,3,4")
+                .is_none()
+        );
+        assert!(parse_link_connected(b"+LINK_CONN:0,0,\"TCP\",0").is_none());
+        assert!(parse_link_connected(b"+LINK_CONN:").is_none());
+        assert!(
+            parse_link_connected(b"+LINK_CONN:0,0,\"TCP\",0,\"192.168.1.2\",80,\xff").is_none()
+        );
+    }
+
+    #[test]
+    fn test_parse_segment_status() {
+        use crate::types::ConnectionId;
+
+        let status = parse_segment_status(b"3,SEND OK").unwrap();
+        assert_eq!(status, (MultiplexingType::NonMultiplexed, 3, true));
+        let status = parse_segment_status(b"1,12,SEND FAIL").unwrap();
+        let multiplexed = MultiplexingType::Multiplexed(ConnectionId::One);
+        assert_eq!(status, (multiplexed, 12, false));
+
+        // Missing segments, invalid link IDs and malformed fields
+        assert!(parse_segment_status(b"SEND OK").is_none());
+        assert!(parse_segment_status(b",SEND OK").is_none());
+        assert!(parse_segment_status(b"9,1,SEND OK").is_none());
+        assert!(parse_segment_status(b"10,1,SEND OK").is_none());
+        assert!(parse_segment_status(b"1,x,SEND OK").is_none());
+        assert!(parse_segment_status(b"1,2,3,SEND OK").is_none());
+        assert!(parse_segment_status(b"1,SEND").is_none());
+    }
+}
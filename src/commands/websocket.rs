@@ -0,0 +1,237 @@
+//! Commands for the WebSocket client built into ESP-AT firmwares (v3.0 and
+//! later).
+//!
+//! The firmware supports up to three parallel WebSocket connections with link
+//! IDs 0 to 2.
+
+use core::fmt::Write;
+
+use atat::{AtatCmd, InternalError};
+use heapless::{String, Vec};
+use numtoa::NumToA;
+
+use crate::commands::{
+    requests::{is_valid_string, quoted_max_len, write_quoted},
+    responses, serialize, to_string,
+};
+
+/// Maximum length of a received WebSocket payload.
+pub const MAX_PAYLOAD_LEN: usize = 256;
+
+/// A message received on a WebSocket connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Message {
+    pub link_id: u8,
+    pub data: Vec<u8, MAX_PAYLOAD_LEN>,
+}
+
+impl Message {
+    /// Parse a `+WS_DATA:<link_id>,<len>,<data>` URC.
+    pub(crate) fn parse(resp: &[u8]) -> Option<Self> {
+        let rest = resp.strip_prefix(b"+WS_DATA:")?;
+        let mut fields = rest.splitn(3, |&b| b == b',');
+        let link_id = core::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+        let len: usize = core::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+        let data = fields.next()?.get(..len)?;
+        Some(Self {
+            link_id,
+            data: Vec::from_slice(data).ok()?,
+        })
+    }
+}
+
+/// Configure the keep-alive behaviour of a WebSocket connection.
+#[derive(Debug)]
+pub struct Configure {
+    link_id: u8,
+    ping_interval_s: u16,
+    ping_timeout_s: u16,
+}
+
+impl Configure {
+    pub fn new(link_id: u8, ping_interval_s: u16, ping_timeout_s: u16) -> Self {
+        Self {
+            link_id,
+            ping_interval_s,
+            ping_timeout_s,
+        }
+    }
+}
+
+/// Length of the longest `AT+WSCFG` command.
+const CONFIGURE_LEN: usize = "AT+WSCFG=255,65535,65535\r\n".len();
+
+impl AtatCmd<CONFIGURE_LEN> for Configure {
+    type Response = responses::EmptyResponse;
+
+    fn as_bytes(&self) -> Vec<u8, CONFIGURE_LEN> {
+        serialize(|buf| {
+            let mut link_buf = [0; 3];
+            let mut interval_buf = [0; 5];
+            let mut timeout_buf = [0; 5];
+            write!(
+                buf,
+                "AT+WSCFG={},{},{}\r\n",
+                self.link_id.numtoa_str(10, &mut link_buf),
+                self.ping_interval_s.numtoa_str(10, &mut interval_buf),
+                self.ping_timeout_s.numtoa_str(10, &mut timeout_buf),
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Maximum length of a WebSocket URI.
+const MAX_URI_LEN: usize = 256;
+
+/// Open a WebSocket connection to the specified URI (`ws://` or `wss://`).
+#[derive(Debug)]
+pub struct Open {
+    link_id: u8,
+    uri: String<MAX_URI_LEN>,
+}
+
+impl Open {
+    /// Return `None` if the URI is longer than 256 bytes.
+    pub fn new(link_id: u8, uri: &str) -> Option<Self> {
+        Some(Self {
+            link_id,
+            uri: to_string(uri).ok()?,
+        })
+    }
+
+    /// Whether the URI contains no control characters.
+    pub fn is_valid(&self) -> bool {
+        is_valid_string(self.uri.as_bytes())
+    }
+}
+
+/// Length of the longest `AT+WSOPEN` command.
+const OPEN_LEN: usize = "AT+WSOPEN=255,".len() + quoted_max_len(MAX_URI_LEN) + "\r\n".len();
+
+impl AtatCmd<OPEN_LEN> for Open {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 20_000;
+
+    fn as_bytes(&self) -> Vec<u8, OPEN_LEN> {
+        serialize(|buf| {
+            let mut link_buf = [0; 3];
+            write!(
+                buf,
+                "AT+WSOPEN={},",
+                self.link_id.numtoa_str(10, &mut link_buf),
+            )?;
+            write_quoted(buf, self.uri.as_bytes())?;
+            write!(buf, "\r\n")?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Prepare to send `length` bytes of data as a text frame.
+///
+/// This message MUST be followed by a `SendData` message.
+#[derive(Debug)]
+pub struct PrepareSend {
+    link_id: u8,
+    length: u16,
+}
+
+impl PrepareSend {
+    pub fn new(link_id: u8, length: u16) -> Self {
+        Self { link_id, length }
+    }
+}
+
+/// Length of the longest `AT+WSSEND` command.
+const PREPARE_SEND_LEN: usize = "AT+WSSEND=255,65535\r\n".len();
+
+impl AtatCmd<PREPARE_SEND_LEN> for PrepareSend {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, PREPARE_SEND_LEN> {
+        serialize(|buf| {
+            let mut link_buf = [0; 3];
+            let mut length_buf = [0; 5];
+            write!(
+                buf,
+                "AT+WSSEND={},{}\r\n",
+                self.link_id.numtoa_str(10, &mut link_buf),
+                self.length.numtoa_str(10, &mut length_buf),
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+/// Close a WebSocket connection.
+#[derive(Debug)]
+pub struct Close {
+    link_id: u8,
+}
+
+impl Close {
+    pub fn new(link_id: u8) -> Self {
+        Self { link_id }
+    }
+}
+
+/// Length of the longest `AT+WSCLOSE` command.
+const CLOSE_LEN: usize = "AT+WSCLOSE=255\r\n".len();
+
+impl AtatCmd<CLOSE_LEN> for Close {
+    type Response = responses::EmptyResponse;
+    const MAX_TIMEOUT_MS: u32 = 5_000;
+
+    fn as_bytes(&self) -> Vec<u8, CLOSE_LEN> {
+        serialize(|buf| {
+            let mut link_buf = [0; 3];
+            write!(
+                buf,
+                "AT+WSCLOSE={}\r\n",
+                self.link_id.numtoa_str(10, &mut link_buf),
+            )?;
+            Ok(())
+        })
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, atat::Error> {
+        responses::EmptyResponse::from_resp(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_parse() {
+        // The data may contain commas and line breaks
+        let message = Message::parse(b"+WS_DATA:1,6,a,b\r\nc").unwrap();
+        assert_eq!(message.link_id, 1);
+        assert_eq!(&message.data[..], b"a,b\r\nc");
+
+        // Truncated URCs
+        assert_eq!(Message::parse(b"+WS_DATA:1,6,a,b"), None);
+        assert_eq!(Message::parse(b"+WS_DATA:1,6"), None);
+        assert_eq!(Message::parse(b"+WS_DATA:"), None);
+
+        // Binary garbage in the link ID or the length
+        assert_eq!(Message::parse(b"+WS_DATA:\xff,1,a"), None);
+        assert_eq!(Message::parse(b"+WS_DATA:1,\xff,a"), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! Splitting of the bytes received from the device into responses and URCs.
+
+use core::marker::PhantomData;
+
+use atat::{
+    digest::{DigestResult, Digester, ParseError, Parser},
+    InternalError,
+};
+
+use crate::{commands::urcs::IpdHeader, find, UrcParser};
+
+/// Maximum length of the header of an `+IPD` message.
+const MAX_IPD_HEADER_LEN: usize = 64;
+
+/// A digester that knows the peculiarities of the ESP8266 AT firmwares.
+///
+/// Compared to the `DefaultDigester` of atat, it
+///
+/// - knows the `SEND OK`, `SEND FAIL` and `FAIL` result codes,
+/// - swallows the `>` prompt that is sent before data can be sent, and the
+///   `Recv <len> bytes` confirmation after it was received,
+/// - strips the echo of the command if echo is enabled, and
+/// - frames `+IPD` messages and `AT+CIPRECVDATA` responses by their length
+///   field, because the payload is binary and may contain line breaks or
+///   result codes.
+///
+/// URCs are matched by `P`, which defaults to [`UrcParser`]. A custom parser
+/// can be used to work around firmware quirks, falling back to `UrcParser`
+/// for all other URCs.
+///
+/// [`UrcParser`]: ../enum.UrcParser.html
+#[derive(Debug)]
+pub struct EspDigester<P = UrcParser> {
+    _urc_parser: PhantomData<P>,
+}
+
+impl<P: Parser> EspDigester<P> {
+    pub fn new() -> Self {
+        Self {
+            _urc_parser: PhantomData,
+        }
+    }
+}
+
+impl<P: Parser> Default for EspDigester<P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Parser> Digester for EspDigester<P> {
+    fn digest<'a>(&mut self, buf: &'a [u8]) -> (DigestResult<'a>, usize) {
+        match P::parse(buf) {
+            Ok((urc, len)) => return (DigestResult::Urc(urc), len),
+            Err(ParseError::Incomplete) => return (DigestResult::None, 0),
+            Err(ParseError::NoMatch) => {}
+        }
+
+        let start = buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+        let rest = &buf[start..];
+
+        if rest.starts_with(b"+IPD,") {
+            return match ipd_len(rest) {
+                Ok((len, consumed)) => (DigestResult::Urc(&rest[..len]), start + consumed),
+                Err(ParseError::Incomplete) => (DigestResult::None, 0),
+                // Drop the prefix of a malformed message to not get stuck
+                Err(ParseError::NoMatch) => (DigestResult::None, start + b"+IPD,".len()),
+            };
+        }
+
+        // The prompt is only a signal to send the data, the response of the
+        // command was already complete with the preceding OK.
+        if rest.starts_with(b">") {
+            let len = if rest.starts_with(b"> ") { 2 } else { 1 };
+            return (DigestResult::None, start + len);
+        }
+
+        // The result of sending the data follows the confirmation, if any
+        if rest.starts_with(b"Recv ") {
+            match find(rest, b"\r\n") {
+                Some(len) if is_recv_line(&rest[..len]) => {
+                    return (DigestResult::None, start + len + 2)
+                }
+                Some(_) => {}
+                None => return (DigestResult::None, 0),
+            }
+        }
+
+        let body_start = match echo_len(rest) {
+            Ok(len) => len,
+            Err(_) => return (DigestResult::None, 0),
+        };
+
+        // Received data may end with whitespace, so it must not be trimmed
+        let data_end = match recv_data_len(&rest[body_start..]) {
+            Ok(len) => Some(body_start + len),
+            Err(ParseError::Incomplete) => return (DigestResult::None, 0),
+            Err(ParseError::NoMatch) => None,
+        };
+
+        let mut line_start = data_end.unwrap_or(body_start);
+        while let Some(line_len) = find(&rest[line_start..], b"\r\n") {
+            let line = &rest[line_start..line_start + line_len];
+            let end = start + line_start + line_len + 2;
+            let body = match data_end {
+                Some(data_end) => &rest[body_start..data_end],
+                None => trim(&rest[body_start..line_start]),
+            };
+            match line {
+                b"OK" => return (DigestResult::Response(Ok(body)), end),
+                // The body only tells how many bytes were received
+                b"SEND OK" => return (DigestResult::Response(Ok(&[])), end),
+                b"ERROR" => return (DigestResult::Response(Err(InternalError::Error)), end),
+                b"FAIL" | b"SEND FAIL" => {
+                    return (
+                        DigestResult::Response(Err(InternalError::Custom(line))),
+                        end,
+                    )
+                }
+                _ => line_start += line_len + 2,
+            }
+        }
+        (DigestResult::None, 0)
+    }
+}
+
+/// Whether the line is a `Recv <len> bytes` confirmation of sent data.
+fn is_recv_line(line: &[u8]) -> bool {
+    line.strip_prefix(b"Recv ")
+        .and_then(|rest| rest.strip_suffix(b" bytes"))
+        .is_some_and(|len| !len.is_empty() && len.iter().all(u8::is_ascii_digit))
+}
+
+/// Return the length of the echo of a command at the start of `buf`, or 0 if
+/// there is none.
+///
+/// The echo is the command followed by `"\r\r\n"`, because the device adds
+/// a line break to the one of the command. Other lines starting with `AT`,
+/// like the `AT version:` line of the `AT+GMR` response, are kept.
+fn echo_len(buf: &[u8]) -> Result<usize, ParseError> {
+    match buf {
+        [b'A', b'T', b'+', ..] | [b'A', b'T', b'E', ..] | [b'A', b'T', b'\r', ..] => {}
+        [b'A'] | [b'A', b'T'] => return Err(ParseError::Incomplete),
+        _ => return Ok(0),
+    }
+    let end = find(buf, b"\n").ok_or(ParseError::Incomplete)? + 1;
+    if buf[..end].ends_with(b"\r\r\n") {
+        Ok(end)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Return the length of an `+IPD` message including its header, and the
+/// number of bytes it takes in the buffer.
+///
+/// The data is framed by the length field of the header only, so binary data
+/// containing colons or line breaks is received intact. Notifications of the
+/// passive receive mode are returned without their line break.
+fn ipd_len(buf: &[u8]) -> Result<(usize, usize), ParseError> {
+    let header = match IpdHeader::parse(buf) {
+        Err(ParseError::Incomplete) if buf.len() > MAX_IPD_HEADER_LEN => {
+            return Err(ParseError::NoMatch)
+        }
+        header => header?,
+    };
+    let data_start = match header.data_start {
+        Some(data_start) => data_start,
+        None => return Ok((header.line_end, header.line_end + 2)),
+    };
+    let end = data_start
+        .checked_add(header.len)
+        .ok_or(ParseError::NoMatch)?;
+    if buf.len() < end {
+        Err(ParseError::Incomplete)
+    } else {
+        Ok((end, end))
+    }
+}
+
+/// Return the length of an `AT+CIPRECVDATA` response up to the end of the
+/// data.
+///
+/// The response is `+CIPRECVDATA,<len>:<data>` on NONOS firmwares and
+/// `+CIPRECVDATA:<len>,[<remote_ip>,<remote_port>,]<data>` on ESP-AT
+/// firmwares.
+fn recv_data_len(buf: &[u8]) -> Result<usize, ParseError> {
+    const PREFIX_LEN: usize = b"+CIPRECVDATA,".len();
+    let separator = match buf.get(..PREFIX_LEN) {
+        Some(b"+CIPRECVDATA,") => b':',
+        Some(b"+CIPRECVDATA:") => b',',
+        Some(_) => return Err(ParseError::NoMatch),
+        None if b"+CIPRECVDATA".starts_with(buf) => return Err(ParseError::Incomplete),
+        None => return Err(ParseError::NoMatch),
+    };
+    let len_len = match buf[PREFIX_LEN..].iter().position(|&b| b == separator) {
+        Some(index) => index,
+        None if buf.len() > MAX_IPD_HEADER_LEN => return Err(ParseError::NoMatch),
+        None => return Err(ParseError::Incomplete),
+    };
+    let len: usize = core::str::from_utf8(&buf[PREFIX_LEN..PREFIX_LEN + len_len])
+        .ok()
+        .and_then(|len| len.parse().ok())
+        .ok_or(ParseError::NoMatch)?;
+    let mut data_start = PREFIX_LEN + len_len + 1;
+    if separator == b',' {
+        match buf.get(data_start) {
+            // Skip the remote IP and port
+            Some(b'"') => {
+                for _ in 0..2 {
+                    data_start += find(&buf[data_start..], b",").ok_or(ParseError::Incomplete)? + 1;
+                }
+            }
+            Some(_) => {}
+            None => return Err(ParseError::Incomplete),
+        }
+    }
+    let end = data_start.checked_add(len).ok_or(ParseError::NoMatch)?;
+    if buf.len() < end {
+        Err(ParseError::Incomplete)
+    } else {
+        Ok(end)
+    }
+}
+
+/// Strip leading and trailing whitespace.
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |index| index + 1);
+    &bytes[start..end]
+}
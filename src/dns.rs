@@ -0,0 +1,123 @@
+//! Caching of resolved hostnames.
+//!
+//! Resolving a hostname with the device takes up to a few seconds, which
+//! adds up for applications that connect to the same hosts over and over,
+//! e.g. for periodic HTTP requests. The [`DnsCache`] keeps the most recently
+//! used addresses for a limited time.
+
+use core::net::Ipv4Addr;
+
+use atat::AtatClient;
+use heapless::{String, Vec};
+
+use crate::{EspClient, EspResult};
+
+/// A cached address.
+#[derive(Debug)]
+struct Entry {
+    hostname: String<64>,
+    ip: Ipv4Addr,
+    /// Time of the resolution in milliseconds, if a time source is set
+    resolved_ms: Option<u64>,
+    /// Value of the use counter of the cache when the entry was last used
+    last_used: u32,
+}
+
+/// Caches up to `S` resolved hostnames, evicting the least recently used.
+///
+/// Entries expire after the TTL, which requires a time source on the client
+/// (see [`EspClient::set_time_source`]). Without one, entries are kept until
+/// they are evicted or the cache is cleared, e.g. after the device was
+/// reset.
+///
+/// [`EspClient::set_time_source`]: ../struct.EspClient.html#method.set_time_source
+#[derive(Debug)]
+pub struct DnsCache<const S: usize> {
+    entries: Vec<Entry, S>,
+    ttl_ms: u64,
+    uses: u32,
+}
+
+impl<const S: usize> DnsCache<S> {
+    /// Create a cache whose entries expire after `ttl_ms` milliseconds.
+    pub fn new(ttl_ms: u64) -> Self {
+        Self {
+            entries: Vec::new(),
+            ttl_ms,
+            uses: 0,
+        }
+    }
+
+    /// Return the address of a hostname, resolving it with the device if it
+    /// is not cached or expired.
+    pub fn resolve<C: AtatClient, const N: usize>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        hostname: &str,
+    ) -> EspResult<Ipv4Addr> {
+        let now = esp.time_ms();
+        self.uses = self.uses.wrapping_add(1);
+        let ttl_ms = self.ttl_ms;
+        let position = self.entries.iter().position(|entry| {
+            entry.hostname == hostname
+                && match (entry.resolved_ms, now) {
+                    (Some(resolved), Some(now)) => now.saturating_sub(resolved) < ttl_ms,
+                    _ => true,
+                }
+        });
+        if let Some(position) = position {
+            let entry = &mut self.entries[position];
+            entry.last_used = self.uses;
+            return Ok(entry.ip);
+        }
+
+        let ip = esp.resolve_hostname(hostname)?;
+        self.insert(hostname, ip, now);
+        Ok(ip)
+    }
+
+    /// Forget the address of a hostname, e.g. after connecting to it failed.
+    pub fn invalidate(&mut self, hostname: &str) {
+        self.entries.retain(|entry| entry.hostname != hostname);
+    }
+
+    /// Forget all addresses.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Return the number of cached addresses, at most `S`.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn insert(&mut self, hostname: &str, ip: Ipv4Addr, now: Option<u64>) {
+        // Hostnames longer than the device accepts fail to resolve
+        let hostname: String<64> = match hostname.parse() {
+            Ok(hostname) => hostname,
+            Err(()) => return,
+        };
+        self.invalidate(&hostname);
+        if self.entries.is_full() {
+            let oldest = self
+                .entries
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, entry)| self.uses.wrapping_sub(entry.last_used))
+                .map(|(index, _)| index);
+            if let Some(oldest) = oldest {
+                self.entries.swap_remove(oldest);
+            }
+        }
+        let _ = self.entries.push(Entry {
+            hostname,
+            ip,
+            resolved_ms: now,
+            last_used: self.uses,
+        });
+    }
+}
@@ -0,0 +1,111 @@
+//! Helpers to use the client with the embassy framework.
+//!
+//! [`EmbassyClock`] is the timer passed to the client constructors, and
+//! [`run_ingress`] feeds the received bytes to the ingress manager from a
+//! dedicated task.
+//!
+//! The client itself is blocking. To share it between tasks, put it into a
+//! `Mutex` of `embassy-sync` (e.g. with a `CriticalSectionRawMutex`) in a
+//! `static`, and lock it around every call. The ingress task must not take
+//! that lock, otherwise a task waiting for a response would never get it.
+//! Note that a blocking call stalls the executor that runs it, so the
+//! ingress task should run on a higher priority executor (or from an
+//! interrupt executor) than the tasks using the client.
+
+use atat::{clock::Clock, Digester, IngressManager};
+use embassy_time::Instant;
+use embedded_io_async::Read;
+
+/// Number of bytes that are read before they are passed on to the ingress
+/// manager.
+const CHUNK_LEN: usize = 32;
+
+/// A timer with millisecond precision based on `embassy-time`.
+pub struct EmbassyClock {
+    start: Instant,
+    duration_ms: u32,
+    started: bool,
+}
+
+impl EmbassyClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            duration_ms: 0,
+            started: false,
+        }
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis()
+    }
+}
+
+impl Default for EmbassyClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock<1000> for EmbassyClock {
+    type Error = &'static str;
+
+    /// Return the time since the timer was started.
+    ///
+    /// The tick count wraps around after about 49 days.
+    fn now(&mut self) -> fugit::TimerInstantU32<1000> {
+        fugit::TimerInstantU32::from_ticks(self.elapsed_ms() as u32)
+    }
+
+    fn start(&mut self, duration: fugit::TimerDurationU32<1000>) -> Result<(), Self::Error> {
+        self.start = Instant::now();
+        self.duration_ms = duration.ticks();
+        self.started = true;
+        Ok(())
+    }
+
+    fn cancel(&mut self) -> Result<(), Self::Error> {
+        if !self.started {
+            Err("cannot cancel stopped timer")
+        } else {
+            self.started = false;
+            Ok(())
+        }
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Self::Error> {
+        if self.elapsed_ms() >= u64::from(self.duration_ms) {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+/// Read from the serial port and feed the ingress manager, forever.
+///
+/// This is meant to be the body of a dedicated task. It only returns when
+/// reading fails.
+pub async fn run_ingress<
+    R,
+    D,
+    const BUF_LEN: usize,
+    const RES_CAPACITY: usize,
+    const URC_CAPACITY: usize,
+>(
+    serial_rx: &mut R,
+    ingress: &mut IngressManager<D, BUF_LEN, RES_CAPACITY, URC_CAPACITY>,
+) -> R::Error
+where
+    R: Read,
+    D: Digester,
+{
+    let mut chunk = [0; CHUNK_LEN];
+    loop {
+        match serial_rx.read(&mut chunk).await {
+            Ok(len) => ingress.write(&chunk[..len]),
+            Err(e) => return e,
+        }
+        ingress.digest();
+    }
+}
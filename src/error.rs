@@ -0,0 +1,131 @@
+//! Errors returned by the client.
+
+use core::fmt;
+
+/// An error that occurred while talking to the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EspError {
+    /// The device did not respond in time, or joining an access point timed
+    /// out
+    Timeout,
+    /// Joining an access point failed because the password is wrong
+    WrongPassword,
+    /// Joining an access point failed because it could not be found
+    ApNotFound,
+    /// Joining an access point failed for another reason
+    JoinFailed,
+    /// The connection (link) ID is not valid or not connected
+    LinkInvalid,
+    /// A connection with this link ID is already established
+    AlreadyConnected,
+    /// Sending data over a connection failed
+    SendFail,
+    /// The connection was closed before the expected data was received
+    ConnectionClosed,
+    /// The device is busy processing a previous command
+    Busy,
+    /// The command failed with `ERROR`
+    CommandFailed,
+    /// The command failed with `FAIL`
+    Fail,
+    /// The firmware does not support the command
+    Unsupported,
+    /// The response could not be parsed
+    Parse,
+    /// The response did not match the command
+    InvalidResponse,
+    /// The command or response does not fit into its buffer
+    Overflow,
+    /// An argument contains characters that can't be sent in a command
+    InvalidArgument,
+    /// The data to send is empty, longer than the firmware accepts, or its
+    /// length differs from the announced length
+    InvalidLength,
+    /// The command was aborted
+    Aborted,
+    /// Reading from or writing to the serial port failed
+    Serial,
+    /// Driving a control pin (RST or CH_PD) or delaying failed
+    Pin,
+}
+
+impl EspError {
+    /// Map a reason code reported by `AT+CWJAP` to an error.
+    pub(crate) fn from_join_code(code: u8) -> Self {
+        match code {
+            1 => EspError::Timeout,
+            2 => EspError::WrongPassword,
+            3 => EspError::ApNotFound,
+            _ => EspError::JoinFailed,
+        }
+    }
+
+    /// Map an extended error code printed as `ERR CODE:0x...` by ESP-AT
+    /// firmwares to an error.
+    ///
+    /// The codes are `0x01<sub>xxxx`, where the sub-category tells why the
+    /// command failed.
+    pub(crate) fn from_error_code(code: u32) -> Self {
+        match (code >> 16) & 0xff {
+            // Missing terminator or AT prefix, wrong parameter length,
+            // type, number or value
+            0x02..=0x08 => EspError::InvalidArgument,
+            0x09 => EspError::Unsupported,
+            0x0b => EspError::Busy,
+            _ => EspError::CommandFailed,
+        }
+    }
+
+    /// Whether the error is likely to go away when trying again, i.e. the
+    /// device was busy or did not answer in time.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, EspError::Busy | EspError::Timeout)
+    }
+}
+
+impl fmt::Display for EspError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EspError::Timeout => "timeout",
+            EspError::WrongPassword => "wrong password",
+            EspError::ApNotFound => "access point not found",
+            EspError::JoinFailed => "joining the access point failed",
+            EspError::LinkInvalid => "link is not valid",
+            EspError::AlreadyConnected => "already connected",
+            EspError::SendFail => "sending data failed",
+            EspError::ConnectionClosed => "connection closed",
+            EspError::Busy => "device is busy",
+            EspError::CommandFailed => "command failed",
+            EspError::Fail => "command failed with FAIL",
+            EspError::Unsupported => "not supported by the firmware",
+            EspError::Parse => "response could not be parsed",
+            EspError::InvalidResponse => "invalid response",
+            EspError::Overflow => "buffer overflow",
+            EspError::InvalidArgument => "invalid characters in argument",
+            EspError::InvalidLength => "invalid data length",
+            EspError::Aborted => "command aborted",
+            EspError::Serial => "serial port error",
+            EspError::Pin => "pin or delay error",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EspError {}
+
+impl From<atat::Error> for EspError {
+    fn from(e: atat::Error) -> Self {
+        match e {
+            atat::Error::Read | atat::Error::Write => EspError::Serial,
+            atat::Error::Timeout => EspError::Timeout,
+            atat::Error::InvalidResponse => EspError::InvalidResponse,
+            atat::Error::Aborted => EspError::Aborted,
+            atat::Error::Overflow => EspError::Overflow,
+            atat::Error::Parse => EspError::Parse,
+            // The only custom errors are the `FAIL` result codes
+            atat::Error::Custom => EspError::Fail,
+            _ => EspError::CommandFailed,
+        }
+    }
+}
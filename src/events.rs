@@ -0,0 +1,96 @@
+//! Callback based notifications.
+//!
+//! Instead of polling the client state, an [`EspEventHandler`] can be
+//! passed to [`EspClient::poll_events`]. Its methods are called for the
+//! events reported by URCs since the last call, including the URCs that
+//! were processed during commands.
+//!
+//! [`EspClient::poll_events`]: ../struct.EspClient.html#method.poll_events
+
+use crate::{
+    commands::urcs::{NetworkData, MAX_DATA_LEN},
+    types::MultiplexingType,
+};
+
+/// Receives notifications about events reported by the device.
+///
+/// All methods do nothing by default, so only the relevant ones need to be
+/// implemented. They are called after the client state was updated. `N`
+/// is the data capacity of the client.
+pub trait EspEventHandler<const N: usize = MAX_DATA_LEN> {
+    /// The device restarted unexpectedly
+    fn on_rebooted(&mut self) {}
+
+    /// The station connected to an access point
+    fn on_wifi_connected(&mut self) {}
+
+    /// The station obtained an IP address
+    fn on_wifi_got_ip(&mut self) {}
+
+    /// The station disconnected from the access point
+    fn on_wifi_disconnected(&mut self) {}
+
+    /// A connection was established
+    fn on_connection_opened(&mut self, _mux: MultiplexingType) {}
+
+    /// A connection was closed
+    fn on_connection_closed(&mut self, _mux: MultiplexingType) {}
+
+    /// Data was received over a connection
+    ///
+    /// Data passed to this method is not returned by `poll_network_data`.
+    fn on_data(&mut self, _data: &NetworkData<N>) {}
+
+    /// Data was received and is buffered by the device, in passive receive
+    /// mode
+    fn on_data_available(&mut self, _mux: MultiplexingType) {}
+
+    /// The MQTT client connected to the broker
+    #[cfg(feature = "mqtt")]
+    fn on_mqtt_connected(&mut self) {}
+
+    /// The MQTT client lost the connection to the broker
+    #[cfg(feature = "mqtt")]
+    fn on_mqtt_disconnected(&mut self) {}
+}
+
+/// Number of events that are kept until they're passed to a handler.
+pub(crate) const MAX_PENDING_EVENTS: usize = 16;
+
+/// An event that is kept until it's passed to a handler.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Event {
+    Rebooted,
+    WifiConnected,
+    WifiGotIp,
+    WifiDisconnected,
+    ConnectionOpened(MultiplexingType),
+    ConnectionClosed(MultiplexingType),
+    DataAvailable(MultiplexingType),
+    #[cfg(feature = "mqtt")]
+    MqttConnected,
+    #[cfg(feature = "mqtt")]
+    MqttDisconnected,
+}
+
+impl Event {
+    /// Call the method of `handler` that corresponds to the event.
+    pub(crate) fn notify<H, const N: usize>(self, handler: &mut H)
+    where
+        H: EspEventHandler<N> + ?Sized,
+    {
+        match self {
+            Event::Rebooted => handler.on_rebooted(),
+            Event::WifiConnected => handler.on_wifi_connected(),
+            Event::WifiGotIp => handler.on_wifi_got_ip(),
+            Event::WifiDisconnected => handler.on_wifi_disconnected(),
+            Event::ConnectionOpened(mux) => handler.on_connection_opened(mux),
+            Event::ConnectionClosed(mux) => handler.on_connection_closed(mux),
+            Event::DataAvailable(mux) => handler.on_data_available(mux),
+            #[cfg(feature = "mqtt")]
+            Event::MqttConnected => handler.on_mqtt_connected(),
+            #[cfg(feature = "mqtt")]
+            Event::MqttDisconnected => handler.on_mqtt_disconnected(),
+        }
+    }
+}
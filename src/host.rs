@@ -0,0 +1,184 @@
+//! Helpers to use the client on a host with an operating system.
+
+use std::{
+    convert::TryInto,
+    io,
+    thread::{self, JoinHandle},
+    time::Instant as StdInstant,
+};
+
+use atat::{clock::Clock, Digester, IngressManager};
+use embedded_hal::serial;
+use fugit::Instant;
+
+/// A timer with millisecond precision.
+pub struct SysTimer {
+    start: StdInstant,
+    duration_ms: u32,
+    started: bool,
+}
+
+impl SysTimer {
+    pub fn new() -> SysTimer {
+        SysTimer {
+            start: StdInstant::now(),
+            duration_ms: 0,
+            started: false,
+        }
+    }
+}
+
+impl Default for SysTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock<1000> for SysTimer {
+    type Error = &'static str;
+
+    /// Return current time `Instant`
+    fn now(&mut self) -> fugit::TimerInstantU32<1000> {
+        let milliseconds = (StdInstant::now() - self.start).as_millis();
+        let ticks: u32 = milliseconds.try_into().expect("u32 timer overflow");
+        Instant::<u32, 1, 1000>::from_ticks(ticks)
+    }
+
+    /// Start timer with a `duration`
+    fn start(&mut self, duration: fugit::TimerDurationU32<1000>) -> Result<(), Self::Error> {
+        // (Re)set start and duration
+        self.start = StdInstant::now();
+        self.duration_ms = duration.ticks();
+
+        // Set started flag
+        self.started = true;
+
+        Ok(())
+    }
+
+    /// Tries to stop this timer.
+    ///
+    /// An error will be returned if the timer has already been canceled or was never started.
+    /// An error is also returned if the timer is not `Periodic` and has already expired.
+    fn cancel(&mut self) -> Result<(), Self::Error> {
+        if !self.started {
+            Err("cannot cancel stopped timer")
+        } else {
+            self.started = false;
+            Ok(())
+        }
+    }
+
+    /// Wait until timer `duration` has expired.
+    /// Must return `nb::Error::WouldBlock` if timer `duration` is not yet over.
+    /// Must return `OK(())` as soon as timer `duration` has expired.
+    fn wait(&mut self) -> nb::Result<(), Self::Error> {
+        let now = StdInstant::now();
+        if (now - self.start).as_millis() > self.duration_ms.into() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+/// The serial TX half on top of a writer of the standard library, e.g. a
+/// port opened with the `serialport` crate.
+pub struct SerialWriter<W>(pub W);
+
+/// An I/O error of a [`SerialWriter`].
+#[derive(Debug)]
+pub struct SerialError(pub io::Error);
+
+impl serial::Error for SerialError {
+    fn kind(&self) -> serial::ErrorKind {
+        serial::ErrorKind::Other
+    }
+}
+
+impl<W: io::Write> serial::nb::Write<u8> for SerialWriter<W> {
+    type Error = SerialError;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        match self.0.write(&[word]) {
+            Ok(1) => Ok(()),
+            Ok(_) => Err(nb::Error::WouldBlock),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.0.flush().map_err(io_error)
+    }
+}
+
+/// Map timeouts and interruptions to `WouldBlock`, so they are retried.
+fn io_error(e: io::Error) -> nb::Error<SerialError> {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted => {
+            nb::Error::WouldBlock
+        }
+        _ => nb::Error::Other(SerialError(e)),
+    }
+}
+
+/// Spawn a thread that reads from the serial port and feeds the ingress
+/// manager.
+///
+/// Read timeouts and interruptions are ignored. The thread ends on any other
+/// read error, which is returned when joining it.
+pub fn spawn_reader<
+    R,
+    D,
+    const BUF_LEN: usize,
+    const RES_CAPACITY: usize,
+    const URC_CAPACITY: usize,
+>(
+    mut serial_rx: R,
+    mut ingress: IngressManager<D, BUF_LEN, RES_CAPACITY, URC_CAPACITY>,
+) -> io::Result<JoinHandle<io::Error>>
+where
+    R: io::Read + Send + 'static,
+    D: Digester + Send + 'static,
+{
+    thread::Builder::new()
+        .name("serial_read".into())
+        .spawn(move || loop {
+            let mut buffer = [0; 32];
+            match serial_rx.read(&mut buffer[..]) {
+                Ok(bytes_read) => ingress.write(&buffer[0..bytes_read]),
+                Err(e) => match e.kind() {
+                    io::ErrorKind::WouldBlock
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted => {
+                        // Ignore
+                    }
+                    _ => return e,
+                },
+            }
+            ingress.digest();
+            ingress.digest();
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let mut timer = SysTimer::new();
+
+        // Wait 500 ms
+        let before = StdInstant::now();
+        timer
+            .start(fugit::Duration::<u32, 1, 1000>::from_ticks(500))
+            .unwrap();
+        nb::block!(timer.wait()).unwrap();
+        let after = StdInstant::now();
+
+        let duration_ms = (after - before).as_millis();
+        assert!(duration_ms >= 500);
+        assert!(duration_ms < 1000);
+    }
+}
@@ -0,0 +1,400 @@
+//! HTTP requests over TCP connections.
+//!
+//! Unlike the HTTP client built into ESP-AT firmwares (see
+//! [`EspClient::http_request`](../struct.EspClient.html#method.http_request)),
+//! this works with every firmware and streams the response body, so that it
+//! doesn't have to fit into memory at once. Requests are sent as HTTP/1.0,
+//! which keeps servers from using chunked transfer encoding, and the
+//! connection is closed by the server after the response. For TLS, open
+//! the socket with
+//! [`TcpSocket::connect_ssl`](../socket/struct.TcpSocket.html#method.connect_ssl)
+//! or use [`https_get`](fn.https_get.html).
+//!
+//! ```ignore
+//! let mut socket = TcpSocket::connect(&mut esp, mux, remote)?;
+//! let request = Request::get("example.com", "/");
+//! let mut response = http::send(&mut esp, &mut socket, &request, &mut delay, 5_000)?;
+//! loop {
+//!     let len = response.read_body(&mut esp, &mut socket, &mut buf, &mut delay, 5_000)?;
+//!     if len == 0 {
+//!         break;
+//!     }
+//!     // Process buf[..len]
+//! }
+//! ```
+
+use core::{fmt::Write, net::SocketAddr};
+
+use atat::AtatClient;
+use embedded_hal::delay::blocking::DelayUs;
+use heapless::Vec;
+
+pub use crate::commands::http::Method;
+use crate::{
+    socket::{Received, TcpSocket},
+    types, EspClient, EspError, EspResult,
+};
+
+/// Maximum length of the request line and headers of a request.
+pub const MAX_HEAD_LEN: usize = 512;
+
+/// Maximum length of a response header line that is interpreted, longer
+/// lines are skipped.
+const MAX_LINE_LEN: usize = 128;
+
+/// An HTTP request.
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    pub method: Method,
+    /// Value of the `Host` header
+    pub host: &'a str,
+    /// Path and query, e.g. `/api/status?verbose=1`
+    pub path: &'a str,
+    /// Additional headers as name and value, e.g. `("Accept", "text/plain")`
+    pub headers: &'a [(&'a str, &'a str)],
+    /// Content type and request body
+    pub body: Option<(&'a str, &'a [u8])>,
+}
+
+impl<'a> Request<'a> {
+    /// Create a GET request.
+    pub fn get(host: &'a str, path: &'a str) -> Self {
+        Self {
+            method: Method::Get,
+            host,
+            path,
+            headers: &[],
+            body: None,
+        }
+    }
+
+    /// Create a POST request with the specified body.
+    pub fn post(host: &'a str, path: &'a str, content_type: &'a str, body: &'a [u8]) -> Self {
+        Self {
+            method: Method::Post,
+            host,
+            path,
+            headers: &[],
+            body: Some((content_type, body)),
+        }
+    }
+
+    /// Add headers to the request.
+    pub fn with_headers(self, headers: &'a [(&'a str, &'a str)]) -> Self {
+        Self { headers, ..self }
+    }
+
+    /// Encode the request line and headers.
+    fn write_head(&self, buf: &mut Vec<u8, MAX_HEAD_LEN>) -> core::fmt::Result {
+        write!(buf, "{} {} HTTP/1.0\r\n", self.method.as_str(), self.path)?;
+        write!(buf, "Host: {}\r\n", self.host)?;
+        if let Some((content_type, body)) = self.body {
+            write!(buf, "Content-Type: {}\r\n", content_type)?;
+            write!(buf, "Content-Length: {}\r\n", body.len())?;
+        }
+        for (name, value) in self.headers {
+            write!(buf, "{}: {}\r\n", name, value)?;
+        }
+        write!(buf, "\r\n")
+    }
+}
+
+/// The status and headers of a response, whose body is read with
+/// [`read_body`](#method.read_body).
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Response {
+    /// Status code, e.g. 200
+    pub status: u16,
+    /// Length of the body, if announced by the server
+    pub content_length: Option<usize>,
+    /// Number of body bytes that were not read yet, if known
+    remaining: Option<usize>,
+}
+
+impl Response {
+    /// Whether the status code indicates success (2xx).
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Read the next part of the body into `buf` and return the number of
+    /// bytes read, waiting at most `timeout_ms`.
+    ///
+    /// Returns `Ok(0)` once the whole body was read.
+    pub fn read_body<C, D, const N: usize>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        socket: &mut TcpSocket<N>,
+        buf: &mut [u8],
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> EspResult<usize>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        let len = match self.remaining {
+            Some(0) => return Ok(0),
+            Some(remaining) => remaining.min(buf.len()),
+            None => buf.len(),
+        };
+        match socket.recv_timeout(esp, &mut buf[..len], delay, timeout_ms)? {
+            Received::Data(read) => {
+                self.remaining = self.remaining.map(|remaining| remaining - read);
+                Ok(read)
+            }
+            // The body of a response without length ends with the connection
+            Received::Closed if self.remaining.is_none() => Ok(0),
+            Received::Closed => Err(nb::Error::Other(EspError::ConnectionClosed)),
+            Received::TimedOut => Err(nb::Error::Other(EspError::Timeout)),
+        }
+    }
+}
+
+/// Send a request over an open connection and read the status and headers
+/// of the response.
+///
+/// Every read waits at most `timeout_ms`. Fails with `EspError::Overflow` if
+/// the request line and headers are longer than
+/// [`MAX_HEAD_LEN`](constant.MAX_HEAD_LEN.html), and with `EspError::Parse`
+/// if the response is not HTTP.
+pub fn send<C, D, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    socket: &mut TcpSocket<N>,
+    request: &Request,
+    delay: &mut D,
+    timeout_ms: u32,
+) -> EspResult<Response>
+where
+    C: AtatClient,
+    D: DelayUs,
+{
+    let mut head = Vec::new();
+    request
+        .write_head(&mut head)
+        .map_err(|_| nb::Error::Other(EspError::Overflow))?;
+    socket.send(esp, &head)?;
+    if let Some((_, body)) = request.body.filter(|(_, body)| !body.is_empty()) {
+        socket.send(esp, body)?;
+    }
+
+    let mut line = [0; MAX_LINE_LEN];
+    let status_line = read_line(esp, socket, &mut line, delay, timeout_ms)?;
+    let status = parse_status_line(status_line).ok_or(nb::Error::Other(EspError::Parse))?;
+
+    let mut content_length = None;
+    loop {
+        let header = read_line(esp, socket, &mut line, delay, timeout_ms)?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some(len) = parse_content_length(header) {
+            content_length = len;
+        }
+    }
+    Ok(Response {
+        status,
+        content_length,
+        remaining: content_length,
+    })
+}
+
+/// Fetch an `https://` URL into `buf` and return the status code and the
+/// length of the body.
+///
+/// The hostname is resolved, a TLS connection is opened on the link `mux`
+/// and closed again afterwards. On ESP-AT firmwares, the hostname is
+/// announced with SNI, which most cloud endpoints require. Every read waits
+/// at most `timeout_ms`. Fails with `EspError::Overflow` if the body doesn't
+/// fit into `buf`, and with `EspError::InvalidArgument` if the URL is not
+/// valid.
+///
+/// This blocks until the body was received, even in non-blocking mode.
+pub fn https_get<C, D, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    mux: types::MultiplexingType,
+    url: &str,
+    buf: &mut [u8],
+    delay: &mut D,
+    timeout_ms: u32,
+) -> EspResult<(u16, usize)>
+where
+    C: AtatClient,
+    D: DelayUs,
+{
+    let (host, port, path) =
+        parse_https_url(url).ok_or(nb::Error::Other(EspError::InvalidArgument))?;
+    let ip = esp.resolve_hostname(host)?;
+    let server_name = match esp.dialect() {
+        types::Dialect::EspAt => Some(host),
+        types::Dialect::NonOs => None,
+    };
+    let mut socket =
+        TcpSocket::connect_ssl(esp, mux, SocketAddr::new(ip.into(), port), server_name)?;
+    let result = fetch(
+        esp,
+        &mut socket,
+        &Request::get(host, path),
+        buf,
+        delay,
+        timeout_ms,
+    );
+    socket.close(esp)?;
+    result
+}
+
+/// Send a request and read the whole body into `buf`.
+fn fetch<C, D, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    socket: &mut TcpSocket<N>,
+    request: &Request,
+    buf: &mut [u8],
+    delay: &mut D,
+    timeout_ms: u32,
+) -> EspResult<(u16, usize)>
+where
+    C: AtatClient,
+    D: DelayUs,
+{
+    let mut response = send(esp, socket, request, delay, timeout_ms)?;
+    if response.content_length.unwrap_or_default() > buf.len() {
+        return Err(nb::Error::Other(EspError::Overflow));
+    }
+    let mut len = 0;
+    loop {
+        if len == buf.len() {
+            // Make sure that the body is complete
+            return match response.read_body(esp, socket, &mut [0], delay, timeout_ms)? {
+                0 => Ok((response.status, len)),
+                _ => Err(nb::Error::Other(EspError::Overflow)),
+            };
+        }
+        match response.read_body(esp, socket, &mut buf[len..], delay, timeout_ms)? {
+            0 => return Ok((response.status, len)),
+            read => len += read,
+        }
+    }
+}
+
+/// Return the status code of a status line, e.g. `HTTP/1.1 200 OK`.
+fn parse_status_line(line: &str) -> Option<u16> {
+    line.strip_prefix("HTTP/1.")
+        .and_then(|rest| rest.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+}
+
+/// Return the value of a `Content-Length` header, `None` for other headers.
+///
+/// Invalid lengths are ignored, i.e. the body is read until the connection
+/// is closed.
+fn parse_content_length(header: &str) -> Option<Option<usize>> {
+    let (name, value) = header.split_once(':')?;
+    if !name.eq_ignore_ascii_case("Content-Length") {
+        return None;
+    }
+    Some(value.trim().parse().ok())
+}
+
+/// Split an `https://` URL into host, port and path.
+fn parse_https_url(url: &str) -> Option<(&str, u16, &str)> {
+    let rest = url.strip_prefix("https://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.split_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, 443),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port, path))
+}
+
+/// Read a line and return it without the line break.
+///
+/// Lines that are longer than `buf` are truncated, lines that are not UTF-8
+/// are returned as empty.
+fn read_line<'b, C, D, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    socket: &mut TcpSocket<N>,
+    buf: &'b mut [u8],
+    delay: &mut D,
+    timeout_ms: u32,
+) -> EspResult<&'b str>
+where
+    C: AtatClient,
+    D: DelayUs,
+{
+    let len = match socket.read_until(esp, buf, b'\n', delay, timeout_ms) {
+        Ok(len) => len,
+        Err(nb::Error::Other(EspError::Overflow)) => {
+            // Skip the rest of the line
+            let mut skipped = [0; 16];
+            loop {
+                match socket.read_until(esp, &mut skipped, b'\n', delay, timeout_ms) {
+                    Ok(_) => break buf.len(),
+                    Err(nb::Error::Other(EspError::Overflow)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Err(e) => return Err(e),
+    };
+    let line = &buf[..len];
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Ok(core::str::from_utf8(line).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(parse_status_line("HTTP/1.1 200 OK"), Some(200));
+        assert_eq!(parse_status_line("HTTP/1.0 404 Not Found"), Some(404));
+        // The reason phrase is optional
+        assert_eq!(parse_status_line("HTTP/1.1 204"), Some(204));
+        assert_eq!(parse_status_line("HTTP/1.1 OK"), None);
+        assert_eq!(parse_status_line("SSH-2.0-OpenSSH_8.9"), None);
+    }
+
+    #[test]
+    fn test_parse_content_length() {
+        assert_eq!(parse_content_length("content-length: 42"), Some(Some(42)));
+        assert_eq!(parse_content_length("Content-Length: -1"), Some(None));
+        assert_eq!(parse_content_length("Content-Type: text/plain"), None);
+    }
+
+    #[test]
+    fn test_parse_https_url() {
+        assert_eq!(
+            parse_https_url("https://example.com:8443/api?x=1"),
+            Some(("example.com", 8443, "/api?x=1"))
+        );
+        assert_eq!(
+            parse_https_url("https://example.com"),
+            Some(("example.com", 443, "/"))
+        );
+        assert_eq!(parse_https_url("http://example.com/"), None);
+        assert_eq!(parse_https_url("https://:443/"), None);
+    }
+
+    #[test]
+    fn test_write_head() {
+        let mut head = Vec::new();
+        Request::post("example.com", "/api", "application/json", b"{}")
+            .with_headers(&[("Accept", "*/*")])
+            .write_head(&mut head)
+            .unwrap();
+        assert_eq!(
+            core::str::from_utf8(&head).unwrap(),
+            "POST /api HTTP/1.0\r\nHost: example.com\r\nContent-Type: application/json\r\n\
+            Content-Length: 2\r\nAccept: */*\r\n\r\n"
+        );
+    }
+}
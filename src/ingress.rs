@@ -0,0 +1,74 @@
+//! Feeding the bytes received from the device to the client.
+
+use atat::{Digester, IngressManager};
+use embedded_hal::serial;
+
+/// Number of bytes that are read before they are passed on to the ingress
+/// manager.
+const CHUNK_LEN: usize = 32;
+
+/// Owns the RX half of the serial port and the ingress manager returned by
+/// the client constructors.
+///
+/// [`pump`](#method.pump) needs to be called regularly, e.g. from the UART
+/// interrupt or a dedicated task, so that the client receives responses and
+/// URCs.
+pub struct IngressPump<
+    RX,
+    D,
+    const BUF_LEN: usize,
+    const RES_CAPACITY: usize,
+    const URC_CAPACITY: usize,
+> where
+    RX: serial::nb::Read<u8>,
+    D: Digester,
+{
+    rx: RX,
+    ingress: IngressManager<D, BUF_LEN, RES_CAPACITY, URC_CAPACITY>,
+}
+
+impl<RX, D, const BUF_LEN: usize, const RES_CAPACITY: usize, const URC_CAPACITY: usize>
+    IngressPump<RX, D, BUF_LEN, RES_CAPACITY, URC_CAPACITY>
+where
+    RX: serial::nb::Read<u8>,
+    D: Digester,
+{
+    pub fn new(rx: RX, ingress: IngressManager<D, BUF_LEN, RES_CAPACITY, URC_CAPACITY>) -> Self {
+        Self { rx, ingress }
+    }
+
+    /// Read all bytes that are available and digest them.
+    ///
+    /// Every call digests at least once, even if no bytes were available, so
+    /// that responses and URCs that are still buffered are processed.
+    pub fn pump(&mut self) -> Result<(), RX::Error> {
+        loop {
+            let mut chunk = [0; CHUNK_LEN];
+            let mut len = 0;
+            let mut result = Ok(());
+            while len < CHUNK_LEN {
+                match self.rx.read() {
+                    Ok(byte) => {
+                        chunk[len] = byte;
+                        len += 1;
+                    }
+                    Err(nb::Error::WouldBlock) => break,
+                    Err(nb::Error::Other(e)) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            self.ingress.write(&chunk[..len]);
+            self.ingress.digest();
+            if len < CHUNK_LEN || result.is_err() {
+                return result;
+            }
+        }
+    }
+
+    /// Return the RX half and the ingress manager.
+    pub fn release(self) -> (RX, IngressManager<D, BUF_LEN, RES_CAPACITY, URC_CAPACITY>) {
+        (self.rx, self.ingress)
+    }
+}
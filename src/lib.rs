@@ -2,44 +2,421 @@
 
 #![no_std]
 
-use atat::{clock::Clock, digest::ParseError, AtatClient, ClientBuilder, DefaultDigester, Queues};
-use embedded_hal::serial;
-use heapless::String;
+#[cfg(feature = "std")]
+extern crate std;
 
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use atat::{clock::Clock, digest::ParseError, AtatClient, ClientBuilder, Queues};
+use embedded_hal::{delay::blocking::DelayUs, digital::blocking::OutputPin, serial};
+use heapless::{Deque, String, Vec};
+
+#[cfg(feature = "ble")]
+pub mod ble;
 pub mod commands;
+pub mod digest;
+pub mod dns;
+#[cfg(feature = "embassy")]
+pub mod embassy;
+pub mod error;
+pub mod events;
+#[cfg(feature = "std")]
+pub mod host;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod ingress;
+#[cfg(feature = "std")]
+pub mod mock;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "pki")]
+pub mod pki;
+#[cfg(feature = "provisioning")]
+pub mod provisioning;
+pub mod resources;
+pub mod retry;
+pub mod roaming;
+pub mod rssi;
+#[cfg(feature = "rtic")]
+pub mod rtic;
+pub mod socket;
+pub mod supervisor;
+#[cfg(feature = "embedded-svc")]
+pub mod svc;
+mod trace;
+#[cfg(feature = "std")]
+pub mod transcript;
 pub mod types;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
-use commands::{requests, responses};
+use commands::{
+    gpio, requests, responses,
+    urcs::{self, Urc},
+};
+pub use error::EspError;
+pub use resources::StaticResources;
 use types::ConfigWithDefault;
 
-/// Type alias for a result that may return an ATAT error.
-pub type EspResult<T> = Result<T, nb::Error<atat::Error>>;
+/// Number of `AT` probes sent while waiting for the device to boot.
+///
+/// Every probe is bounded by the timeout of the AT test command (1 s).
+const BOOT_PROBE_ATTEMPTS: u8 = 5;
+
+/// Default backoff for retrying a command while the device is busy.
+const DEFAULT_BUSY_BACKOFF: retry::Exponential = retry::Exponential {
+    retries: 3,
+    initial_delay_ms: 100,
+    max_delay_ms: 1_000,
+};
+
+/// Duration for which the RST line is pulled low during a hard reset.
+const RESET_PULSE_MS: u32 = 10;
+
+/// Interval in which the local address is queried while waiting for DHCP.
+const IP_POLL_INTERVAL_MS: u32 = 100;
+
+/// Interval in which an abortable operation is polled.
+const ABORT_POLL_INTERVAL_MS: u32 = 10;
+
+/// Baud rates that ESP8266 modules commonly ship with, most common first.
+///
+/// Useful as argument to
+/// [`EspClient::detect_baud_rate`](struct.EspClient.html#method.detect_baud_rate).
+pub const COMMON_BAUD_RATES: [u32; 3] = [115_200, 74_880, 9_600];
+
+/// Size of the ingress buffer of the clients created with
+/// [`EspClient::new`](struct.EspClient.html#method.new).
+///
+/// The buffer must hold the longest response, which is the list of
+/// supported commands that [`init`](struct.EspClient.html#method.init)
+/// queries from ESP-AT firmwares. The response queue must be just as large.
+/// With NONOS firmwares, a smaller buffer can be used with
+/// [`new_with_digester`](struct.EspClient.html#method.new_with_digester).
+pub const INGRESS_BUF_LEN: usize = 12 * 1024;
+
+/// Type alias for a result that may return an [`EspError`](error/enum.EspError.html).
+pub type EspResult<T> = Result<T, nb::Error<EspError>>;
 
 /// URC parser
 pub enum UrcParser {}
 
+/// Prefixes of single-line URCs.
+const URC_PREFIXES: &[&[u8]] = &[
+    b"busy ",
+    b"link is not valid",
+    b"ALREADY CONNECTED",
+    b"ERR CODE:",
+    b"+LINK_CONN:",
+    b"+QUITT",
+    b"+CIPUPDATE:",
+    #[cfg(feature = "mqtt")]
+    b"+MQTTCONNECTED:",
+    #[cfg(feature = "mqtt")]
+    b"+MQTTDISCONNECTED:",
+    #[cfg(feature = "ble")]
+    b"+BLECONN:",
+    #[cfg(feature = "ble")]
+    b"+BLEDISCONN:",
+    #[cfg(feature = "websocket")]
+    b"+WS_CONNECTED:",
+    #[cfg(feature = "websocket")]
+    b"+WS_DISCONNECTED:",
+    #[cfg(feature = "websocket")]
+    b"+WS_CLOSED:",
+];
+
+/// Whether `line` is a single-line URC without a common prefix.
+fn is_status_line(line: &[u8]) -> bool {
+    match line {
+        b"WIFI CONNECTED" | b"WIFI GOT IP" | b"WIFI DISCONNECT" | b"CONNECT" | b"CLOSED" => true,
+        [link_id, b',', status @ ..] if status == b"CONNECT" || status == b"CLOSED" => {
+            link_id.is_ascii_digit()
+        }
+        _ => urcs::parse_segment_status(line).is_some(),
+    }
+}
+
 impl atat::Parser for UrcParser {
-    fn parse(_buf: &[u8]) -> Result<(&[u8], usize), ParseError> {
+    fn parse(buf: &[u8]) -> Result<(&[u8], usize), ParseError> {
+        // After a reset, the device prints boot messages (partially at a
+        // different baud rate) before announcing that it's ready. Everything
+        // up to and including the "ready" line is consumed.
+        if let Some(end) = find_ready_line(buf) {
+            return Ok((b"ready", end));
+        }
+
+        let start = buf
+            .iter()
+            .take_while(|&&b| b == b'\r' || b == b'\n')
+            .count();
+        let line = &buf[start..];
+
+        // MQTT messages may contain line breaks, so they are framed by their
+        // length field instead.
+        #[cfg(feature = "mqtt")]
+        {
+            const MQTT_MESSAGE: &[u8] = b"+MQTTSUBRECV:";
+            if line.starts_with(MQTT_MESSAGE) {
+                let len = mqtt_message_len(line)?;
+                return match line.get(len..len + 2) {
+                    Some(b"\r\n") => Ok((&line[..len], start + len + 2)),
+                    Some(_) => Ok((&line[..len], start + len)),
+                    None => Err(ParseError::Incomplete),
+                };
+            } else if !line.is_empty() && MQTT_MESSAGE.starts_with(line) {
+                return Err(ParseError::Incomplete);
+            }
+        }
+
+        // Other payloads are framed by a length field. The tuples contain the
+        // URC prefix and the number of fields before the length field.
+        #[cfg(any(feature = "ble", feature = "websocket"))]
+        const FRAMED_URCS: &[(&[u8], usize)] = &[
+            #[cfg(feature = "ble")]
+            (b"+WRITE:", 4),
+            #[cfg(feature = "websocket")]
+            (b"+WS_DATA:", 1),
+        ];
+        #[cfg(any(feature = "ble", feature = "websocket"))]
+        for (prefix, len_field) in FRAMED_URCS {
+            if line.starts_with(prefix) {
+                let len = framed_urc_len(line, *len_field)?;
+                return match line.get(len..len + 2) {
+                    Some(b"\r\n") => Ok((&line[..len], start + len + 2)),
+                    Some(_) => Ok((&line[..len], start + len)),
+                    None => Err(ParseError::Incomplete),
+                };
+            } else if !line.is_empty() && prefix.starts_with(line) {
+                return Err(ParseError::Incomplete);
+            }
+        }
+
+        if let Some(len) = find(line, b"\r\n") {
+            if is_status_line(&line[..len]) {
+                return Ok((&line[..len], start + len + 2));
+            }
+        }
+
+        // Join failure reasons. Responses to `AT+CWJAP?` share the prefix,
+        // but are followed by a quoted SSID instead of a number.
+        const JOIN_FAILURE: &[u8] = b"+CWJAP:";
+        if line.starts_with(JOIN_FAILURE) {
+            return match line.get(JOIN_FAILURE.len()) {
+                Some(b) if b.is_ascii_digit() => match find(line, b"\r\n") {
+                    Some(len) => Ok((&line[..len], start + len + 2)),
+                    None => Err(ParseError::Incomplete),
+                },
+                Some(_) => Err(ParseError::NoMatch),
+                None => Err(ParseError::Incomplete),
+            };
+        } else if !line.is_empty() && JOIN_FAILURE.starts_with(line) {
+            return Err(ParseError::Incomplete);
+        }
+
+        for prefix in URC_PREFIXES {
+            if line.starts_with(prefix) {
+                return match find(line, b"\r\n") {
+                    Some(len) => Ok((&line[..len], start + len + 2)),
+                    None => Err(ParseError::Incomplete),
+                };
+            } else if !line.is_empty() && prefix.starts_with(line) {
+                return Err(ParseError::Incomplete);
+            }
+        }
         Err(ParseError::NoMatch)
     }
 }
 
+/// Return the index after the first complete "ready" line in `buf`, if any.
+///
+/// A "ready" line that follows a final result code or an `+IPD` message is
+/// ignored, so that the pending response or data is digested first. This
+/// also keeps "ready" lines within received data from being mistaken for a
+/// restart.
+fn find_ready_line(buf: &[u8]) -> Option<usize> {
+    const READY: &[u8] = b"ready\r\n";
+    let mut line_start = 0;
+    while line_start < buf.len() {
+        let line = &buf[line_start..];
+        if line.starts_with(READY) {
+            let garbage = &buf[..line_start];
+            if find(garbage, b"OK\r\n").is_some() || find(garbage, b"ERROR\r\n").is_some() {
+                return None;
+            }
+            return Some(line_start + READY.len());
+        }
+        if let Ok(_) | Err(ParseError::Incomplete) = urcs::IpdHeader::parse(line) {
+            return None;
+        }
+        line_start += find(line, b"\n")? + 1;
+    }
+    None
+}
+
+/// Return the length of the `+MQTTSUBRECV:<LinkID>,"<topic>",<len>,<data>` URC
+/// at the start of `line`.
+#[cfg(feature = "mqtt")]
+fn mqtt_message_len(line: &[u8]) -> Result<usize, ParseError> {
+    let topic_start = find(line, b",\"").ok_or(ParseError::Incomplete)? + 2;
+    let topic_len = find(&line[topic_start..], b"\",").ok_or(ParseError::Incomplete)?;
+    let len_start = topic_start + topic_len + 2;
+    let len_len = find(&line[len_start..], b",").ok_or(ParseError::Incomplete)?;
+    let len: usize = core::str::from_utf8(&line[len_start..len_start + len_len])
+        .ok()
+        .and_then(|len| len.parse().ok())
+        .ok_or(ParseError::NoMatch)?;
+    let end = (len_start + len_len + 1)
+        .checked_add(len)
+        .ok_or(ParseError::NoMatch)?;
+    if line.len() < end {
+        Err(ParseError::Incomplete)
+    } else {
+        Ok(end)
+    }
+}
+
+/// Return the length of a URC at the start of `line` whose payload is
+/// preceded by a length field, with `len_field` fields before it.
+#[cfg(any(feature = "ble", feature = "websocket"))]
+fn framed_urc_len(line: &[u8], len_field: usize) -> Result<usize, ParseError> {
+    let mut len_start = 0;
+    for _ in 0..len_field {
+        len_start += find(&line[len_start..], b",").ok_or(ParseError::Incomplete)? + 1;
+    }
+    let len_len = find(&line[len_start..], b",").ok_or(ParseError::Incomplete)?;
+    let len: usize = core::str::from_utf8(&line[len_start..len_start + len_len])
+        .ok()
+        .and_then(|len| len.parse().ok())
+        .ok_or(ParseError::NoMatch)?;
+    let end = (len_start + len_len + 1)
+        .checked_add(len)
+        .ok_or(ParseError::NoMatch)?;
+    if line.len() < end {
+        Err(ParseError::Incomplete)
+    } else {
+        Ok(end)
+    }
+}
+
+/// Return the index of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
 /// An ESP8266 client.
-pub struct EspClient<
-    TX,
-    CLK,
-    const TIMER_HZ: u32,
-    const RES_CAPACITY: usize,
-    const URC_CAPACITY: usize,
-> where
-    TX: serial::nb::Write<u8>,
-    CLK: Clock<TIMER_HZ>,
-{
-    client: atat::Client<TX, CLK, TIMER_HZ, RES_CAPACITY, URC_CAPACITY>,
+///
+/// The client is generic over the underlying AT client, which is usually an
+/// `atat::Client` created by one of the `new` constructors. Other
+/// implementations (e.g. mocks for tests) can be used with
+/// [`from_client`](#method.from_client).
+pub struct EspClient<C: AtatClient, const N: usize = { urcs::MAX_DATA_LEN }> {
+    client: C,
+    /// The state that doesn't depend on the capacity of received network data
+    state: ClientState,
+    /// Marks the capacity of received network data
+    data_capacity: PhantomData<[u8; N]>,
+}
+
+/// The state of an [`EspClient`] that doesn't depend on the capacity of
+/// received network data.
+struct ClientState {
+    /// The chip that the AT firmware runs on
+    chip: types::Chip,
+    /// The firmware dialect used to serialize and parse commands
+    dialect: types::Dialect,
+    /// The optional features supported by the firmware, if known
+    capabilities: Option<types::Capabilities>,
+    /// Offset of the SNTP timezone from UTC in minutes, if known
+    utc_offset_min: Option<i16>,
+    /// The configuration applied by `init`, with later changes of the WiFi
+    /// mode, replayed after a reset
+    init_config: Option<types::InitConfig>,
+    /// The receive mode, replayed after a reset
+    receive_mode: types::ReceiveMode,
+    /// Whether extended error codes are enabled, replayed after a reset
+    error_codes: bool,
+    /// Whether ESP-AT firmwares persist configuration changes
+    /// (`AT+SYSSTORE`), if known
+    store_enabled: Option<bool>,
+    /// How often and after which delay a command is retried while the
+    /// device is busy
+    busy_backoff: retry::Exponential,
+    /// Number of times the current command failed because the device was
+    /// busy
+    busy_attempts: u8,
+    /// Time after which the current command is retried, if a time source is
+    /// set, in non-blocking mode
+    busy_until_ms: Option<u64>,
+    /// Blocks for the given number of milliseconds before a busy command is
+    /// retried, in blocking mode
+    busy_delay: Option<fn(u32)>,
+    /// Whether a command was sent, but its response not received yet
+    command_pending: bool,
+    /// Whether a command was cancelled, whose late response must be
+    /// discarded before the next command
+    cancelled: bool,
+    /// The connection and length announced with `AT+CIPSEND` that the data
+    /// must match
+    prepared: Option<(types::MultiplexingType, usize)>,
+    /// Bitmask of open connections, indexed by link ID
+    open_links: u8,
+    /// Bitmask of connections closed since the last readiness poll
+    closed_links: u8,
+    /// Bitmask of connections accepted by the server that were not taken
+    /// yet
+    accepted_links: u8,
+    /// Bitmask of connections with data buffered by the device, in passive
+    /// receive mode
+    available_links: u8,
+    /// Traffic statistics, indexed by link ID
+    stats: [types::ConnectionStats; types::MAX_CONNECTIONS],
+    /// Number of segments that `send` keeps in flight
+    send_window: u8,
+    /// ID of the last segment written with `AT+CIPSENDBUF` that was sent,
+    /// indexed by link ID
+    last_sent_segments: [u32; types::MAX_CONNECTIONS],
+    /// Bitmask of connections on which a segment written with
+    /// `AT+CIPSENDBUF` failed
+    failed_segments: u8,
+    /// Returns the current time in milliseconds
+    time_source: Option<fn() -> u64>,
+    /// Latency and failure statistics per command, if enabled
+    metrics: Option<Vec<types::CommandMetrics, { types::MAX_COMMAND_METRICS }>>,
+    /// Time the pending command was sent, if a time source is set
+    command_sent_ms: Option<u64>,
+    /// Name of the pending command, for the metrics
+    command_name: String<16>,
+    /// Whether the device has announced that it's ready since the last reset
+    ready: bool,
+    /// Whether the device announced that it's ready again without a reset
+    rebooted: bool,
+    /// Whether the station is connected to an access point
+    wifi_connected: bool,
+    /// Whether the station obtained an IP address
+    got_ip: bool,
+    /// Whether `WIFI DISCONNECT` was received since it was last reported
+    wifi_lost: bool,
+    /// Events reported since they were last passed to a handler
+    events: Deque<events::Event, { events::MAX_PENDING_EVENTS }>,
+    /// Whether the MQTT client is connected to a broker
+    #[cfg(feature = "mqtt")]
+    mqtt_connected: bool,
+    /// Bitmask of connected Bluetooth LE peers, indexed by connection index
+    #[cfg(feature = "ble")]
+    ble_connections: u8,
+    /// Which WebSocket connections are open, indexed by link ID
+    #[cfg(feature = "websocket")]
+    websocket_links: [bool; websocket::MAX_LINKS],
 }
 
 impl<TX, CLK, const TIMER_HZ: u32, const RES_CAPACITY: usize, const URC_CAPACITY: usize>
-    EspClient<TX, CLK, TIMER_HZ, RES_CAPACITY, URC_CAPACITY>
+    EspClient<atat::Client<TX, CLK, TIMER_HZ, RES_CAPACITY, URC_CAPACITY>>
 where
     TX: serial::nb::Write<u8>,
     CLK: Clock<TIMER_HZ>,
@@ -56,83 +433,2390 @@ where
         queues: Queues<RES_CAPACITY, URC_CAPACITY>,
     ) -> (
         Self,
-        atat::IngressManager<
-            DefaultDigester<UrcParser>,
-            6000, // BUF_LEN: Number of incoming bytes that can be handled
-            RES_CAPACITY,
-            URC_CAPACITY,
-        >,
+        atat::IngressManager<digest::EspDigester, INGRESS_BUF_LEN, RES_CAPACITY, URC_CAPACITY>,
+    ) {
+        Self::new_with_config(
+            serial_tx,
+            timer,
+            queues,
+            atat::Config::new(atat::Mode::Blocking),
+        )
+    }
+
+    /// Create a new ESP8266 client in non-blocking mode.
+    ///
+    /// Methods that send a single command return `nb::Error::WouldBlock`
+    /// until the response has arrived and must be called again with the
+    /// same arguments, e.g. using `nb::block!` or from a superloop. Methods
+    /// that send a sequence of commands (like [`init`](#method.init)) still
+    /// block until the whole sequence has completed.
+    pub fn new_non_blocking(
+        serial_tx: TX,
+        timer: CLK,
+        queues: Queues<RES_CAPACITY, URC_CAPACITY>,
+    ) -> (
+        Self,
+        atat::IngressManager<digest::EspDigester, INGRESS_BUF_LEN, RES_CAPACITY, URC_CAPACITY>,
+    ) {
+        Self::new_with_config(
+            serial_tx,
+            timer,
+            queues,
+            atat::Config::new(atat::Mode::NonBlocking),
+        )
+    }
+
+    /// Create a new ESP8266 client with a custom configuration of the
+    /// underlying AT client.
+    ///
+    /// This allows tuning e.g. the mode, the command cooldown and the flush
+    /// timeout. See [`new_non_blocking`](#method.new_non_blocking) for the
+    /// implications of the non-blocking mode.
+    pub fn new_with_config(
+        serial_tx: TX,
+        timer: CLK,
+        queues: Queues<RES_CAPACITY, URC_CAPACITY>,
+        config: atat::Config,
+    ) -> (
+        Self,
+        atat::IngressManager<digest::EspDigester, INGRESS_BUF_LEN, RES_CAPACITY, URC_CAPACITY>,
+    ) {
+        Self::new_with_digester(serial_tx, timer, queues, config, digest::EspDigester::new())
+    }
+
+    /// Create a new ESP8266 client with a custom digester.
+    ///
+    /// The digester splits the received bytes into responses and URCs. To
+    /// only change how URCs are matched, pass an
+    /// [`EspDigester`](digest/struct.EspDigester.html) with a custom URC
+    /// parser. Note that the client only understands the URCs known to
+    /// [`Urc`](commands/urcs/enum.Urc.html).
+    ///
+    /// The size of the ingress buffer is selected with `BUF_LEN`. It must
+    /// hold the longest response, see [`INGRESS_BUF_LEN`](constant.INGRESS_BUF_LEN.html)
+    /// for ESP-AT firmwares. With NONOS firmwares, it must hold an `+IPD`
+    /// message with [`MAX_DATA_LEN`](commands/urcs/constant.MAX_DATA_LEN.html)
+    /// bytes and the results of a scan.
+    pub fn new_with_digester<D: atat::Digester, const BUF_LEN: usize>(
+        serial_tx: TX,
+        timer: CLK,
+        queues: Queues<RES_CAPACITY, URC_CAPACITY>,
+        config: atat::Config,
+        digester: D,
+    ) -> (
+        Self,
+        atat::IngressManager<D, BUF_LEN, RES_CAPACITY, URC_CAPACITY>,
     ) {
-        let config = atat::Config::new(atat::Mode::Blocking);
-        let digester = DefaultDigester::new();
         let (client, ingress) =
             ClientBuilder::new(serial_tx, timer, digester, config).build(queues);
-        (Self { client }, ingress)
+        (Self::from_client(client), ingress)
+    }
+}
+
+impl<C: AtatClient> EspClient<C> {
+    /// Create a new ESP8266 client on top of an existing AT client.
+    ///
+    /// The AT client must be set up to split responses and URCs like the
+    /// [`EspDigester`](digest/struct.EspDigester.html) does.
+    pub fn from_client(client: C) -> Self {
+        Self {
+            client,
+            state: ClientState {
+                chip: types::Chip::default(),
+                dialect: types::Dialect::default(),
+                capabilities: None,
+                utc_offset_min: None,
+                init_config: None,
+                receive_mode: types::ReceiveMode::Active,
+                error_codes: false,
+                store_enabled: None,
+                busy_backoff: DEFAULT_BUSY_BACKOFF,
+                busy_attempts: 0,
+                busy_until_ms: None,
+                busy_delay: None,
+                command_pending: false,
+                cancelled: false,
+                prepared: None,
+                open_links: 0,
+                closed_links: 0,
+                accepted_links: 0,
+                available_links: 0,
+                stats: [types::ConnectionStats::default(); types::MAX_CONNECTIONS],
+                send_window: 1,
+                last_sent_segments: [0; types::MAX_CONNECTIONS],
+                failed_segments: 0,
+                time_source: None,
+                metrics: None,
+                command_sent_ms: None,
+                command_name: String::new(),
+                ready: false,
+                rebooted: false,
+                wifi_connected: false,
+                got_ip: false,
+                wifi_lost: false,
+                events: Deque::new(),
+                #[cfg(feature = "mqtt")]
+                mqtt_connected: false,
+                #[cfg(feature = "ble")]
+                ble_connections: 0,
+                #[cfg(feature = "websocket")]
+                websocket_links: [false; websocket::MAX_LINKS],
+            },
+            data_capacity: PhantomData,
+        }
+    }
+}
+
+impl<C: AtatClient, const N: usize> EspClient<C, N> {
+    /// Change the capacity of received network data to `M` bytes.
+    ///
+    /// The default of 1460 bytes fits the largest segments sent by the
+    /// firmware. A smaller capacity reduces the stack usage when polling
+    /// data, but data of larger `+IPD` messages is dropped. The capacity
+    /// must not be changed while data is pending.
+    pub fn with_data_capacity<const M: usize>(self) -> EspClient<C, M> {
+        EspClient {
+            client: self.client,
+            state: self.state,
+            data_capacity: PhantomData,
+        }
+    }
+
+    /// Return the chip that the client expects the AT firmware to run on.
+    pub fn chip(&self) -> types::Chip {
+        self.state.chip
+    }
+
+    /// Set the chip that the AT firmware runs on.
+    ///
+    /// This also switches the client to the firmware dialect usually found on
+    /// that chip. If that doesn't match the firmware on the device, override
+    /// it afterwards with [`set_dialect`](#method.set_dialect).
+    pub fn set_chip(&mut self, chip: types::Chip) {
+        self.state.chip = chip;
+        self.state.dialect = chip.default_dialect();
+    }
+
+    /// Return the firmware dialect that the client speaks.
+    pub fn dialect(&self) -> types::Dialect {
+        self.state.dialect
+    }
+
+    /// Set the firmware dialect that the client speaks.
+    ///
+    /// This must match the firmware running on the device. The default is
+    /// [`Dialect::NonOs`](types/enum.Dialect.html#variant.NonOs).
+    pub fn set_dialect(&mut self, dialect: types::Dialect) {
+        self.state.dialect = dialect;
+    }
+
+    /// Set how many times a command is retried while the device is busy.
+    ///
+    /// A busy device (`busy p...` or `busy s...`) drops the command. The
+    /// default is 3, use 0 to disable retrying. The delay between the
+    /// attempts is set with [`set_busy_backoff`](#method.set_busy_backoff).
+    pub fn set_busy_retries(&mut self, retries: u8) {
+        self.state.busy_backoff.retries = retries;
+        self.state.busy_attempts = 0;
+    }
+
+    /// Set how often and after which delay a command is retried while the
+    /// device is busy.
+    ///
+    /// The default is 3 retries, starting with a delay of 100 ms that is
+    /// doubled up to 1 s. Use equal delays for a fixed backoff.
+    ///
+    /// In blocking mode, the client waits with the function set with
+    /// [`set_busy_delay`](#method.set_busy_delay), without one commands are
+    /// retried immediately. In non-blocking mode, the command returns
+    /// `WouldBlock` until the delay measured with the
+    /// [time source](#method.set_time_source) has passed, without one
+    /// commands are retried immediately as well. The client never spins on
+    /// the clock. To wait with a `DelayUs` instead, disable the retries and wrap
+    /// the operation with [`retry`](#method.retry).
+    pub fn set_busy_backoff(&mut self, backoff: retry::Exponential) {
+        self.state.busy_backoff = backoff;
+        self.state.busy_attempts = 0;
+    }
+
+    /// Set a function that blocks for the given number of milliseconds.
+    ///
+    /// It's used in blocking mode to wait before a command is retried while
+    /// the device is busy, see [`set_busy_backoff`](#method.set_busy_backoff).
+    pub fn set_busy_delay(&mut self, delay_ms: fn(u32)) {
+        self.state.busy_delay = Some(delay_ms);
     }
 
     /// Send a raw command to the device.
+    ///
+    /// If the device tells why the command failed (e.g. `busy p...` or
+    /// `link is not valid`), the corresponding error is returned. Commands
+    /// are retried while the device is busy, see
+    /// [`set_busy_backoff`](#method.set_busy_backoff).
     pub fn send_command<T, const LEN: usize>(&mut self, command: &T) -> EspResult<T::Response>
     where
         T: atat::AtatCmd<LEN>,
     {
-        self.client.send(command)
+        loop {
+            let non_blocking = matches!(self.client.get_mode(), atat::Mode::NonBlocking);
+            if let Some(until_ms) = self.state.busy_until_ms {
+                match self.time_ms() {
+                    Some(now_ms) if now_ms < until_ms && non_blocking => {
+                        return Err(nb::Error::WouldBlock);
+                    }
+                    _ => self.state.busy_until_ms = None,
+                }
+            }
+            match self.send_once(command) {
+                Err(nb::Error::Other(EspError::Busy)) => {
+                    self.state.busy_attempts = self.state.busy_attempts.saturating_add(1);
+                    let attempts = self.state.busy_attempts;
+                    match retry::RetryPolicy::retry(
+                        &mut self.state.busy_backoff,
+                        EspError::Busy,
+                        attempts,
+                    ) {
+                        Some(delay_ms) if non_blocking => {
+                            self.state.busy_until_ms =
+                                self.time_ms().map(|now_ms| now_ms + u64::from(delay_ms));
+                        }
+                        Some(delay_ms) => {
+                            if let Some(delay) = self.state.busy_delay {
+                                delay(delay_ms);
+                            }
+                        }
+                        None => {
+                            self.state.busy_attempts = 0;
+                            return Err(nb::Error::Other(EspError::Busy));
+                        }
+                    }
+                }
+                Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+                result => {
+                    self.state.busy_attempts = 0;
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Send a command given as text and return the raw response, waiting at
+    /// most `TIMEOUT_MS`.
+    ///
+    /// This is meant for bring-up and for commands that are not implemented
+    /// by this crate, e.g. `esp.raw_command::<128, 1_000>("AT+GMR")`. The
+    /// response is the text before the final `OK`, responses longer than `L`
+    /// bytes fail with `EspError::Parse`. Commands that don't start with `AT`,
+    /// are longer than 254 bytes or contain control characters are rejected
+    /// with `EspError::InvalidArgument`. The state of the client isn't updated,
+    /// so commands that change e.g. the WiFi or connection state should be
+    /// avoided.
+    pub fn raw_command<const L: usize, const TIMEOUT_MS: u32>(
+        &mut self,
+        command: &str,
+    ) -> EspResult<responses::StringResponse<L>> {
+        let command = requests::RawCommand::<L>::new(command)
+            .filter(|command| command.is_valid())
+            .ok_or(nb::Error::Other(EspError::InvalidArgument))?;
+        self.send_command(&requests::WithTimeout::<_, TIMEOUT_MS>::new(command))
+    }
+
+    /// Run `operation` until it succeeds or `policy` gives up.
+    ///
+    /// The operation can be any call on the client, e.g.
+    /// `|esp| esp.get_local_address()`. After a failure, the policy is asked
+    /// whether and how long to wait before the next attempt, see
+    /// [`retry`](retry/index.html). Failures to delay are reported as
+    /// `EspError::Pin`.
+    ///
+    /// This blocks until the operation is done, even in non-blocking mode.
+    pub fn retry<T, R, D, F>(
+        &mut self,
+        policy: &mut R,
+        delay: &mut D,
+        mut operation: F,
+    ) -> EspResult<T>
+    where
+        R: retry::RetryPolicy,
+        D: DelayUs,
+        F: FnMut(&mut Self) -> EspResult<T>,
+    {
+        let mut attempt: u8 = 0;
+        loop {
+            let error = match nb::block!(operation(self)) {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            attempt = attempt.saturating_add(1);
+            let delay_ms = policy
+                .retry(error, attempt)
+                .ok_or(nb::Error::Other(error))?;
+            delay
+                .delay_ms(delay_ms)
+                .map_err(|_| nb::Error::Other(EspError::Pin))?;
+        }
+    }
+
+    /// Send a command that is part of a sequence.
+    ///
+    /// This blocks until the response has arrived, even in non-blocking mode,
+    /// because the sequence could not be resumed otherwise.
+    pub(crate) fn send_blocking<T, const LEN: usize>(
+        &mut self,
+        command: &T,
+    ) -> EspResult<T::Response>
+    where
+        T: atat::AtatCmd<LEN>,
+    {
+        nb::block!(self.send_command(command)).map_err(nb::Error::Other)
+    }
+
+    /// Send a command once, without retrying.
+    fn send_once<T, const LEN: usize>(&mut self, command: &T) -> EspResult<T::Response>
+    where
+        T: atat::AtatCmd<LEN>,
+    {
+        // Discard failure reasons of earlier commands, unless the response to
+        // a pending command is still being waited for
+        if !self.state.command_pending {
+            self.process_urcs();
+            if self.state.cancelled {
+                self.resync()?;
+            }
+            self.state.command_sent_ms = self.time_ms();
+        }
+        let traced = trace::Traced::new(command);
+        let result = self.client.send(&traced);
+        if let Some(name) = traced.take_name() {
+            self.state.command_name = name;
+        }
+        self.state.command_pending = matches!(result, Err(nb::Error::WouldBlock));
+        if !self.state.command_pending && self.state.metrics.is_some() {
+            self.record_metrics(result.is_err());
+        }
+        result.map_err(|e| {
+            e.map(|e| {
+                self.take_urc(|urc| urc.error().is_some())
+                    .and_then(|urc| urc.error())
+                    .unwrap_or_else(|| EspError::from(e))
+            })
+        })
+    }
+
+    /// Discard the late response of a cancelled command.
+    ///
+    /// The device is probed with an AT test command. While it's still busy
+    /// with the cancelled command, the probe fails with `EspError::Busy` and
+    /// is repeated before the next command. Like any other command, the
+    /// probe is traced and recorded in the metrics.
+    fn resync(&mut self) -> EspResult<()> {
+        self.client.reset();
+        self.state.cancelled = false;
+        let result = nb::block!(self.send_once(&requests::At)).map_err(nb::Error::Other);
+        if result.is_err() {
+            self.state.cancelled = true;
+        }
+        result.map(|_| ())
+    }
+
+    /// Abandon the command whose response is pending, in non-blocking mode.
+    ///
+    /// The client is returned to a clean state: buffered responses are
+    /// dropped and the response of the cancelled command, which the device
+    /// may still send, is discarded before the next command. The device
+    /// keeps processing the cancelled command, so the next command fails
+    /// with `EspError::Busy` until it's done. Data announced with
+    /// [`prepare_send_data`](#method.prepare_send_data) can't be sent
+    /// anymore.
+    pub fn cancel(&mut self) {
+        if self.state.command_pending || self.state.prepared.is_some() {
+            self.client.reset();
+            self.state.command_pending = false;
+            self.state.prepared = None;
+            self.state.busy_attempts = 0;
+            self.state.busy_until_ms = None;
+            self.state.cancelled = true;
+        }
+    }
+
+    /// Run a non-blocking `operation` until it completes, cancelling it if
+    /// it takes longer than `timeout_ms`.
+    ///
+    /// The operation can be any call on the client, e.g.
+    /// `|esp| esp.list_access_points()`. After the deadline, the pending
+    /// command is cancelled (see [`cancel`](#method.cancel)) and
+    /// `EspError::Timeout` is returned. This requires the client to be in
+    /// non-blocking mode, blocking calls can't be interrupted. Failures to
+    /// delay are reported as `EspError::Pin`.
+    pub fn run_with_deadline<T, D, F>(
+        &mut self,
+        timeout_ms: u32,
+        delay: &mut D,
+        operation: F,
+    ) -> EspResult<T>
+    where
+        D: DelayUs,
+        F: FnMut(&mut Self) -> EspResult<T>,
+    {
+        self.run_until(delay, operation, |waited_ms| {
+            (waited_ms >= timeout_ms).then_some(EspError::Timeout)
+        })
+    }
+
+    /// Run a non-blocking `operation` until it completes, cancelling it once
+    /// `abort` is set, e.g. from an interrupt handler or another task.
+    ///
+    /// After cancelling, `EspError::Aborted` is returned, see
+    /// [`run_with_deadline`](#method.run_with_deadline). The flag is not
+    /// cleared.
+    pub fn run_abortable<T, D, F>(
+        &mut self,
+        abort: &AtomicBool,
+        delay: &mut D,
+        operation: F,
+    ) -> EspResult<T>
+    where
+        D: DelayUs,
+        F: FnMut(&mut Self) -> EspResult<T>,
+    {
+        self.run_until(delay, operation, |_| {
+            abort.load(Ordering::Acquire).then_some(EspError::Aborted)
+        })
+    }
+
+    /// Poll `operation` until it completes or `expired` returns the error to
+    /// fail with, given the time waited so far.
+    fn run_until<T, D, F, E>(
+        &mut self,
+        delay: &mut D,
+        mut operation: F,
+        mut expired: E,
+    ) -> EspResult<T>
+    where
+        D: DelayUs,
+        F: FnMut(&mut Self) -> EspResult<T>,
+        E: FnMut(u32) -> Option<EspError>,
+    {
+        let mut waited_ms = 0;
+        loop {
+            match operation(self) {
+                Err(nb::Error::WouldBlock) => {}
+                result => return result,
+            }
+            if let Some(error) = expired(waited_ms) {
+                self.cancel();
+                return Err(nb::Error::Other(error));
+            }
+            delay
+                .delay_ms(ABORT_POLL_INTERVAL_MS)
+                .map_err(|_| nb::Error::Other(EspError::Pin))?;
+            waited_ms = waited_ms.saturating_add(ABORT_POLL_INTERVAL_MS);
+        }
+    }
+
+    /// Record the outcome of a command in the metrics.
+    fn record_metrics(&mut self, failed: bool) {
+        let latency_ms = match (self.state.command_sent_ms, self.time_ms()) {
+            (Some(sent_ms), Some(now_ms)) => {
+                Some(now_ms.saturating_sub(sent_ms).min(u64::from(u32::MAX)) as u32)
+            }
+            _ => None,
+        };
+        let metrics = match self.state.metrics.as_mut() {
+            Some(metrics) => metrics,
+            None => return,
+        };
+        let name = &self.state.command_name;
+        let index = match metrics.iter().position(|m| m.command == *name) {
+            Some(index) => index,
+            None => {
+                // Commands beyond the capacity are not recorded
+                if metrics
+                    .push(types::CommandMetrics::new(name.clone()))
+                    .is_err()
+                {
+                    return;
+                }
+                metrics.len() - 1
+            }
+        };
+        metrics[index].record(latency_ms, failed);
+    }
+
+    /// Open a TCP or UDP connection.
+    ///
+    /// If the connection is already open, the firmware answers with
+    /// `ALREADY CONNECTED` and `ERROR`, which is reported as
+    /// `ConnectResponse::AlreadyConnected` instead of an error. If the
+    /// connection can't be established, e.g. because the remote host refuses
+    /// it, `EspError::CommandFailed` is returned.
+    pub fn establish_connection(
+        &mut self,
+        connection: &requests::EstablishConnection,
+    ) -> EspResult<responses::ConnectResponse> {
+        match self.send_command(connection) {
+            Ok(responses::ConnectResponse::Connected) => {
+                self.state.stats[connection.mux().index()] = types::ConnectionStats::default();
+                self.state.open_links |= 1 << connection.mux().index();
+                Ok(responses::ConnectResponse::Connected)
+            }
+            Err(nb::Error::Other(EspError::AlreadyConnected)) => {
+                self.state.open_links |= 1 << connection.mux().index();
+                Ok(responses::ConnectResponse::AlreadyConnected)
+            }
+            result => result,
+        }
+    }
+
+    /// Set the server name that TLS connections opened afterwards on the link
+    /// announce (SNI).
+    ///
+    /// Only supported by ESP-AT firmwares. Invalid hostnames are rejected
+    /// with `EspError::InvalidArgument`.
+    pub fn set_tls_sni(&mut self, mux: types::MultiplexingType, hostname: &str) -> EspResult<()> {
+        if self.state.dialect != types::Dialect::EspAt {
+            return Err(nb::Error::Other(EspError::Unsupported));
+        }
+        self.ensure_supported(|capabilities| capabilities.tls_sni)?;
+        let command = requests::ConfigureTlsSni::new(mux, hostname)
+            .filter(|command| command.is_valid())
+            .ok_or(nb::Error::Other(EspError::InvalidArgument))?;
+        self.send_command(&command)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Close a TCP or UDP connection, or all of them.
+    ///
+    /// Closing a connection that is not open is reported as
+    /// `CloseResponse::NotOpen` instead of an error.
+    pub fn close_connection(
+        &mut self,
+        connection: &requests::CloseConnection,
+    ) -> EspResult<responses::CloseResponse> {
+        let result = match self.send_command(connection) {
+            Err(nb::Error::Other(EspError::LinkInvalid)) => Ok(responses::CloseResponse::NotOpen),
+            result => result,
+        };
+        if result.is_ok() {
+            match connection.mux() {
+                Some(mux) => self.state.open_links &= !(1 << mux.index()),
+                None => self.state.open_links = 0,
+            }
+        }
+        result
+    }
+
+    /// Close all connections, e.g. when shutting down or to recover from
+    /// errors.
+    ///
+    /// With multiplexing enabled, all connections are closed at once with
+    /// `AT+CIPCLOSE=5`. If the firmware rejects that, e.g. because
+    /// multiplexing is disabled, the single connection is closed, followed
+    /// by every connection that is still tracked as open. Connections that
+    /// are not open are ignored.
+    ///
+    /// This blocks until all connections are closed, even in non-blocking
+    /// mode.
+    pub fn close_all_connections(&mut self) -> EspResult<()> {
+        match nb::block!(self.close_connection(&requests::CloseConnection::all())) {
+            Ok(_) => {}
+            Err(EspError::CommandFailed) => {
+                let single =
+                    requests::CloseConnection::new(types::MultiplexingType::NonMultiplexed);
+                match nb::block!(self.close_connection(&single)) {
+                    Ok(_) | Err(EspError::CommandFailed) => {}
+                    Err(e) => return Err(nb::Error::Other(e)),
+                }
+                for index in 0..types::MAX_CONNECTIONS as u8 {
+                    if self.state.open_links & (1 << index) == 0 {
+                        continue;
+                    }
+                    if let Some(id) = types::ConnectionId::from_index(index) {
+                        let link = requests::CloseConnection::new(
+                            types::MultiplexingType::Multiplexed(id),
+                        );
+                        nb::block!(self.close_connection(&link)).map_err(nb::Error::Other)?;
+                    }
+                }
+            }
+            Err(e) => return Err(nb::Error::Other(e)),
+        }
+        self.state.open_links = 0;
+        self.state.accepted_links = 0;
+        self.state.available_links = 0;
+        Ok(())
+    }
+
+    /// Announce that `length` bytes will be sent over the connection.
+    ///
+    /// Must be followed by [`send_data`](#method.send_data) with exactly
+    /// `length` bytes. Lengths of zero or above
+    /// [`MAX_SEND_LEN`](commands/requests/constant.MAX_SEND_LEN.html) are
+    /// rejected with `EspError::InvalidLength`.
+    pub fn prepare_send_data(
+        &mut self,
+        mux: types::MultiplexingType,
+        length: usize,
+    ) -> EspResult<()> {
+        if !(1..=requests::MAX_SEND_LEN).contains(&length) {
+            return Err(nb::Error::Other(EspError::InvalidLength));
+        }
+        self.send_command(&requests::PrepareSendData::new(mux, length as u16))
+            .map(|_: responses::EmptyResponse| self.state.prepared = Some((mux, length)))
+    }
+
+    /// Send the data announced by a preceding prepare command, e.g.
+    /// [`prepare_send_data`](#method.prepare_send_data).
+    ///
+    /// If the length was announced with `prepare_send_data`, data of a
+    /// different length is rejected with `EspError::InvalidLength`. A
+    /// `SEND FAIL` result is reported as `EspError::SendFail`.
+    pub fn send_data<const LEN: usize>(&mut self, data: &requests::SendData<LEN>) -> EspResult<()> {
+        if !self.state.command_pending {
+            if data.len() > LEN {
+                return Err(nb::Error::Other(EspError::Overflow));
+            }
+            if matches!(self.state.prepared, Some((_, len)) if len != data.len()) {
+                return Err(nb::Error::Other(EspError::InvalidLength));
+            }
+        }
+        let result = match self.send_command(data) {
+            Ok(responses::EmptyResponse) => Ok(()),
+            Err(nb::Error::Other(EspError::Fail)) => Err(nb::Error::Other(EspError::SendFail)),
+            Err(e) => Err(e),
+        };
+        if !matches!(result, Err(nb::Error::WouldBlock)) {
+            if let Some((mux, len)) = self.state.prepared.take() {
+                let now = self.time_ms();
+                let stats = &mut self.state.stats[mux.index()];
+                if result.is_ok() {
+                    stats.bytes_sent = stats.bytes_sent.wrapping_add(len as u32);
+                    stats.segments_sent = stats.segments_sent.wrapping_add(1);
+                } else {
+                    stats.send_failures = stats.send_failures.wrapping_add(1);
+                }
+                stats.last_activity_ms = now.or(stats.last_activity_ms);
+            }
+        }
+        result
+    }
+
+    /// Send `data` over the connection, split into segments of at most
+    /// [`MAX_SEND_LEN`](commands/requests/constant.MAX_SEND_LEN.html) bytes.
+    ///
+    /// With `AT+CIPSEND`, every segment takes two round trips, one until the
+    /// `>` prompt and one until `SEND OK`, as the firmware answers commands
+    /// sent before `SEND OK` with `busy s...`. With a
+    /// [send window](#method.set_send_window) above 1, the segments are
+    /// written to the send buffer of the device with `AT+CIPSENDBUF`
+    /// instead, and the next segment is written while the previous ones are
+    /// still being sent. Either way, this returns once all segments were
+    /// sent, and fails with `EspError::SendFail` if one of them failed.
+    ///
+    /// This blocks until all data is sent, even in non-blocking mode.
+    pub fn send(&mut self, mux: types::MultiplexingType, data: &[u8]) -> EspResult<()> {
+        if self.state.send_window > 1 {
+            return self.send_buffered(mux, data);
+        }
+        for segment in data.chunks(requests::MAX_SEND_LEN) {
+            nb::block!(self.prepare_send_data(mux, segment.len())).map_err(nb::Error::Other)?;
+            let segment = requests::SendData::<{ requests::MAX_SEND_LEN }>::from_bytes(segment);
+            nb::block!(self.send_data(&segment)).map_err(nb::Error::Other)?;
+        }
+        Ok(())
+    }
+
+    /// Set how many segments [`send`](#method.send) keeps in flight on TCP
+    /// connections.
+    ///
+    /// The default of 1 waits for every segment to be sent before the next
+    /// one is announced. Larger windows use the send buffer of the device,
+    /// which is only supported by NONOS firmwares, `send` fails with
+    /// `EspError::Unsupported` on ESP-AT firmwares. The firmware buffers
+    /// about 5 segments at most.
+    pub fn set_send_window(&mut self, window: u8) {
+        self.state.send_window = window.max(1);
+    }
+
+    /// Send `data` with `AT+CIPSENDBUF`, keeping up to the send window of
+    /// segments in flight.
+    fn send_buffered(&mut self, mux: types::MultiplexingType, data: &[u8]) -> EspResult<()> {
+        if self.state.dialect != types::Dialect::NonOs {
+            return Err(nb::Error::Other(EspError::Unsupported));
+        }
+        self.state.failed_segments &= !(1 << mux.index());
+        let mut last_segment = None;
+        for segment in data.chunks(requests::MAX_SEND_LEN) {
+            if let Some(last_segment) = last_segment {
+                self.wait_for_segments(mux, last_segment, self.state.send_window - 1)?;
+            }
+            let prepare = requests::PrepareSendBuffer::new(mux, segment.len() as u16);
+            let status = self.send_blocking(&prepare)?;
+            self.update_segments(mux, &status);
+            let data =
+                requests::SendBufferedData::<{ requests::MAX_SEND_LEN }>::from_bytes(segment);
+            self.send_blocking(&data)?;
+            let now = self.time_ms();
+            let stats = &mut self.state.stats[mux.index()];
+            stats.bytes_sent = stats.bytes_sent.wrapping_add(segment.len() as u32);
+            stats.segments_sent = stats.segments_sent.wrapping_add(1);
+            stats.last_activity_ms = now.or(stats.last_activity_ms);
+            last_segment = Some(status.segment);
+        }
+        match last_segment {
+            Some(last_segment) => self.wait_for_segments(mux, last_segment, 0),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait until at most `in_flight` segments up to `last_segment` are not
+    /// sent yet, failing if one of them failed.
+    ///
+    /// The outcomes are taken from the URCs, and queried from the device if
+    /// the URCs don't suffice, e.g. because received data holds them back.
+    fn wait_for_segments(
+        &mut self,
+        mux: types::MultiplexingType,
+        last_segment: u32,
+        in_flight: u8,
+    ) -> EspResult<()> {
+        self.process_urcs();
+        loop {
+            if self.state.failed_segments & (1 << mux.index()) != 0 {
+                return Err(nb::Error::Other(EspError::SendFail));
+            }
+            let sent = self.state.last_sent_segments[mux.index()];
+            if last_segment.saturating_sub(sent) <= u32::from(in_flight) {
+                return Ok(());
+            }
+            let status = self.send_blocking(&requests::GetSendBufferStatus::new(mux))?;
+            self.update_segments(mux, &status);
+        }
+    }
+
+    /// Update the state of the segments of a connection with the state of
+    /// its send buffer.
+    fn update_segments(
+        &mut self,
+        mux: types::MultiplexingType,
+        status: &responses::SendBufferStatus,
+    ) {
+        let sent = &mut self.state.last_sent_segments[mux.index()];
+        *sent = (*sent).max(status.acknowledged);
+        if status.failed {
+            self.state.failed_segments |= 1 << mux.index();
+            let stats = &mut self.state.stats[mux.index()];
+            stats.send_failures = stats.send_failures.wrapping_add(1);
+        }
     }
 
     /// Test whether the device is connected and able to communicate.
     pub fn selftest(&mut self) -> EspResult<()> {
-        self.client
-            .send(&requests::At)
+        self.send_command(&requests::At)
             .map(|_: responses::EmptyResponse| ())
     }
 
+    /// Find the baud rate the device is configured with.
+    ///
+    /// For every entry in `baud_rates`, `reconfigure` is called to switch the
+    /// host UART to that baud rate, after which the device is probed with AT
+    /// test commands. The first baud rate that the device answers on is
+    /// returned. If none of them works, the last probe error is returned.
+    ///
+    /// When this function returns successfully, the host UART is left
+    /// configured with the detected baud rate.
+    pub fn detect_baud_rate<F>(&mut self, baud_rates: &[u32], mut reconfigure: F) -> EspResult<u32>
+    where
+        F: FnMut(u32),
+    {
+        let mut last_error = nb::Error::Other(EspError::Timeout);
+        for &baud_rate in baud_rates {
+            reconfigure(baud_rate);
+            // The first probe may fail because of garbage received while the
+            // baud rates did not match, so give it a second chance.
+            for _ in 0..2 {
+                match nb::block!(self.selftest()) {
+                    Ok(()) => return Ok(baud_rate),
+                    Err(e) => last_error = nb::Error::Other(e),
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Reset the device and bring it into a well-defined state.
+    ///
+    /// The device is restarted, echo is disabled and the multiplexing, `+IPD`
+    /// and WiFi mode settings from `config` are applied (without persisting
+    /// them to flash). The order matters: echo must be off before responses
+    /// are parsed, and multiplexing can only be changed while no connection
+    /// is open, which is guaranteed right after a reset. On ESP-AT firmwares,
+    /// the optional features supported by the firmware are detected as well.
+    pub fn init(&mut self, config: &types::InitConfig) -> EspResult<()> {
+        nb::block!(self.restart()).map_err(nb::Error::Other)?;
+        self.wait_for_ready()?;
+
+        // The restart reverted the configuration that is not replayed here
+        self.state.receive_mode = types::ReceiveMode::Active;
+        self.state.error_codes = false;
+        self.apply_init_config(config)?;
+        self.state.init_config = Some(*config);
+
+        if self.state.dialect == types::Dialect::EspAt {
+            nb::block!(self.detect_capabilities()).map_err(nb::Error::Other)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the configuration of [`init`](#method.init) to the device.
+    fn apply_init_config(&mut self, config: &types::InitConfig) -> EspResult<()> {
+        self.send_blocking(&requests::SetEcho::new(false))
+            .map(|_: responses::EmptyResponse| ())?;
+        self.send_blocking(&requests::SetMultiplexing::new(config.multiplexing))
+            .map(|_: responses::EmptyResponse| ())?;
+        self.send_blocking(&requests::SetShowRemoteInfo::new(config.show_remote_info))
+            .map(|_: responses::EmptyResponse| ())?;
+        nb::block!(self.set_wifi_mode(config.wifi_mode, types::Target::Current))
+            .map_err(nb::Error::Other)
+    }
+
+    /// Restart the device and apply the configuration again, e.g. to recover
+    /// from a firmware crash.
+    ///
+    /// The configuration applied by [`init`](#method.init) is replayed,
+    /// including later changes of the WiFi mode, as well as the receive mode
+    /// and extended error codes if they were changed. Connections are closed
+    /// by the restart and must be opened again. The function blocks until
+    /// the configuration is applied. If the device doesn't process commands
+    /// anymore, use [`hard_reset_and_reinit`](#method.hard_reset_and_reinit)
+    /// instead.
+    pub fn reset_and_reinit(&mut self) -> EspResult<()> {
+        nb::block!(self.restart()).map_err(nb::Error::Other)?;
+        self.wait_for_ready()?;
+        self.reapply_config()
+    }
+
+    /// Reset the device through its RST line and apply the configuration
+    /// again, see [`reset_and_reinit`](#method.reset_and_reinit) and
+    /// [`hard_reset`](#method.hard_reset).
+    pub fn hard_reset_and_reinit<P, D>(&mut self, rst: &mut P, delay: &mut D) -> EspResult<()>
+    where
+        P: OutputPin,
+        D: DelayUs,
+    {
+        self.hard_reset(rst, delay)?;
+        self.reapply_config()
+    }
+
+    /// Replay the configuration tracked by the client after a reset.
+    fn reapply_config(&mut self) -> EspResult<()> {
+        match self.state.init_config {
+            Some(config) => self.apply_init_config(&config)?,
+            None => self
+                .send_blocking(&requests::SetEcho::new(false))
+                .map(|_: responses::EmptyResponse| ())?,
+        }
+        if self.state.receive_mode != types::ReceiveMode::Active {
+            nb::block!(self.set_receive_mode(self.state.receive_mode)).map_err(nb::Error::Other)?;
+        }
+        if self.state.error_codes {
+            nb::block!(self.set_error_codes(true)).map_err(nb::Error::Other)?;
+        }
+        Ok(())
+    }
+
+    /// Query which optional features the firmware supports.
+    ///
+    /// This is only supported by ESP-AT firmwares and done automatically by
+    /// [`init`](#method.init) for those. The result is cached and can be
+    /// retrieved later with [`capabilities`](#method.capabilities).
+    pub fn detect_capabilities(&mut self) -> EspResult<types::Capabilities> {
+        let capabilities = self.send_command(&requests::GetCapabilities)?;
+        self.state.capabilities = Some(capabilities);
+        Ok(capabilities)
+    }
+
+    /// Return the optional features supported by the firmware.
+    ///
+    /// Returns `None` if they haven't been detected yet.
+    pub fn capabilities(&self) -> Option<types::Capabilities> {
+        self.state.capabilities
+    }
+
+    /// Set whether configuration changes are persisted to flash, on ESP-AT
+    /// firmwares.
+    ///
+    /// ESP-AT firmwares don't have the `_CUR` and `_DEF` command variants
+    /// of the NONOS firmware, the store mode applies to all commands that
+    /// change the configuration instead. Methods that take a
+    /// [`Target`](types/enum.Target.html) switch the store mode as needed,
+    /// so this is only required for other commands. Persisting is enabled
+    /// after a restart of the device.
+    pub fn set_parameter_store(&mut self, enabled: bool) -> EspResult<()> {
+        self.ensure_supported(|capabilities| capabilities.sysstore)?;
+        self.send_command(&requests::SetParameterStore::new(enabled))
+            .map(|_: responses::EmptyResponse| self.state.store_enabled = Some(enabled))
+    }
+
+    /// Set whether ESP-AT firmwares print an extended error code before
+    /// `ERROR`.
+    ///
+    /// The codes tell why a command failed, e.g. because a parameter is
+    /// invalid, and are reported as the matching
+    /// [`EspError`](error/enum.EspError.html) instead of the generic
+    /// `EspError::CommandFailed`. Disabled after a restart of the device.
+    pub fn set_error_codes(&mut self, enabled: bool) -> EspResult<()> {
+        self.ensure_supported(|capabilities| capabilities.syslog)?;
+        self.send_command(&requests::SetSystemLog::new(enabled))
+            .map(|_: responses::EmptyResponse| self.state.error_codes = enabled)
+    }
+
+    /// Query whether configuration changes are persisted to flash, on ESP-AT
+    /// firmwares.
+    pub fn get_parameter_store(&mut self) -> EspResult<bool> {
+        self.ensure_supported(|capabilities| capabilities.sysstore)?;
+        let enabled = self.send_command(&requests::GetParameterStore)?.enabled;
+        self.state.store_enabled = Some(enabled);
+        Ok(enabled)
+    }
+
+    /// Switch the store mode of ESP-AT firmwares to match `target`, if it
+    /// doesn't already.
+    ///
+    /// Blocks until the store mode was changed, even in non-blocking mode.
+    fn select_target(&mut self, target: types::Target) -> EspResult<()> {
+        let supported = match self.state.capabilities {
+            Some(capabilities) => capabilities.sysstore,
+            None => true,
+        };
+        let enabled = target == types::Target::Default;
+        if self.state.dialect != types::Dialect::EspAt
+            || !supported
+            || self.state.store_enabled == Some(enabled)
+        {
+            return Ok(());
+        }
+        nb::block!(self.set_parameter_store(enabled)).map_err(nb::Error::Other)
+    }
+
+    /// Configure which optional system messages are printed.
+    ///
+    /// Only supported by ESP-AT firmwares. Both the simple and the detailed
+    /// connection messages are recognized, so this doesn't affect the client
+    /// state tracking.
+    pub fn set_system_messages(&mut self, messages: types::SystemMessages) -> EspResult<()> {
+        self.ensure_supported(|capabilities| capabilities.sysmsg)?;
+        self.send_command(&requests::SetSystemMessages::new(messages))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Query which optional system messages are printed.
+    ///
+    /// Only supported by ESP-AT firmwares.
+    pub fn get_system_messages(&mut self) -> EspResult<types::SystemMessages> {
+        self.ensure_supported(|capabilities| capabilities.sysmsg)?;
+        self.send_command(&requests::GetSystemMessages)
+    }
+
+    /// Set the 802.11 PHY protocols of an interface, e.g. to limit the
+    /// station to 802.11b/g for legacy access points.
+    ///
+    /// Only supported by ESP-AT firmwares. At least one of 802.11b, g and n
+    /// must be enabled, otherwise `EspError::InvalidArgument` is returned.
+    pub fn set_phy_protocols(
+        &mut self,
+        interface: types::WifiInterface,
+        protocols: types::PhyProtocols,
+    ) -> EspResult<()> {
+        self.ensure_supported(|capabilities| capabilities.phy_protocols)?;
+        if !(protocols.b || protocols.g || protocols.n) {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.send_command(&requests::SetPhyProtocols::new(interface, protocols))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Query the 802.11 PHY protocols of an interface.
+    ///
+    /// Only supported by ESP-AT firmwares.
+    pub fn get_phy_protocols(
+        &mut self,
+        interface: types::WifiInterface,
+    ) -> EspResult<types::PhyProtocols> {
+        self.ensure_supported(|capabilities| capabilities.phy_protocols)?;
+        self.send_command(&requests::GetPhyProtocols::new(interface))
+    }
+
+    /// Restart the device.
+    ///
+    /// The device is not usable until it has booted again, use
+    /// [`poll_ready`](#method.poll_ready) or
+    /// [`wait_for_ready`](#method.wait_for_ready) to find out when that's the
+    /// case.
+    pub fn restart(&mut self) -> EspResult<()> {
+        self.send_command(&requests::Restart)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.reset_state();
+        Ok(())
+    }
+
+    /// Reset the device through its RST line.
+    ///
+    /// Unlike [`restart`](#method.restart), this also works if the device is
+    /// wedged and does not process AT commands anymore. The RST line is
+    /// pulled low for 10 ms and released again, then the function blocks
+    /// until the device has booted. Failures to drive the pin or to delay are
+    /// reported as `EspError::Pin`.
+    pub fn hard_reset<P, D>(&mut self, rst: &mut P, delay: &mut D) -> EspResult<()>
+    where
+        P: OutputPin,
+        D: DelayUs,
+    {
+        let pin_error = |_| nb::Error::Other(EspError::Pin);
+        rst.set_low().map_err(pin_error)?;
+        delay
+            .delay_ms(RESET_PULSE_MS)
+            .map_err(|_| nb::Error::Other(EspError::Pin))?;
+        rst.set_high().map_err(pin_error)?;
+        self.reset_state();
+        self.wait_for_ready()
+    }
+
+    /// Power down the device by pulling its CH_PD (enable) line low.
+    ///
+    /// All state that the client tracks about the device is discarded, as are
+    /// pending responses and URCs. Failures to drive the pin are reported as
+    /// `EspError::Pin`.
+    pub fn power_down<P: OutputPin>(&mut self, enable: &mut P) -> EspResult<()> {
+        enable
+            .set_low()
+            .map_err(|_| nb::Error::Other(EspError::Pin))?;
+        self.client.reset();
+        self.reset_state();
+        Ok(())
+    }
+
+    /// Power up the device by pulling its CH_PD (enable) line high.
+    ///
+    /// The function blocks until the device has booted. Failures to drive the
+    /// pin are reported as `EspError::Pin`.
+    pub fn power_up<P: OutputPin>(&mut self, enable: &mut P) -> EspResult<()> {
+        enable
+            .set_high()
+            .map_err(|_| nb::Error::Other(EspError::Pin))?;
+        self.reset_state();
+        self.wait_for_ready()
+    }
+
+    /// Restore the factory default settings.
+    ///
+    /// This erases all parameters stored in flash (including WiFi credentials)
+    /// and restarts the device. The function blocks until the device has
+    /// booted again.
+    pub fn restore_factory_defaults(&mut self) -> EspResult<()> {
+        self.send_command(&requests::RestoreFactoryDefaults)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.reset_state();
+        self.wait_for_ready()
+    }
+
+    /// Update the firmware of the device over the air.
+    ///
+    /// The device must be connected to an access point with internet access.
+    /// It downloads the latest AT firmware from the Espressif servers and
+    /// restarts. `on_progress` is called with every step that the device
+    /// reported. Because the client blocks while the update is running, the
+    /// steps are reported once the command completed; on failure they show
+    /// how far the update got. On success, the function blocks until the
+    /// device has booted the new firmware.
+    pub fn update_firmware<F>(&mut self, mut on_progress: F) -> EspResult<()>
+    where
+        F: FnMut(types::UpdateStep),
+    {
+        let result = self
+            .send_command(&requests::UpdateFirmware)
+            .map(|_: responses::EmptyResponse| ());
+        while let Some(Urc::UpdateProgress(step)) =
+            self.take_urc(|urc| matches!(urc, Urc::UpdateProgress(_)))
+        {
+            on_progress(step);
+        }
+        result?;
+        self.reset_state();
+        self.wait_for_ready()
+    }
+
+    /// Roll back to the firmware that was running before the last update,
+    /// e.g. if the new firmware misbehaves after
+    /// [`update_firmware`](#method.update_firmware).
+    ///
+    /// Only supported by ESP-AT firmwares. The function blocks until the
+    /// device has booted the previous firmware.
+    pub fn rollback_firmware(&mut self) -> EspResult<()> {
+        self.ensure_supported(|capabilities| capabilities.rollback)?;
+        self.send_command(&requests::RollbackFirmware)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.reset_state();
+        self.wait_for_ready()
+    }
+
+    /// Check whether the device has announced that it's ready.
+    ///
+    /// Returns `nb::Error::WouldBlock` until the `ready` message was received
+    /// after a restart or power-up.
+    pub fn poll_ready(&mut self) -> EspResult<()> {
+        self.process_urcs();
+        if self.state.ready {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Block until the device is ready to accept commands.
+    ///
+    /// Besides waiting for the `ready` message, the device is probed with AT
+    /// test commands, so this also returns if the device was already running
+    /// (and the `ready` message was missed). Fails with the last probe error
+    /// if the device does not answer within about five seconds.
+    pub fn wait_for_ready(&mut self) -> EspResult<()> {
+        let mut attempts = 0;
+        loop {
+            if self.poll_ready().is_ok() {
+                return Ok(());
+            }
+            attempts += 1;
+            match nb::block!(self.selftest()) {
+                Ok(()) => {
+                    self.state.ready = true;
+                    return Ok(());
+                }
+                Err(e) if attempts >= BOOT_PROBE_ATTEMPTS => return Err(nb::Error::Other(e)),
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Forget all state tracked about the device, e.g. after a reset.
+    fn reset_state(&mut self) {
+        self.state.ready = false;
+        self.state.rebooted = false;
+        self.state.command_pending = false;
+        self.state.cancelled = false;
+        self.state.prepared = None;
+        self.state.open_links = 0;
+        self.state.closed_links = 0;
+        self.state.available_links = 0;
+        self.state.stats = [types::ConnectionStats::default(); types::MAX_CONNECTIONS];
+        self.state.last_sent_segments = [0; types::MAX_CONNECTIONS];
+        self.state.failed_segments = 0;
+        self.state.wifi_connected = false;
+        self.state.got_ip = false;
+        self.state.wifi_lost = false;
+        self.state.events.clear();
+        #[cfg(feature = "mqtt")]
+        {
+            self.state.mqtt_connected = false;
+        }
+        #[cfg(feature = "ble")]
+        {
+            self.state.ble_connections = 0;
+        }
+        #[cfg(feature = "websocket")]
+        {
+            self.state.websocket_links = [false; websocket::MAX_LINKS];
+        }
+    }
+
+    /// Fail if the firmware is known not to support a feature.
+    ///
+    /// If the capabilities haven't been detected, the feature is assumed to
+    /// be supported.
+    fn ensure_supported<F>(&self, supported: F) -> EspResult<()>
+    where
+        F: FnOnce(&types::Capabilities) -> bool,
+    {
+        match self.state.capabilities {
+            Some(ref capabilities) if !supported(capabilities) => {
+                Err(nb::Error::Other(EspError::Unsupported))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Process all pending URCs and update the client state accordingly.
+    ///
+    /// Processing stops at the first URC that carries data for the
+    /// application, which stays in the queue until it's fetched.
+    fn process_urcs(&mut self) {
+        self.take_urc(|_| false);
+    }
+
+    /// Process pending URCs until one matches `wanted` and return it.
+    ///
+    /// URCs that don't match are processed and consumed as long as they
+    /// don't carry data for the application. The first one that does is left
+    /// in the queue, in that case `None` is returned.
+    fn take_urc<F>(&mut self, wanted: F) -> Option<Urc<N>>
+    where
+        F: Fn(&Urc<N>) -> bool,
+    {
+        loop {
+            let mut next = None;
+            self.client.peek_urc_with::<Urc<N>, _>(|urc| {
+                if wanted(&urc) || !urc.has_payload() {
+                    next = Some(urc);
+                    true
+                } else {
+                    false
+                }
+            });
+            let urc = next?;
+            self.handle_urc(&urc);
+            if wanted(&urc) {
+                return Some(urc);
+            }
+        }
+    }
+
+    /// Update the client state according to a single URC and keep the event
+    /// that it reports for [`poll_events`](#method.poll_events).
+    fn handle_urc(&mut self, urc: &Urc<N>) {
+        trace::urc(urc);
+        let rebooted = matches!(urc, Urc::Ready) && self.state.ready;
+        self.update_state(urc);
+        let event = match urc {
+            Urc::Ready if rebooted => events::Event::Rebooted,
+            Urc::WifiConnected => events::Event::WifiConnected,
+            Urc::WifiGotIp => events::Event::WifiGotIp,
+            Urc::WifiDisconnected => events::Event::WifiDisconnected,
+            Urc::ConnectionOpened(mux) => events::Event::ConnectionOpened(*mux),
+            Urc::LinkConnected(info) => events::Event::ConnectionOpened(info.mux),
+            Urc::ConnectionClosed(mux) => events::Event::ConnectionClosed(*mux),
+            Urc::DataAvailable(mux) => events::Event::DataAvailable(*mux),
+            #[cfg(feature = "mqtt")]
+            Urc::MqttConnected => events::Event::MqttConnected,
+            #[cfg(feature = "mqtt")]
+            Urc::MqttDisconnected => events::Event::MqttDisconnected,
+            _ => return,
+        };
+        // The oldest event is dropped if they aren't polled
+        if self.state.events.is_full() {
+            self.state.events.pop_front();
+        }
+        self.state.events.push_back(event).ok();
+    }
+
+    /// Update the client state according to a single URC.
+    fn update_state(&mut self, urc: &Urc<N>) {
+        match urc {
+            Urc::Ready => {
+                // A reset by the client clears the flag beforehand
+                self.state.rebooted |= self.state.ready;
+                self.state.ready = true;
+                self.state.store_enabled = None;
+                self.state.utc_offset_min = None;
+            }
+            #[cfg(feature = "mqtt")]
+            Urc::MqttConnected => self.state.mqtt_connected = true,
+            #[cfg(feature = "mqtt")]
+            Urc::MqttDisconnected => self.state.mqtt_connected = false,
+            #[cfg(feature = "ble")]
+            Urc::BleConnected(conn_index) if *conn_index < 8 => {
+                self.state.ble_connections |= 1 << conn_index;
+            }
+            #[cfg(feature = "ble")]
+            Urc::BleDisconnected(conn_index) if *conn_index < 8 => {
+                self.state.ble_connections &= !(1 << conn_index);
+            }
+            #[cfg(feature = "ble")]
+            Urc::BleConnected(_) | Urc::BleDisconnected(_) | Urc::BleWrite(_) => {
+                /* nothing to track */
+            }
+            #[cfg(feature = "websocket")]
+            Urc::WebSocketConnected(link_id) => {
+                if let Some(open) = self.state.websocket_links.get_mut(usize::from(*link_id)) {
+                    *open = true;
+                }
+            }
+            #[cfg(feature = "websocket")]
+            Urc::WebSocketClosed(link_id) => {
+                if let Some(open) = self.state.websocket_links.get_mut(usize::from(*link_id)) {
+                    *open = false;
+                }
+            }
+            #[cfg(feature = "websocket")]
+            Urc::WebSocketData(_) => { /* nothing to track */ }
+            Urc::WifiConnected => self.state.wifi_connected = true,
+            Urc::WifiGotIp => self.state.got_ip = true,
+            Urc::WifiDisconnected => {
+                self.state.wifi_connected = false;
+                self.state.got_ip = false;
+                self.state.wifi_lost = true;
+            }
+            Urc::ConnectionOpened(mux) => {
+                // Connections opened by the client are already known from
+                // the response, so unknown ones were accepted by the server
+                if self.state.open_links & (1 << mux.index()) == 0 {
+                    self.state.accepted_links |= 1 << mux.index();
+                }
+                self.state.stats[mux.index()] = types::ConnectionStats::default();
+                self.state.last_sent_segments[mux.index()] = 0;
+                self.state.open_links |= 1 << mux.index();
+            }
+            Urc::LinkConnected(info) => {
+                if info.incoming {
+                    self.state.accepted_links |= 1 << info.mux.index();
+                }
+                self.state.stats[info.mux.index()] = types::ConnectionStats::default();
+                self.state.last_sent_segments[info.mux.index()] = 0;
+                self.state.open_links |= 1 << info.mux.index();
+            }
+            Urc::ConnectionClosed(mux) => {
+                self.state.open_links &= !(1 << mux.index());
+                self.state.accepted_links &= !(1 << mux.index());
+                self.state.closed_links |= 1 << mux.index();
+            }
+            Urc::NetworkData(data) => self.record_received(data),
+            Urc::DataAvailable(mux) => self.state.available_links |= 1 << mux.index(),
+            Urc::SegmentSent(mux, segment) => {
+                let sent = &mut self.state.last_sent_segments[mux.index()];
+                *sent = (*sent).max(*segment);
+            }
+            Urc::SegmentFailed(mux, _) => {
+                self.state.failed_segments |= 1 << mux.index();
+                let stats = &mut self.state.stats[mux.index()];
+                stats.send_failures = stats.send_failures.wrapping_add(1);
+            }
+            Urc::UpdateProgress(_) | Urc::TransparentQuit => { /* nothing to track */ }
+            #[cfg(feature = "mqtt")]
+            Urc::MqttMessage(_) => { /* fetched by the application */ }
+            Urc::JoinFailed(_)
+            | Urc::Busy
+            | Urc::LinkInvalid
+            | Urc::AlreadyConnected
+            | Urc::ErrorCode(_) => { /* reported by send_command */ }
+        }
+    }
+
     /// Query and return the firmware version.
     pub fn get_firmware_version(&mut self) -> EspResult<responses::FirmwareVersion> {
-        self.client.send(&requests::GetFirmwareVersion)
+        self.send_command(&requests::GetFirmwareVersion)
     }
 
     /// Return the current WiFi mode.
     pub fn get_current_wifi_mode(&mut self) -> EspResult<types::WifiMode> {
-        self.client.send(&requests::GetCurrentWifiMode)
+        self.send_command(
+            &requests::GetWifiMode::new(types::Target::Current).with_dialect(self.state.dialect),
+        )
     }
 
     /// Return the default WiFi mode.
     pub fn get_default_wifi_mode(&mut self) -> EspResult<types::WifiMode> {
-        self.client.send(&requests::GetDefaultWifiMode)
+        self.send_command(
+            &requests::GetWifiMode::new(types::Target::Default).with_dialect(self.state.dialect),
+        )
     }
 
     /// Return the current and default WiFi mode.
     pub fn get_wifi_mode(&mut self) -> EspResult<ConfigWithDefault<types::WifiMode>> {
         Ok(ConfigWithDefault {
-            current: self.client.send(&requests::GetCurrentWifiMode)?,
-            default: self.client.send(&requests::GetDefaultWifiMode)?,
+            current: self.get_current_wifi_mode()?,
+            default: self.get_default_wifi_mode()?,
         })
     }
 
     /// Set the WiFi mode.
-    pub fn set_wifi_mode(&mut self, mode: types::WifiMode, persist: bool) -> EspResult<()> {
-        self.client
-            .send(&requests::SetWifiMode::to(mode, persist))
-            .map(|_: responses::EmptyResponse| ())
+    ///
+    /// With [`WifiMode::Off`](types/enum.WifiMode.html#variant.Off), the
+    /// radio is disabled entirely, which saves power and stops RF emissions
+    /// while the module keeps running. This is only supported by ESP-AT
+    /// firmwares. Switching to another mode enables the radio again, the
+    /// station has to rejoin its access point.
+    pub fn set_wifi_mode(&mut self, mode: types::WifiMode, target: types::Target) -> EspResult<()> {
+        if mode == types::WifiMode::Off && self.state.dialect != types::Dialect::EspAt {
+            return Err(nb::Error::Other(EspError::Unsupported));
+        }
+        self.select_target(target)?;
+        self.send_command(&requests::SetWifiMode::to(mode, target).with_dialect(self.state.dialect))
+            .map(|_: responses::EmptyResponse| {
+                if let Some(config) = self.state.init_config.as_mut() {
+                    config.wifi_mode = mode;
+                }
+                if mode == types::WifiMode::Off {
+                    self.state.wifi_connected = false;
+                    self.state.got_ip = false;
+                }
+            })
+    }
+
+    /// Return the current and the persisted UART configuration.
+    pub fn get_uart_config(&mut self) -> EspResult<ConfigWithDefault<types::UartConfig>> {
+        Ok(ConfigWithDefault {
+            current: self.send_command(&requests::GetUartConfig::new(types::Target::Current))?,
+            default: self.send_command(&requests::GetUartConfig::new(types::Target::Default))?,
+        })
+    }
+
+    /// Change the UART configuration of the device.
+    ///
+    /// The device switches to the new configuration right after confirming
+    /// the command. The new configuration is returned, so that the caller
+    /// can reconfigure its own UART accordingly before sending any further
+    /// commands.
+    ///
+    /// When enabling hardware flow control, the RTS and CTS lines must be
+    /// connected and the host UART configured for flow control as well,
+    /// otherwise the device stops sending. Flow control is recommended for
+    /// baud rates above 115200, see
+    /// [`FlowControl`](types/enum.FlowControl.html).
+    pub fn set_uart_config(
+        &mut self,
+        config: types::UartConfig,
+        target: types::Target,
+    ) -> EspResult<types::UartConfig> {
+        self.send_command(&requests::SetUartConfig::to(config, target))
+            .map(|_: responses::EmptyResponse| config)
     }
 
     /// Join the specified access point.
+    ///
+    /// If the device reports why the connection failed, the reason is
+    /// returned as error. Quotes, commas and backslashes in the SSID and PSK
+    /// are escaped, control characters are rejected with
+    /// `EspError::InvalidArgument` and PSKs longer than 64 bytes with
+    /// `EspError::Overflow`.
     pub fn join_access_point(
         &mut self,
-        ssid: impl Into<String<32>>,
-        psk: impl Into<String<64>>,
-        persist: bool,
+        ssid: impl Into<types::Ssid>,
+        psk: &str,
+        target: types::Target,
+    ) -> EspResult<responses::JoinResponse> {
+        let command = requests::JoinAccessPoint::new(ssid, psk, target)
+            .ok_or(nb::Error::Other(EspError::Overflow))?
+            .with_dialect(self.state.dialect);
+        check_join(&command)?;
+        self.select_target(target)?;
+        self.join(&command)
+    }
+
+    /// Join the specified access point, waiting at most `TIMEOUT_MS`.
+    ///
+    /// How long joining takes depends on the access point and the firmware,
+    /// this allows to deviate from the default timeout of 25 seconds.
+    pub fn join_access_point_with_timeout<const TIMEOUT_MS: u32>(
+        &mut self,
+        ssid: impl Into<types::Ssid>,
+        psk: &str,
+        target: types::Target,
+    ) -> EspResult<responses::JoinResponse> {
+        let command = requests::JoinAccessPoint::new(ssid, psk, target)
+            .ok_or(nb::Error::Other(EspError::Overflow))?
+            .with_dialect(self.state.dialect);
+        check_join(&command)?;
+        self.select_target(target)?;
+        self.join(&requests::WithTimeout::<_, TIMEOUT_MS>::new(command))
+    }
+
+    /// Join the access point with the specified BSSID.
+    ///
+    /// This is useful to choose a specific access point of a network with
+    /// multiple access points, e.g. when using repeaters.
+    pub fn join_access_point_with_bssid(
+        &mut self,
+        ssid: impl Into<types::Ssid>,
+        psk: &str,
+        bssid: types::MacAddress,
+        target: types::Target,
     ) -> EspResult<responses::JoinResponse> {
-        self.client
-            .send(&requests::JoinAccessPoint::new(ssid, psk, persist))
+        let command = requests::JoinAccessPoint::new(ssid, psk, target)
+            .ok_or(nb::Error::Other(EspError::Overflow))?
+            .with_bssid(bssid)
+            .with_dialect(self.state.dialect);
+        check_join(&command)?;
+        self.select_target(target)?;
+        self.join(&command)
+    }
+
+    /// Join the specified access point with extended association parameters,
+    /// e.g. a longer listen interval for low-power products.
+    ///
+    /// Only supported by ESP-AT firmwares. Values outside the ranges
+    /// accepted by the firmware are rejected with `EspError::InvalidArgument`.
+    pub fn join_access_point_with_options(
+        &mut self,
+        ssid: impl Into<types::Ssid>,
+        psk: &str,
+        options: types::JoinOptions,
+        target: types::Target,
+    ) -> EspResult<responses::JoinResponse> {
+        if self.state.dialect == types::Dialect::NonOs {
+            return Err(nb::Error::Other(EspError::Unsupported));
+        }
+        let command = requests::JoinAccessPoint::new(ssid, psk, target)
+            .ok_or(nb::Error::Other(EspError::Overflow))?
+            .with_options(options)
+            .with_dialect(self.state.dialect);
+        check_join(&command)?;
+        self.select_target(target)?;
+        self.join(&command)
+    }
+
+    /// Join the first of the specified networks that accepts the connection.
+    ///
+    /// The networks are tried in order, which is useful for devices deployed
+    /// at multiple sites. With `scan`, the access points in range are listed
+    /// first and networks that were not found are skipped. Returns the index
+    /// of the joined network together with the join response. If no network
+    /// could be joined, the error of the last attempt is returned
+    /// (`EspError::ApNotFound` if the scan found none of the networks).
+    /// Errors unrelated to joining, e.g. of the serial port, abort
+    /// immediately.
+    ///
+    /// This blocks until a network was joined or all attempts failed, even
+    /// in non-blocking mode.
+    pub fn join_any(
+        &mut self,
+        networks: &[types::Credentials],
+        scan: bool,
+    ) -> EspResult<(usize, responses::JoinResponse)> {
+        let in_range = if scan {
+            Some(nb::block!(self.list_access_points()).map_err(nb::Error::Other)?)
+        } else {
+            None
+        };
+        let mut error = EspError::ApNotFound;
+        for (index, network) in networks.iter().enumerate() {
+            if let Some(found) = &in_range {
+                if !found.access_points.iter().any(|ap| ap.ssid == network.ssid) {
+                    continue;
+                }
+            }
+            match nb::block!(self.join_access_point(
+                network.ssid.clone(),
+                network.psk.as_str(),
+                types::Target::Current,
+            )) {
+                Ok(response) if response.connected => return Ok((index, response)),
+                Ok(_) => error = EspError::JoinFailed,
+                Err(
+                    e @ (EspError::Timeout
+                    | EspError::WrongPassword
+                    | EspError::ApNotFound
+                    | EspError::JoinFailed
+                    | EspError::Fail
+                    | EspError::InvalidArgument),
+                ) => error = e,
+                Err(e) => return Err(nb::Error::Other(e)),
+            }
+        }
+        Err(nb::Error::Other(error))
+    }
+
+    /// Send a join command and complete the response with the status lines,
+    /// which are processed as URCs.
+    fn join<T, const LEN: usize>(&mut self, command: &T) -> EspResult<responses::JoinResponse>
+    where
+        T: atat::AtatCmd<LEN, Response = responses::JoinResponse>,
+    {
+        let mut response = self.send_command(command)?;
+        self.process_urcs();
+        response.connected |= self.state.wifi_connected;
+        response.got_ip |= self.state.got_ip;
+        Ok(response)
+    }
+
+    /// Resolve a hostname of at most 64 bytes to an IPv4 address.
+    ///
+    /// Resolution can take several seconds. To avoid this for hosts that are
+    /// connected to repeatedly, use a [`DnsCache`](dns/struct.DnsCache.html).
+    pub fn resolve_hostname(&mut self, hostname: &str) -> EspResult<core::net::Ipv4Addr> {
+        let command = requests::ResolveHostname::new(hostname)
+            .filter(|command| command.is_valid())
+            .ok_or(nb::Error::Other(EspError::InvalidArgument))?;
+        self.send_command(&command)
+            .map(|address: responses::ResolvedAddress| address.ip)
+    }
+
+    /// Disconnect from the access point.
+    pub fn leave_access_point(&mut self) -> EspResult<()> {
+        self.send_command(&requests::LeaveAccessPoint)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Configure the soft AP, which is enabled with the WiFi mode.
+    ///
+    /// Invalid configurations, e.g. passwords shorter than 8 characters, are
+    /// rejected with `EspError::InvalidArgument`.
+    pub fn set_soft_ap_config(
+        &mut self,
+        config: types::SoftApConfig,
+        target: types::Target,
+    ) -> EspResult<()> {
+        let command =
+            requests::SetSoftApConfig::to(config, target).with_dialect(self.state.dialect);
+        if !command.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.select_target(target)?;
+        self.send_command(&command)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Disconnect the station with the specified MAC address from the soft AP,
+    /// e.g. to end a provisioning session.
+    ///
+    /// Only supported by ESP-AT firmwares.
+    pub fn disconnect_station(&mut self, mac: types::MacAddress) -> EspResult<()> {
+        self.disconnect_stations(&requests::DisconnectStation::new(mac))
+    }
+
+    /// Disconnect all stations from the soft AP.
+    ///
+    /// Only supported by ESP-AT firmwares.
+    pub fn disconnect_all_stations(&mut self) -> EspResult<()> {
+        self.disconnect_stations(&requests::DisconnectStation::all())
+    }
+
+    fn disconnect_stations(&mut self, command: &requests::DisconnectStation) -> EspResult<()> {
+        if self.state.dialect != types::Dialect::EspAt {
+            return Err(nb::Error::Other(EspError::Unsupported));
+        }
+        self.send_command(command)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Return the access point the station is connected to, `None` if it's
+    /// not connected.
+    pub fn get_joined_access_point(&mut self) -> EspResult<Option<responses::JoinedAccessPoint>> {
+        self.send_command(&requests::GetJoinedAccessPoint::new(self.state.dialect))
+            .map(|status: responses::StationStatus| status.access_point)
+    }
+
+    /// Return the signal strength of the joined access point in dBm, `None`
+    /// if the station is not connected.
+    pub fn rssi(&mut self) -> EspResult<Option<i8>> {
+        self.get_joined_access_point()
+            .map(|access_point| access_point.map(|access_point| access_point.rssi))
+    }
+
+    /// Scan for access points in range.
+    pub fn list_access_points(&mut self) -> EspResult<responses::AccessPoints> {
+        self.send_command(&requests::ListAccessPoints)
+    }
+
+    /// Block until the station obtained an IP address and return it.
+    ///
+    /// Joining an access point may succeed before DHCP has completed, so this
+    /// should be called before opening connections. The local address is
+    /// queried every 100 ms until it is set or `timeout_ms` have passed, in
+    /// which case `EspError::Timeout` is returned. Failures to delay are
+    /// reported as `EspError::Pin`.
+    pub fn wait_for_ip<D: DelayUs>(
+        &mut self,
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> EspResult<core::net::Ipv4Addr> {
+        let mut waited_ms = 0;
+        loop {
+            let address = nb::block!(self.get_local_address()).map_err(nb::Error::Other)?;
+            if let Some(ip) = address.station.and_then(|station| station.ip) {
+                self.state.got_ip = true;
+                return Ok(ip);
+            }
+            if waited_ms >= timeout_ms {
+                return Err(nb::Error::Other(EspError::Timeout));
+            }
+            delay
+                .delay_ms(IP_POLL_INTERVAL_MS)
+                .map_err(|_| nb::Error::Other(EspError::Pin))?;
+            waited_ms += IP_POLL_INTERVAL_MS;
+        }
+    }
+
+    /// Configure whether and how the station reconnects to the access point
+    /// by itself after the connection was lost.
+    ///
+    /// Only supported by ESP-AT firmwares. Values outside of the ranges
+    /// documented in [`ReconnectConfig`](types/struct.ReconnectConfig.html)
+    /// are rejected with `EspError::InvalidArgument`.
+    pub fn set_reconnect_config(
+        &mut self,
+        config: types::ReconnectConfig,
+        target: types::Target,
+    ) -> EspResult<()> {
+        self.ensure_supported(|capabilities| capabilities.reconnect_config)?;
+        if !config.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.select_target(target)?;
+        self.send_command(&requests::SetReconnectConfig::new(config))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Return how the station reconnects to the access point by itself.
+    ///
+    /// Only supported by ESP-AT firmwares.
+    pub fn get_reconnect_config(&mut self) -> EspResult<types::ReconnectConfig> {
+        self.ensure_supported(|capabilities| capabilities.reconnect_config)?;
+        self.send_command(&requests::GetReconnectConfig)
     }
 
     /// Return the current connection status.
     pub fn get_connection_status(&mut self) -> EspResult<types::ConnectionStatus> {
-        self.client.send(&requests::GetConnectionStatus)
+        self.send_command(&requests::GetConnectionStatus)
+    }
+
+    /// Start a server that accepts connections on a port.
+    ///
+    /// Multiplexing must be enabled, see
+    /// [`InitConfig`](types/struct.InitConfig.html). Accepted connections
+    /// are taken with [`take_accepted`](#method.take_accepted) or with a
+    /// [`TcpServer`](socket/struct.TcpServer.html). SSL servers are only
+    /// supported by ESP-AT firmwares, other firmwares fail with
+    /// `EspError::Unsupported`.
+    pub fn start_server(&mut self, server: &requests::StartServer) -> EspResult<()> {
+        if server.is_ssl() && self.state.dialect != types::Dialect::EspAt {
+            return Err(nb::Error::Other(EspError::Unsupported));
+        }
+        self.send_command(server)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Stop the server.
+    ///
+    /// Connections that were accepted stay open, but are no longer returned
+    /// by [`take_accepted`](#method.take_accepted).
+    pub fn stop_server(&mut self) -> EspResult<()> {
+        self.send_command(&requests::StopServer)
+            .map(|_: responses::EmptyResponse| self.state.accepted_links = 0)
+    }
+
+    /// Return a connection that was accepted by the server, if any.
+    ///
+    /// Every connection is returned once.
+    pub fn take_accepted(&mut self) -> Option<types::MultiplexingType> {
+        self.process_urcs();
+        let index = self.state.accepted_links.trailing_zeros();
+        let id = types::ConnectionId::from_index(index as u8)?;
+        self.state.accepted_links &= !(1 << index);
+        Some(types::MultiplexingType::Multiplexed(id))
+    }
+
+    /// Return the open connections with their protocol and addresses.
+    ///
+    /// This tells apart connections that the device opened as client from
+    /// those accepted by its server.
+    pub fn get_links(&mut self) -> EspResult<responses::Links> {
+        self.send_command(&requests::GetLinks::new(self.state.dialect))
+    }
+
+    /// Return the state of the station.
+    ///
+    /// Only supported by ESP-AT firmwares. In addition to the states of
+    /// [`get_connection_status`](#method.get_connection_status), it reports
+    /// whether the station is still connecting or waiting for DHCP, but not
+    /// the state of the TCP and UDP connections.
+    pub fn get_station_state(&mut self) -> EspResult<types::ConnectionStatus> {
+        self.ensure_supported(|capabilities| capabilities.cwstate)?;
+        self.send_command(&requests::GetStationState)
+    }
+
+    /// Return the SSID of the access point the station is configured to
+    /// join, also while it's not connected.
+    ///
+    /// Only supported by ESP-AT firmwares. Returns `None` if no access point
+    /// is configured.
+    pub fn get_configured_ssid(&mut self) -> EspResult<Option<types::Ssid>> {
+        self.ensure_supported(|capabilities| capabilities.cwstate)?;
+        self.send_command(&requests::GetConfiguredSsid)
+            .map(|config: responses::StationConfig| config.ssid)
     }
 
-    /// Return the locally assigned IP and MAC address.
+    /// Return the sleep mode.
+    pub fn get_sleep_mode(&mut self) -> EspResult<types::SleepMode> {
+        self.send_command(&requests::GetSleepMode::new(self.state.dialect))
+    }
+
+    /// Set the sleep mode, which saves power while the station is connected.
+    ///
+    /// The sleep mode is not persisted by the firmware.
+    /// [`SleepMode::ModemListenInterval`](types/enum.SleepMode.html#variant.ModemListenInterval)
+    /// is only supported by ESP-AT firmwares.
+    pub fn set_sleep_mode(&mut self, mode: types::SleepMode) -> EspResult<()> {
+        if mode == types::SleepMode::ModemListenInterval
+            && self.state.dialect != types::Dialect::EspAt
+        {
+            return Err(nb::Error::Other(EspError::Unsupported));
+        }
+        self.send_command(&requests::SetSleepMode::to(mode).with_dialect(self.state.dialect))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Return the IP and MAC addresses of the station and access point
+    /// interfaces.
     pub fn get_local_address(&mut self) -> EspResult<responses::LocalAddress> {
-        self.client.send(&requests::GetLocalAddress)
+        self.send_command(&requests::GetLocalAddress)
+    }
+
+    /// Read the persisted configuration of the device.
+    ///
+    /// The default configuration is read, see
+    /// [`DeviceConfig`](types/struct.DeviceConfig.html). Optional settings
+    /// that the firmware doesn't support or that fail to be read are
+    /// reported as `None`. This blocks until all settings are read, even in
+    /// non-blocking mode.
+    pub fn read_config(&mut self) -> EspResult<types::DeviceConfig> {
+        let esp_at = self.state.dialect == types::Dialect::EspAt;
+        let reconnect = if esp_at {
+            optional(nb::block!(self.get_reconnect_config()))?
+        } else {
+            None
+        };
+        let system_messages = if esp_at {
+            optional(nb::block!(self.get_system_messages()))?
+        } else {
+            None
+        };
+        let sleep_mode = optional(nb::block!(self.get_sleep_mode()))?;
+        let ap_credentials_stored = if esp_at {
+            optional(nb::block!(self.get_configured_ssid()))?.map(|ssid| ssid.is_some())
+        } else {
+            None
+        };
+        let target = types::Target::Default;
+        Ok(types::DeviceConfig {
+            wifi_mode: nb::block!(self.get_default_wifi_mode()).map_err(nb::Error::Other)?,
+            uart: self.send_blocking(&requests::GetUartConfig::new(target))?,
+            reconnect,
+            system_messages,
+            sleep_mode,
+            ap_credentials_stored,
+            joined_ssid: nb::block!(self.get_joined_access_point())
+                .map_err(nb::Error::Other)?
+                .map(|access_point| access_point.ssid),
+            ip: self.send_blocking(
+                &requests::GetIpConfig::new(target).with_dialect(self.state.dialect),
+            )?,
+        })
+    }
+
+    /// Persist a configuration read with [`read_config`](#method.read_config)
+    /// to the device.
+    ///
+    /// Only the settings that differ from the configuration of the device are
+    /// changed, settings that are `None` are left alone, as are the settings
+    /// that are only reported, see
+    /// [`DeviceConfig`](types/struct.DeviceConfig.html). On ESP-AT
+    /// firmwares, storing parameters in flash (`AT+SYSSTORE`) is enabled
+    /// first and stays enabled afterwards. The UART
+    /// configuration is changed last and takes effect right away, so the
+    /// host has to reconfigure its own UART if it differs. Fails with
+    /// `EspError::Unsupported` if a setting is not supported by the
+    /// firmware. This blocks until the configuration is applied, even in
+    /// non-blocking mode.
+    pub fn apply_config(&mut self, config: &types::DeviceConfig) -> EspResult<()> {
+        let current = self.read_config()?;
+        // Settings without a `_DEF` variant are persisted by the store mode
+        self.select_target(types::Target::Default)?;
+        if current.wifi_mode != config.wifi_mode {
+            nb::block!(self.set_wifi_mode(config.wifi_mode, types::Target::Default))
+                .map_err(nb::Error::Other)?;
+        }
+        match config.reconnect {
+            Some(reconnect) if current.reconnect != Some(reconnect) => {
+                nb::block!(self.set_reconnect_config(reconnect, types::Target::Default))
+                    .map_err(nb::Error::Other)?
+            }
+            _ => {}
+        }
+        match config.system_messages {
+            Some(messages) if current.system_messages != Some(messages) => {
+                nb::block!(self.set_system_messages(messages)).map_err(nb::Error::Other)?
+            }
+            _ => {}
+        }
+        if current.uart != config.uart {
+            nb::block!(self.set_uart_config(config.uart, types::Target::Default))
+                .map_err(nb::Error::Other)?;
+        }
+        Ok(())
+    }
+
+    /// Return the IP address, gateway and netmask of the station.
+    pub fn get_ip_config(&mut self) -> EspResult<responses::IpConfig> {
+        self.send_command(
+            &requests::GetIpConfig::new(types::Target::Current).with_dialect(self.state.dialect),
+        )
+    }
+
+    /// Configure the SNTP client, which synchronizes the clock of the device.
+    ///
+    /// Timezones that are not whole hours are only supported by ESP-AT
+    /// firmwares, other invalid configurations are rejected with
+    /// `EspError::InvalidArgument` as well.
+    pub fn configure_sntp(&mut self, config: &requests::ConfigureSntp) -> EspResult<()> {
+        self.ensure_supported(|capabilities| capabilities.sntp)?;
+        if !config.is_valid(self.state.dialect) {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.send_command(config)
+            .map(|_: responses::EmptyResponse| {
+                self.state.utc_offset_min = Some(config.utc_offset_min());
+            })
+    }
+
+    /// Return the current local time, `None` if the clock of the device was
+    /// not synchronized yet.
+    ///
+    /// The time is in the timezone configured with
+    /// [`configure_sntp`](#method.configure_sntp), the Unix timestamp is
+    /// available with [`DateTime::unix_timestamp`]. If the timezone is not
+    /// known yet, it's queried first, which blocks even in non-blocking
+    /// mode.
+    ///
+    /// [`DateTime::unix_timestamp`]: types/struct.DateTime.html#method.unix_timestamp
+    pub fn now(&mut self) -> EspResult<Option<types::DateTime>> {
+        self.ensure_supported(|capabilities| capabilities.sntp)?;
+        let utc_offset_min = match self.state.utc_offset_min {
+            Some(utc_offset_min) => utc_offset_min,
+            None => {
+                let config = self.send_blocking(&requests::GetSntpConfig)?;
+                self.state.utc_offset_min = Some(config.utc_offset_min);
+                config.utc_offset_min
+            }
+        };
+        self.send_command(&requests::GetSntpTime::new(utc_offset_min))
+            .map(|response| response.time)
+    }
+
+    /// Return the system clock of the device as Unix timestamp in seconds.
+    ///
+    /// Only supported by ESP-AT v2 firmwares. Unlike [`now`](#method.now),
+    /// this doesn't depend on the configured timezone; the value starts at
+    /// zero on boot until the clock is set or synchronized with SNTP.
+    pub fn get_timestamp(&mut self) -> EspResult<u64> {
+        self.ensure_supported(|capabilities| capabilities.timestamp)?;
+        self.send_command(&requests::GetTimestamp)
+            .map(|timestamp: responses::Timestamp| timestamp.seconds)
+    }
+
+    /// Set the system clock of the device to a Unix timestamp in seconds.
+    pub fn set_timestamp(&mut self, seconds: u64) -> EspResult<()> {
+        self.ensure_supported(|capabilities| capabilities.timestamp)?;
+        self.send_command(&requests::SetTimestamp::new(seconds))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Return the remaining free heap of the device in bytes.
+    ///
+    /// A steadily decreasing value hints at a memory leak in the firmware.
+    pub fn get_free_ram(&mut self) -> EspResult<u32> {
+        self.send_command(&requests::GetFreeRam)
+            .map(|ram: responses::FreeRam| ram.bytes)
+    }
+
+    /// Read the raw value of the ADC connected to the TOUT pin.
+    pub fn read_adc(&mut self) -> EspResult<u16> {
+        self.send_command(&requests::ReadAdc)
+            .map(|adc: responses::AdcValue| adc.raw)
+    }
+
+    /// Configure a pin of the device as GPIO.
+    ///
+    /// `function` is the IO MUX function number that selects the GPIO
+    /// function of the pin, see [`gpio::ConfigurePin`][ConfigurePin].
+    ///
+    /// [ConfigurePin]: commands/gpio/struct.ConfigurePin.html
+    pub fn configure_gpio(
+        &mut self,
+        pin: u8,
+        function: u8,
+        direction: gpio::Direction,
+        pull_up: bool,
+    ) -> EspResult<()> {
+        self.send_command(&gpio::ConfigurePin::new(pin, function, pull_up))
+            .map(|_: responses::EmptyResponse| ())?;
+        self.send_command(&gpio::SetDirection::new(pin, direction))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Set the level of a GPIO pin of the device.
+    pub fn write_gpio(&mut self, pin: u8, level: gpio::Level) -> EspResult<()> {
+        self.send_command(&gpio::WritePin::new(pin, level))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Read the level of a GPIO pin of the device.
+    pub fn read_gpio(&mut self, pin: u8) -> EspResult<gpio::Level> {
+        self.send_command(&gpio::ReadPin::new(pin))
+            .map(|state: gpio::PinState| state.level)
+    }
+
+    /// Whether the station is connected to an access point.
+    ///
+    /// This is tracked from the `WIFI CONNECTED` and `WIFI DISCONNECT`
+    /// messages of the device, so it is only accurate once the client has
+    /// seen the device join, e.g. through
+    /// [`join_access_point`](#method.join_access_point). Use
+    /// [`get_connection_status`](#method.get_connection_status) to query the
+    /// device instead.
+    pub fn is_wifi_connected(&mut self) -> bool {
+        self.process_urcs();
+        self.state.wifi_connected
+    }
+
+    /// Whether the station obtained an IP address.
+    ///
+    /// Tracked like [`is_wifi_connected`](#method.is_wifi_connected).
+    pub fn has_ip(&mut self) -> bool {
+        self.process_urcs();
+        self.state.got_ip
+    }
+
+    /// Return whether the station lost the connection to the access point
+    /// since the last call.
+    ///
+    /// Open connections are closed by the device in that case, so
+    /// applications should stop sending and rejoin.
+    pub fn take_wifi_disconnect(&mut self) -> bool {
+        self.process_urcs();
+        core::mem::replace(&mut self.state.wifi_lost, false)
+    }
+
+    /// Return whether the device restarted unexpectedly since the last call,
+    /// e.g. because of a crash or a brownout.
+    ///
+    /// The device lost its configuration and all connections in that case,
+    /// so it should be initialized again.
+    pub fn take_reboot(&mut self) -> bool {
+        self.process_urcs();
+        core::mem::replace(&mut self.state.rebooted, false)
+    }
+
+    /// Whether the connection is open.
+    ///
+    /// Tracked by the client from the results of
+    /// [`establish_connection`](#method.establish_connection) and the
+    /// `CONNECT` and `CLOSED` messages of the device. A `CLOSED` message
+    /// that arrived after received data is only processed once the data was
+    /// read.
+    pub fn is_connection_open(&mut self, mux: types::MultiplexingType) -> bool {
+        self.process_urcs();
+        self.state.open_links & (1 << mux.index()) != 0
+    }
+
+    /// Return which connections have received data pending, can send data,
+    /// or were closed since the last poll.
+    ///
+    /// In active receive mode, received data is delivered in order over all
+    /// connections, so at most one connection is readable at a time: the one
+    /// whose data arrived first. In passive receive mode, all connections
+    /// with data buffered by the device are readable. Data buffered by a
+    /// [`TcpSocket`](socket/struct.TcpSocket.html) is not taken into
+    /// account. No connection is writable while a send is in progress.
+    pub fn poll_readiness(&mut self) -> types::Readiness {
+        self.process_urcs();
+        let mut readable = 0;
+        self.client.peek_urc_with::<Urc<N>, _>(|urc| {
+            if let Urc::NetworkData(data) = urc {
+                readable = 1 << data.mux.index();
+            }
+            false
+        });
+        let sending = self.state.command_pending || self.state.prepared.is_some();
+        types::Readiness {
+            readable: readable | self.state.available_links,
+            writable: if sending { 0 } else { self.state.open_links },
+            closed: core::mem::replace(&mut self.state.closed_links, 0),
+        }
+    }
+
+    /// Return the traffic statistics of a connection.
+    ///
+    /// The statistics are reset when the connection is opened. To record the
+    /// time of the last activity, set a time source with
+    /// [`set_time_source`](#method.set_time_source).
+    pub fn stats(&mut self, mux: types::MultiplexingType) -> types::ConnectionStats {
+        self.process_urcs();
+        self.state.stats[mux.index()]
+    }
+
+    /// Set a function that returns the current time in milliseconds, e.g.
+    /// since boot.
+    ///
+    /// It's used to record the time of the last activity in the
+    /// [`stats`](#method.stats) of a connection, and the latencies in the
+    /// [`command_metrics`](#method.command_metrics).
+    pub fn set_time_source(&mut self, now: fn() -> u64) {
+        self.state.time_source = Some(now);
+    }
+
+    /// Enable or disable recording latency and failure statistics per
+    /// command.
+    ///
+    /// Metrics help to tune timeouts and to detect a degrading serial link.
+    /// Latencies are only recorded if a time source is set. Statistics are
+    /// kept for the first [`MAX_COMMAND_METRICS`] distinct commands.
+    /// Disabling discards the recorded statistics.
+    ///
+    /// [`MAX_COMMAND_METRICS`]: types/constant.MAX_COMMAND_METRICS.html
+    pub fn set_metrics_enabled(&mut self, enabled: bool) {
+        match (enabled, self.state.metrics.is_some()) {
+            (true, false) => self.state.metrics = Some(Vec::new()),
+            (false, _) => self.state.metrics = None,
+            (true, true) => {}
+        }
+    }
+
+    /// Return the statistics recorded per command, empty if metrics are
+    /// disabled.
+    pub fn command_metrics(&self) -> &[types::CommandMetrics] {
+        self.state.metrics.as_deref().unwrap_or_default()
+    }
+
+    /// Discard the recorded statistics, keeping metrics enabled.
+    pub fn reset_metrics(&mut self) {
+        if let Some(metrics) = self.state.metrics.as_mut() {
+            metrics.clear();
+        }
+    }
+
+    /// Process pending URCs and notify `handler` about the events reported
+    /// since the last call, in the order in which they occurred.
+    ///
+    /// This includes the events of URCs that were processed during commands.
+    /// Up to 16 events are kept between two calls, older ones are dropped.
+    /// Received data is passed to [`EspEventHandler::on_data`] instead of
+    /// being returned by [`poll_network_data`](#method.poll_network_data).
+    ///
+    /// [`EspEventHandler::on_data`]: events/trait.EspEventHandler.html#method.on_data
+    pub fn poll_events<H>(&mut self, handler: &mut H)
+    where
+        H: events::EspEventHandler<N> + ?Sized,
+    {
+        loop {
+            let urc = self.take_urc(|urc| matches!(urc, Urc::NetworkData(_)));
+            while let Some(event) = self.state.events.pop_front() {
+                event.notify(handler);
+            }
+            match urc {
+                Some(Urc::NetworkData(data)) => handler.on_data(&data),
+                _ => return,
+            }
+        }
+    }
+
+    /// Count received data in the statistics of its connection.
+    fn record_received(&mut self, data: &urcs::NetworkData<N>) {
+        self.record_received_len(data.mux, data.data.len());
+    }
+
+    /// Update the statistics of a connection with `len` received bytes.
+    fn record_received_len(&mut self, mux: types::MultiplexingType, len: usize) {
+        let now = self.time_ms();
+        let stats = &mut self.state.stats[mux.index()];
+        stats.bytes_received = stats.bytes_received.wrapping_add(len as u32);
+        stats.segments_received = stats.segments_received.wrapping_add(1);
+        stats.last_activity_ms = now.or(stats.last_activity_ms);
+    }
+
+    /// Return the current time in milliseconds, if a time source is set.
+    pub(crate) fn time_ms(&self) -> Option<u64> {
+        self.state.time_source.map(|now| now())
+    }
+
+    /// Return the next data received over a TCP, UDP or SSL connection, if
+    /// any.
+    ///
+    /// Received data holds back the URCs that arrived after it, so this
+    /// should be called regularly while connections are open, unless it's
+    /// passed to an event handler with [`poll_events`](#method.poll_events).
+    pub fn poll_network_data(&mut self) -> Option<urcs::NetworkData<N>> {
+        match self.take_urc(|urc| matches!(urc, Urc::NetworkData(_))) {
+            Some(Urc::NetworkData(data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Set whether received data is sent to the host immediately (the
+    /// default) or buffered by the device.
+    ///
+    /// In passive mode, the device announces received data and keeps it
+    /// until it's read with [`receive_data`](#method.receive_data), which
+    /// allows to receive more data than fits into the URC queue without
+    /// losing any. TCP flow control slows down the peer while the buffer of
+    /// the device is full. Passive mode requires AT firmware 1.7 or ESP-AT.
+    pub fn set_receive_mode(&mut self, mode: types::ReceiveMode) -> EspResult<()> {
+        self.send_command(&requests::SetReceiveMode::new(mode)).map(
+            |_: responses::EmptyResponse| {
+                self.state.receive_mode = mode;
+                if mode == types::ReceiveMode::Active {
+                    self.state.available_links = 0;
+                }
+            },
+        )
+    }
+
+    /// Read up to `N` bytes of the data buffered by the device for a
+    /// connection, in passive receive mode.
+    ///
+    /// At most 2048 bytes are read at once, the limit of the firmware. The
+    /// data is empty if nothing is buffered. Larger amounts of data are
+    /// read in multiple chunks by calling this repeatedly. Whether data is
+    /// buffered can be checked with
+    /// [`poll_readiness`](#method.poll_readiness). The response queue must
+    /// be able to hold `N` bytes plus about 40 bytes of header.
+    pub fn receive_data(
+        &mut self,
+        mux: types::MultiplexingType,
+    ) -> EspResult<urcs::NetworkData<N>> {
+        let data = self.send_command(&requests::ReceiveData::<N>::new(mux))?;
+        if data.data.len() < N.min(requests::MAX_RECEIVE_LEN) {
+            self.state.available_links &= !(1 << mux.index());
+        }
+        if !data.data.is_empty() {
+            self.record_received(&data);
+        }
+        Ok(data)
+    }
+
+    /// Read the data buffered by the device for a connection into `buf`, in
+    /// passive receive mode.
+    ///
+    /// Like [`receive_data`](#method.receive_data), but up to the length of
+    /// `buf` is read and the data is copied into `buf` directly instead of
+    /// into a `NetworkData`, which saves a copy of the data on the stack.
+    /// At most 2048 bytes are read at once, the limit of the firmware.
+    /// Returns the number of bytes read, which is 0 if nothing is buffered.
+    /// The response queue must be able to hold the requested length plus
+    /// about 40 bytes of header.
+    pub fn receive_data_into(
+        &mut self,
+        mux: types::MultiplexingType,
+        buf: &mut [u8],
+    ) -> EspResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let requested = buf.len().min(requests::MAX_RECEIVE_LEN);
+        let received: responses::ReceivedData =
+            self.send_command(&requests::ReceiveDataInto::new(mux, buf))?;
+        if received.len < requested {
+            self.state.available_links &= !(1 << mux.index());
+        }
+        if received.len > 0 {
+            self.record_received_len(mux, received.len);
+        }
+        Ok(received.len)
+    }
+
+    /// Return the next data received over the specified connection, if it's
+    /// the next pending data or buffered by the device.
+    pub(crate) fn take_network_data(
+        &mut self,
+        mux: types::MultiplexingType,
+    ) -> EspResult<Option<urcs::NetworkData<N>>> {
+        if let Some(Urc::NetworkData(data)) =
+            self.take_urc(|urc| matches!(urc, Urc::NetworkData(data) if data.mux == mux))
+        {
+            return Ok(Some(data));
+        }
+        if self.state.available_links & (1 << mux.index()) == 0 {
+            return Ok(None);
+        }
+        let data = nb::block!(self.receive_data(mux)).map_err(nb::Error::Other)?;
+        Ok(Some(data).filter(|data| !data.data.is_empty()))
+    }
+
+    /// Return a handle to the certificate and key storage of the device.
+    ///
+    /// Fails if the firmware is known not to support it, see
+    /// [`capabilities`](#method.capabilities).
+    #[cfg(feature = "pki")]
+    pub fn pki(&mut self) -> EspResult<pki::PkiManager<'_, C, N>> {
+        self.ensure_supported(|capabilities| capabilities.pki)?;
+        Ok(pki::PkiManager { esp: self })
+    }
+
+    /// Return a handle to the MQTT client built into the firmware.
+    ///
+    /// Fails if the firmware is known not to support MQTT, see
+    /// [`capabilities`](#method.capabilities).
+    #[cfg(feature = "mqtt")]
+    pub fn mqtt(&mut self) -> EspResult<mqtt::MqttClient<'_, C, N>> {
+        self.ensure_supported(|capabilities| capabilities.mqtt)?;
+        Ok(mqtt::MqttClient { esp: self })
+    }
+
+    /// Send an HTTP request with the HTTP client built into the firmware.
+    ///
+    /// The response body is written to `body` and its length returned. Fails
+    /// with `EspError::Overflow` if the request is too long to be encoded or
+    /// the response body doesn't fit into `body`, with
+    /// `EspError::InvalidArgument` if it contains control characters, and
+    /// with `EspError::Unsupported` if the firmware is known not to support
+    /// HTTP.
+    #[cfg(feature = "http")]
+    pub fn http_request(
+        &mut self,
+        request: commands::http::Request,
+        body: &mut [u8],
+    ) -> EspResult<usize> {
+        self.ensure_supported(|capabilities| capabilities.http_client)?;
+        if !request.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        let command = commands::http::HttpClient::new(request, body)
+            .ok_or(nb::Error::Other(EspError::Overflow))?;
+        self.send_command(&command)
+            .map(|response: commands::http::Response| response.len)
+    }
+
+    /// Return a handle to the WebSocket client built into the firmware.
+    ///
+    /// Fails if the firmware is known not to support WebSockets, see
+    /// [`capabilities`](#method.capabilities).
+    #[cfg(feature = "websocket")]
+    pub fn websocket(&mut self) -> EspResult<websocket::WebSocketClient<'_, C, N>> {
+        self.ensure_supported(|capabilities| capabilities.websocket)?;
+        Ok(websocket::WebSocketClient { esp: self })
+    }
+
+    /// Return a handle to the Bluetooth LE GATT server of the firmware.
+    ///
+    /// Fails if the firmware is known not to support Bluetooth LE, see
+    /// [`capabilities`](#method.capabilities).
+    #[cfg(feature = "ble")]
+    pub fn ble(&mut self) -> EspResult<ble::BleServer<'_, C, N>> {
+        self.ensure_supported(|capabilities| capabilities.ble)?;
+        Ok(ble::BleServer { esp: self })
+    }
+}
+
+/// Report a setting that the firmware doesn't support or fails to return as
+/// `None`.
+///
+/// Failures of the communication with the device are still returned.
+fn optional<T>(result: Result<T, EspError>) -> EspResult<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(
+            EspError::Unsupported
+            | EspError::CommandFailed
+            | EspError::Fail
+            | EspError::Parse
+            | EspError::InvalidResponse,
+        ) => Ok(None),
+        Err(e) => Err(nb::Error::Other(e)),
+    }
+}
+
+/// Fail if the SSID or PSK of a join command can't be sent to the device.
+fn check_join(command: &requests::JoinAccessPoint) -> EspResult<()> {
+    if command.is_valid() {
+        Ok(())
+    } else {
+        Err(nb::Error::Other(EspError::InvalidArgument))
     }
 }
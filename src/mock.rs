@@ -0,0 +1,641 @@
+//! A scripted fake ESP8266 for testing without hardware.
+//!
+//! [`MockEsp`] hands out a TX half that is passed to the client instead of
+//! the serial port, and an RX half that is fed to the ingress manager, e.g.
+//! with [`spawn_reader`](../host/fn.spawn_reader.html). Commands written by
+//! the client are answered with the responses registered with
+//! [`MockEsp::on`], all other commands are answered with `ERROR`. URCs and
+//! received network data can be injected at any time.
+
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    format, io,
+    string::String,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+    vec::Vec,
+};
+
+use embedded_hal::serial;
+
+/// How long a read on the RX half waits for data before timing out.
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+
+#[derive(Default)]
+struct State {
+    /// Registered responses, matched by command line
+    responses: Vec<(String, Vec<u8>)>,
+    /// Commands received from the client, without line terminator
+    received: Vec<String>,
+    /// Data sent by the client after `AT+CIPSEND`
+    sent_data: Vec<Vec<u8>>,
+    /// Bytes written by the client that don't form a complete line yet
+    line: Vec<u8>,
+    /// Number of data bytes still expected after `AT+CIPSEND`
+    data_remaining: usize,
+    /// Bytes to be read by the client
+    output: VecDeque<u8>,
+}
+
+impl State {
+    fn process(&mut self, byte: u8) {
+        self.line.push(byte);
+        if self.data_remaining > 0 {
+            self.data_remaining -= 1;
+            if self.data_remaining == 0 {
+                let data = core::mem::take(&mut self.line);
+                self.reply(format!("\r\nRecv {} bytes\r\n\r\nSEND OK\r\n", data.len()));
+                self.sent_data.push(data);
+            }
+            return;
+        }
+        if !self.line.ends_with(b"\r\n") {
+            return;
+        }
+
+        let line = core::mem::take(&mut self.line);
+        let command = String::from_utf8_lossy(&line[..line.len() - 2]).into_owned();
+        match self.response(&command) {
+            Some(response) => {
+                if command.starts_with("AT+CIPSEND=") {
+                    self.data_remaining = send_len(&command);
+                }
+                self.reply(&response);
+            }
+            None => self.reply("\r\nERROR\r\n"),
+        }
+        self.received.push(command);
+    }
+
+    /// Return the response to `command`, consuming it if there are more
+    /// responses for the same command.
+    fn response(&mut self, command: &str) -> Option<Vec<u8>> {
+        let mut matches = self
+            .responses
+            .iter()
+            .enumerate()
+            .filter(|(_, (c, _))| c == command)
+            .map(|(index, _)| index);
+        let index = matches.next()?;
+        if matches.next().is_some() {
+            Some(self.responses.remove(index).1)
+        } else {
+            Some(self.responses[index].1.clone())
+        }
+    }
+
+    fn reply(&mut self, response: impl AsRef<[u8]>) {
+        self.output.extend(response.as_ref());
+    }
+}
+
+/// Return the length argument of an `AT+CIPSEND=[<link_id>,]<len>` command.
+fn send_len(command: &str) -> usize {
+    command
+        .rsplit(['=', ','])
+        .next()
+        .and_then(|len| len.parse().ok())
+        .unwrap_or(0)
+}
+
+/// A scripted fake ESP8266.
+#[derive(Clone, Default)]
+pub struct MockEsp {
+    state: Arc<Mutex<State>>,
+}
+
+impl MockEsp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `command` (without line terminator) with `response`.
+    ///
+    /// The response is sent verbatim, so it must contain the result code,
+    /// e.g. `"\r\nOK\r\n"`. For `AT+CIPSEND`, the response should end with
+    /// the `>` prompt, the data sent afterwards is acknowledged with
+    /// `SEND OK` and can be inspected with [`sent_data`](#method.sent_data).
+    ///
+    /// If a command is registered several times, the responses are used in
+    /// order and the last one is repeated.
+    pub fn on(&self, command: &str, response: impl AsRef<[u8]>) -> &Self {
+        self.lock()
+            .responses
+            .push((command.into(), response.as_ref().into()));
+        self
+    }
+
+    /// Send unsolicited bytes to the client, e.g. a URC.
+    pub fn inject(&self, bytes: &[u8]) {
+        self.lock().output.extend(bytes);
+    }
+
+    /// Send data received over a connection to the client as `+IPD`
+    /// message.
+    pub fn inject_network_data(&self, link_id: Option<u8>, data: &[u8]) {
+        let header = match link_id {
+            Some(link_id) => format!("\r\n+IPD,{},{}:", link_id, data.len()),
+            None => format!("\r\n+IPD,{}:", data.len()),
+        };
+        let mut state = self.lock();
+        state.output.extend(header.as_bytes());
+        state.output.extend(data);
+    }
+
+    /// Return the commands received so far, without line terminators.
+    pub fn received(&self) -> Vec<String> {
+        self.lock().received.clone()
+    }
+
+    /// Return the data sent by the client after `AT+CIPSEND` so far.
+    pub fn sent_data(&self) -> Vec<Vec<u8>> {
+        self.lock().sent_data.clone()
+    }
+
+    /// Return the TX half, to be passed to the client.
+    pub fn tx(&self) -> MockTx {
+        MockTx { esp: self.clone() }
+    }
+
+    /// Return the RX half, to be fed to the ingress manager.
+    pub fn rx(&self) -> MockRx {
+        MockRx { esp: self.clone() }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// The serial TX half of a [`MockEsp`].
+pub struct MockTx {
+    esp: MockEsp,
+}
+
+impl serial::nb::Write<u8> for MockTx {
+    type Error = Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.esp.lock().process(word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The serial RX half of a [`MockEsp`].
+pub struct MockRx {
+    esp: MockEsp,
+}
+
+impl serial::nb::Read<u8> for MockRx {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.esp
+            .lock()
+            .output
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl io::Read for MockRx {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut len = 0;
+        {
+            let mut state = self.esp.lock();
+            while len < buf.len() {
+                match state.output.pop_front() {
+                    Some(byte) => {
+                        buf[len] = byte;
+                        len += 1;
+                    }
+                    None => break,
+                }
+            }
+        }
+        if len == 0 {
+            thread::sleep(READ_TIMEOUT);
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        Ok(len)
+    }
+}
+
+/// An AT client over a [`MockEsp`], for tests of the ESP client.
+///
+/// The bytes received from the mock are split by the
+/// [`EspDigester`](../digest/struct.EspDigester.html) as they arrive, like
+/// atat's ingress manager does with a buffer of
+/// [`INGRESS_BUF_LEN`](../constant.INGRESS_BUF_LEN.html) bytes. The mock
+/// answers while a command is written, so in blocking mode a command whose
+/// response is incomplete times out right away.
+#[cfg(test)]
+pub(crate) struct MockClient {
+    tx: MockTx,
+    rx: MockRx,
+    mode: atat::Mode,
+    digester: crate::digest::EspDigester,
+    /// Received bytes that were not digested yet
+    buf: Vec<u8>,
+    /// Digested responses, errors with the result code if it's custom
+    responses: VecDeque<Result<Vec<u8>, Option<Vec<u8>>>>,
+    /// Digested URCs
+    urcs: VecDeque<Vec<u8>>,
+    /// Whether a command was sent whose response wasn't returned yet
+    pending: bool,
+}
+
+#[cfg(test)]
+impl MockClient {
+    pub(crate) fn new(esp: &MockEsp, mode: atat::Mode) -> Self {
+        Self {
+            tx: esp.tx(),
+            rx: esp.rx(),
+            mode,
+            digester: crate::digest::EspDigester::new(),
+            buf: Vec::new(),
+            responses: VecDeque::new(),
+            urcs: VecDeque::new(),
+            pending: false,
+        }
+    }
+
+    /// Digest the bytes sent by the mock so far.
+    ///
+    /// If the buffer overflows, its bytes are discarded and the pending
+    /// command fails.
+    fn digest(&mut self) {
+        use embedded_hal::serial::nb::Read;
+
+        let mut chunk = Vec::with_capacity(64);
+        loop {
+            chunk.clear();
+            while chunk.len() < 64 {
+                match self.rx.read() {
+                    Ok(byte) => chunk.push(byte),
+                    Err(_) => break,
+                }
+            }
+            if chunk.is_empty() {
+                return;
+            }
+            if self.buf.len() + chunk.len() > crate::INGRESS_BUF_LEN {
+                self.buf.clear();
+                self.responses.push_back(Err(None));
+                continue;
+            }
+            self.buf.extend_from_slice(&chunk);
+            self.digest_buf();
+        }
+    }
+
+    /// Split the buffered bytes into responses and URCs.
+    fn digest_buf(&mut self) {
+        use atat::digest::{DigestResult, Digester};
+
+        loop {
+            let (result, len) = self.digester.digest(&self.buf);
+            match result {
+                DigestResult::Urc(urc) => self.urcs.push_back(urc.to_vec()),
+                DigestResult::Response(Ok(response)) => {
+                    self.responses.push_back(Ok(response.to_vec()))
+                }
+                DigestResult::Response(Err(atat::InternalError::Custom(code))) => {
+                    self.responses.push_back(Err(Some(code.to_vec())))
+                }
+                DigestResult::Response(Err(_)) => self.responses.push_back(Err(None)),
+                DigestResult::Prompt(_) | DigestResult::None => {}
+            }
+            if len == 0 {
+                return;
+            }
+            self.buf.drain(..len);
+        }
+    }
+}
+
+#[cfg(test)]
+impl atat::AtatClient for MockClient {
+    fn send<A: atat::AtatCmd<LEN>, const LEN: usize>(
+        &mut self,
+        cmd: &A,
+    ) -> nb::Result<A::Response, atat::Error> {
+        use embedded_hal::serial::nb::Write;
+
+        if !self.pending {
+            for &byte in cmd.as_bytes().iter() {
+                self.tx.write(byte).unwrap();
+            }
+            if !A::EXPECTS_RESPONSE_CODE {
+                return cmd.parse(Ok(&[])).map_err(nb::Error::Other);
+            }
+            self.pending = true;
+        }
+        self.check_response(cmd)
+    }
+
+    fn peek_urc_with<URC: atat::AtatUrc, F: FnOnce(URC::Response) -> bool>(&mut self, f: F) {
+        self.digest();
+        if let Some(urc) = self.urcs.front() {
+            // Like atat, URCs that can't be parsed are dropped
+            let handled = match URC::parse(urc) {
+                Some(urc) => f(urc),
+                None => true,
+            };
+            if handled {
+                self.urcs.pop_front();
+            }
+        }
+    }
+
+    fn check_response<A: atat::AtatCmd<LEN>, const LEN: usize>(
+        &mut self,
+        cmd: &A,
+    ) -> nb::Result<A::Response, atat::Error> {
+        self.digest();
+        let response = match self.responses.pop_front() {
+            Some(response) => response,
+            None if self.mode == atat::Mode::NonBlocking => return Err(nb::Error::WouldBlock),
+            None => {
+                self.pending = false;
+                return Err(nb::Error::Other(atat::Error::Timeout));
+            }
+        };
+        self.pending = false;
+        let response = match response {
+            Ok(ref bytes) => Ok(&bytes[..]),
+            Err(Some(ref code)) => Err(atat::InternalError::Custom(code)),
+            Err(None) => Err(atat::InternalError::Error),
+        };
+        cmd.parse(response).map_err(nb::Error::Other)
+    }
+
+    fn get_mode(&self) -> atat::Mode {
+        self.mode
+    }
+
+    fn reset(&mut self) {
+        self.digest();
+        self.buf.clear();
+        self.responses.clear();
+        self.pending = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::serial::nb::{Read, Write};
+
+    use crate::{socket::TcpSocket, types, EspClient, EspError};
+
+    fn write_all(tx: &mut MockTx, bytes: &[u8]) {
+        for &byte in bytes {
+            tx.write(byte).unwrap();
+        }
+    }
+
+    fn read_all(rx: &mut MockRx) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while let Ok(byte) = rx.read() {
+            bytes.push(byte);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_send_data() {
+        let esp = MockEsp::new();
+        esp.on("AT+CIPSEND=5", "\r\nOK\r\n> ");
+        let (mut tx, mut rx) = (esp.tx(), esp.rx());
+
+        write_all(&mut tx, b"AT+GMR\r\n");
+        assert_eq!(read_all(&mut rx), b"\r\nERROR\r\n");
+
+        write_all(&mut tx, b"AT+CIPSEND=5\r\nhello");
+        assert_eq!(
+            read_all(&mut rx),
+            b"\r\nOK\r\n> \r\nRecv 5 bytes\r\n\r\nSEND OK\r\n"
+        );
+        assert_eq!(esp.received(), ["AT+GMR", "AT+CIPSEND=5"]);
+        assert_eq!(esp.sent_data(), [b"hello"]);
+    }
+
+    /// Return a client on top of a mock that was initialized with the
+    /// default configuration.
+    fn initialized(esp: &MockEsp, mode: atat::Mode) -> EspClient<MockClient> {
+        esp.on("AT+RST", "\r\nOK\r\nready\r\n")
+            .on("ATE0", "ATE0\r\r\n\r\nOK\r\n")
+            .on("AT+CIPMUX=0", "\r\nOK\r\n")
+            .on("AT+CIPDINFO=0", "\r\nOK\r\n")
+            .on("AT+CWMODE_CUR=1", "\r\nOK\r\n");
+        let mut client = EspClient::from_client(MockClient::new(esp, mode));
+        nb::block!(client.init(&types::InitConfig::default())).unwrap();
+        client
+    }
+
+    #[test]
+    fn test_init() {
+        let esp = MockEsp::new();
+        initialized(&esp, atat::Mode::Blocking);
+        assert_eq!(
+            esp.received(),
+            [
+                "AT+RST",
+                "ATE0",
+                "AT+CIPMUX=0",
+                "AT+CIPDINFO=0",
+                "AT+CWMODE_CUR=1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join() {
+        let esp = MockEsp::new();
+        let mut client = initialized(&esp, atat::Mode::Blocking);
+        esp.on(
+            "AT+CWJAP_CUR=\"my\\,wifi\",\"secret123\"",
+            "\r\nWIFI CONNECTED\r\nWIFI GOT IP\r\n\r\nOK\r\n",
+        );
+        let response = client
+            .join_access_point("my,wifi", "secret123", types::Target::Current)
+            .unwrap();
+        assert!(response.connected);
+
+        // Failure reasons are reported instead of the generic error
+        esp.on(
+            "AT+CWJAP_CUR=\"mywifi\",\"wrongpsk\"",
+            "\r\n+CWJAP:2\r\n\r\nFAIL\r\n",
+        );
+        assert_eq!(
+            client
+                .join_access_point("mywifi", "wrongpsk", types::Target::Current)
+                .unwrap_err(),
+            nb::Error::Other(EspError::WrongPassword)
+        );
+    }
+
+    #[test]
+    fn test_connect_send_receive() {
+        let esp = MockEsp::new();
+        let mut client = initialized(&esp, atat::Mode::Blocking);
+        esp.on(
+            "AT+CIPSTART=\"TCP\",\"192.168.1.2\",80",
+            "CONNECT\r\n\r\nOK\r\n",
+        )
+        .on("AT+CIPSEND=5", "\r\nOK\r\n> ");
+        let mux = types::MultiplexingType::NonMultiplexed;
+        let mut socket =
+            TcpSocket::connect(&mut client, mux, "192.168.1.2:80".parse().unwrap()).unwrap();
+        socket.send(&mut client, b"hello").unwrap();
+        assert_eq!(esp.sent_data(), [b"hello"]);
+
+        esp.inject_network_data(None, b"wor\r\nOK\r\nld");
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut client, &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"wor\r\nOK\r\nld");
+        assert_eq!(
+            socket.recv(&mut client, &mut buf),
+            Err(nb::Error::WouldBlock)
+        );
+
+        let stats = client.stats(mux);
+        assert_eq!((stats.bytes_sent, stats.bytes_received), (5, 11));
+    }
+
+    #[test]
+    fn test_reset_and_reinit() {
+        let esp = MockEsp::new();
+        let mut client = initialized(&esp, atat::Mode::Blocking);
+        esp.on("AT+CIPRECVMODE=1", "\r\nOK\r\n");
+        client
+            .set_receive_mode(types::ReceiveMode::Passive)
+            .unwrap();
+        client.reset_and_reinit().unwrap();
+        // The init configuration and the later changes are replayed
+        assert_eq!(
+            esp.received()[6..],
+            [
+                "AT+RST",
+                "ATE0",
+                "AT+CIPMUX=0",
+                "AT+CIPDINFO=0",
+                "AT+CWMODE_CUR=1",
+                "AT+CIPRECVMODE=1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let esp = MockEsp::new();
+        let mut client = initialized(&esp, atat::Mode::NonBlocking);
+        esp.on("AT+CWLAP", "").on("AT", "\r\nOK\r\n");
+        assert_eq!(
+            client.list_access_points().unwrap_err(),
+            nb::Error::WouldBlock
+        );
+        client.cancel();
+
+        // The late response is discarded before the next command
+        esp.inject(b"\r\n+CWLAP:(3,\"mywifi\",-58,\"aa:bb:cc:dd:ee:ff\",6)\r\n\r\nOK\r\n");
+        assert_eq!(client.selftest(), Ok(()));
+        assert_eq!(esp.received()[5..], ["AT+CWLAP", "AT", "AT"]);
+    }
+
+    #[test]
+    fn test_poll_events() {
+        use crate::{commands::urcs::NetworkData, events::EspEventHandler};
+
+        #[derive(Default)]
+        struct Handler {
+            disconnected: bool,
+            closed: Vec<types::MultiplexingType>,
+            data: Vec<Vec<u8>>,
+        }
+
+        impl EspEventHandler for Handler {
+            fn on_wifi_disconnected(&mut self) {
+                self.disconnected = true;
+            }
+
+            fn on_connection_closed(&mut self, mux: types::MultiplexingType) {
+                self.closed.push(mux);
+            }
+
+            fn on_data(&mut self, data: &NetworkData) {
+                self.data.push(data.data.to_vec());
+            }
+        }
+
+        let esp = MockEsp::new();
+        let mut client = initialized(&esp, atat::Mode::Blocking);
+        esp.on("AT", "\r\nOK\r\n");
+        esp.inject(b"WIFI DISCONNECT\r\n");
+        // Events of URCs processed during a command are kept
+        assert_eq!(client.selftest(), Ok(()));
+        esp.inject_network_data(None, b"hello");
+        esp.inject(b"CLOSED\r\n");
+
+        let mut handler = Handler::default();
+        client.poll_events(&mut handler);
+        assert!(handler.disconnected);
+        assert_eq!(handler.closed, [types::MultiplexingType::NonMultiplexed]);
+        assert_eq!(handler.data, [b"hello"]);
+        assert_eq!(client.poll_network_data(), None);
+    }
+
+    #[test]
+    fn test_busy_delay() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        static DELAYED_MS: AtomicU32 = AtomicU32::new(0);
+        fn delay(ms: u32) {
+            DELAYED_MS.fetch_add(ms, Ordering::Relaxed);
+        }
+
+        let esp = MockEsp::new();
+        let mut client = initialized(&esp, atat::Mode::Blocking);
+        client.set_busy_delay(delay);
+        esp.on("AT", "busy p...\r\n")
+            .on("AT", "busy p...\r\n")
+            .on("AT", "\r\nOK\r\n");
+        assert_eq!(client.selftest(), Ok(()));
+        // Waited 100 ms and 200 ms with the default backoff
+        assert_eq!(DELAYED_MS.load(Ordering::Relaxed), 300);
+        assert_eq!(esp.received()[5..], ["AT", "AT", "AT"]);
+    }
+
+    #[test]
+    fn test_detect_capabilities() {
+        use std::fmt::Write;
+
+        // Recent ESP-AT firmwares list up to 300 commands
+        let mut resp = String::new();
+        for (i, name) in ["AT+MQTTCONN", "AT+CIPSNTPCFG", "AT+SYSSTORE"]
+            .iter()
+            .enumerate()
+        {
+            write!(resp, "+CMD:{},\"{}\",1,1,1,0\r\n", i, name).unwrap();
+        }
+        for i in 3..300 {
+            write!(resp, "+CMD:{},\"AT+BLEGATTS{:04}\",1,1,1,1\r\n", i, i).unwrap();
+        }
+        resp.push_str("\r\nOK\r\n");
+        assert!(resp.len() > 10 * 1024);
+
+        let esp = MockEsp::new();
+        esp.on("AT+CMD?", resp);
+        let mut client = EspClient::from_client(MockClient::new(&esp, atat::Mode::Blocking));
+        let capabilities = client.detect_capabilities().unwrap();
+        assert!(capabilities.mqtt && capabilities.sntp && capabilities.sysstore);
+        assert!(!capabilities.http_client);
+    }
+}
@@ -0,0 +1,602 @@
+//! High level access to MQTT brokers.
+//!
+//! ESP-AT firmwares have an MQTT client built in, which is used with the
+//! [`MqttClient`]. NONOS firmwares lack it, so the [`SocketMqttClient`]
+//! implements the parts of MQTT 3.1.1 that devices usually need over a
+//! [`TcpSocket`](../socket/struct.TcpSocket.html) instead. Both offer the
+//! same operations and deliver the same [`Message`]s.
+
+use core::{convert::TryFrom, net::SocketAddr};
+
+use atat::AtatClient;
+use embedded_hal::delay::blocking::DelayUs;
+use heapless::{Deque, String, Vec};
+
+use crate::{
+    commands::{
+        mqtt, responses, to_string,
+        urcs::{Urc, MAX_DATA_LEN},
+    },
+    socket::TcpSocket,
+    types, EspClient, EspError, EspResult,
+};
+
+pub use crate::commands::mqtt::{Message, QoS, Scheme, MAX_PAYLOAD_LEN, MAX_TOPIC_LEN};
+
+/// A handle to the MQTT client of the device.
+///
+/// Created with [`EspClient::mqtt`](../struct.EspClient.html#method.mqtt).
+pub struct MqttClient<'a, C: AtatClient, const N: usize = MAX_DATA_LEN> {
+    pub(crate) esp: &'a mut EspClient<C, N>,
+}
+
+impl<'a, C: AtatClient, const N: usize> MqttClient<'a, C, N> {
+    /// Connect to the specified broker.
+    ///
+    /// If `reconnect` is set to `true`, the firmware automatically reconnects
+    /// when the connection to the broker is lost. Strings with control
+    /// characters are rejected with `EspError::InvalidArgument`, hosts longer
+    /// than 128 bytes and other strings longer than 64 bytes with
+    /// `EspError::Overflow`.
+    pub fn connect(
+        &mut self,
+        scheme: Scheme,
+        host: &str,
+        port: u16,
+        client_id: &str,
+        credentials: Option<(&str, &str)>,
+        reconnect: bool,
+    ) -> EspResult<()> {
+        let (username, password) = credentials.unwrap_or(("", ""));
+        let user_config = mqtt::SetUserConfig::new(scheme, client_id, username, password)
+            .ok_or(nb::Error::Other(EspError::Overflow))?;
+        let connect = mqtt::Connect::new(host, port, reconnect)
+            .ok_or(nb::Error::Other(EspError::Overflow))?;
+        if !user_config.is_valid() || !connect.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.esp
+            .send_blocking(&user_config)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp
+            .send_blocking(&connect)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp.state.mqtt_connected = true;
+        Ok(())
+    }
+
+    /// Whether the client is connected to a broker.
+    pub fn is_connected(&mut self) -> bool {
+        self.esp.process_urcs();
+        self.esp.state.mqtt_connected
+    }
+
+    /// Publish a text message.
+    ///
+    /// Topics are limited to 128 and payloads to 256 bytes. Strings with
+    /// control characters are rejected with `EspError::InvalidArgument`.
+    pub fn publish(&mut self, topic: &str, payload: &str, qos: QoS, retain: bool) -> EspResult<()> {
+        let command = mqtt::Publish::new(topic, payload, qos, retain)
+            .ok_or(nb::Error::Other(EspError::Overflow))?;
+        if !command.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.esp
+            .send_command(&command)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Subscribe to a topic.
+    ///
+    /// Received messages can be fetched with [`poll`](#method.poll).
+    pub fn subscribe(&mut self, topic: &str, qos: QoS) -> EspResult<()> {
+        let command =
+            mqtt::Subscribe::new(topic, qos).ok_or(nb::Error::Other(EspError::Overflow))?;
+        if !command.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.esp
+            .send_command(&command)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Unsubscribe from a topic.
+    pub fn unsubscribe(&mut self, topic: &str) -> EspResult<()> {
+        let command = mqtt::Unsubscribe::new(topic).ok_or(nb::Error::Other(EspError::Overflow))?;
+        if !command.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.esp
+            .send_command(&command)
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Return the next message received on a subscribed topic, if any.
+    pub fn poll(&mut self) -> Option<Message> {
+        match self.esp.take_urc(|urc| matches!(urc, Urc::MqttMessage(_))) {
+            Some(Urc::MqttMessage(message)) => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Disconnect from the broker.
+    pub fn disconnect(&mut self) -> EspResult<()> {
+        self.esp
+            .send_command(&mqtt::Clean)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp.state.mqtt_connected = false;
+        Ok(())
+    }
+}
+
+/// Maximum length of an MQTT packet sent or received by the
+/// [`SocketMqttClient`].
+pub const MAX_PACKET_LEN: usize = 5 + 2 + MAX_TOPIC_LEN + 2 + MAX_PAYLOAD_LEN;
+
+/// Number of received messages that are queued until they are polled, older
+/// messages are dropped.
+pub const MAX_QUEUED_MESSAGES: usize = 4;
+
+// Control packet types, in the upper nibble of the first byte
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const UNSUBSCRIBE: u8 = 10;
+const UNSUBACK: u8 = 11;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+/// A minimal MQTT 3.1.1 client over a TCP connection.
+///
+/// Supports publishing and subscribing with QoS 0 and 1, messages published
+/// with QoS 2 are rejected with `EspError::Unsupported`. Like the
+/// [`TcpSocket`](../socket/struct.TcpSocket.html) it is built on, the client
+/// doesn't borrow the [`EspClient`](../struct.EspClient.html), which is
+/// passed to every call instead. Every wait for the broker is bounded by the
+/// timeout passed to [`connect`](#method.connect).
+///
+/// The broker closes the connection if it doesn't hear from the client
+/// within 1.5 times the keep alive interval, so [`ping`](#method.ping)
+/// should be called regularly while nothing is published.
+#[derive(Debug)]
+pub struct SocketMqttClient<const N: usize = MAX_DATA_LEN> {
+    socket: TcpSocket<N>,
+    timeout_ms: u32,
+    /// Identifier of the last packet that required an acknowledgement
+    packet_id: u16,
+    /// Received messages that were not polled yet
+    messages: Deque<Message, MAX_QUEUED_MESSAGES>,
+}
+
+impl<const N: usize> SocketMqttClient<N> {
+    /// Connect to the broker at `remote` over the link `mux`.
+    ///
+    /// The session is clean, i.e. subscriptions of earlier connections are
+    /// not restored. Fails with `EspError::Fail` if the broker refuses the
+    /// connection, e.g. because of wrong credentials.
+    ///
+    /// This blocks until the broker accepted the connection, even in
+    /// non-blocking mode.
+    #[allow(clippy::too_many_arguments)]
+    pub fn connect<C, D>(
+        esp: &mut EspClient<C, N>,
+        mux: types::MultiplexingType,
+        remote: SocketAddr,
+        client_id: &str,
+        credentials: Option<(&str, &str)>,
+        keep_alive_s: u16,
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> EspResult<Self>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        let mut packet = Packet::new(CONNECT << 4);
+        packet.push_str("MQTT")?;
+        // Protocol level 4 (3.1.1), clean session
+        let mut flags = 0x02;
+        if credentials.is_some() {
+            flags |= 0xc0;
+        }
+        packet.push(&[4, flags])?;
+        packet.push(&keep_alive_s.to_be_bytes())?;
+        packet.push_str(client_id)?;
+        if let Some((username, password)) = credentials {
+            packet.push_str(username)?;
+            packet.push_str(password)?;
+        }
+
+        let mut client = Self {
+            socket: TcpSocket::connect(esp, mux, remote)?,
+            timeout_ms,
+            packet_id: 0,
+            messages: Deque::new(),
+        };
+        client.send(esp, &packet)?;
+        let ack = client.wait_for(esp, delay, CONNACK)?;
+        // Return code of the broker, 0 is accepted
+        if ack.get(1) != Some(&0) {
+            client.socket.close(esp)?;
+            return Err(nb::Error::Other(EspError::Fail));
+        }
+        Ok(client)
+    }
+
+    /// Publish a message.
+    ///
+    /// With QoS 1, this blocks until the broker acknowledged the message.
+    pub fn publish<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        delay: &mut D,
+        topic: &str,
+        payload: &[u8],
+        qos: QoS,
+        retain: bool,
+    ) -> EspResult<()>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        let qos_bits = match qos {
+            QoS::AtMostOnce => 0,
+            QoS::AtLeastOnce => 1,
+            QoS::ExactlyOnce => return Err(nb::Error::Other(EspError::Unsupported)),
+        };
+        let mut packet = Packet::new(PUBLISH << 4 | qos_bits << 1 | u8::from(retain));
+        packet.push_str(topic)?;
+        if qos == QoS::AtLeastOnce {
+            let packet_id = self.next_packet_id();
+            packet.push(&packet_id.to_be_bytes())?;
+            packet.push(payload)?;
+            self.send(esp, &packet)?;
+            self.wait_for_ack(esp, delay, PUBACK, packet_id).map(|_| ())
+        } else {
+            packet.push(payload)?;
+            self.send(esp, &packet)
+        }
+    }
+
+    /// Subscribe to a topic, which may contain wildcards.
+    ///
+    /// This blocks until the broker acknowledged the subscription, and fails
+    /// with `EspError::Fail` if the broker refused it. Received messages can
+    /// be fetched with [`poll`](#method.poll).
+    pub fn subscribe<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        delay: &mut D,
+        topic: &str,
+        qos: QoS,
+    ) -> EspResult<()>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        let qos_bits = match qos {
+            QoS::AtMostOnce => 0,
+            QoS::AtLeastOnce => 1,
+            QoS::ExactlyOnce => return Err(nb::Error::Other(EspError::Unsupported)),
+        };
+        let packet_id = self.next_packet_id();
+        // The reserved flags of SUBSCRIBE must be 0b0010
+        let mut packet = Packet::new(SUBSCRIBE << 4 | 0x02);
+        packet.push(&packet_id.to_be_bytes())?;
+        packet.push_str(topic)?;
+        packet.push(&[qos_bits])?;
+        self.send(esp, &packet)?;
+        let ack = self.wait_for_ack(esp, delay, SUBACK, packet_id)?;
+        // Granted QoS, or 0x80 for failure
+        match ack.get(2) {
+            Some(0x80) | None => Err(nb::Error::Other(EspError::Fail)),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Unsubscribe from a topic.
+    ///
+    /// This blocks until the broker acknowledged the request.
+    pub fn unsubscribe<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        delay: &mut D,
+        topic: &str,
+    ) -> EspResult<()>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        let packet_id = self.next_packet_id();
+        let mut packet = Packet::new(UNSUBSCRIBE << 4 | 0x02);
+        packet.push(&packet_id.to_be_bytes())?;
+        packet.push_str(topic)?;
+        self.send(esp, &packet)?;
+        self.wait_for_ack(esp, delay, UNSUBACK, packet_id)
+            .map(|_| ())
+    }
+
+    /// Tell the broker that the client is alive and wait for its answer.
+    pub fn ping<C, D>(&mut self, esp: &mut EspClient<C, N>, delay: &mut D) -> EspResult<()>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        self.send(esp, &Packet::new(PINGREQ << 4))?;
+        self.wait_for(esp, delay, PINGRESP).map(|_| ())
+    }
+
+    /// Return the next message received on a subscribed topic, if any.
+    ///
+    /// Returns `WouldBlock` if no message was received, and fails with
+    /// `EspError::ConnectionClosed` once the broker closed the connection.
+    pub fn poll<C, D>(&mut self, esp: &mut EspClient<C, N>, delay: &mut D) -> EspResult<Message>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        while self.messages.is_empty() {
+            let mut header = [0];
+            match self.socket.recv(esp, &mut header)? {
+                0 => return Err(nb::Error::Other(EspError::ConnectionClosed)),
+                _ => {
+                    let body = self.read_body(esp, delay)?;
+                    self.handle(esp, header[0], &body)?;
+                }
+            }
+        }
+        self.messages.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+
+    /// Disconnect from the broker and close the connection.
+    pub fn disconnect<C: AtatClient>(mut self, esp: &mut EspClient<C, N>) -> EspResult<()> {
+        self.send(esp, &Packet::new(DISCONNECT << 4))?;
+        self.socket.close(esp)
+    }
+
+    fn next_packet_id(&mut self) -> u16 {
+        // Packet identifiers must not be 0
+        self.packet_id = self.packet_id.checked_add(1).unwrap_or(1);
+        self.packet_id
+    }
+
+    fn send<C: AtatClient>(&mut self, esp: &mut EspClient<C, N>, packet: &Packet) -> EspResult<()> {
+        self.socket.send(esp, &packet.encode())
+    }
+
+    /// Read the remaining length and the body of a packet whose first byte
+    /// was read.
+    ///
+    /// Packets that are too long are skipped and fail with
+    /// `EspError::Overflow`.
+    fn read_body<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        delay: &mut D,
+    ) -> EspResult<Vec<u8, MAX_PACKET_LEN>>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        let mut len = 0;
+        for shift in (0..28).step_by(7) {
+            let mut byte = [0];
+            self.socket
+                .read_exact(esp, &mut byte, delay, self.timeout_ms)?;
+            len |= usize::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = Vec::new();
+        if len > MAX_PACKET_LEN {
+            let mut skipped = [0; 64];
+            while len > 0 {
+                let chunk = len.min(skipped.len());
+                self.socket
+                    .read_exact(esp, &mut skipped[..chunk], delay, self.timeout_ms)?;
+                len -= chunk;
+            }
+            return Err(nb::Error::Other(EspError::Overflow));
+        }
+        body.resize_default(len).unwrap();
+        self.socket
+            .read_exact(esp, &mut body, delay, self.timeout_ms)?;
+        Ok(body)
+    }
+
+    /// Wait for a packet of the specified type, queueing the messages that
+    /// arrive in the meantime.
+    fn wait_for<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        delay: &mut D,
+        packet_type: u8,
+    ) -> EspResult<Vec<u8, MAX_PACKET_LEN>>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        loop {
+            let mut header = [0];
+            self.socket
+                .read_exact(esp, &mut header, delay, self.timeout_ms)?;
+            let body = self.read_body(esp, delay)?;
+            if header[0] >> 4 == packet_type {
+                return Ok(body);
+            }
+            self.handle(esp, header[0], &body)?;
+        }
+    }
+
+    /// Wait for the acknowledgement of the packet with the specified
+    /// identifier.
+    fn wait_for_ack<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        delay: &mut D,
+        packet_type: u8,
+        packet_id: u16,
+    ) -> EspResult<Vec<u8, MAX_PACKET_LEN>>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        loop {
+            let ack = self.wait_for(esp, delay, packet_type)?;
+            if ack.get(..2) == Some(&packet_id.to_be_bytes()[..]) {
+                return Ok(ack);
+            }
+        }
+    }
+
+    /// Process a packet that was not waited for.
+    fn handle<C: AtatClient>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        header: u8,
+        body: &[u8],
+    ) -> EspResult<()> {
+        if header >> 4 != PUBLISH {
+            return Ok(());
+        }
+        let publish = parse_publish(header, body).ok_or(nb::Error::Other(EspError::Parse))?;
+        if let Some(packet_id) = publish.packet_id {
+            let mut ack = Packet::new(PUBACK << 4);
+            ack.push(&packet_id)?;
+            self.send(esp, &ack)?;
+        }
+        let message = Message {
+            topic: publish.topic,
+            payload: Vec::from_slice(publish.payload)
+                .map_err(|_| nb::Error::Other(EspError::Overflow))?,
+        };
+        if self.messages.is_full() {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message).ok();
+        Ok(())
+    }
+}
+
+/// An MQTT packet being encoded.
+struct Packet {
+    header: u8,
+    body: Vec<u8, MAX_PACKET_LEN>,
+}
+
+impl Packet {
+    fn new(header: u8) -> Self {
+        Self {
+            header,
+            body: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> EspResult<()> {
+        self.body
+            .extend_from_slice(bytes)
+            .map_err(|_| nb::Error::Other(EspError::Overflow))
+    }
+
+    /// Append a string prefixed with its length.
+    fn push_str(&mut self, string: &str) -> EspResult<()> {
+        let len = u16::try_from(string.len()).map_err(|_| nb::Error::Other(EspError::Overflow))?;
+        self.push(&len.to_be_bytes())?;
+        self.push(string.as_bytes())
+    }
+
+    /// Encode the packet with the fixed header.
+    fn encode(&self) -> Vec<u8, { MAX_PACKET_LEN + 5 }> {
+        let mut encoded = Vec::new();
+        encoded.push(self.header).unwrap();
+        // Remaining length, 7 bits per byte with continuation bit
+        let mut len = self.body.len();
+        loop {
+            let byte = (len % 128) as u8;
+            len /= 128;
+            encoded
+                .push(if len > 0 { byte | 0x80 } else { byte })
+                .unwrap();
+            if len == 0 {
+                break;
+            }
+        }
+        encoded.extend_from_slice(&self.body).unwrap();
+        encoded
+    }
+}
+
+/// A received PUBLISH packet.
+#[derive(Debug, PartialEq)]
+struct Publish<'a> {
+    topic: String<MAX_TOPIC_LEN>,
+    /// Identifier to acknowledge, for QoS 1 and 2
+    packet_id: Option<[u8; 2]>,
+    payload: &'a [u8],
+}
+
+/// Parse the body of a PUBLISH packet with the specified first byte.
+fn parse_publish(header: u8, body: &[u8]) -> Option<Publish<'_>> {
+    let qos = (header >> 1) & 0x03;
+    let topic_len = body.get(..2)?;
+    let topic_len = usize::from(u16::from_be_bytes([topic_len[0], topic_len[1]]));
+    let topic = body
+        .get(2..2 + topic_len)
+        .and_then(|topic| core::str::from_utf8(topic).ok())
+        .and_then(|topic| to_string(topic).ok())?;
+    let mut payload_start = 2 + topic_len;
+    let mut packet_id = None;
+    if qos > 0 {
+        let id = body.get(payload_start..payload_start + 2)?;
+        packet_id = Some([id[0], id[1]]);
+        payload_start += 2;
+    }
+    Some(Publish {
+        topic,
+        packet_id,
+        payload: &body[payload_start..],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_encode() {
+        let mut packet = Packet::new(CONNECT << 4);
+        packet.push_str("MQTT").unwrap();
+        assert_eq!(&packet.encode()[..], b"\x10\x06\x00\x04MQTT");
+
+        // The remaining length takes two bytes from 128 bytes on
+        let mut packet = Packet::new(PUBLISH << 4);
+        packet.push(&[0; 321]).unwrap();
+        let encoded = packet.encode();
+        assert_eq!(&encoded[..3], [PUBLISH << 4, 0xc1, 0x02]);
+        assert_eq!(encoded.len(), 3 + 321);
+    }
+
+    #[test]
+    fn test_parse_publish() {
+        let publish = parse_publish(PUBLISH << 4, b"\x00\x03a/bhello").unwrap();
+        assert_eq!(publish.topic, "a/b");
+        assert_eq!(publish.packet_id, None);
+        assert_eq!(publish.payload, b"hello");
+
+        // Messages with QoS 1 carry the identifier to acknowledge
+        let publish = parse_publish(PUBLISH << 4 | 0x02, b"\x00\x03a/b\x00\x07hi").unwrap();
+        assert_eq!(publish.packet_id, Some([0, 7]));
+        assert_eq!(publish.payload, b"hi");
+
+        // Truncated packets and topics that are not UTF-8 are rejected
+        assert_eq!(parse_publish(PUBLISH << 4 | 0x02, b"\x00\x03a/b\x00"), None);
+        assert_eq!(parse_publish(PUBLISH << 4, b"\x00\x09a/b"), None);
+        assert_eq!(parse_publish(PUBLISH << 4, b"\x00"), None);
+        assert_eq!(parse_publish(PUBLISH << 4, b"\x00\x02\xff\xfe"), None);
+    }
+}
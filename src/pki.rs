@@ -0,0 +1,109 @@
+//! Management of the certificates and keys used for TLS on ESP-AT
+//! firmwares.
+//!
+//! Certificates and keys are stored on the device by kind and index, e.g.
+//! client certificate 0 and its key 0. Connections select the objects they
+//! use by index, so several sets can be installed side by side, e.g. for
+//! different brokers or for rotating certificates. Objects are installed as
+//! DER or PEM, in whatever format the firmware was built to accept.
+//!
+//! Devices that authenticate with a pre-shared key instead of certificates,
+//! which many industrial brokers use for constrained devices, configure it
+//! with [`PkiManager::set_psk`](struct.PkiManager.html#method.set_psk).
+
+use atat::AtatClient;
+use heapless::Vec;
+
+use crate::{
+    commands::{pki, requests, responses, urcs::MAX_DATA_LEN},
+    types, EspClient, EspError, EspResult,
+};
+
+pub use crate::commands::pki::{Kind, TlsCredentials, MAX_OBJECTS, MAX_OBJECT_LEN};
+
+/// A handle to the certificate storage of the device.
+///
+/// Created with [`EspClient::pki`](../struct.EspClient.html#method.pki).
+pub struct PkiManager<'a, C: AtatClient, const N: usize = MAX_DATA_LEN> {
+    pub(crate) esp: &'a mut EspClient<C, N>,
+}
+
+impl<'a, C: AtatClient, const N: usize> PkiManager<'a, C, N> {
+    /// Return the indices of the installed objects of a kind.
+    pub fn list(&mut self, kind: Kind) -> EspResult<Vec<u8, MAX_OBJECTS>> {
+        self.esp
+            .send_blocking(&pki::ListObjects::new(kind))
+            .map(|objects| objects.indices)
+    }
+
+    /// Install an object, replacing an existing one with the same index.
+    ///
+    /// Fails with `EspError::Overflow` if the object is larger than
+    /// [`MAX_OBJECT_LEN`](constant.MAX_OBJECT_LEN.html).
+    pub fn install(&mut self, kind: Kind, index: u8, data: &[u8]) -> EspResult<()> {
+        if data.is_empty() {
+            return Err(nb::Error::Other(EspError::InvalidLength));
+        }
+        if data.len() > MAX_OBJECT_LEN {
+            return Err(nb::Error::Other(EspError::Overflow));
+        }
+        self.esp
+            .send_blocking(&pki::PrepareWriteObject::new(kind, index, data.len()))
+            .map(|_: responses::EmptyResponse| ())?;
+        nb::block!(self
+            .esp
+            .send_data(&requests::SendData::<MAX_OBJECT_LEN>::from_bytes(data)))
+        .map_err(nb::Error::Other)
+    }
+
+    /// Install a client certificate together with its private key.
+    pub fn install_client_identity(&mut self, index: u8, cert: &[u8], key: &[u8]) -> EspResult<()> {
+        self.install(Kind::ClientCert, index, cert)?;
+        self.install(Kind::ClientKey, index, key)
+    }
+
+    /// Remove an installed object.
+    pub fn remove(&mut self, kind: Kind, index: u8) -> EspResult<()> {
+        self.esp
+            .send_blocking(&pki::EraseObject::new(kind, index))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Select the certificates that TLS connections opened afterwards on the
+    /// link use.
+    pub fn select(
+        &mut self,
+        mux: types::MultiplexingType,
+        credentials: TlsCredentials,
+    ) -> EspResult<()> {
+        self.esp
+            .ensure_supported(|capabilities| capabilities.ssl_config)?;
+        self.esp
+            .send_blocking(&pki::ConfigureTlsClient::new(mux, credentials))
+            .map(|_: responses::EmptyResponse| ())
+    }
+
+    /// Authenticate TLS connections opened afterwards on the link with a
+    /// pre-shared key and hint of at most 32 bytes each.
+    ///
+    /// Only supported by ESP-AT firmwares built with PSK support. Control
+    /// characters are rejected with `EspError::InvalidArgument`, longer
+    /// strings with `EspError::Overflow`.
+    pub fn set_psk(
+        &mut self,
+        mux: types::MultiplexingType,
+        psk: &str,
+        hint: &str,
+    ) -> EspResult<()> {
+        self.esp
+            .ensure_supported(|capabilities| capabilities.tls_psk)?;
+        let command = pki::ConfigureTlsPsk::new(mux, psk, hint)
+            .ok_or(nb::Error::Other(EspError::Overflow))?;
+        if !command.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.esp
+            .send_blocking(&command)
+            .map(|_: responses::EmptyResponse| ())
+    }
+}
@@ -0,0 +1,278 @@
+//! Provisioning of WiFi credentials over a soft AP.
+//!
+//! Devices without a user interface often receive the credentials of the
+//! target network from a phone or laptop: the device opens an access point,
+//! the user joins it and sends the credentials, and the device joins the
+//! target network with them. [`provision`] implements the whole flow and
+//! reports its progress to a callback, e.g. to drive a status LED.
+//!
+//! The credentials are sent over a TCP connection to the device, which has
+//! the address `192.168.4.1` in the soft AP network by default, as two lines
+//! with the SSID and the password (empty for open networks):
+//!
+//! ```text
+//! MyNetwork
+//! secret-password
+//! ```
+//!
+//! The device answers with `OK` once it joined the network, or with
+//! `ERR <reason>`, after which the client can try again. Multiplexing must
+//! be enabled for the server, see
+//! [`InitConfig`](../types/struct.InitConfig.html).
+
+use core::fmt::Write;
+
+use atat::AtatClient;
+use embedded_hal::delay::blocking::DelayUs;
+use heapless::String;
+
+use crate::{
+    commands::requests,
+    socket::{TcpServer, TcpSocket},
+    types, EspClient, EspError, EspResult,
+};
+
+/// Default port of the credential server.
+pub const DEFAULT_PORT: u16 = 8266;
+
+/// Interval in which new connections are checked while waiting for them.
+const ACCEPT_POLL_INTERVAL_MS: u32 = 100;
+
+/// Time a client gets to send the credentials after connecting.
+const READ_TIMEOUT_MS: u32 = 30_000;
+
+/// The configuration of the provisioning flow.
+#[derive(Debug, Clone)]
+pub struct ProvisioningConfig<'a> {
+    /// Prefix of the SSID of the soft AP, followed by the last three bytes
+    /// of the MAC address, e.g. `espresso-a1b2c3`
+    pub ssid_prefix: &'a str,
+    /// Password of the soft AP, empty for an open network
+    pub ap_psk: &'a str,
+    /// WiFi channel of the soft AP
+    pub channel: u8,
+    /// Port of the credential server
+    pub port: u16,
+    /// Time after which provisioning is aborted, in milliseconds
+    pub timeout_ms: u32,
+    /// Whether the received credentials are persisted to flash
+    pub target: types::Target,
+}
+
+impl<'a> ProvisioningConfig<'a> {
+    /// An open soft AP on channel 1 that waits up to five minutes for
+    /// credentials, which are persisted to flash.
+    pub fn new(ssid_prefix: &'a str) -> Self {
+        Self {
+            ssid_prefix,
+            ap_psk: "",
+            channel: 1,
+            port: DEFAULT_PORT,
+            timeout_ms: 300_000,
+            target: types::Target::Default,
+        }
+    }
+
+    /// Secure the soft AP with a password of at least 8 characters.
+    pub fn with_ap_psk(self, ap_psk: &'a str) -> Self {
+        Self { ap_psk, ..self }
+    }
+
+    /// Use another channel for the soft AP.
+    pub fn with_channel(self, channel: u8) -> Self {
+        Self { channel, ..self }
+    }
+
+    /// Use another port for the credential server.
+    pub fn with_port(self, port: u16) -> Self {
+        Self { port, ..self }
+    }
+
+    /// Abort provisioning after `timeout_ms` milliseconds.
+    pub fn with_timeout(self, timeout_ms: u32) -> Self {
+        Self { timeout_ms, ..self }
+    }
+
+    /// Select whether the received credentials are persisted to flash.
+    pub fn with_target(self, target: types::Target) -> Self {
+        Self { target, ..self }
+    }
+}
+
+/// The steps of the provisioning flow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Progress {
+    /// The soft AP with the SSID was started and waits for clients
+    ApStarted(types::Ssid),
+    /// A client connected to the credential server
+    ClientConnected,
+    /// Credentials for the network with the SSID were received
+    CredentialsReceived(types::Ssid),
+    /// Joining the network failed, the flow waits for new credentials
+    JoinFailed(EspError),
+    /// The network was joined, the soft AP is torn down next
+    Joined,
+}
+
+/// Provision the credentials of a WiFi network over a soft AP.
+///
+/// Starts the soft AP and the credential server, joins the network with
+/// the received credentials and returns them. The soft AP is torn down
+/// afterwards, also if provisioning fails, leaving the device in station
+/// mode. Fails with `EspError::Timeout` if no network was joined within the
+/// timeout of the configuration.
+///
+/// This blocks until provisioning is complete, even in non-blocking mode.
+pub fn provision<C, D, F, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    config: &ProvisioningConfig,
+    delay: &mut D,
+    mut on_progress: F,
+) -> EspResult<types::Credentials>
+where
+    C: AtatClient,
+    D: DelayUs,
+    F: FnMut(Progress),
+{
+    nb::block!(esp.set_wifi_mode(types::WifiMode::Both, types::Target::Current))
+        .map_err(nb::Error::Other)?;
+    let result = start(esp, config).and_then(|(ssid, mut server)| {
+        on_progress(Progress::ApStarted(ssid));
+        let result = serve(esp, &mut server, config, delay, &mut on_progress);
+        server.close(esp).and(result)
+    });
+    let teardown = nb::block!(esp.set_wifi_mode(types::WifiMode::Station, types::Target::Current))
+        .map_err(nb::Error::Other);
+    result.and_then(|credentials| teardown.map(|_| credentials))
+}
+
+/// Start the soft AP and the credential server.
+fn start<C: AtatClient, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    config: &ProvisioningConfig,
+) -> EspResult<(types::Ssid, TcpServer)> {
+    let ssid = generate_ssid(esp, config.ssid_prefix)?;
+    let ap_config = if config.ap_psk.is_empty() {
+        types::SoftApConfig::open(ssid.clone(), config.channel)
+    } else {
+        types::SoftApConfig::wpa2(ssid.clone(), config.ap_psk, config.channel)
+            .ok_or(nb::Error::Other(EspError::InvalidArgument))?
+    };
+    nb::block!(esp.set_soft_ap_config(ap_config.clone(), types::Target::Current))
+        .map_err(nb::Error::Other)?;
+    let server = TcpServer::listen(esp, &requests::StartServer::tcp(config.port))?;
+    Ok((ssid, server))
+}
+
+/// Return the prefix followed by the last three bytes of the MAC address.
+fn generate_ssid<C: AtatClient, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    prefix: &str,
+) -> EspResult<types::Ssid> {
+    let address = nb::block!(esp.get_local_address()).map_err(nb::Error::Other)?;
+    let mac = address
+        .ap
+        .or(address.station)
+        .and_then(|interface| types::MacAddress::parse(&interface.mac))
+        .ok_or(nb::Error::Other(EspError::CommandFailed))?;
+    let mut ssid: String<32> = String::new();
+    write!(
+        ssid,
+        "{}-{:02x}{:02x}{:02x}",
+        prefix, mac.0[3], mac.0[4], mac.0[5]
+    )
+    .map_err(|_| nb::Error::Other(EspError::InvalidArgument))?;
+    Ok(types::Ssid::from(ssid.as_str()))
+}
+
+/// Accept clients until a network was joined with their credentials.
+fn serve<C, D, F, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    server: &mut TcpServer,
+    config: &ProvisioningConfig,
+    delay: &mut D,
+    on_progress: &mut F,
+) -> EspResult<types::Credentials>
+where
+    C: AtatClient,
+    D: DelayUs,
+    F: FnMut(Progress),
+{
+    let mut waited_ms = 0;
+    loop {
+        let mut socket = match server.accept(esp) {
+            Ok(socket) => socket,
+            Err(nb::Error::WouldBlock) => {
+                if waited_ms >= config.timeout_ms {
+                    return Err(nb::Error::Other(EspError::Timeout));
+                }
+                delay
+                    .delay_ms(ACCEPT_POLL_INTERVAL_MS)
+                    .map_err(|_| nb::Error::Other(EspError::Pin))?;
+                waited_ms += ACCEPT_POLL_INTERVAL_MS;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        on_progress(Progress::ClientConnected);
+        let outcome = handle_client(esp, &mut socket, config, delay, on_progress);
+        let reply: &[u8] = match outcome {
+            Ok(_) => b"OK\n",
+            Err(nb::Error::Other(EspError::InvalidArgument)) => b"ERR invalid credentials\n",
+            Err(_) => b"ERR join failed\n",
+        };
+        // The client may already be gone, which doesn't affect the outcome
+        let _ = socket.send(esp, reply);
+        let _ = socket.close(esp);
+        match outcome {
+            Ok(credentials) => {
+                on_progress(Progress::Joined);
+                return Ok(credentials);
+            }
+            Err(nb::Error::Other(e)) => on_progress(Progress::JoinFailed(e)),
+            Err(nb::Error::WouldBlock) => {}
+        }
+    }
+}
+
+/// Receive the credentials from a client and join the network.
+fn handle_client<C, D, F, const N: usize>(
+    esp: &mut EspClient<C, N>,
+    socket: &mut TcpSocket<N>,
+    config: &ProvisioningConfig,
+    delay: &mut D,
+    on_progress: &mut F,
+) -> EspResult<types::Credentials>
+where
+    C: AtatClient,
+    D: DelayUs,
+    F: FnMut(Progress),
+{
+    let mut ssid = [0; 34];
+    let len = socket.read_until(esp, &mut ssid, b'\n', delay, READ_TIMEOUT_MS)?;
+    let ssid = parse_line(&ssid[..len])
+        .and_then(types::Ssid::new)
+        .filter(|ssid| !ssid.as_bytes().is_empty())
+        .ok_or(nb::Error::Other(EspError::InvalidArgument))?;
+    let mut psk = [0; 66];
+    let len = socket.read_until(esp, &mut psk, b'\n', delay, READ_TIMEOUT_MS)?;
+    let psk: String<64> = parse_line(&psk[..len])
+        .and_then(|psk| core::str::from_utf8(psk).ok())
+        .filter(|psk| psk.is_empty() || (8..=63).contains(&psk.len()))
+        .and_then(|psk| psk.parse().ok())
+        .ok_or(nb::Error::Other(EspError::InvalidArgument))?;
+    on_progress(Progress::CredentialsReceived(ssid.clone()));
+    let response = nb::block!(esp.join_access_point(ssid.clone(), psk.as_str(), config.target))
+        .map_err(nb::Error::Other)?;
+    if !response.connected {
+        return Err(nb::Error::Other(EspError::CommandFailed));
+    }
+    Ok(types::Credentials { ssid, psk })
+}
+
+/// Strip the line ending from a received line.
+fn parse_line(line: &[u8]) -> Option<&[u8]> {
+    let line = line.strip_suffix(b"\n")?;
+    Some(line.strip_suffix(b"\r").unwrap_or(line))
+}
@@ -0,0 +1,45 @@
+//! Statically allocated buffers for the client.
+
+use atat::{bbqueue::BBBuffer, Queues};
+
+/// The response and URC queues of a client, meant to be placed in a `static`.
+///
+/// This avoids the `unsafe` boilerplate otherwise needed to create the
+/// `Queues` for [`EspClient::new`](../struct.EspClient.html#method.new):
+/// declare e.g. a `static RESOURCES: StaticResources<1024, 512>` and pass
+/// `RESOURCES.queues().unwrap()` to the constructor. With ESP-AT firmwares,
+/// the response queue must hold the list of supported commands, see
+/// [`INGRESS_BUF_LEN`](../constant.INGRESS_BUF_LEN.html).
+pub struct StaticResources<const RES_CAPACITY: usize, const URC_CAPACITY: usize> {
+    res_queue: BBBuffer<RES_CAPACITY>,
+    urc_queue: BBBuffer<URC_CAPACITY>,
+}
+
+impl<const RES_CAPACITY: usize, const URC_CAPACITY: usize>
+    StaticResources<RES_CAPACITY, URC_CAPACITY>
+{
+    pub const fn new() -> Self {
+        Self {
+            res_queue: BBBuffer::new(),
+            urc_queue: BBBuffer::new(),
+        }
+    }
+
+    /// Split the buffers into the queues for a client.
+    ///
+    /// The buffers can only be split once, further calls return `None`.
+    pub fn queues(&'static self) -> Option<Queues<RES_CAPACITY, URC_CAPACITY>> {
+        Some(Queues {
+            res_queue: self.res_queue.try_split_framed().ok()?,
+            urc_queue: self.urc_queue.try_split_framed().ok()?,
+        })
+    }
+}
+
+impl<const RES_CAPACITY: usize, const URC_CAPACITY: usize> Default
+    for StaticResources<RES_CAPACITY, URC_CAPACITY>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,68 @@
+//! Policies for retrying operations that failed for transient reasons.
+//!
+//! A policy is passed to [`EspClient::retry`], which retries the operation
+//! while the policy allows it, waiting for the delay that the policy asks
+//! for in between.
+//!
+//! [`EspClient::retry`]: ../struct.EspClient.html#method.retry
+
+use crate::EspError;
+
+/// Decides whether and when a failed operation is retried.
+pub trait RetryPolicy {
+    /// Return the delay in milliseconds before retrying after `error`, or
+    /// `None` to give up.
+    ///
+    /// `attempt` is the number of the failed attempt, starting at 1.
+    fn retry(&mut self, error: EspError, attempt: u8) -> Option<u32>;
+}
+
+/// Never retry.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn retry(&mut self, _error: EspError, _attempt: u8) -> Option<u32> {
+        None
+    }
+}
+
+/// Retry transient failures up to `retries` times with a fixed delay.
+#[derive(Debug, Clone, Copy)]
+pub struct Fixed {
+    pub retries: u8,
+    pub delay_ms: u32,
+}
+
+impl RetryPolicy for Fixed {
+    fn retry(&mut self, error: EspError, attempt: u8) -> Option<u32> {
+        if error.is_transient() && attempt <= self.retries {
+            Some(self.delay_ms)
+        } else {
+            None
+        }
+    }
+}
+
+/// Retry transient failures up to `retries` times, doubling the delay after
+/// every attempt up to `max_delay_ms`.
+#[derive(Debug, Clone, Copy)]
+pub struct Exponential {
+    pub retries: u8,
+    pub initial_delay_ms: u32,
+    pub max_delay_ms: u32,
+}
+
+impl RetryPolicy for Exponential {
+    fn retry(&mut self, error: EspError, attempt: u8) -> Option<u32> {
+        if !error.is_transient() || attempt > self.retries {
+            return None;
+        }
+        let factor = 1u32.checked_shl(u32::from(attempt - 1)).unwrap_or(u32::MAX);
+        Some(
+            self.initial_delay_ms
+                .saturating_mul(factor)
+                .min(self.max_delay_ms),
+        )
+    }
+}
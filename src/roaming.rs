@@ -0,0 +1,122 @@
+//! Roaming between access points of the same network.
+//!
+//! Networks covering a larger area often consist of multiple access points
+//! or repeaters. The firmware stays associated with an access point until
+//! the connection is lost, even if a much stronger one is in range. For
+//! mobile devices, [`Roaming`] switches to the strongest access point when
+//! the signal gets weak.
+
+use atat::AtatClient;
+
+use crate::{types, EspClient, EspError, EspResult};
+
+/// Default signal strength below which a stronger access point is searched.
+const DEFAULT_THRESHOLD_DBM: i8 = -75;
+
+/// Default improvement of the signal strength required to switch.
+const DEFAULT_MIN_IMPROVEMENT_DB: u8 = 8;
+
+/// Rejoins a stronger access point of the same network on weak signal.
+#[derive(Debug)]
+pub struct Roaming {
+    credentials: types::Credentials,
+    threshold_dbm: i8,
+    min_improvement_db: u8,
+}
+
+impl Roaming {
+    /// Create a roaming policy for the network with the specified
+    /// credentials.
+    pub fn new(credentials: types::Credentials) -> Self {
+        Self {
+            credentials,
+            threshold_dbm: DEFAULT_THRESHOLD_DBM,
+            min_improvement_db: DEFAULT_MIN_IMPROVEMENT_DB,
+        }
+    }
+
+    /// Set the signal strength below which a stronger access point is
+    /// searched.
+    ///
+    /// The default is -75 dBm.
+    pub fn with_threshold(self, threshold_dbm: i8) -> Self {
+        Self {
+            threshold_dbm,
+            ..self
+        }
+    }
+
+    /// Set by how much the signal of another access point must be stronger
+    /// to switch to it, which prevents switching back and forth.
+    ///
+    /// The default is 8 dB.
+    pub fn with_min_improvement(self, min_improvement_db: u8) -> Self {
+        Self {
+            min_improvement_db,
+            ..self
+        }
+    }
+
+    /// Check the signal strength and switch to a stronger access point if
+    /// necessary.
+    ///
+    /// This should be called periodically, e.g. every 10 seconds. If the
+    /// signal of the joined access point is below the threshold, the access
+    /// points in range are scanned, and the strongest one of the same
+    /// network is joined if its signal is sufficiently stronger. Returns the
+    /// BSSID of the new access point if the station switched. If the station
+    /// is not connected to the network, nothing is done.
+    ///
+    /// If joining the new access point fails, the network is joined again
+    /// without selecting an access point, and the error is returned.
+    ///
+    /// This blocks until the check and a possible switch are done, even in
+    /// non-blocking mode.
+    pub fn check<C: AtatClient, const N: usize>(
+        &self,
+        esp: &mut EspClient<C, N>,
+    ) -> EspResult<Option<types::MacAddress>> {
+        let joined = match nb::block!(esp.get_joined_access_point()).map_err(nb::Error::Other)? {
+            Some(joined) if joined.ssid == self.credentials.ssid => joined,
+            _ => return Ok(None),
+        };
+        if joined.rssi >= self.threshold_dbm {
+            return Ok(None);
+        }
+
+        let min_rssi = i16::from(joined.rssi) + i16::from(self.min_improvement_db);
+        let found = nb::block!(esp.list_access_points()).map_err(nb::Error::Other)?;
+        let best = found
+            .access_points
+            .iter()
+            .filter(|ap| ap.ssid == self.credentials.ssid && ap.bssid != joined.bssid)
+            .filter(|ap| i16::from(ap.rssi) >= min_rssi)
+            .max_by_key(|ap| ap.rssi);
+        let bssid = match best {
+            Some(ap) => ap.bssid,
+            None => return Ok(None),
+        };
+
+        let credentials = &self.credentials;
+        let result = nb::block!(esp.join_access_point_with_bssid(
+            credentials.ssid.clone(),
+            credentials.psk.as_str(),
+            bssid,
+            types::Target::Current,
+        ));
+        match result {
+            Ok(response) if response.connected => Ok(Some(bssid)),
+            Ok(_) | Err(_) => {
+                // Best effort, the original error is more relevant
+                let _ = nb::block!(esp.join_access_point(
+                    credentials.ssid.clone(),
+                    credentials.psk.as_str(),
+                    types::Target::Current,
+                ));
+                Err(nb::Error::Other(
+                    result.err().unwrap_or(EspError::JoinFailed),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Monitoring of the signal strength.
+//!
+//! The [`RssiMonitor`] keeps the most recent samples of the signal strength,
+//! so that applications can report the link quality, e.g. in a status
+//! message.
+
+use atat::AtatClient;
+use heapless::HistoryBuffer;
+
+use crate::{EspClient, EspResult};
+
+/// Records the signal strength of the joined access point.
+#[derive(Debug)]
+pub struct RssiMonitor<const N: usize> {
+    samples: HistoryBuffer<i8, N>,
+}
+
+impl<const N: usize> RssiMonitor<N> {
+    /// Create a monitor that keeps the last `N` samples.
+    pub fn new() -> Self {
+        Self {
+            samples: HistoryBuffer::new(),
+        }
+    }
+
+    /// Query the signal strength and record it.
+    ///
+    /// This should be called periodically, e.g. every few seconds. Returns
+    /// the signal strength in dBm, or `None` if the station is not connected,
+    /// in which case nothing is recorded.
+    pub fn sample<C: AtatClient, const M: usize>(
+        &mut self,
+        esp: &mut EspClient<C, M>,
+    ) -> EspResult<Option<i8>> {
+        let rssi = esp.rssi()?;
+        if let Some(rssi) = rssi {
+            self.record(rssi);
+        }
+        Ok(rssi)
+    }
+
+    /// Record a signal strength obtained otherwise, e.g. from a scan.
+    pub fn record(&mut self, rssi: i8) {
+        self.samples.write(rssi);
+    }
+
+    /// Forget all samples, e.g. after joining another access point.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Return the number of recorded samples, at most `N`.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Return the most recent sample.
+    pub fn latest(&self) -> Option<i8> {
+        self.samples.recent().copied()
+    }
+
+    /// Return the weakest recorded signal strength.
+    pub fn min(&self) -> Option<i8> {
+        self.samples.as_slice().iter().copied().min()
+    }
+
+    /// Return the strongest recorded signal strength.
+    pub fn max(&self) -> Option<i8> {
+        self.samples.as_slice().iter().copied().max()
+    }
+
+    /// Return the average of the recorded signal strengths, rounded towards
+    /// zero.
+    pub fn average(&self) -> Option<i8> {
+        let samples = self.samples.as_slice();
+        if samples.is_empty() {
+            return None;
+        }
+        let sum: i32 = samples.iter().map(|&rssi| i32::from(rssi)).sum();
+        Some((sum / samples.len() as i32) as i8)
+    }
+}
+
+impl<const N: usize> Default for RssiMonitor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
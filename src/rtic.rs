@@ -0,0 +1,71 @@
+//! A clock for the client based on an RTIC monotonic.
+//!
+//! RTIC owns the monotonic timer, the application only gets to read the
+//! current time through the generated `monotonics::now()` function (RTIC 1)
+//! or `Mono::now()` (RTIC 2). [`MonotonicClock`] turns that function into
+//! the timer expected by the client constructors, e.g.
+//! `MonotonicClock::new(monotonics::now)`.
+//!
+//! The tick rate `HZ` must match the one of the monotonic. The common
+//! `systick-monotonic` and `dwt-systick-monotonic` crates use 64-bit
+//! instants, which are truncated to the 32-bit instants used by atat. The
+//! client only compares short durations, so the wrap-around does no harm.
+
+use atat::clock::Clock;
+use fugit::{TimerDurationU32, TimerInstantU32, TimerInstantU64};
+
+/// The timer of the client, driven by an RTIC monotonic.
+pub struct MonotonicClock<const HZ: u32> {
+    now: fn() -> TimerInstantU64<HZ>,
+    start: TimerInstantU64<HZ>,
+    duration: TimerDurationU32<HZ>,
+    started: bool,
+}
+
+impl<const HZ: u32> MonotonicClock<HZ> {
+    /// Create a clock that reads the time with `now`.
+    pub fn new(now: fn() -> TimerInstantU64<HZ>) -> Self {
+        Self {
+            now,
+            start: now(),
+            duration: TimerDurationU32::from_ticks(0),
+            started: false,
+        }
+    }
+
+    fn elapsed_ticks(&self) -> u64 {
+        (self.now)().ticks().wrapping_sub(self.start.ticks())
+    }
+}
+
+impl<const HZ: u32> Clock<HZ> for MonotonicClock<HZ> {
+    type Error = &'static str;
+
+    fn now(&mut self) -> TimerInstantU32<HZ> {
+        TimerInstantU32::from_ticks((self.now)().ticks() as u32)
+    }
+
+    fn start(&mut self, duration: TimerDurationU32<HZ>) -> Result<(), Self::Error> {
+        self.start = (self.now)();
+        self.duration = duration;
+        self.started = true;
+        Ok(())
+    }
+
+    fn cancel(&mut self) -> Result<(), Self::Error> {
+        if !self.started {
+            Err("cannot cancel stopped timer")
+        } else {
+            self.started = false;
+            Ok(())
+        }
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Self::Error> {
+        if self.elapsed_ticks() >= u64::from(self.duration.ticks()) {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! TCP connections with a socket-like interface.
+//!
+//! A [`TcpSocket`] buffers received data that did not fit into the buffer
+//! of the caller, so that data can be read in arbitrary portions. The socket
+//! doesn't borrow the client, which is passed to every call instead.
+//! Connections from remote clients are accepted with a [`TcpServer`], which
+//! can use TLS on ESP-AT firmwares.
+//!
+//! Received data is delivered in the order in which it arrived, over all
+//! connections. Data for another connection holds back the data of this
+//! one, so all open sockets should be read regularly. Data that was passed to
+//! an event handler with
+//! [`EspClient::poll_events`](../struct.EspClient.html#method.poll_events)
+//! is not received by the sockets anymore.
+
+use core::net::{SocketAddr, SocketAddrV4};
+
+use atat::AtatClient;
+use embedded_hal::delay::blocking::DelayUs;
+use heapless::Vec;
+
+use crate::{
+    commands::{requests, urcs::MAX_DATA_LEN},
+    types, EspClient, EspError, EspResult,
+};
+
+/// Interval in which pending data is checked while waiting for it.
+const RECV_POLL_INTERVAL_MS: u32 = 10;
+
+/// The outcome of waiting for data with a timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Received {
+    /// The number of bytes written into the buffer
+    Data(usize),
+    /// The connection was closed and all data was read
+    Closed,
+    /// No data arrived in time
+    TimedOut,
+}
+
+/// A TCP connection.
+///
+/// `N` is the data capacity of the client, see
+/// [`EspClient::with_data_capacity`](../struct.EspClient.html#method.with_data_capacity).
+#[derive(Debug)]
+pub struct TcpSocket<const N: usize = MAX_DATA_LEN> {
+    mux: types::MultiplexingType,
+    /// Received data that was not read yet, starting at `position`
+    buffer: Vec<u8, N>,
+    position: usize,
+}
+
+impl<const N: usize> TcpSocket<N> {
+    /// Open a TCP connection to `remote`.
+    ///
+    /// An already open connection with the same link ID is used as is. IPv6
+    /// addresses are rejected with `EspError::Unsupported`.
+    ///
+    /// This blocks until the connection is established, even in non-blocking
+    /// mode.
+    pub fn connect<C: AtatClient>(
+        esp: &mut EspClient<C, N>,
+        mux: types::MultiplexingType,
+        remote: SocketAddr,
+    ) -> EspResult<Self> {
+        let remote = ipv4(remote)?;
+        nb::block!(esp.establish_connection(&requests::EstablishConnection::tcp(mux, remote)))
+            .map_err(nb::Error::Other)?;
+        Ok(Self::open(mux))
+    }
+
+    /// Open a TLS connection to `remote`, announcing `server_name` (SNI) if
+    /// specified.
+    ///
+    /// The encryption is handled by the firmware, the certificates used are
+    /// selected with the [`PkiManager`](../pki/struct.PkiManager.html).
+    /// Announcing the server name is only supported by ESP-AT firmwares, IPv6
+    /// addresses are rejected with `EspError::Unsupported`.
+    ///
+    /// This blocks until the connection is established, even in non-blocking
+    /// mode.
+    pub fn connect_ssl<C: AtatClient>(
+        esp: &mut EspClient<C, N>,
+        mux: types::MultiplexingType,
+        remote: SocketAddr,
+        server_name: Option<&str>,
+    ) -> EspResult<Self> {
+        let remote = ipv4(remote)?;
+        if let Some(server_name) = server_name {
+            esp.set_tls_sni(mux, server_name)?;
+        }
+        nb::block!(esp.establish_connection(&requests::EstablishConnection::ssl(mux, remote)))
+            .map_err(nb::Error::Other)?;
+        Ok(Self::open(mux))
+    }
+
+    /// Wrap an open connection.
+    fn open(mux: types::MultiplexingType) -> Self {
+        Self {
+            mux,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// The link ID of the connection.
+    pub fn mux(&self) -> types::MultiplexingType {
+        self.mux
+    }
+
+    /// Send `data`, see [`EspClient::send`].
+    ///
+    /// [`EspClient::send`]: ../struct.EspClient.html#method.send
+    pub fn send<C: AtatClient>(&mut self, esp: &mut EspClient<C, N>, data: &[u8]) -> EspResult<()> {
+        esp.send(self.mux, data)
+    }
+
+    /// Read received data into `buf` and return the number of bytes read.
+    ///
+    /// Returns `WouldBlock` if no data is pending, and `Ok(0)` once the
+    /// connection was closed and all data was read.
+    pub fn recv<C: AtatClient>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        buf: &mut [u8],
+    ) -> EspResult<usize> {
+        if self.fill(esp)? {
+            Ok(self.consume(buf))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Wait until data was received, the connection was closed or
+    /// `timeout_ms` have passed.
+    ///
+    /// Pending data is checked every 10 ms. Failures to delay are reported
+    /// as `EspError::Pin`.
+    pub fn recv_timeout<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        buf: &mut [u8],
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> EspResult<Received>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        match self.wait(esp, delay, &mut 0, timeout_ms) {
+            Ok(true) => Ok(Received::Data(self.consume(buf))),
+            Ok(false) => Ok(Received::Closed),
+            Err(nb::Error::Other(EspError::Timeout)) => Ok(Received::TimedOut),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Fill `buf` completely, waiting at most `timeout_ms` in total.
+    ///
+    /// Fails with `EspError::ConnectionClosed` if the connection was closed
+    /// before enough data was received, and with `EspError::Timeout` if the
+    /// data did not arrive in time.
+    pub fn read_exact<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        buf: &mut [u8],
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> EspResult<()>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        let mut waited_ms = 0;
+        let mut filled = 0;
+        while filled < buf.len() {
+            if !self.wait(esp, delay, &mut waited_ms, timeout_ms)? {
+                return Err(nb::Error::Other(EspError::ConnectionClosed));
+            }
+            filled += self.consume(&mut buf[filled..]);
+        }
+        Ok(())
+    }
+
+    /// Read until and including `delimiter`, waiting at most `timeout_ms` in
+    /// total, and return the number of bytes read.
+    ///
+    /// This is useful for line based protocols, e.g. to read HTTP headers
+    /// with `b'\n'` as delimiter. Data after the delimiter stays buffered
+    /// for the next read. Fails with `EspError::Overflow` if `buf` is full
+    /// before the delimiter was found, and otherwise like
+    /// [`read_exact`](#method.read_exact). After a failure, `buf` contains
+    /// the data read so far.
+    pub fn read_until<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        buf: &mut [u8],
+        delimiter: u8,
+        delay: &mut D,
+        timeout_ms: u32,
+    ) -> EspResult<usize>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        let mut waited_ms = 0;
+        let mut filled = 0;
+        loop {
+            if !self.wait(esp, delay, &mut waited_ms, timeout_ms)? {
+                return Err(nb::Error::Other(EspError::ConnectionClosed));
+            }
+            let pending = &self.buffer[self.position..];
+            let (end, found) = match pending.iter().position(|&b| b == delimiter) {
+                Some(end) => (end + 1, true),
+                None => (pending.len(), false),
+            };
+            let len = end.min(buf.len() - filled);
+            buf[filled..filled + len].copy_from_slice(&pending[..len]);
+            self.position += len;
+            filled += len;
+            if found && len == end {
+                return Ok(filled);
+            }
+            if filled == buf.len() {
+                return Err(nb::Error::Other(EspError::Overflow));
+            }
+        }
+    }
+
+    /// Make sure that received data is buffered.
+    ///
+    /// Returns `WouldBlock` if no data is pending, and `false` if the
+    /// connection was closed and all data was read.
+    fn fill<C: AtatClient>(&mut self, esp: &mut EspClient<C, N>) -> EspResult<bool> {
+        while self.position == self.buffer.len() {
+            match esp.take_network_data(self.mux)? {
+                Some(received) => {
+                    self.buffer = received.data;
+                    self.position = 0;
+                }
+                None if esp.is_connection_open(self.mux) => return Err(nb::Error::WouldBlock),
+                None => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Wait until received data is buffered, adding the time waited to
+    /// `waited_ms`.
+    fn wait<C, D>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        delay: &mut D,
+        waited_ms: &mut u32,
+        timeout_ms: u32,
+    ) -> EspResult<bool>
+    where
+        C: AtatClient,
+        D: DelayUs,
+    {
+        loop {
+            match self.fill(esp) {
+                Err(nb::Error::WouldBlock) => {}
+                result => return result,
+            }
+            if *waited_ms >= timeout_ms {
+                return Err(nb::Error::Other(EspError::Timeout));
+            }
+            delay
+                .delay_ms(RECV_POLL_INTERVAL_MS)
+                .map_err(|_| nb::Error::Other(EspError::Pin))?;
+            *waited_ms += RECV_POLL_INTERVAL_MS;
+        }
+    }
+
+    /// Copy buffered data into `buf` and return the number of bytes copied.
+    fn consume(&mut self, buf: &mut [u8]) -> usize {
+        let pending = &self.buffer[self.position..];
+        let len = pending.len().min(buf.len());
+        buf[..len].copy_from_slice(&pending[..len]);
+        self.position += len;
+        len
+    }
+
+    /// Close the connection, discarding data that was not read.
+    ///
+    /// This blocks until the connection is closed, even in non-blocking mode.
+    pub fn close<C: AtatClient>(self, esp: &mut EspClient<C, N>) -> EspResult<()> {
+        nb::block!(esp.close_connection(&requests::CloseConnection::new(self.mux)))
+            .map(|_| ())
+            .map_err(nb::Error::Other)
+    }
+}
+
+/// A server accepting TCP or SSL connections.
+///
+/// There is only a single server per device. Accepted connections are
+/// received and sent on like connections opened with
+/// [`TcpSocket::connect`](struct.TcpSocket.html#method.connect), the
+/// encryption of SSL connections is handled by the firmware.
+#[derive(Debug)]
+pub struct TcpServer {
+    port: u16,
+}
+
+impl TcpServer {
+    /// Start the server, see [`EspClient::start_server`].
+    ///
+    /// This blocks until the server is started, even in non-blocking mode.
+    ///
+    /// [`EspClient::start_server`]: ../struct.EspClient.html#method.start_server
+    pub fn listen<C: AtatClient, const N: usize>(
+        esp: &mut EspClient<C, N>,
+        server: &requests::StartServer,
+    ) -> EspResult<Self> {
+        nb::block!(esp.start_server(server)).map_err(nb::Error::Other)?;
+        Ok(Self {
+            port: server.port(),
+        })
+    }
+
+    /// The port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Return the next accepted connection.
+    ///
+    /// Returns `WouldBlock` if no connection was accepted.
+    pub fn accept<C: AtatClient, const N: usize>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+    ) -> EspResult<TcpSocket<N>> {
+        esp.take_accepted()
+            .map(TcpSocket::open)
+            .ok_or(nb::Error::WouldBlock)
+    }
+
+    /// Stop the server, accepted connections stay open.
+    ///
+    /// This blocks until the server is stopped, even in non-blocking mode.
+    pub fn close<C: AtatClient, const N: usize>(self, esp: &mut EspClient<C, N>) -> EspResult<()> {
+        nb::block!(esp.stop_server()).map_err(nb::Error::Other)
+    }
+}
+
+/// Return the IPv4 address of `remote`, connections to IPv6 addresses are
+/// not supported.
+fn ipv4(remote: SocketAddr) -> EspResult<SocketAddrV4> {
+    match remote {
+        SocketAddr::V4(remote) => Ok(remote),
+        SocketAddr::V6(_) => Err(nb::Error::Other(EspError::Unsupported)),
+    }
+}
@@ -0,0 +1,126 @@
+//! Supervision of the device with automatic recovery.
+//!
+//! The [`Supervisor`] checks whether the device still answers and whether it
+//! restarted unexpectedly. In both cases, it brings the device back into the
+//! configured state: an unresponsive device is reset through its RST line,
+//! then the device is initialized and the access point is joined again.
+
+use crate::{types, EspClient, EspError, EspResult};
+use atat::AtatClient;
+use embedded_hal::{delay::blocking::DelayUs, digital::blocking::OutputPin};
+
+/// Default number of failed health checks after which the device is reset.
+const DEFAULT_MAX_FAILURES: u8 = 3;
+
+/// Something the supervisor noticed or did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The device did not answer the health check
+    Unresponsive(EspError),
+    /// The device restarted unexpectedly
+    Rebooted,
+    /// The device was reset through its RST line
+    Reset,
+    /// The device was initialized again and rejoined the access point
+    Recovered,
+}
+
+/// Watches the device and recovers it from hangs and unexpected restarts.
+#[derive(Debug)]
+pub struct Supervisor {
+    config: types::InitConfig,
+    access_point: Option<types::Credentials>,
+    max_failures: u8,
+    failures: u8,
+}
+
+impl Supervisor {
+    /// Create a supervisor that initializes the device with `config` after
+    /// a recovery.
+    pub fn new(config: types::InitConfig) -> Self {
+        Self {
+            config,
+            access_point: None,
+            max_failures: DEFAULT_MAX_FAILURES,
+            failures: 0,
+        }
+    }
+
+    /// Join the specified access point after a recovery.
+    pub fn with_access_point(self, credentials: types::Credentials) -> Self {
+        Self {
+            access_point: Some(credentials),
+            ..self
+        }
+    }
+
+    /// Set after how many failed health checks in a row the device is reset.
+    ///
+    /// The default is 3.
+    pub fn with_max_failures(self, max_failures: u8) -> Self {
+        Self {
+            max_failures: max_failures.max(1),
+            ..self
+        }
+    }
+
+    /// Check the health of the device and recover it if necessary.
+    ///
+    /// This should be called periodically, e.g. every few seconds. The device
+    /// is probed with an AT test command. If it fails to answer too often,
+    /// it's reset through `rst`. After a reset or an unexpected restart, the
+    /// device is initialized and the access point joined again.
+    /// `on_event` is called with everything that the supervisor noticed or
+    /// did. If the recovery fails, the error is returned and the recovery is
+    /// attempted again on the next call.
+    ///
+    /// This blocks until the check and a possible recovery are done, even
+    /// in non-blocking mode.
+    pub fn check<C, P, D, F, const N: usize>(
+        &mut self,
+        esp: &mut EspClient<C, N>,
+        rst: &mut P,
+        delay: &mut D,
+        mut on_event: F,
+    ) -> EspResult<()>
+    where
+        C: AtatClient,
+        P: OutputPin,
+        D: DelayUs,
+        F: FnMut(Event),
+    {
+        if esp.take_reboot() {
+            on_event(Event::Rebooted);
+        } else if self.failures < self.max_failures {
+            match nb::block!(esp.selftest()) {
+                Ok(()) => {
+                    self.failures = 0;
+                    return Ok(());
+                }
+                Err(e) => {
+                    self.failures += 1;
+                    on_event(Event::Unresponsive(e));
+                    if self.failures < self.max_failures {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if self.failures >= self.max_failures {
+            esp.hard_reset(rst, delay)?;
+            on_event(Event::Reset);
+        }
+        // Without a successful initialization, reset again on the next call
+        self.failures = self.max_failures;
+        esp.init(&self.config)?;
+        if let Some(types::Credentials { ssid, psk }) = &self.access_point {
+            nb::block!(esp.join_access_point(ssid.clone(), psk.as_str(), types::Target::Current))
+                .map_err(nb::Error::Other)?;
+        }
+        self.failures = 0;
+        on_event(Event::Recovered);
+        Ok(())
+    }
+}
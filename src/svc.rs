@@ -0,0 +1,182 @@
+//! Implementation of the WiFi trait of `embedded-svc`.
+//!
+//! This allows code written against that abstraction, e.g. shared with
+//! products using ESP-IDF directly, to use an external module through
+//! espresso. Only the station mode is supported, configuring the access
+//! point fails with `EspError::Unsupported`.
+
+use atat::AtatClient;
+use embedded_svc::wifi::{
+    AccessPointInfo, AuthMethod, Capability, ClientConfiguration, Configuration, SecondaryChannel,
+    Wifi,
+};
+use enumset::EnumSet;
+use heapless::Vec;
+
+use crate::{
+    commands::{responses, urcs::MAX_DATA_LEN},
+    types, EspClient, EspError,
+};
+
+/// An [`EspClient`] implementing the `embedded-svc` WiFi trait.
+///
+/// The client is still accessible with [`client`](#method.client), e.g. to
+/// open connections.
+///
+/// [`EspClient`]: ../struct.EspClient.html
+pub struct EspWifi<C: AtatClient, const M: usize = MAX_DATA_LEN> {
+    esp: EspClient<C, M>,
+    configuration: Configuration,
+    started: bool,
+}
+
+impl<C: AtatClient, const M: usize> EspWifi<C, M> {
+    /// Wrap an initialized client.
+    pub fn new(esp: EspClient<C, M>) -> Self {
+        Self {
+            esp,
+            configuration: Configuration::None,
+            started: false,
+        }
+    }
+
+    pub fn client(&mut self) -> &mut EspClient<C, M> {
+        &mut self.esp
+    }
+
+    pub fn into_inner(self) -> EspClient<C, M> {
+        self.esp
+    }
+
+    fn client_configuration(&self) -> Option<&ClientConfiguration> {
+        match &self.configuration {
+            Configuration::Client(client) => Some(client),
+            _ => None,
+        }
+    }
+}
+
+impl<C: AtatClient, const M: usize> Wifi for EspWifi<C, M> {
+    type Error = EspError;
+
+    fn get_capabilities(&self) -> Result<EnumSet<Capability>, Self::Error> {
+        let mut capabilities = EnumSet::empty();
+        capabilities.insert(Capability::Client);
+        Ok(capabilities)
+    }
+
+    fn get_configuration(&self) -> Result<Configuration, Self::Error> {
+        Ok(self.configuration.clone())
+    }
+
+    fn set_configuration(&mut self, conf: &Configuration) -> Result<(), Self::Error> {
+        match conf {
+            Configuration::None | Configuration::Client(_) => {
+                self.configuration = conf.clone();
+                Ok(())
+            }
+            Configuration::AccessPoint(_) | Configuration::Mixed(_, _) => {
+                Err(EspError::Unsupported)
+            }
+        }
+    }
+
+    /// Switch the device to station mode.
+    fn start(&mut self) -> Result<(), Self::Error> {
+        nb::block!(self
+            .esp
+            .set_wifi_mode(types::WifiMode::Station, types::Target::Current))?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// Disconnect from the access point.
+    ///
+    /// The radio stays on, the AT firmware can't turn it off.
+    fn stop(&mut self) -> Result<(), Self::Error> {
+        nb::block!(self.esp.leave_access_point())?;
+        self.started = false;
+        Ok(())
+    }
+
+    /// Join the configured access point.
+    ///
+    /// The channel and the authentication method of the configuration are
+    /// ignored, the firmware determines them itself.
+    fn connect(&mut self) -> Result<(), Self::Error> {
+        let client = self
+            .client_configuration()
+            .cloned()
+            .ok_or(EspError::InvalidArgument)?;
+        let response = match client.bssid {
+            Some(bssid) => nb::block!(self.esp.join_access_point_with_bssid(
+                client.ssid.as_str(),
+                client.password.as_str(),
+                types::MacAddress(bssid),
+                types::Target::Current,
+            ))?,
+            None => nb::block!(self.esp.join_access_point(
+                client.ssid.as_str(),
+                client.password.as_str(),
+                types::Target::Current,
+            ))?,
+        };
+        if response.connected {
+            Ok(())
+        } else {
+            Err(EspError::JoinFailed)
+        }
+    }
+
+    fn disconnect(&mut self) -> Result<(), Self::Error> {
+        nb::block!(self.esp.leave_access_point())
+    }
+
+    fn is_started(&self) -> Result<bool, Self::Error> {
+        Ok(self.started)
+    }
+
+    /// Whether the station is connected, as of the last call on the client.
+    fn is_connected(&self) -> Result<bool, Self::Error> {
+        Ok(self.esp.state.wifi_connected)
+    }
+
+    /// Scan for access points.
+    ///
+    /// Networks whose SSID is not valid UTF-8 are skipped, but included in
+    /// the total count.
+    fn scan_n<const N: usize>(&mut self) -> Result<(Vec<AccessPointInfo, N>, usize), Self::Error> {
+        let found = nb::block!(self.esp.list_access_points())?.access_points;
+        let access_points = found.iter().filter_map(access_point_info).take(N).collect();
+        Ok((access_points, found.len()))
+    }
+}
+
+fn access_point_info(access_point: &responses::AccessPoint) -> Option<AccessPointInfo> {
+    Some(AccessPointInfo {
+        ssid: access_point.ssid.as_str()?.into(),
+        bssid: access_point.bssid.0,
+        channel: access_point.channel,
+        secondary_channel: SecondaryChannel::None,
+        signal_strength: access_point.rssi,
+        protocols: EnumSet::empty(),
+        auth_method: auth_method(access_point.encryption),
+    })
+}
+
+/// Map the encryption reported by the firmware, unknown methods are reported
+/// as `AuthMethod::None`.
+fn auth_method(encryption: types::Encryption) -> AuthMethod {
+    match encryption {
+        types::Encryption::Open => AuthMethod::None,
+        types::Encryption::Wep => AuthMethod::WEP,
+        types::Encryption::WpaPsk => AuthMethod::WPA,
+        types::Encryption::Wpa2Psk => AuthMethod::WPA2Personal,
+        types::Encryption::WpaWpa2Psk => AuthMethod::WPAWPA2Personal,
+        types::Encryption::Wpa2Enterprise => AuthMethod::WPA2Enterprise,
+        types::Encryption::Wpa3Psk => AuthMethod::WPA3Personal,
+        types::Encryption::Wpa2Wpa3Psk => AuthMethod::WPA2WPA3Personal,
+        types::Encryption::Other(8) => AuthMethod::WAPIPersonal,
+        types::Encryption::Other(_) => AuthMethod::None,
+    }
+}
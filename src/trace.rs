@@ -0,0 +1,143 @@
+//! Tracing of the AT dialogue.
+//!
+//! With the `log` or `defmt` feature, every command sent, response received
+//! and URC processed is logged at trace level, e.g. `-> AT+CWMODE=1` and
+//! `<- OK`. Long messages, e.g. with network data, are truncated to 96
+//! bytes. Without either feature, nothing is logged and the hooks compile to
+//! nothing.
+
+use core::{
+    cell::RefCell,
+    fmt::{self, Write},
+};
+
+use atat::{AtatCmd, Error, InternalError};
+use heapless::{String, Vec};
+
+/// Maximum length of the text of a traced message.
+#[cfg(any(feature = "log", feature = "defmt"))]
+const MAX_TRACE_LEN: usize = 96;
+
+/// Wraps a command to trace it and its response.
+///
+/// The name of the command, e.g. `AT+CWJAP`, is captured when it's
+/// serialized, so that it can be recorded in the metrics.
+pub(crate) struct Traced<'a, C> {
+    command: &'a C,
+    name: RefCell<Option<String<16>>>,
+}
+
+impl<'a, C> Traced<'a, C> {
+    pub(crate) fn new(command: &'a C) -> Self {
+        Self {
+            command,
+            name: RefCell::new(None),
+        }
+    }
+
+    /// Return the name of the command, if it was serialized.
+    pub(crate) fn take_name(&self) -> Option<String<16>> {
+        self.name.borrow_mut().take()
+    }
+}
+
+impl<C, const LEN: usize> AtatCmd<LEN> for Traced<'_, C>
+where
+    C: AtatCmd<LEN>,
+{
+    type Response = C::Response;
+    const MAX_TIMEOUT_MS: u32 = C::MAX_TIMEOUT_MS;
+    const CAN_ABORT: bool = C::CAN_ABORT;
+    const EXPECTS_RESPONSE_CODE: bool = C::EXPECTS_RESPONSE_CODE;
+
+    fn as_bytes(&self) -> Vec<u8, LEN> {
+        let bytes = self.command.as_bytes();
+        emit("->", format_args!("{}", Escaped(&bytes)));
+        *self.name.borrow_mut() = Some(command_name(&bytes));
+        bytes
+    }
+
+    fn parse(&self, resp: Result<&[u8], InternalError>) -> Result<Self::Response, Error> {
+        match resp {
+            Ok([]) => emit("<-", format_args!("OK")),
+            Ok(bytes) => emit("<-", format_args!("{} OK", Escaped(bytes))),
+            Err(ref e) => emit("<-", format_args!("{:?}", e)),
+        }
+        self.command.parse(resp)
+    }
+}
+
+/// Return the name of a serialized command, or `DATA` for data sent after a
+/// command.
+///
+/// Names are truncated to 16 bytes, which only affects unknown commands.
+fn command_name(bytes: &[u8]) -> String<16> {
+    let mut name = String::new();
+    if !bytes.starts_with(b"AT") {
+        let _ = name.push_str("DATA");
+        return name;
+    }
+    for &b in bytes.iter().take_while(|b| !b"=?\r".contains(b)) {
+        if !b.is_ascii() || name.push(b as char).is_err() {
+            break;
+        }
+    }
+    name
+}
+
+/// Trace a URC that was processed.
+pub(crate) fn urc(urc: &impl fmt::Debug) {
+    emit("<-", format_args!("{:?}", urc));
+}
+
+/// Log a message, truncated to `MAX_TRACE_LEN` bytes.
+#[allow(unused_variables)]
+fn emit(direction: &str, args: fmt::Arguments) {
+    #[cfg(any(feature = "log", feature = "defmt"))]
+    {
+        let mut text = Truncating(heapless::String::new());
+        let _ = text.write_fmt(args);
+        #[cfg(feature = "log")]
+        log::trace!("{} {}", direction, text.0);
+        #[cfg(feature = "defmt")]
+        defmt::trace!("{=str} {=str}", direction, text.0.as_str());
+    }
+}
+
+/// Writes into a string until it's full, then ends the text with `...`.
+#[cfg(any(feature = "log", feature = "defmt"))]
+struct Truncating(heapless::String<MAX_TRACE_LEN>);
+
+#[cfg(any(feature = "log", feature = "defmt"))]
+impl Write for Truncating {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.0.len() + c.len_utf8() > MAX_TRACE_LEN - 3 {
+                // Leave room for the ellipsis, which also ends the formatting
+                let _ = self.0.push_str("...");
+                return Err(fmt::Error);
+            }
+            let _ = self.0.push(c);
+        }
+        Ok(())
+    }
+}
+
+/// Displays bytes as text, with line breaks and other non-printable bytes
+/// escaped.
+struct Escaped<'a>(&'a [u8]);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self.0.strip_suffix(b"\r\n").unwrap_or(self.0);
+        for &b in bytes {
+            match b {
+                b'\r' => f.write_str("\\r")?,
+                b'\n' => f.write_str("\\n")?,
+                b' '..=b'~' => f.write_char(b as char)?,
+                _ => write!(f, "\\x{:02x}", b)?,
+            }
+        }
+        Ok(())
+    }
+}
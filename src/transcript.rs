@@ -0,0 +1,396 @@
+//! Recording of the raw serial traffic of a session, and replaying it in
+//! tests.
+//!
+//! The TX and RX halves of the serial port are wrapped with
+//! [`Transcript::record_tx`] and [`Transcript::record_rx`]. The recorded
+//! transcript can be saved with [`Transcript::write_to`] and loaded again
+//! with [`Transcript::read_from`]. A loaded transcript can be run through a
+//! digester with [`Transcript::replay`], or turned into a [`MockEsp`] that
+//! answers the recorded commands with the recorded responses.
+//!
+//! The file format has one chunk per line, starting with `TX ` or `RX `,
+//! followed by the bytes with non-printable characters escaped as in Rust
+//! byte strings.
+//!
+//! [`MockEsp`]: ../mock/struct.MockEsp.html
+
+use std::{
+    io::{self, BufRead},
+    string::String,
+    sync::{Arc, Mutex},
+    vec::Vec,
+};
+
+use atat::{digest::DigestResult, Digester};
+use embedded_hal::serial;
+
+use crate::{mock::MockEsp, EspError};
+
+/// Bytes sent or received in one go.
+pub type Chunk = (Direction, Vec<u8>);
+
+/// Direction of a chunk of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent to the device
+    Tx,
+    /// Received from the device
+    Rx,
+}
+
+/// Something the digester found when replaying a transcript.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Replayed {
+    Urc(Vec<u8>),
+    Response(Result<Vec<u8>, EspError>),
+    Prompt(u8),
+}
+
+/// The raw serial traffic of a session.
+///
+/// Clones share the same recording.
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    chunks: Arc<Mutex<Vec<Chunk>>>,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the bytes written to `tx`.
+    pub fn record_tx<W: serial::nb::Write<u8>>(&self, tx: W) -> Recorder<W> {
+        Recorder {
+            inner: tx,
+            direction: Direction::Tx,
+            transcript: self.clone(),
+        }
+    }
+
+    /// Record the bytes read from `rx`.
+    pub fn record_rx<R: io::Read>(&self, rx: R) -> Recorder<R> {
+        Recorder {
+            inner: rx,
+            direction: Direction::Rx,
+            transcript: self.clone(),
+        }
+    }
+
+    /// Append bytes, merging them with the last chunk if it has the same
+    /// direction.
+    pub fn push(&self, direction: Direction, bytes: &[u8]) {
+        let mut chunks = self.chunks.lock().unwrap();
+        match chunks.last_mut() {
+            Some((last, chunk)) if *last == direction => chunk.extend_from_slice(bytes),
+            _ => chunks.push((direction, bytes.into())),
+        }
+    }
+
+    /// Return the recorded chunks.
+    pub fn chunks(&self) -> Vec<Chunk> {
+        self.chunks.lock().unwrap().clone()
+    }
+
+    /// Save the transcript.
+    pub fn write_to(&self, mut out: impl io::Write) -> io::Result<()> {
+        for (direction, bytes) in self.chunks() {
+            let prefix: &[u8] = match direction {
+                Direction::Tx => b"TX ",
+                Direction::Rx => b"RX ",
+            };
+            out.write_all(prefix)?;
+            for &byte in &bytes {
+                out.write_all(&core::ascii::escape_default(byte).collect::<Vec<_>>())?;
+            }
+            out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Load a transcript saved with [`write_to`](#method.write_to).
+    pub fn read_from(input: impl BufRead) -> io::Result<Self> {
+        let transcript = Self::new();
+        for line in input.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let direction = match line.get(..3) {
+                Some("TX ") => Direction::Tx,
+                Some("RX ") => Direction::Rx,
+                _ => return Err(invalid_data("unknown direction")),
+            };
+            transcript.push(direction, &unescape(&line[3..])?);
+        }
+        Ok(transcript)
+    }
+
+    /// Run the received bytes through `digester` and return what it found.
+    pub fn replay<D: Digester>(&self, mut digester: D) -> Vec<Replayed> {
+        let mut buf = Vec::new();
+        for (_, bytes) in self
+            .chunks()
+            .into_iter()
+            .filter(|(direction, _)| *direction == Direction::Rx)
+        {
+            buf.extend(bytes);
+        }
+
+        let mut replayed = Vec::new();
+        loop {
+            let (result, len) = digester.digest(&buf);
+            let item = match result {
+                DigestResult::Urc(urc) => Some(Replayed::Urc(urc.into())),
+                DigestResult::Response(response) => Some(Replayed::Response(
+                    response
+                        .map(Vec::from)
+                        .map_err(|e| EspError::from(atat::Error::from(e))),
+                )),
+                DigestResult::Prompt(prompt) => Some(Replayed::Prompt(prompt)),
+                DigestResult::None => None,
+            };
+            if item.is_none() && len == 0 {
+                return replayed;
+            }
+            replayed.extend(item);
+            buf.drain(..len);
+        }
+    }
+
+    /// Return a mock device that answers every recorded command with the
+    /// bytes received up to the next command.
+    ///
+    /// URCs received between commands are sent along with the preceding
+    /// response. Data sent after `AT+CIPSEND` is acknowledged by the mock,
+    /// so anything received after it until the next command is dropped.
+    pub fn mock(&self) -> MockEsp {
+        let esp = MockEsp::new();
+        let mut command: Option<String> = None;
+        let mut sending_data = false;
+        let mut chunks = self.chunks().into_iter().peekable();
+        while let Some((direction, bytes)) = chunks.next() {
+            match (direction, &command) {
+                (Direction::Tx, _) => match bytes.strip_suffix(b"\r\n") {
+                    Some(line) => {
+                        let c = String::from_utf8_lossy(line).into_owned();
+                        // Keep quiet on commands without a recorded response
+                        if let Some((Direction::Tx, _)) | None = chunks.peek() {
+                            esp.on(&c, "");
+                        }
+                        command = Some(c);
+                        sending_data = false;
+                    }
+                    None => sending_data = true,
+                },
+                // The mock acknowledges sent data by itself
+                (Direction::Rx, _) if sending_data => {}
+                (Direction::Rx, Some(c)) => {
+                    esp.on(c, bytes);
+                }
+                (Direction::Rx, None) => esp.inject(&bytes),
+            }
+        }
+        esp
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reverse `core::ascii::escape_default`.
+fn unescape(s: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let escaped = match iter.next() {
+            Some(b'r') => b'\r',
+            Some(b'n') => b'\n',
+            Some(b't') => b'\t',
+            Some(b'x') => {
+                let hex = [
+                    iter.next().unwrap_or_default(),
+                    iter.next().unwrap_or_default(),
+                ];
+                core::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| invalid_data("invalid hex escape"))?
+            }
+            Some(c @ b'\\') | Some(c @ b'\'') | Some(c @ b'"') => c,
+            _ => return Err(invalid_data("invalid escape")),
+        };
+        bytes.push(escaped);
+    }
+    Ok(bytes)
+}
+
+/// A serial TX or RX half whose traffic is recorded in a [`Transcript`].
+pub struct Recorder<T> {
+    inner: T,
+    direction: Direction,
+    transcript: Transcript,
+}
+
+impl<T> Recorder<T> {
+    /// Return the wrapped TX or RX half.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<W: serial::nb::Write<u8>> serial::nb::Write<u8> for Recorder<W> {
+    type Error = W::Error;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.inner.write(word)?;
+        self.transcript.push(self.direction, &[word]);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+impl<R: io::Read> io::Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.transcript.push(self.direction, &buf[..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::digest::EspDigester;
+
+    const TRANSCRIPT: &[u8] = b"TX AT+CIPSEND=5\\r\\n\n\
+        RX \\r\\nOK\\r\\n> \n\
+        TX hello\n\
+        RX \\r\\nRecv 5 bytes\\r\\n\\r\\nSEND OK\\r\\n\\r\\n+IPD,3:\\x00\\r\\n\n";
+
+    #[test]
+    fn test_replay() {
+        let transcript = Transcript::read_from(TRANSCRIPT).unwrap();
+        assert_eq!(
+            transcript.replay(EspDigester::<crate::UrcParser>::new()),
+            [
+                Replayed::Response(Ok(Vec::new())),
+                Replayed::Response(Ok(Vec::new())),
+                Replayed::Urc(b"+IPD,3:\x00\r\n".to_vec()),
+            ]
+        );
+
+        let mut saved = Vec::new();
+        transcript.write_to(&mut saved).unwrap();
+        assert_eq!(saved, TRANSCRIPT);
+    }
+
+    #[test]
+    fn test_replay_passive_receive() {
+        let transcript = Transcript::read_from(
+            &b"RX \\r\\n+IPD,0,7\\r\\n\n\
+            TX AT+CIPRECVDATA=0,1460\\r\\n\n\
+            RX \\r\\n+CIPRECVDATA:7,OK\\r\\n \\r\\n\\r\\nOK\\r\\n\n"[..],
+        )
+        .unwrap();
+        assert_eq!(
+            transcript.replay(EspDigester::<crate::UrcParser>::new()),
+            [
+                Replayed::Urc(b"+IPD,0,7".to_vec()),
+                Replayed::Response(Ok(b"+CIPRECVDATA:7,OK\r\n \r\n".to_vec())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_ipd_ipv6_remote() {
+        let transcript =
+            Transcript::read_from(&b"RX \\r\\n+IPD,1,6,\"fe80::1\",80:a:\\r\\nb\\r\\n\n"[..])
+                .unwrap();
+        assert_eq!(
+            transcript.replay(EspDigester::<crate::UrcParser>::new()),
+            [Replayed::Urc(b"+IPD,1,6,\"fe80::1\",80:a:\r\nb\r".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_replay_ipd_containing_ready() {
+        let transcript =
+            Transcript::read_from(&b"RX \\r\\n+IPD,0,9:\\r\\nready\\r\\n\\r\\nready\\r\\n\n"[..])
+                .unwrap();
+        assert_eq!(
+            transcript.replay(EspDigester::<crate::UrcParser>::new()),
+            [
+                Replayed::Urc(b"+IPD,0,9:\r\nready\r\n".to_vec()),
+                Replayed::Urc(b"ready".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_send_buffer() {
+        let transcript = Transcript::read_from(
+            &b"TX AT+CIPSENDBUF=5\\r\\n\n\
+            RX \\r\\n1,0\\r\\n\\r\\nOK\\r\\n> \n\
+            TX hello\n\
+            RX \\r\\nRecv 5 bytes\\r\\n\n\
+            TX AT+CIPSENDBUF=5\\r\\n\n\
+            RX \\r\\n1,SEND OK\\r\\n\\r\\n2,1\\r\\n\\r\\nOK\\r\\n> \n\
+            TX world\n\
+            RX \\r\\nRecv 5 bytes\\r\\n\\r\\n2,SEND OK\\r\\n\n"[..],
+        )
+        .unwrap();
+        assert_eq!(
+            transcript.replay(EspDigester::<crate::UrcParser>::new()),
+            [
+                Replayed::Response(Ok(b"1,0".to_vec())),
+                Replayed::Urc(b"1,SEND OK".to_vec()),
+                Replayed::Response(Ok(b"2,1".to_vec())),
+                Replayed::Urc(b"2,SEND OK".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_firmware_version() {
+        const VERSION: &[u8] = b"AT version:1.7.4.0(May 11 2020 19:13:04)\r\n\
+            SDK version:3.0.4(9532ceb)\r\n\
+            compile time:May 11 2020 19:19:44";
+        // The version line must not be mistaken for the echo of the command
+        for echo in [&b""[..], b"AT+GMR\r\r\n"] {
+            let transcript = Transcript::new();
+            transcript.push(Direction::Rx, echo);
+            transcript.push(Direction::Rx, VERSION);
+            transcript.push(Direction::Rx, b"\r\nOK\r\n");
+            assert_eq!(
+                transcript.replay(EspDigester::<crate::UrcParser>::new()),
+                [Replayed::Response(Ok(VERSION.to_vec()))]
+            );
+        }
+    }
+
+    #[test]
+    fn test_replay_error_code() {
+        let transcript = Transcript::read_from(
+            &b"TX AT+CWMODE=5\\r\\n\n\
+            RX \\r\\nERR CODE:0x01070000\\r\\n\\r\\nERROR\\r\\n\n"[..],
+        )
+        .unwrap();
+        let replayed = transcript.replay(EspDigester::<crate::UrcParser>::new());
+        assert_eq!(replayed[0], Replayed::Urc(b"ERR CODE:0x01070000".to_vec()));
+        assert!(matches!(replayed[1], Replayed::Response(Err(_))));
+        assert_eq!(
+            <crate::commands::urcs::Urc as atat::AtatUrc>::parse(b"ERR CODE:0x01070000")
+                .and_then(|urc| urc.error()),
+            Some(EspError::InvalidArgument)
+        );
+    }
+}
@@ -1,8 +1,91 @@
 //! Shared types.
 
+use core::fmt::{self, Write};
+
+/// The Espressif chip that runs the AT firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Chip {
+    #[default]
+    Esp8266,
+    Esp32,
+    Esp32C3,
+}
+
+impl Chip {
+    /// Return the firmware dialect that is usually found on this chip.
+    ///
+    /// ESP32 class chips only support the ESP-AT firmware, while ESP8266
+    /// modules usually ship with the NONOS based firmware.
+    pub fn default_dialect(&self) -> Dialect {
+        match self {
+            Chip::Esp8266 => Dialect::NonOs,
+            Chip::Esp32 | Chip::Esp32C3 => Dialect::EspAt,
+        }
+    }
+}
+
+/// Which configuration a command reads or writes.
+///
+/// The NONOS firmware has separate commands for both configurations (with
+/// `_CUR` and `_DEF` suffixes). ESP-AT firmwares only have a single variant
+/// of these commands, whether changes are persisted is controlled globally
+/// by `AT+SYSSTORE` instead. The commands ignore the target there, but the
+/// methods of the client switch `AT+SYSSTORE` accordingly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Target {
+    /// The configuration currently in use, lost on restart
+    #[default]
+    Current,
+    /// The configuration persisted to flash, applied on every start
+    Default,
+}
+
+impl Target {
+    /// Return the `CUR` or `DEF` part of the command suffixes.
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            Target::Current => "CUR",
+            Target::Default => "DEF",
+        }
+    }
+}
+
+/// The family of the AT firmware running on the device.
+///
+/// The firmware families differ in the syntax of several commands. Most
+/// notably, the NONOS firmware uses `_CUR` and `_DEF` command suffixes to
+/// select whether a configuration should be persisted to flash, while ESP-AT
+/// firmwares only have a single variant of these commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Dialect {
+    /// NONOS SDK based AT firmware (AT version 1.x)
+    #[default]
+    NonOs,
+    /// ESP-IDF based ESP-AT firmware (AT version 2.x and later)
+    EspAt,
+}
+
+impl Dialect {
+    /// Return the command suffix for accessing the current or the default
+    /// (persisted) configuration.
+    pub(crate) fn suffix(&self, target: Target) -> &'static str {
+        match (self, target) {
+            (Dialect::NonOs, Target::Current) => "_CUR",
+            (Dialect::NonOs, Target::Default) => "_DEF",
+            (Dialect::EspAt, _) => "",
+        }
+    }
+}
+
 /// The WiFi mode.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum WifiMode {
+    /// The radio is disabled (ESP-AT only)
+    Off,
     /// Station mode (client)
     Station,
     /// Access point mode (server)
@@ -14,6 +97,7 @@ pub enum WifiMode {
 impl WifiMode {
     pub(crate) fn as_at_str(&self) -> &'static str {
         match self {
+            WifiMode::Off => "0",
             WifiMode::Station => "1",
             WifiMode::Ap => "2",
             WifiMode::Both => "3",
@@ -21,6 +105,87 @@ impl WifiMode {
     }
 }
 
+/// The sleep mode of the device.
+///
+/// The firmwares number the modes differently, the dialect is taken into
+/// account when sending them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SleepMode {
+    /// The device doesn't sleep
+    Disabled,
+    /// The radio is turned off between DTIM beacons of the access point
+    Modem,
+    /// The CPU is paused as well while the radio is off
+    Light,
+    /// The radio is turned off for the listen interval of the station
+    /// (ESP-AT only)
+    ModemListenInterval,
+}
+
+impl SleepMode {
+    pub(crate) fn as_at_str(&self, dialect: Dialect) -> &'static str {
+        match (self, dialect) {
+            (SleepMode::Disabled, _) => "0",
+            (SleepMode::Light, Dialect::NonOs) => "1",
+            (SleepMode::Modem, Dialect::NonOs) => "2",
+            (SleepMode::Modem, Dialect::EspAt) => "1",
+            (SleepMode::Light, Dialect::EspAt) => "2",
+            (SleepMode::ModemListenInterval, _) => "3",
+        }
+    }
+
+    pub(crate) fn from_at_str(mode: &str, dialect: Dialect) -> Option<Self> {
+        match (mode, dialect) {
+            ("0", _) => Some(SleepMode::Disabled),
+            ("1", Dialect::NonOs) => Some(SleepMode::Light),
+            ("2", Dialect::NonOs) => Some(SleepMode::Modem),
+            ("1", Dialect::EspAt) => Some(SleepMode::Modem),
+            ("2", Dialect::EspAt) => Some(SleepMode::Light),
+            ("3", Dialect::EspAt) => Some(SleepMode::ModemListenInterval),
+            _ => None,
+        }
+    }
+}
+
+/// A step of an over-the-air firmware update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UpdateStep {
+    /// The update server was found
+    FoundServer,
+    /// The device connected to the update server
+    Connected,
+    /// The latest firmware version was determined
+    GotVersion,
+    /// The firmware is being downloaded and written to flash
+    Updating,
+}
+
+/// The configuration applied by [`EspClient::init`][init].
+///
+/// [init]: ../struct.EspClient.html#method.init
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InitConfig {
+    /// The WiFi mode to switch to.
+    pub wifi_mode: WifiMode,
+    /// Whether multiple parallel connections should be enabled.
+    pub multiplexing: bool,
+    /// Whether `+IPD` messages should include the remote IP and port.
+    pub show_remote_info: bool,
+}
+
+impl Default for InitConfig {
+    fn default() -> Self {
+        Self {
+            wifi_mode: WifiMode::Station,
+            multiplexing: false,
+            show_remote_info: false,
+        }
+    }
+}
+
 /// Wraps both the current configuration and the default configuration.
 pub struct ConfigWithDefault<T> {
     /// The current configuration.
@@ -29,9 +194,303 @@ pub struct ConfigWithDefault<T> {
     pub default: T,
 }
 
+/// A MAC address, e.g. the BSSID of an access point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MacAddress(pub [u8; 6]);
+
+impl MacAddress {
+    /// Write the address in the `aa:bb:cc:dd:ee:ff` notation used by the AT
+    /// commands.
+    pub(crate) fn write_at_str<const L: usize>(
+        &self,
+        buf: &mut heapless::Vec<u8, L>,
+    ) -> fmt::Result {
+        write!(buf, "{}", self)
+    }
+
+    /// Parse an address in the `aa:bb:cc:dd:ee:ff` notation.
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let mut octets = [0; 6];
+        let mut parts = s.split(':');
+        for octet in octets.iter_mut() {
+            let part = parts.next().filter(|part| part.len() == 2)?;
+            *octet = u8::from_str_radix(part, 16).ok()?;
+        }
+        match parts.next() {
+            Some(_) => None,
+            None => Some(Self(octets)),
+        }
+    }
+}
+
+impl fmt::Display for MacAddress {
+    /// Format the address in the usual `aa:bb:cc:dd:ee:ff` notation.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+/// Maximum length of an SSID.
+pub(crate) const MAX_SSID_LEN: usize = 32;
+
+/// Maximum length of a WPA2 pre-shared key.
+pub(crate) const MAX_PSK_LEN: usize = 64;
+
+/// The SSID of a WiFi network.
+///
+/// SSIDs are arbitrary byte strings of up to 32 bytes and not necessarily
+/// valid UTF-8, e.g. if a network name is Latin-1 encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ssid(heapless::Vec<u8, MAX_SSID_LEN>);
+
+impl Ssid {
+    /// Create an SSID from raw bytes, fails if longer than 32 bytes.
+    pub fn new(bytes: &[u8]) -> Option<Self> {
+        heapless::Vec::from_slice(bytes).ok().map(Self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Return the SSID as string if it is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        core::str::from_utf8(&self.0).ok()
+    }
+
+    /// Return a displayable version of the SSID, with invalid UTF-8
+    /// sequences replaced by `U+FFFD`.
+    pub fn lossy(&self) -> impl fmt::Display + '_ {
+        Lossy(&self.0)
+    }
+}
+
+impl From<&str> for Ssid {
+    /// Panics if the string is longer than 32 bytes.
+    fn from(ssid: &str) -> Self {
+        Self::new(ssid.as_bytes()).expect("SSID longer than 32 bytes")
+    }
+}
+
+impl From<heapless::String<32>> for Ssid {
+    fn from(ssid: heapless::String<32>) -> Self {
+        Self(ssid.into_bytes())
+    }
+}
+
+/// Displays bytes as UTF-8, replacing invalid sequences.
+struct Lossy<'a>(&'a [u8]);
+
+impl fmt::Display for Lossy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = self.0;
+        loop {
+            match core::str::from_utf8(bytes) {
+                Ok(valid) => return f.write_str(valid),
+                Err(e) => {
+                    let (valid, rest) = bytes.split_at(e.valid_up_to());
+                    f.write_str(core::str::from_utf8(valid).unwrap_or_default())?;
+                    f.write_char(char::REPLACEMENT_CHARACTER)?;
+                    bytes = &rest[e.error_len().unwrap_or(rest.len())..];
+                }
+            }
+        }
+    }
+}
+
+/// The SSID and PSK of a WiFi network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Credentials {
+    pub ssid: Ssid,
+    pub psk: heapless::String<MAX_PSK_LEN>,
+}
+
+impl Credentials {
+    /// Return `None` if the PSK is longer than 64 bytes.
+    pub fn new(ssid: impl Into<Ssid>, psk: &str) -> Option<Self> {
+        Some(Self {
+            ssid: ssid.into(),
+            psk: psk.parse().ok()?,
+        })
+    }
+}
+
+/// The configuration of the soft AP.
+///
+/// Networks with a password use WPA2-PSK.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SoftApConfig {
+    pub ssid: Ssid,
+    /// The password, empty for an open network
+    pub psk: heapless::String<MAX_PSK_LEN>,
+    /// WiFi channel in the range 1-13
+    pub channel: u8,
+    /// Maximum number of connected stations, the firmware default if `None`
+    pub max_connections: Option<u8>,
+    /// Don't broadcast the SSID
+    pub hidden: bool,
+}
+
+impl SoftApConfig {
+    /// An open network without password.
+    pub fn open(ssid: impl Into<Ssid>, channel: u8) -> Self {
+        Self {
+            ssid: ssid.into(),
+            psk: heapless::String::new(),
+            channel,
+            max_connections: None,
+            hidden: false,
+        }
+    }
+
+    /// A network secured with WPA2-PSK, `None` if the PSK is longer than 64
+    /// bytes.
+    pub fn wpa2(ssid: impl Into<Ssid>, psk: &str, channel: u8) -> Option<Self> {
+        Some(Self {
+            psk: psk.parse().ok()?,
+            ..Self::open(ssid, channel)
+        })
+    }
+
+    /// Limit the number of connected stations (1-10 on ESP-AT firmwares).
+    pub fn with_max_connections(self, max_connections: u8) -> Self {
+        Self {
+            max_connections: Some(max_connections),
+            ..self
+        }
+    }
+
+    /// Don't broadcast the SSID.
+    pub fn hidden(self) -> Self {
+        Self {
+            hidden: true,
+            ..self
+        }
+    }
+
+    /// Whether the values are within the ranges accepted by the firmware.
+    pub(crate) fn is_valid(&self) -> bool {
+        !self.ssid.as_bytes().is_empty()
+            && (self.psk.is_empty() || self.psk.len() >= 8)
+            && (1..=13).contains(&self.channel)
+            && self.max_connections.unwrap_or(1) >= 1
+    }
+}
+
+/// Extended association parameters of ESP-AT firmwares.
+///
+/// Mainly useful for low-power products: a longer listen interval lets the
+/// station sleep through more beacons in modem-sleep mode, at the cost of
+/// latency for incoming data. Unset values keep the defaults of the
+/// firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct JoinOptions {
+    /// Refuse to join open and WEP access points (`pci_en`)
+    pub require_encryption: bool,
+    /// Seconds between reconnection attempts after a failed join, up to
+    /// 7200; 0 disables reconnecting
+    pub reconnect_interval_s: Option<u16>,
+    /// Number of beacon intervals between wakeups in modem-sleep mode, in
+    /// the range 1-100
+    pub listen_interval: Option<u8>,
+}
+
+impl JoinOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse to join open and WEP access points.
+    pub fn with_required_encryption(self) -> Self {
+        Self {
+            require_encryption: true,
+            ..self
+        }
+    }
+
+    /// Set the seconds between reconnection attempts after a failed join.
+    pub fn with_reconnect_interval(self, interval_s: u16) -> Self {
+        Self {
+            reconnect_interval_s: Some(interval_s),
+            ..self
+        }
+    }
+
+    /// Set the number of beacon intervals between wakeups in modem-sleep
+    /// mode.
+    pub fn with_listen_interval(self, listen_interval: u8) -> Self {
+        Self {
+            listen_interval: Some(listen_interval),
+            ..self
+        }
+    }
+
+    /// Whether the values are within the ranges accepted by the firmware.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.reconnect_interval_s.unwrap_or_default() <= 7200
+            && (1..=100).contains(&self.listen_interval.unwrap_or(1))
+    }
+}
+
+/// The encryption of an access point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Encryption {
+    Open,
+    Wep,
+    WpaPsk,
+    Wpa2Psk,
+    WpaWpa2Psk,
+    Wpa2Enterprise,
+    Wpa3Psk,
+    Wpa2Wpa3Psk,
+    /// Unknown encryption
+    Other(u8),
+}
+
+impl Encryption {
+    pub(crate) fn from_at_code(code: u8) -> Self {
+        match code {
+            0 => Encryption::Open,
+            1 => Encryption::Wep,
+            2 => Encryption::WpaPsk,
+            3 => Encryption::Wpa2Psk,
+            4 => Encryption::WpaWpa2Psk,
+            5 => Encryption::Wpa2Enterprise,
+            6 => Encryption::Wpa3Psk,
+            7 => Encryption::Wpa2Wpa3Psk,
+            other => Encryption::Other(other),
+        }
+    }
+}
+
 /// The connection status.
+///
+/// `AT+CIPSTATUS` reports the status of the station together with the state
+/// of the TCP and UDP connections, while the station state query of ESP-AT
+/// firmwares (`AT+CWSTATE`) tells the steps of joining an AP apart.
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ConnectionStatus {
+    /// The station has not started to connect to an AP, e.g. because none is
+    /// configured (`AT+CWSTATE` only)
+    NotStarted,
+    /// The station is connecting or reconnecting to an AP (`AT+CWSTATE`
+    /// only)
+    Connecting,
+    /// The station is connected to an AP, but has no IPv4 address yet
+    /// (`AT+CWSTATE` only)
+    ConnectedWithoutIp,
     /// The ESP8266 Station is connected to an AP and its IP is obtained
     ConnectedToAccessPoint,
     /// The ESP8266 Station has created a TCP or UDP transmission
@@ -45,7 +504,8 @@ pub enum ConnectionStatus {
 }
 
 /// The ESP8266 can manage up to five parallel connections with id 0..4.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ConnectionId {
     Zero,
     One,
@@ -64,21 +524,314 @@ impl ConnectionId {
             ConnectionId::Four => "4",
         }
     }
+
+    pub(crate) fn index(&self) -> usize {
+        match self {
+            ConnectionId::Zero => 0,
+            ConnectionId::One => 1,
+            ConnectionId::Two => 2,
+            ConnectionId::Three => 3,
+            ConnectionId::Four => 4,
+        }
+    }
+
+    pub(crate) fn from_index(index: u8) -> Option<Self> {
+        match index {
+            0 => Some(ConnectionId::Zero),
+            1 => Some(ConnectionId::One),
+            2 => Some(ConnectionId::Two),
+            3 => Some(ConnectionId::Three),
+            4 => Some(ConnectionId::Four),
+            _ => None,
+        }
+    }
 }
 
 /// The ESP8266 can either run in single-connection mode (`NonMultiplexed`) or
 /// in multi-connection mode (`Multiplexed`).
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MultiplexingType {
     NonMultiplexed,
     Multiplexed(ConnectionId),
 }
 
+impl MultiplexingType {
+    /// Index of the connection in per-connection tables.
+    ///
+    /// The single connection of the non-multiplexed mode shares the index
+    /// with link ID 0, as the modes can't be used at the same time.
+    pub(crate) fn index(&self) -> usize {
+        match self {
+            MultiplexingType::NonMultiplexed => 0,
+            MultiplexingType::Multiplexed(id) => id.index(),
+        }
+    }
+}
+
+/// Maximum number of parallel connections.
+pub(crate) const MAX_CONNECTIONS: usize = 5;
+
+/// Traffic statistics of a connection.
+///
+/// Counted by the client since the connection was opened. Only data sent
+/// with [`EspClient::prepare_send_data`] and received with
+/// [`EspClient::poll_network_data`] (or passed to an event handler) is
+/// counted.
+///
+/// [`EspClient::prepare_send_data`]: ../struct.EspClient.html#method.prepare_send_data
+/// [`EspClient::poll_network_data`]: ../struct.EspClient.html#method.poll_network_data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionStats {
+    pub bytes_sent: u32,
+    pub segments_sent: u32,
+    /// Number of segments that failed to send
+    pub send_failures: u32,
+    pub bytes_received: u32,
+    pub segments_received: u32,
+    /// Time of the last sent or received segment in milliseconds, if a time
+    /// source is set
+    pub last_activity_ms: Option<u64>,
+}
+
+/// Maximum number of commands that metrics are recorded for.
+pub const MAX_COMMAND_METRICS: usize = 16;
+
+/// Latency and failure statistics of a command, as returned by
+/// [`EspClient::command_metrics`].
+///
+/// Latencies are measured from sending the command until its response was
+/// received, and are only recorded if a time source is set (see
+/// [`EspClient::set_time_source`]). Each attempt of a command that is
+/// retried while the device is busy counts separately.
+///
+/// [`EspClient::command_metrics`]: ../struct.EspClient.html#method.command_metrics
+/// [`EspClient::set_time_source`]: ../struct.EspClient.html#method.set_time_source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CommandMetrics {
+    /// The command without parameters, e.g. `AT+CWJAP`, or `DATA` for data
+    /// sent on a connection
+    pub command: heapless::String<16>,
+    /// Number of times the command was sent
+    pub count: u32,
+    /// Number of times the command failed, including timeouts
+    pub failures: u32,
+    pub min_ms: u32,
+    pub max_ms: u32,
+    /// Sum of all latencies in milliseconds
+    pub total_ms: u64,
+    /// Number of latencies that were recorded
+    timed: u32,
+}
+
+impl CommandMetrics {
+    pub(crate) fn new(command: heapless::String<16>) -> Self {
+        Self {
+            command,
+            ..Self::default()
+        }
+    }
+
+    /// Return the average latency in milliseconds, `None` if none was
+    /// recorded.
+    pub fn avg_ms(&self) -> Option<u32> {
+        // The average is at most the maximum, so it fits
+        self.total_ms
+            .checked_div(u64::from(self.timed))
+            .map(|avg_ms| avg_ms as u32)
+    }
+
+    /// Record an attempt with its latency, if known.
+    pub(crate) fn record(&mut self, latency_ms: Option<u32>, failed: bool) {
+        self.count = self.count.saturating_add(1);
+        if failed {
+            self.failures = self.failures.saturating_add(1);
+        }
+        if let Some(latency_ms) = latency_ms {
+            self.min_ms = if self.timed == 0 {
+                latency_ms
+            } else {
+                self.min_ms.min(latency_ms)
+            };
+            self.max_ms = self.max_ms.max(latency_ms);
+            self.total_ms += u64::from(latency_ms);
+            self.timed += 1;
+        }
+    }
+}
+
+/// Which connections are ready for I/O, as returned by
+/// [`EspClient::poll_readiness`].
+///
+/// [`EspClient::poll_readiness`]: ../struct.EspClient.html#method.poll_readiness
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Readiness {
+    pub(crate) readable: u8,
+    pub(crate) writable: u8,
+    pub(crate) closed: u8,
+}
+
+impl Readiness {
+    /// Whether received data is pending for the connection.
+    pub fn is_readable(&self, mux: MultiplexingType) -> bool {
+        self.readable & (1 << mux.index()) != 0
+    }
+
+    /// Whether the connection is open and data can be sent.
+    pub fn is_writable(&self, mux: MultiplexingType) -> bool {
+        self.writable & (1 << mux.index()) != 0
+    }
+
+    /// Whether the connection was closed since the last poll.
+    pub fn is_closed(&self, mux: MultiplexingType) -> bool {
+        self.closed & (1 << mux.index()) != 0
+    }
+
+    /// Whether any connection is ready.
+    pub fn is_empty(&self) -> bool {
+        self.readable == 0 && self.writable == 0 && self.closed == 0
+    }
+}
+
+/// How received data is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReceiveMode {
+    /// Data is sent to the host in `+IPD` messages as soon as it arrives
+    #[default]
+    Active,
+    /// Data is buffered by the device and read by the host with
+    /// `AT+CIPRECVDATA`, the device only announces how much is available
+    Passive,
+}
+
+impl ReceiveMode {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            ReceiveMode::Active => "0",
+            ReceiveMode::Passive => "1",
+        }
+    }
+}
+
+/// Which optional system messages ESP-AT firmwares print, configured with
+/// `AT+SYSMSG`.
+///
+/// All messages are recognized by the client regardless of the
+/// configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SystemMessages {
+    /// Print `+QUITT` when the transparent transmission mode is left
+    pub quit_transparent: bool,
+    /// Announce opened connections with the detailed `+LINK_CONN` message
+    /// instead of `[<link_id>,]CONNECT`
+    pub link_info: bool,
+    /// Print the `CONNECT` and `CLOSED` messages in transparent transmission
+    /// mode as well
+    pub link_status: bool,
+}
+
+impl SystemMessages {
+    pub(crate) fn to_at_value(self) -> u8 {
+        u8::from(self.quit_transparent)
+            | u8::from(self.link_info) << 1
+            | u8::from(self.link_status) << 2
+    }
+
+    pub(crate) fn from_at_value(value: u8) -> Self {
+        Self {
+            quit_transparent: value & 1 != 0,
+            link_info: value & (1 << 1) != 0,
+            link_status: value & (1 << 2) != 0,
+        }
+    }
+}
+
+/// A WiFi interface of the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WifiInterface {
+    Station,
+    /// The soft AP
+    Ap,
+}
+
+impl WifiInterface {
+    /// Return the name of the interface used in command names.
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            WifiInterface::Station => "STA",
+            WifiInterface::Ap => "AP",
+        }
+    }
+}
+
+/// The 802.11 PHY protocols an interface uses, configured with
+/// `AT+CWSTAPROTO` and `AT+CWAPPROTO`.
+///
+/// Some legacy access points only associate reliably if the station is
+/// limited to 802.11b/g.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PhyProtocols {
+    /// 802.11b
+    pub b: bool,
+    /// 802.11g
+    pub g: bool,
+    /// 802.11n
+    pub n: bool,
+    /// Espressif long range mode (ESP32 only)
+    pub long_range: bool,
+}
+
+impl PhyProtocols {
+    /// 802.11b/g/n, the default of the firmware.
+    pub fn bgn() -> Self {
+        Self {
+            b: true,
+            g: true,
+            n: true,
+            long_range: false,
+        }
+    }
+
+    /// 802.11b/g only.
+    pub fn bg() -> Self {
+        Self {
+            n: false,
+            ..Self::bgn()
+        }
+    }
+
+    pub(crate) fn to_at_value(self) -> u8 {
+        u8::from(self.b)
+            | u8::from(self.g) << 1
+            | u8::from(self.n) << 2
+            | u8::from(self.long_range) << 3
+    }
+
+    pub(crate) fn from_at_value(value: u8) -> Self {
+        Self {
+            b: value & 1 != 0,
+            g: value & (1 << 1) != 0,
+            n: value & (1 << 2) != 0,
+            long_range: value & (1 << 3) != 0,
+        }
+    }
+}
+
 /// The connection protocol.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Protocol {
     Tcp,
     Udp,
+    /// TLS over TCP
+    Ssl,
 }
 
 impl Protocol {
@@ -86,6 +839,397 @@ impl Protocol {
         match self {
             Protocol::Tcp => "TCP",
             Protocol::Udp => "UDP",
+            Protocol::Ssl => "SSL",
+        }
+    }
+
+    /// Parse the connection type, including the IPv6 variants like `TCPv6`
+    /// of ESP-AT firmwares.
+    pub(crate) fn from_at_str(s: &str) -> Option<Self> {
+        match s.strip_suffix("v6").unwrap_or(s) {
+            "TCP" => Some(Protocol::Tcp),
+            "UDP" => Some(Protocol::Udp),
+            "SSL" => Some(Protocol::Ssl),
+            _ => None,
+        }
+    }
+}
+
+/// The number of data bits per UART frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataBits {
+    Five,
+    Six,
+    Seven,
+    Eight,
+}
+
+impl DataBits {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            DataBits::Five => "5",
+            DataBits::Six => "6",
+            DataBits::Seven => "7",
+            DataBits::Eight => "8",
+        }
+    }
+
+    pub(crate) fn from_at_str(s: &str) -> Option<Self> {
+        match s {
+            "5" => Some(DataBits::Five),
+            "6" => Some(DataBits::Six),
+            "7" => Some(DataBits::Seven),
+            "8" => Some(DataBits::Eight),
+            _ => None,
+        }
+    }
+}
+
+/// The number of stop bits per UART frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StopBits {
+    One,
+    OneAndHalf,
+    Two,
+}
+
+impl StopBits {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            StopBits::One => "1",
+            StopBits::OneAndHalf => "2",
+            StopBits::Two => "3",
+        }
+    }
+
+    pub(crate) fn from_at_str(s: &str) -> Option<Self> {
+        match s {
+            "1" => Some(StopBits::One),
+            "2" => Some(StopBits::OneAndHalf),
+            "3" => Some(StopBits::Two),
+            _ => None,
+        }
+    }
+}
+
+/// The UART parity mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+impl Parity {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            Parity::None => "0",
+            Parity::Odd => "1",
+            Parity::Even => "2",
+        }
+    }
+
+    pub(crate) fn from_at_str(s: &str) -> Option<Self> {
+        match s {
+            "0" => Some(Parity::None),
+            "1" => Some(Parity::Odd),
+            "2" => Some(Parity::Even),
+            _ => None,
+        }
+    }
+}
+
+/// The UART hardware flow control mode.
+///
+/// On the ESP8266, RTS is GPIO15 and CTS is GPIO13. Without flow control,
+/// the host must read every byte in time, which gets unreliable at high baud
+/// rates: at 921600 baud, dropped bytes in the middle of received data
+/// corrupt the framing of the `+IPD` messages. Use `RtsCts` for baud rates
+/// above 115200 if the host UART supports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FlowControl {
+    /// No flow control
+    None,
+    /// RTS only
+    Rts,
+    /// CTS only
+    Cts,
+    /// Both RTS and CTS
+    RtsCts,
+}
+
+impl FlowControl {
+    pub(crate) fn as_at_str(&self) -> &'static str {
+        match self {
+            FlowControl::None => "0",
+            FlowControl::Rts => "1",
+            FlowControl::Cts => "2",
+            FlowControl::RtsCts => "3",
+        }
+    }
+
+    pub(crate) fn from_at_str(s: &str) -> Option<Self> {
+        match s {
+            "0" => Some(FlowControl::None),
+            "1" => Some(FlowControl::Rts),
+            "2" => Some(FlowControl::Cts),
+            "3" => Some(FlowControl::RtsCts),
+            _ => None,
+        }
+    }
+}
+
+/// The UART configuration of the ESP8266.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UartConfig {
+    /// Baud rate, in the range 110 to 4608000
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub stop_bits: StopBits,
+    pub parity: Parity,
+    pub flow_control: FlowControl,
+}
+
+impl Default for UartConfig {
+    /// The factory default configuration (115200 8N1, no flow control).
+    fn default() -> Self {
+        Self {
+            baud_rate: 115_200,
+            data_bits: DataBits::Eight,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+/// How ESP-AT firmwares reconnect to the access point by themselves after
+/// the connection was lost.
+///
+/// Device-managed reconnection should be disabled if the application
+/// reconnects on its own, e.g. with a
+/// [`Supervisor`](../supervisor/struct.Supervisor.html), so that both don't
+/// interfere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReconnectConfig {
+    /// Seconds between two attempts, up to 7200; 0 disables reconnecting
+    pub interval_s: u16,
+    /// Number of attempts, up to 1000; 0 keeps trying forever
+    pub max_attempts: u16,
+}
+
+impl ReconnectConfig {
+    /// Don't reconnect automatically.
+    pub fn disabled() -> Self {
+        Self {
+            interval_s: 0,
+            max_attempts: 0,
+        }
+    }
+
+    /// Whether the values are within the ranges accepted by the firmware.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.interval_s <= 7200 && self.max_attempts <= 1000
+    }
+}
+
+/// The persisted configuration of a device, as returned by
+/// [`EspClient::read_config`] and applied with [`EspClient::apply_config`].
+///
+/// Comparing a configuration with a known good one allows enforcing the
+/// same configuration on all devices of a fleet. The settings that are
+/// persisted to flash are read, except for the joined access point, which
+/// is the current connection.
+///
+/// The access point credentials, the joined access point, the IP
+/// configuration and the sleep mode are only reported, they are not applied:
+/// the password of the access point can't be read back, the IP configuration
+/// is usually assigned by DHCP, so applying it would pin every device to the
+/// address of the device it was read from, and the sleep mode is not
+/// persisted by the firmware.
+///
+/// [`EspClient::read_config`]: ../struct.EspClient.html#method.read_config
+/// [`EspClient::apply_config`]: ../struct.EspClient.html#method.apply_config
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceConfig {
+    pub wifi_mode: WifiMode,
+    pub uart: UartConfig,
+    /// How the station reconnects by itself, `None` if not supported by the
+    /// firmware (ESP-AT only)
+    pub reconnect: Option<ReconnectConfig>,
+    /// The optional system messages, `None` if not supported by the firmware
+    /// (ESP-AT only)
+    pub system_messages: Option<SystemMessages>,
+    /// The sleep mode, `None` if it couldn't be read. It's not persisted by
+    /// the firmware, so it's the mode set since the last reset. Only
+    /// reported, not applied.
+    pub sleep_mode: Option<SleepMode>,
+    /// Whether the station is configured to join an access point, `None` if
+    /// not supported by the firmware (ESP-AT only). Only reported, not
+    /// applied.
+    pub ap_credentials_stored: Option<bool>,
+    /// The SSID of the joined access point, `None` if the station is not
+    /// connected. Only reported, not applied.
+    pub joined_ssid: Option<Ssid>,
+    /// The default IP configuration of the station. Only reported, not
+    /// applied.
+    pub ip: crate::commands::responses::IpConfig,
+}
+
+/// A civil date and time in the timezone configured for SNTP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    pub year: u16,
+    /// Month in the range 1-12
+    pub month: u8,
+    /// Day of the month in the range 1-31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Offset of the local time from UTC in minutes
+    pub utc_offset_min: i16,
+}
+
+impl DateTime {
+    /// Return the number of seconds since 1970-01-01 00:00:00 UTC.
+    pub fn unix_timestamp(&self) -> i64 {
+        // Days since the epoch of the proleptic Gregorian calendar, see
+        // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let year = i64::from(self.year) - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = i64::from(self.month);
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        let seconds =
+            i64::from(self.hour) * 3600 + i64::from(self.minute) * 60 + i64::from(self.second);
+        days * 86_400 + seconds - i64::from(self.utc_offset_min) * 60
+    }
+
+    /// Parse the `asctime` format used by the firmware, e.g.
+    /// `Thu Aug 04 14:48:05 2016`.
+    pub(crate) fn parse_asctime(s: &str, utc_offset_min: i16) -> Option<Self> {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        let mut fields = s.split_ascii_whitespace().skip(1);
+        let month = fields.next()?;
+        let month = MONTHS.iter().position(|&m| m == month)? as u8 + 1;
+        let day = fields.next()?.parse().ok()?;
+        let mut time = fields.next()?.split(':');
+        let hour = time.next()?.parse().ok()?;
+        let minute = time.next()?.parse().ok()?;
+        let second = time.next()?.parse().ok()?;
+        let year = fields.next()?.parse().ok()?;
+        Some(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            utc_offset_min,
+        })
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Format in the ISO 8601 notation, e.g. `2016-08-04T14:48:05+02:00`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.utc_offset_min < 0 { '-' } else { '+' };
+        let offset = self.utc_offset_min.unsigned_abs();
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            sign,
+            offset / 60,
+            offset % 60
+        )
+    }
+}
+
+/// Optional command families supported by the firmware.
+///
+/// Determined at runtime with `AT+CMD?`, which is only available on ESP-AT
+/// firmwares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Capabilities {
+    /// MQTT client (`AT+MQTTCONN` and friends)
+    pub mqtt: bool,
+    /// HTTP client (`AT+HTTPCLIENT`)
+    pub http_client: bool,
+    /// WebSocket client (`AT+WSOPEN` and friends)
+    pub websocket: bool,
+    /// Bluetooth LE (`AT+BLEINIT` and friends)
+    pub ble: bool,
+    /// SNTP time synchronization (`AT+CIPSNTPCFG`)
+    pub sntp: bool,
+    /// SSL/TLS client configuration (`AT+CIPSSLCCONF`)
+    pub ssl_config: bool,
+    /// Global parameter store control (`AT+SYSSTORE`)
+    pub sysstore: bool,
+    /// Station state query (`AT+CWSTATE`)
+    pub cwstate: bool,
+    /// System message configuration (`AT+SYSMSG`)
+    pub sysmsg: bool,
+    /// Device-side reconnect configuration (`AT+CWRECONNCFG`)
+    pub reconnect_config: bool,
+    /// Certificate and key storage (`AT+SYSMFG`)
+    pub pki: bool,
+    /// Pre-shared keys for TLS client connections (`AT+CIPSSLCPSK`)
+    pub tls_psk: bool,
+    /// Unix timestamp of the system clock (`AT+SYSTIMESTAMP`)
+    pub timestamp: bool,
+    /// Server name indication for TLS client connections (`AT+CIPSSLCSNI`)
+    pub tls_sni: bool,
+    /// Extended error codes (`AT+SYSLOG`)
+    pub syslog: bool,
+    /// Rollback to the previous firmware (`AT+SYSROLLBACK`)
+    pub rollback: bool,
+    /// PHY protocol selection (`AT+CWSTAPROTO` and `AT+CWAPPROTO`)
+    pub phy_protocols: bool,
+}
+
+impl Capabilities {
+    /// Mark the command family of the specified command as supported.
+    pub(crate) fn add_command(&mut self, command: &str) {
+        match command {
+            "AT+MQTTCONN" => self.mqtt = true,
+            "AT+HTTPCLIENT" => self.http_client = true,
+            "AT+WSOPEN" => self.websocket = true,
+            "AT+BLEINIT" => self.ble = true,
+            "AT+CIPSNTPCFG" => self.sntp = true,
+            "AT+CIPSSLCCONF" => self.ssl_config = true,
+            "AT+SYSSTORE" => self.sysstore = true,
+            "AT+CWSTATE" => self.cwstate = true,
+            "AT+SYSMSG" => self.sysmsg = true,
+            "AT+CWRECONNCFG" => self.reconnect_config = true,
+            "AT+SYSMFG" => self.pki = true,
+            "AT+CIPSSLCPSK" => self.tls_psk = true,
+            "AT+SYSTIMESTAMP" => self.timestamp = true,
+            "AT+CIPSSLCSNI" => self.tls_sni = true,
+            "AT+SYSLOG" => self.syslog = true,
+            "AT+SYSROLLBACK" => self.rollback = true,
+            "AT+CWSTAPROTO" => self.phy_protocols = true,
+            _ => {}
         }
     }
 }
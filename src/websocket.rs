@@ -0,0 +1,118 @@
+//! High level access to the WebSocket client built into ESP-AT firmwares.
+
+use core::convert::TryInto;
+
+use atat::AtatClient;
+
+use crate::{
+    commands::{
+        requests, responses,
+        urcs::{Urc, MAX_DATA_LEN},
+        websocket,
+    },
+    EspClient, EspError, EspResult,
+};
+
+pub use crate::commands::websocket::Message;
+
+/// Number of parallel WebSocket connections supported by the firmware.
+pub const MAX_LINKS: usize = 3;
+
+/// Maximum length of a frame that can be sent with
+/// [`WebSocketClient::send`](struct.WebSocketClient.html#method.send).
+pub const MAX_SEND_LEN: usize = 256;
+
+/// A handle to the WebSocket client of the device.
+///
+/// Created with [`EspClient::websocket`](../struct.EspClient.html#method.websocket).
+pub struct WebSocketClient<'a, C: AtatClient, const N: usize = MAX_DATA_LEN> {
+    pub(crate) esp: &'a mut EspClient<C, N>,
+}
+
+impl<'a, C: AtatClient, const N: usize> WebSocketClient<'a, C, N> {
+    /// Open a connection to the specified URI (`ws://` or `wss://`).
+    ///
+    /// The link ID must be in the range 0 to 2. The firmware sends a ping
+    /// every `ping_interval_s` seconds and closes the connection if no pong
+    /// was received within `ping_timeout_s` seconds. URIs with control
+    /// characters are rejected with `EspError::InvalidArgument`, URIs longer
+    /// than 256 bytes with `EspError::Overflow`.
+    pub fn open(
+        &mut self,
+        link_id: u8,
+        uri: &str,
+        ping_interval_s: u16,
+        ping_timeout_s: u16,
+    ) -> EspResult<()> {
+        if usize::from(link_id) >= MAX_LINKS {
+            return Err(nb::Error::Other(EspError::LinkInvalid));
+        }
+        let open =
+            websocket::Open::new(link_id, uri).ok_or(nb::Error::Other(EspError::Overflow))?;
+        if !open.is_valid() {
+            return Err(nb::Error::Other(EspError::InvalidArgument));
+        }
+        self.esp
+            .send_blocking(&websocket::Configure::new(
+                link_id,
+                ping_interval_s,
+                ping_timeout_s,
+            ))
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp
+            .send_blocking(&open)
+            .map(|_: responses::EmptyResponse| ())?;
+        self.esp.state.websocket_links[usize::from(link_id)] = true;
+        Ok(())
+    }
+
+    /// Whether the connection with the specified link ID is open.
+    pub fn is_open(&mut self, link_id: u8) -> bool {
+        self.esp.process_urcs();
+        self.esp
+            .state
+            .websocket_links
+            .get(usize::from(link_id))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Send a text frame.
+    pub fn send(&mut self, link_id: u8, data: &str) -> EspResult<()> {
+        if data.len() > MAX_SEND_LEN {
+            return Err(nb::Error::Other(EspError::Overflow));
+        }
+        self.esp
+            .send_blocking(&websocket::PrepareSend::new(
+                link_id,
+                data.len().try_into().unwrap(),
+            ))
+            .map(|_: responses::EmptyResponse| ())?;
+        nb::block!(self
+            .esp
+            .send_data(&requests::SendData::<MAX_SEND_LEN>::new(data)))
+        .map_err(nb::Error::Other)
+    }
+
+    /// Return the next received message, if any.
+    pub fn poll(&mut self) -> Option<Message> {
+        match self
+            .esp
+            .take_urc(|urc| matches!(urc, Urc::WebSocketData(_)))
+        {
+            Some(Urc::WebSocketData(message)) => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Close the connection with the specified link ID.
+    pub fn close(&mut self, link_id: u8) -> EspResult<()> {
+        self.esp
+            .send_command(&websocket::Close::new(link_id))
+            .map(|_: responses::EmptyResponse| ())?;
+        if let Some(open) = self.esp.state.websocket_links.get_mut(usize::from(link_id)) {
+            *open = false;
+        }
+        Ok(())
+    }
+}